@@ -0,0 +1,1294 @@
+use crate::ast::{Ast, Decl, Expr, ExprId, Function, NodeId, SideTable, Stmt, StmtId};
+use crate::lexical::{Constant, Operator, UnaryOperator, ValueType};
+use crate::resolver::{Resolution, Resolutions, Resolver};
+use crate::semantic::{Binding, Diagnostic, SemanticAnalyzer};
+use std::collections::HashMap;
+
+/// System V の整数引数に使うレジスタ. long は 64 bit, それ以外は 32 bit の名前を使う
+const INT_REGISTERS: [(&str, &str); 6] = [
+    ("%rdi", "%edi"), ("%rsi", "%esi"), ("%rdx", "%edx"), ("%rcx", "%ecx"), ("%r8", "%r8d"), ("%r9", "%r9d"),
+];
+
+/// System V の浮動小数点数の引数に使うレジスタの数
+const FLOAT_REGISTERS: usize = 8;
+
+/// 構文木から x86-64 System V 向けの GNU アセンブラのソースを生成する.
+/// 式の値は long なら %rax, それ以外の整数なら %eax, float と double なら %xmm0 に置き,
+/// 計算途中の値はスタックに積む. int より小さい型は %eax に符号拡張かゼロ拡張した値で持つ.
+/// 実行ファイルの終了コードは main 関数の戻り値になる
+pub struct AsmGenerator<'a>
+{
+    ast: &'a Ast,
+    resolutions: Resolutions,
+
+    // 意味解析で求めた式の型と, 代入先の宣言
+    types: SideTable<ExprId, ValueType>,
+    assignment_bindings: SideTable<StmtId, Binding>,
+
+    // グローバル変数の型. 同じ名前で複数回宣言された場合は最後の宣言の型
+    globals: Vec<(String, ValueType)>,
+
+    // 関数名と定義. 関数の再定義は意味解析でエラーになる. 定義のない関数はプロトタイプを持つ
+    functions: HashMap<String, &'a Function>,
+
+    output: String,
+    label_count: usize,
+
+    // 生成中の関数
+    function: Option<&'a Function>,
+
+    // ローカル変数の (深さ, スロット) ごとの %rbp からの位置
+    offsets: HashMap<(usize, usize), usize>,
+
+    // 関数の先頭から積んだ 8 バイト単位の値の数. 呼び出し前にスタックを 16 バイトに揃えるのに使う
+    depth: usize,
+
+    // break と continue の飛び先
+    loops: Vec<(String, String)>,
+
+    // return と, 値を返さずに関数の終わりに達した場合の飛び先
+    return_label: String,
+    fall_off_label: String,
+}
+
+impl<'a> AsmGenerator<'a>
+{
+    pub fn new(ast: &'a Ast) -> Self
+    {
+        AsmGenerator {
+            ast,
+            resolutions: Resolutions::default(),
+            types: SideTable::new(),
+            assignment_bindings: SideTable::new(),
+            globals: Vec::new(),
+            functions: HashMap::new(),
+            output: String::new(),
+            label_count: 0,
+            function: None,
+            offsets: HashMap::new(),
+            depth: 0,
+            loops: Vec::new(),
+            return_label: String::new(),
+            fall_off_label: String::new(),
+        }
+    }
+
+    /// アセンブラのソースを生成する. 意味解析でエラーがある場合は生成しない
+    pub fn generate(mut self) -> Result<String, Vec<Diagnostic>>
+    {
+        let ast = self.ast;
+
+        let mut analyzer = SemanticAnalyzer::new(ast);
+        // 定義のない関数は外部のシンボルを呼び出す
+        analyzer.set_external_functions(true);
+        let mut errors: Vec<Diagnostic> = analyzer.analyze().into_iter()
+            .filter(|diagnostic| diagnostic.is_error())
+            .collect();
+
+        let mut functions: Vec<&'a Function> = Vec::new();
+        for declaration in ast.declarations()
+        {
+            match declaration
+            {
+                Decl::Function(function) =>
+                    {
+                        functions.retain(|defined| defined.name != function.name);
+                        functions.push(function);
+                        self.functions.insert(function.name.clone(), function);
+                    }
+                Decl::Prototype(function) =>
+                    {
+                        // 定義がない場合は外部の f_ で始まるシンボルを呼び出す
+                        self.functions.entry(function.name.clone()).or_insert(function);
+                    }
+                Decl::Variable { type_specifier, name, .. } =>
+                    {
+                        match self.globals.iter_mut().find(|(global, _)| global == name)
+                        {
+                            Some(global) => global.1 = type_specifier.clone(),
+                            None => self.globals.push((name.clone(), type_specifier.clone())),
+                        }
+                    }
+            }
+        }
+
+        // 終了コードとして返すので, main 関数は引数を取れない
+        let main = functions.iter().find(|function| function.name == "main").copied();
+        if let Some(main) = main
+        {
+            if !main.parameters.is_empty()
+            {
+                errors.push(Diagnostic::error("main 関数は引数を取れません".to_string()));
+            }
+        }
+        if !errors.is_empty()
+        {
+            return Err(errors);
+        }
+
+        for index in 0..ast.expr_count()
+        {
+            let id = ExprId::new(index);
+            if let Some(value_type) = analyzer.expr_type(id)
+            {
+                self.types.insert(id, value_type.clone());
+            }
+        }
+        for index in 0..ast.stmt_count()
+        {
+            let id = StmtId::new(index);
+            if let Some(binding) = analyzer.assignment_binding(id)
+            {
+                self.assignment_bindings.insert(id, *binding);
+            }
+        }
+        self.resolutions = Resolver::new(ast).resolve().clone();
+
+        self.output.push_str("# NagatoInterpreter が生成した x86-64 アセンブリ\n");
+        self.directive(".text");
+        for function in functions.iter()
+        {
+            self.function_definition(function);
+        }
+        self.initializer();
+        self.main(main);
+
+        // グローバル変数は値の型に関係なく 8 バイトずつ確保する
+        self.line("");
+        self.directive(".bss");
+        self.directive(".p2align 3");
+        for (name, _) in self.globals.clone().iter()
+        {
+            self.label(&format!("g_{}", name));
+            self.directive(".zero 8");
+        }
+        self.line("");
+        self.directive(".section .note.GNU-stack,\"\",@progbits");
+
+        Ok(self.output)
+    }
+
+    fn line(&mut self, text: &str)
+    {
+        self.output.push_str(text);
+        self.output.push('\n');
+    }
+
+    fn directive(&mut self, text: &str)
+    {
+        self.line(&format!("    {}", text));
+    }
+
+    fn label(&mut self, name: &str)
+    {
+        self.line(&format!("{}:", name));
+    }
+
+    fn emit(&mut self, instruction: &str)
+    {
+        self.line(&format!("    {}", instruction));
+    }
+
+    fn new_label(&mut self) -> String
+    {
+        self.label_count += 1;
+        format!(".L{}", self.label_count)
+    }
+
+    fn function_definition(&mut self, function: &'a Function)
+    {
+        self.function = Some(function);
+        self.return_label = self.new_label();
+        self.fall_off_label = self.new_label();
+
+        // スコープの深さごとに使うスロットの数を数え, 浅いスコープから順に配置する
+        let mut slot_counts = vec![function.parameters.len()];
+        for stmt in function.body.iter()
+        {
+            self.count_slots(*stmt, &mut slot_counts);
+        }
+        self.offsets.clear();
+        let mut offset = 0;
+        for (depth, count) in slot_counts.iter().enumerate()
+        {
+            for slot in 0..*count
+            {
+                offset += 8;
+                self.offsets.insert((depth, slot), offset);
+            }
+        }
+        let frame_size = offset.div_ceil(16) * 16;
+
+        self.line("");
+        self.label(&format!("f_{}", function.name));
+        self.emit("pushq %rbp");
+        self.emit("movq %rsp, %rbp");
+        if frame_size > 0
+        {
+            self.emit(&format!("subq ${}, %rsp", frame_size));
+        }
+        self.depth = 0;
+
+        // 引数をレジスタまたは呼び出し元のスタックからスロットに移す
+        let mut int_count = 0;
+        let mut float_count = 0;
+        let mut stack_count = 0;
+        for (slot, parameter) in function.parameters.iter().enumerate()
+        {
+            let offset = self.offsets[&(0, slot)];
+            match parameter.type_specifier
+            {
+                ref value_type if value_type.is_floating() && float_count < FLOAT_REGISTERS =>
+                    {
+                        self.emit(&format!("movsd %xmm{}, -{}(%rbp)", float_count, offset));
+                        float_count += 1;
+                    }
+                ref value_type if !value_type.is_floating() && int_count < INT_REGISTERS.len() =>
+                    {
+                        let (move_instruction, register) = integer_register(value_type, int_count);
+                        self.emit(&format!("{} {}, -{}(%rbp)", move_instruction, register, offset));
+                        int_count += 1;
+                    }
+                _ =>
+                    {
+                        self.emit(&format!("movq {}(%rbp), %rax", 16 + 8 * stack_count));
+                        self.emit(&format!("movq %rax, -{}(%rbp)", offset));
+                        stack_count += 1;
+                    }
+            }
+        }
+
+        for stmt in function.body.iter()
+        {
+            self.statement(*stmt);
+        }
+
+        // 非 void 関数が値を返さずに終わった場合はインタプリタと同じくエラーとして終了する
+        let fall_off_label = self.fall_off_label.clone();
+        self.label(&fall_off_label);
+        if function.return_type != ValueType::Void
+        {
+            self.emit("movl $1, %edi");
+            self.emit("call exit@PLT");
+        }
+        let return_label = self.return_label.clone();
+        self.label(&return_label);
+        self.emit("leave");
+        self.emit("ret");
+
+        self.function = None;
+    }
+
+    /// 宣言文に割り当てられたスロットから, 深さごとのスロット数を求める
+    fn count_slots(&self, id: StmtId, slot_counts: &mut Vec<usize>)
+    {
+        match self.ast.stmt(id)
+        {
+            Stmt::Declaration(Decl::Variable { .. }) =>
+                {
+                    if let Resolution::Local { depth, slot } = self.resolutions.stmt(id)
+                    {
+                        if slot_counts.len() <= depth
+                        {
+                            slot_counts.resize(depth + 1, 0);
+                        }
+                        slot_counts[depth] = slot_counts[depth].max(slot + 1);
+                    }
+                }
+            Stmt::Block(stmts) =>
+                {
+                    for stmt in stmts.iter()
+                    {
+                        self.count_slots(*stmt, slot_counts);
+                    }
+                }
+            Stmt::If { then_branch, else_branch, .. } =>
+                {
+                    self.count_slots(*then_branch, slot_counts);
+                    if let Some(else_branch) = else_branch
+                    {
+                        self.count_slots(*else_branch, slot_counts);
+                    }
+                }
+            Stmt::While { body, .. } => self.count_slots(*body, slot_counts),
+            _ => {}
+        }
+    }
+
+    /// グローバル変数の初期化子を宣言順に実行する関数
+    fn initializer(&mut self)
+    {
+        let ast = self.ast;
+        self.line("");
+        self.label("nagato_initialize");
+        self.emit("pushq %rbp");
+        self.emit("movq %rsp, %rbp");
+        self.depth = 0;
+        for declaration in ast.declarations()
+        {
+            if let Decl::Variable { name, initializer: Some(initializer), .. } = declaration
+            {
+                let value_type = self.global_type(name);
+                self.converted(*initializer, &value_type);
+                self.store(&value_type, &format!("g_{}(%rip)", name));
+            }
+        }
+        self.emit("popq %rbp");
+        self.emit("ret");
+    }
+
+    /// グローバル変数を初期化してから main 関数を呼び出し, 戻り値を終了コードにする
+    fn main(&mut self, main: Option<&Function>)
+    {
+        self.line("");
+        self.directive(".globl main");
+        self.label("main");
+        self.emit("pushq %rbp");
+        self.emit("movq %rsp, %rbp");
+        self.emit("call nagato_initialize");
+        match main.map(|main| &main.return_type)
+        {
+            Some(ValueType::Void) =>
+                {
+                    self.emit("call f_main");
+                    self.emit("movl $0, %eax");
+                }
+            Some(return_type) =>
+                {
+                    self.emit("call f_main");
+                    self.convert(return_type, &ValueType::Int);
+                }
+            None => self.emit("movl $0, %eax"),
+        }
+        self.emit("popq %rbp");
+        self.emit("ret");
+    }
+
+    fn global_type(&self, name: &str) -> ValueType
+    {
+        self.globals.iter()
+            .find(|(global, _)| global == name)
+            .map(|(_, value_type)| value_type.clone())
+            .unwrap_or(ValueType::Int)
+    }
+
+    fn statement(&mut self, id: StmtId)
+    {
+        let ast = self.ast;
+        match ast.stmt(id)
+        {
+            Stmt::Declaration(Decl::Variable { type_specifier, name, initializer }) =>
+                {
+                    // 初期化子がない変数は VM と同じく 0 で初期化しておく
+                    match initializer
+                    {
+                        Some(initializer) => self.converted(*initializer, type_specifier),
+                        None => self.emit("xorq %rax, %rax"),
+                    }
+                    let location = self.location(self.resolutions.stmt(id), name);
+                    match initializer
+                    {
+                        Some(_) => self.store(type_specifier, &location),
+                        None => self.emit(&format!("movq %rax, {}", location)),
+                    }
+                }
+            Stmt::Declaration(Decl::Function(_) | Decl::Prototype(_)) => {}
+            Stmt::Assignment { name, value } =>
+                {
+                    let value_type = self.assignment_type(id, name);
+                    self.converted(*value, &value_type);
+                    let location = self.location(self.resolutions.stmt(id), name);
+                    self.store(&value_type, &location);
+                }
+            Stmt::Expression(expr) =>
+                {
+                    self.expression(*expr);
+                }
+            Stmt::Block(stmts) =>
+                {
+                    for stmt in stmts.iter()
+                    {
+                        self.statement(*stmt);
+                    }
+                }
+            Stmt::If { condition, then_branch, else_branch } =>
+                {
+                    let else_label = self.new_label();
+                    let end_label = self.new_label();
+                    self.jump_if_false(*condition, &else_label);
+                    self.statement(*then_branch);
+                    self.emit(&format!("jmp {}", end_label));
+                    self.label(&else_label);
+                    if let Some(else_branch) = else_branch
+                    {
+                        self.statement(*else_branch);
+                    }
+                    self.label(&end_label);
+                }
+            Stmt::While { condition, body } =>
+                {
+                    let start_label = self.new_label();
+                    let end_label = self.new_label();
+                    self.label(&start_label);
+                    self.jump_if_false(*condition, &end_label);
+                    self.loops.push((end_label.clone(), start_label.clone()));
+                    self.statement(*body);
+                    self.loops.pop();
+                    self.emit(&format!("jmp {}", start_label));
+                    self.label(&end_label);
+                }
+            Stmt::Return(value) =>
+                {
+                    if let Some(value) = value
+                    {
+                        let return_type = self.function.map(|function| function.return_type.clone()).unwrap_or(ValueType::Void);
+                        self.converted(*value, &return_type);
+                    }
+                    let return_label = self.return_label.clone();
+                    self.emit(&format!("jmp {}", return_label));
+                }
+            Stmt::Break | Stmt::Continue =>
+                {
+                    // ループの外の break と continue はインタプリタでは関数から抜ける
+                    let target = match (ast.stmt(id), self.loops.last())
+                    {
+                        (Stmt::Break, Some((end, _))) => end.clone(),
+                        (Stmt::Continue, Some((_, start))) => start.clone(),
+                        _ => self.fall_off_label.clone(),
+                    };
+                    self.emit(&format!("jmp {}", target));
+                }
+        }
+    }
+
+    /// 代入先の変数の宣言された型
+    fn assignment_type(&self, id: StmtId, name: &str) -> ValueType
+    {
+        match self.assignment_bindings.get(id)
+        {
+            Some(Binding::Local(stmt)) => match self.ast.stmt(*stmt)
+            {
+                Stmt::Declaration(Decl::Variable { type_specifier, .. }) => type_specifier.clone(),
+                _ => ValueType::Int,
+            },
+            Some(Binding::Parameter(index)) => self.function
+                .map(|function| function.parameters[*index].type_specifier.clone())
+                .unwrap_or(ValueType::Int),
+            _ => self.global_type(name),
+        }
+    }
+
+    /// 変数のメモリ上の位置
+    fn location(&self, resolution: Resolution, name: &str) -> String
+    {
+        match resolution
+        {
+            Resolution::Local { depth, slot } => format!("-{}(%rbp)", self.offsets[&(depth, slot)]),
+            Resolution::Global => format!("g_{}(%rip)", name),
+        }
+    }
+
+    fn load(&mut self, value_type: &ValueType, location: &str)
+    {
+        match value_type
+        {
+            ValueType::Float => self.emit(&format!("movss {}, %xmm0", location)),
+            ValueType::Double => self.emit(&format!("movsd {}, %xmm0", location)),
+            ValueType::Long | ValueType::UnsignedLong => self.emit(&format!("movq {}, %rax", location)),
+            _ => self.emit(&format!("movl {}, %eax", location)),
+        }
+    }
+
+    fn store(&mut self, value_type: &ValueType, location: &str)
+    {
+        match value_type
+        {
+            ValueType::Float => self.emit(&format!("movss %xmm0, {}", location)),
+            ValueType::Double => self.emit(&format!("movsd %xmm0, {}", location)),
+            ValueType::Long | ValueType::UnsignedLong => self.emit(&format!("movq %rax, {}", location)),
+            _ => self.emit(&format!("movl %eax, {}", location)),
+        }
+    }
+
+    /// 計算途中の値をスタックに積む
+    fn push(&mut self, value_type: &ValueType)
+    {
+        if value_type.is_floating()
+        {
+            self.emit("subq $8, %rsp");
+            self.emit("movsd %xmm0, (%rsp)");
+        } else {
+            self.emit("pushq %rax");
+        }
+        self.depth += 1;
+    }
+
+    /// スタックに積んだ値を %rax か %xmm0 に戻す
+    fn pop(&mut self, value_type: &ValueType)
+    {
+        if value_type.is_floating()
+        {
+            self.emit("movsd (%rsp), %xmm0");
+            self.emit("addq $8, %rsp");
+        } else {
+            self.emit("popq %rax");
+        }
+        self.depth -= 1;
+    }
+
+    /// %eax の値を int より小さい型の範囲に符号拡張かゼロ拡張で揃える
+    fn normalize(&mut self, value_type: &ValueType)
+    {
+        match value_type
+        {
+            ValueType::Char => self.emit("movsbl %al, %eax"),
+            ValueType::UnsignedChar => self.emit("movzbl %al, %eax"),
+            ValueType::Short => self.emit("movswl %ax, %eax"),
+            ValueType::UnsignedShort => self.emit("movzwl %ax, %eax"),
+            _ => {}
+        }
+    }
+
+    /// %rax か %xmm0 の値の型を変換する. 浮動小数点数から整数へは 0 方向に切り捨てる.
+    /// %rcx と %xmm1 を壊す
+    fn convert(&mut self, from: &ValueType, to: &ValueType)
+    {
+        if from == to || *to == ValueType::Void
+        {
+            return;
+        }
+
+        // _Bool は 0 以外を 1 にする
+        if *to == ValueType::Bool
+        {
+            self.test_value(from);
+            self.emit("setne %al");
+            self.emit("movzbl %al, %eax");
+            return;
+        }
+
+        let wide = |value_type: &ValueType| value_type.size() == 8;
+        match (from.is_floating(), to.is_floating())
+        {
+            (false, false) =>
+                {
+                    // 64 bit に広げる場合は元の型の符号に合わせて拡張する. 狭める場合は下位のビットを使う
+                    if wide(to) && !wide(from)
+                    {
+                        self.emit(if from.is_unsigned() { "movl %eax, %eax" } else { "cltq" });
+                    }
+                }
+            (false, true) =>
+                {
+                    let suffix = if *to == ValueType::Float { "ss" } else { "sd" };
+                    match from
+                    {
+                        ValueType::UnsignedLong => self.unsigned_long_to_floating(suffix),
+                        ValueType::Long => self.emit(&format!("cvtsi2{}q %rax, %xmm0", suffix)),
+                        ValueType::UnsignedInt =>
+                            {
+                                self.emit("movl %eax, %eax");
+                                self.emit(&format!("cvtsi2{}q %rax, %xmm0", suffix));
+                            }
+                        _ => self.emit(&format!("cvtsi2{}l %eax, %xmm0", suffix)),
+                    }
+                }
+            (true, false) =>
+                {
+                    let suffix = if *from == ValueType::Float { "ss" } else { "sd" };
+                    match to
+                    {
+                        ValueType::UnsignedLong => self.floating_to_unsigned_long(suffix),
+                        // unsigned int は 64 bit で切り捨ててから下位の 32 bit を使う
+                        ValueType::Long | ValueType::UnsignedInt => self.emit(&format!("cvtt{}2siq %xmm0, %rax", suffix)),
+                        _ => self.emit(&format!("cvtt{}2sil %xmm0, %eax", suffix)),
+                    }
+                }
+            (true, true) =>
+                {
+                    if *to == ValueType::Double
+                    {
+                        self.emit("cvtss2sd %xmm0, %xmm0");
+                    } else {
+                        self.emit("cvtsd2ss %xmm0, %xmm0");
+                    }
+                }
+        }
+        self.normalize(to);
+    }
+
+    /// %rax の unsigned long を浮動小数点数にする. 2^63 以上の値は半分にして変換してから 2 倍する
+    fn unsigned_long_to_floating(&mut self, suffix: &str)
+    {
+        let large = self.new_label();
+        let end = self.new_label();
+        self.emit("testq %rax, %rax");
+        self.emit(&format!("js {}", large));
+        self.emit(&format!("cvtsi2{}q %rax, %xmm0", suffix));
+        self.emit(&format!("jmp {}", end));
+        self.label(&large);
+        // 切り捨てたビットを残して丸めの結果を合わせる
+        self.emit("movq %rax, %rcx");
+        self.emit("shrq %rcx");
+        self.emit("andl $1, %eax");
+        self.emit("orq %rax, %rcx");
+        self.emit(&format!("cvtsi2{}q %rcx, %xmm0", suffix));
+        self.emit(&format!("add{} %xmm0, %xmm0", suffix));
+        self.label(&end);
+    }
+
+    /// %xmm0 の浮動小数点数を unsigned long にする. 2^63 以上の値は 2^63 を引いて変換してから最上位ビットを立てる
+    fn floating_to_unsigned_long(&mut self, suffix: &str)
+    {
+        let large = self.new_label();
+        let end = self.new_label();
+        if suffix == "ss"
+        {
+            self.emit("movl $0x5f000000, %ecx");
+            self.emit("movd %ecx, %xmm1");
+        } else {
+            self.emit("movabsq $0x43e0000000000000, %rcx");
+            self.emit("movq %rcx, %xmm1");
+        }
+        self.emit(&format!("ucomi{} %xmm1, %xmm0", suffix));
+        self.emit(&format!("jae {}", large));
+        self.emit(&format!("cvtt{}2siq %xmm0, %rax", suffix));
+        self.emit(&format!("jmp {}", end));
+        self.label(&large);
+        self.emit(&format!("sub{} %xmm1, %xmm0", suffix));
+        self.emit(&format!("cvtt{}2siq %xmm0, %rax", suffix));
+        self.emit("btcq $63, %rax");
+        self.label(&end);
+    }
+
+    fn expr_type(&self, id: ExprId) -> ValueType
+    {
+        self.types.get(id).cloned().unwrap_or(ValueType::Int)
+    }
+
+    /// 式を計算し, 指定した型に変換する
+    fn converted(&mut self, id: ExprId, value_type: &ValueType)
+    {
+        self.expression(id);
+        let expr_type = self.expr_type(id);
+        self.convert(&expr_type, value_type);
+    }
+
+    /// 値が 0 なら ZF が立つように比較する. 浮動小数点数の NaN は真として扱う
+    fn test_value(&mut self, value_type: &ValueType)
+    {
+        match value_type
+        {
+            ValueType::Float | ValueType::Double =>
+                {
+                    let suffix = if *value_type == ValueType::Float { "ss" } else { "sd" };
+                    self.emit("xorpd %xmm1, %xmm1");
+                    self.emit(&format!("ucomi{} %xmm1, %xmm0", suffix));
+                    // NaN の場合は PF が立つので setne と setp の和を取る
+                    self.emit("setne %al");
+                    self.emit("setp %cl");
+                    self.emit("orb %cl, %al");
+                    self.emit("testb %al, %al");
+                }
+            ValueType::Long | ValueType::UnsignedLong =>
+                {
+                    self.emit("testq %rax, %rax");
+                }
+            _ =>
+                {
+                    self.emit("testl %eax, %eax");
+                }
+        }
+    }
+
+    fn jump_if_false(&mut self, condition: ExprId, label: &str)
+    {
+        self.expression(condition);
+        let condition_type = self.expr_type(condition);
+        self.test_value(&condition_type);
+        self.emit(&format!("je {}", label));
+    }
+
+    /// 関数を呼び出す. 呼び出し時点でスタックが 16 バイト境界に揃うよう調整する
+    fn call(&mut self, symbol: &str, stack_arguments: usize)
+    {
+        let padding = (self.depth + stack_arguments) % 2;
+        if padding == 1
+        {
+            self.emit("subq $8, %rsp");
+        }
+        self.emit(&format!("call {}", symbol));
+        let size = 8 * (stack_arguments + padding);
+        if size > 0
+        {
+            self.emit(&format!("addq ${}, %rsp", size));
+        }
+    }
+
+    fn expression(&mut self, id: ExprId)
+    {
+        let ast = self.ast;
+        match ast.expr(id)
+        {
+            Expr::Constant(Constant::Integer(val)) => self.emit(&format!("movl ${}, %eax", val)),
+            Expr::Constant(Constant::UnsignedInt(val)) => self.emit(&format!("movl ${}, %eax", *val as i32)),
+            Expr::Constant(Constant::Long(val)) => self.emit(&format!("movabsq ${}, %rax", val)),
+            Expr::Constant(Constant::UnsignedLong(val)) => self.emit(&format!("movabsq ${}, %rax", *val as i64)),
+            Expr::Constant(Constant::Float(val)) =>
+                {
+                    self.emit(&format!("movl ${}, %eax", val.to_bits() as i32));
+                    self.emit("movd %eax, %xmm0");
+                }
+            Expr::Constant(Constant::Double(val)) =>
+                {
+                    self.emit(&format!("movabsq ${}, %rax", val.to_bits() as i64));
+                    self.emit("movq %rax, %xmm0");
+                }
+            Expr::Identifier(name) =>
+                {
+                    let location = self.location(self.resolutions.expr(id), name);
+                    let value_type = self.expr_type(id);
+                    self.load(&value_type, &location);
+                }
+            Expr::Unary { op, operand } =>
+                {
+                    let operand_type = self.expr_type(*operand);
+                    match op
+                    {
+                        // '-' は整数拡張した型で計算する. 浮動小数点数は符号ビットを反転する
+                        UnaryOperator::Minus =>
+                            {
+                                let value_type = operand_type.promoted();
+                                self.converted(*operand, &value_type);
+                                match value_type
+                                {
+                                    ValueType::Float =>
+                                        {
+                                            self.emit("movd %xmm0, %eax");
+                                            self.emit("btcl $31, %eax");
+                                            self.emit("movd %eax, %xmm0");
+                                        }
+                                    ValueType::Double =>
+                                        {
+                                            self.emit("movq %xmm0, %rax");
+                                            self.emit("btcq $63, %rax");
+                                            self.emit("movq %rax, %xmm0");
+                                        }
+                                    ValueType::Long | ValueType::UnsignedLong => self.emit("negq %rax"),
+                                    _ => self.emit("negl %eax"),
+                                }
+                            }
+                        UnaryOperator::LogicalNot =>
+                            {
+                                self.expression(*operand);
+                                self.test_value(&operand_type);
+                                self.emit("sete %al");
+                                self.emit("movzbl %al, %eax");
+                            }
+                    }
+                }
+            Expr::Binary { op, lhs, rhs } => self.binary(*op, *lhs, *rhs),
+            Expr::Cast { value_type, operand } => self.converted(*operand, value_type),
+            Expr::Call { name, arguments } => self.function_call(name, arguments),
+            Expr::ArrayAccess { .. } =>
+                {
+                    unimplemented!("配列は未実装です");
+                }
+        }
+    }
+
+    fn function_call(&mut self, name: &str, arguments: &[ExprId])
+    {
+        let function = self.functions[name];
+
+        // 引数を左から順に計算して積む
+        for (argument, parameter) in arguments.iter().zip(function.parameters.iter())
+        {
+            self.converted(*argument, &parameter.type_specifier);
+            self.push(&parameter.type_specifier);
+        }
+
+        // レジスタに入らない引数は右から順に積み直す
+        let count = arguments.len();
+        let mut int_count = 0;
+        let mut float_count = 0;
+        let mut stack_arguments = Vec::new();
+        for (index, parameter) in function.parameters.iter().enumerate()
+        {
+            let offset = 8 * (count - 1 - index);
+            match parameter.type_specifier
+            {
+                ref value_type if value_type.is_floating() && float_count < FLOAT_REGISTERS =>
+                    {
+                        self.emit(&format!("movsd {}(%rsp), %xmm{}", offset, float_count));
+                        float_count += 1;
+                    }
+                ref value_type if !value_type.is_floating() && int_count < INT_REGISTERS.len() =>
+                    {
+                        let (move_instruction, register) = integer_register(value_type, int_count);
+                        self.emit(&format!("{} {}(%rsp), {}", move_instruction, offset, register));
+                        int_count += 1;
+                    }
+                _ => stack_arguments.push(index),
+            }
+        }
+
+        let padding = (self.depth + stack_arguments.len()) % 2;
+        if padding == 1
+        {
+            self.emit("subq $8, %rsp");
+        }
+        for (pushed, index) in stack_arguments.iter().rev().enumerate()
+        {
+            let offset = 8 * (count - 1 - index + pushed + padding);
+            self.emit(&format!("pushq {}(%rsp)", offset));
+        }
+        self.emit(&format!("call f_{}", name));
+
+        let size = 8 * (stack_arguments.len() + padding + count);
+        if size > 0
+        {
+            self.emit(&format!("addq ${}, %rsp", size));
+        }
+        self.depth -= count;
+
+        // 外部の関数が int より小さい型の上位ビットを揃えているとは限らない
+        self.normalize(&function.return_type);
+    }
+
+    fn binary(&mut self, op: Operator, lhs: ExprId, rhs: ExprId)
+    {
+        let lhs_type = self.expr_type(lhs);
+        let rhs_type = self.expr_type(rhs);
+
+        if let Operator::LogicalAnd | Operator::LogicalOr = op
+        {
+            self.logical(op, lhs, &lhs_type, rhs, &rhs_type);
+            return;
+        }
+
+        // 両辺を通常の算術変換による共通の型に揃える. 浮動小数点数の剰余は double の fmod で計算する
+        let common = ValueType::common(&lhs_type, &rhs_type);
+        let operand_type = if op == Operator::Modulo && common.is_floating() { ValueType::Double } else { common };
+
+        // 左辺を積んで右辺を計算し, 右辺を %rcx か %xmm1 に, 左辺を %rax か %xmm0 に置く
+        self.converted(lhs, &operand_type);
+        self.push(&operand_type);
+        self.converted(rhs, &operand_type);
+        match operand_type.size()
+        {
+            _ if operand_type.is_floating() => self.emit("movapd %xmm0, %xmm1"),
+            8 => self.emit("movq %rax, %rcx"),
+            _ => self.emit("movl %eax, %ecx"),
+        }
+        self.pop(&operand_type);
+
+        if operand_type.is_floating()
+        {
+            let suffix = if operand_type == ValueType::Float { "ss" } else { "sd" };
+            match op
+            {
+                Operator::Plus => self.emit(&format!("add{} %xmm1, %xmm0", suffix)),
+                Operator::Minus => self.emit(&format!("sub{} %xmm1, %xmm0", suffix)),
+                Operator::Multiply => self.emit(&format!("mul{} %xmm1, %xmm0", suffix)),
+                Operator::Divide => self.emit(&format!("div{} %xmm1, %xmm0", suffix)),
+                Operator::Modulo =>
+                    {
+                        self.call("fmod@PLT", 0);
+                        self.convert(&ValueType::Double, &ValueType::Int);
+                    }
+                op => self.float_comparison(op, suffix),
+            }
+            return;
+        }
+
+        // 64 bit の型は q, それ以外は l の命令で計算する
+        let (suffix, rax, rcx, rdx) = if operand_type.size() == 8
+        {
+            ("q", "%rax", "%rcx", "%rdx")
+        } else {
+            ("l", "%eax", "%ecx", "%edx")
+        };
+        let unsigned = operand_type.is_unsigned();
+        match op
+        {
+            Operator::Plus => self.emit(&format!("add{} {}, {}", suffix, rcx, rax)),
+            Operator::Minus => self.emit(&format!("sub{} {}, {}", suffix, rcx, rax)),
+            Operator::Multiply => self.emit(&format!("imul{} {}, {}", suffix, rcx, rax)),
+            Operator::Divide | Operator::Modulo =>
+                {
+                    // 最小値を -1 で割ると idiv が例外になるので, インタプリタの折り返しと同じく
+                    // 商は符号反転 (最小値は最小値のまま), 余りは 0 にする
+                    let mut end = None;
+                    if unsigned
+                    {
+                        self.emit("xorl %edx, %edx");
+                        self.emit(&format!("div{} {}", suffix, rcx));
+                    } else {
+                        let divide = self.new_label();
+                        let label = self.new_label();
+                        self.emit(&format!("cmp{} $-1, {}", suffix, rcx));
+                        self.emit(&format!("jne {}", divide));
+                        if op == Operator::Modulo
+                        {
+                            self.emit("xorl %eax, %eax");
+                        } else {
+                            self.emit(&format!("neg{} {}", suffix, rax));
+                        }
+                        self.emit(&format!("jmp {}", label));
+                        self.label(&divide);
+                        self.emit(if suffix == "q" { "cqto" } else { "cltd" });
+                        self.emit(&format!("idiv{} {}", suffix, rcx));
+                        end = Some(label);
+                    }
+                    if op == Operator::Modulo
+                    {
+                        self.emit(&format!("mov{} {}, {}", suffix, rdx, rax));
+                    }
+                    if let Some(end) = end
+                    {
+                        self.label(&end);
+                    }
+                }
+            op =>
+                {
+                    let set = match (op, unsigned)
+                    {
+                        (Operator::LessThan, false) => "setl",
+                        (Operator::GreaterThan, false) => "setg",
+                        (Operator::LessThanOrEqual, false) => "setle",
+                        (Operator::GreaterThanOrEqual, false) => "setge",
+                        (Operator::LessThan, true) => "setb",
+                        (Operator::GreaterThan, true) => "seta",
+                        (Operator::LessThanOrEqual, true) => "setbe",
+                        (Operator::GreaterThanOrEqual, true) => "setae",
+                        (Operator::Equal, _) => "sete",
+                        _ => "setne",
+                    };
+                    self.emit(&format!("cmp{} {}, {}", suffix, rcx, rax));
+                    self.emit(&format!("{} %al", set));
+                    self.emit("movzbl %al, %eax");
+                }
+        }
+    }
+
+    /// %xmm0 と %xmm1 を比較して結果を %eax に置く. NaN との比較は != 以外すべて偽になる
+    fn float_comparison(&mut self, op: Operator, suffix: &str)
+    {
+        match op
+        {
+            Operator::LessThan | Operator::LessThanOrEqual =>
+                {
+                    self.emit(&format!("ucomi{} %xmm0, %xmm1", suffix));
+                    self.emit(if op == Operator::LessThan { "seta %al" } else { "setae %al" });
+                }
+            Operator::GreaterThan | Operator::GreaterThanOrEqual =>
+                {
+                    self.emit(&format!("ucomi{} %xmm1, %xmm0", suffix));
+                    self.emit(if op == Operator::GreaterThan { "seta %al" } else { "setae %al" });
+                }
+            Operator::Equal =>
+                {
+                    self.emit(&format!("ucomi{} %xmm1, %xmm0", suffix));
+                    self.emit("sete %al");
+                    self.emit("setnp %cl");
+                    self.emit("andb %cl, %al");
+                }
+            _ =>
+                {
+                    self.emit(&format!("ucomi{} %xmm1, %xmm0", suffix));
+                    self.emit("setne %al");
+                    self.emit("setp %cl");
+                    self.emit("orb %cl, %al");
+                }
+        }
+        self.emit("movzbl %al, %eax");
+    }
+
+    /// '&&' と '||'. 左辺で結果が決まる場合は右辺を計算しない
+    fn logical(&mut self, op: Operator, lhs: ExprId, lhs_type: &ValueType, rhs: ExprId, rhs_type: &ValueType)
+    {
+        let short_circuit = self.new_label();
+        let end = self.new_label();
+        let jump = if op == Operator::LogicalAnd { "je" } else { "jne" };
+
+        self.expression(lhs);
+        self.test_value(lhs_type);
+        self.emit(&format!("{} {}", jump, short_circuit));
+        self.expression(rhs);
+        self.test_value(rhs_type);
+        self.emit("setne %al");
+        self.emit("movzbl %al, %eax");
+        self.emit(&format!("jmp {}", end));
+        self.label(&short_circuit);
+        self.emit(&format!("movl ${}, %eax", if op == Operator::LogicalAnd { 0 } else { 1 }));
+        self.label(&end);
+    }
+}
+
+/// 整数の引数を受け渡す命令とレジスタ. long は 64 bit, それ以外は 32 bit で渡す
+fn integer_register(value_type: &ValueType, index: usize) -> (&'static str, &'static str)
+{
+    let (register64, register32) = INT_REGISTERS[index];
+    if value_type.size() == 8 { ("movq", register64) } else { ("movl", register32) }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::asm_backend::AsmGenerator;
+    use crate::interpreter::{Interpreter, VariableType};
+    use crate::lexical::{Lexer, ValueType};
+    use crate::parser::Parser;
+    use std::process::Command;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn generate(program: &str) -> String
+    {
+        let mut lexer = Lexer::new(program.to_string());
+        lexer.tokenize();
+
+        let mut parser = Parser::new(lexer.tokens().clone());
+        parser.parse();
+        AsmGenerator::new(parser.ast()).generate().unwrap()
+    }
+
+    /// x86-64 の Linux で C コンパイラが使える場合のみ実行する
+    fn cc_available() -> bool
+    {
+        cfg!(all(target_arch = "x86_64", target_os = "linux"))
+            && Command::new("cc").arg("--version").output().is_ok_and(|output| output.status.success())
+    }
+
+    /// 生成したアセンブリを cc でアセンブルして実行し, 終了コードを返す
+    fn run_asm(program: &str) -> i32
+    {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let directory = std::env::temp_dir()
+            .join(format!("nagato_asm_{}_{}", std::process::id(), COUNT.fetch_add(1, Ordering::SeqCst)));
+        std::fs::create_dir_all(&directory).unwrap();
+        let source = directory.join("program.s");
+        let executable = directory.join("program");
+        std::fs::write(&source, generate(program)).unwrap();
+
+        let compile = Command::new("cc")
+            .arg("-o")
+            .arg(&executable)
+            .arg(&source)
+            .arg("-lm")
+            .output()
+            .unwrap();
+        assert!(compile.status.success(), "{}", String::from_utf8_lossy(&compile.stderr));
+
+        let status = Command::new(&executable).status().unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        status.code().unwrap()
+    }
+
+    /// 終了コードがインタプリタの main の戻り値の下位 8 ビットと一致することを確認する
+    fn assert_same(program: &str)
+    {
+        if !cc_available()
+        {
+            return;
+        }
+
+        let mut interpreter = Interpreter::load(program).unwrap();
+        let expected = match Interpreter::cast(&ValueType::Int, interpreter.call("main", &[]).unwrap())
+        {
+            VariableType::Int(val) => val & 0xff,
+            other => panic!("main の戻り値が int ではありません : {:?}", other),
+        };
+        assert_eq!(run_asm(program), expected, "{}", program);
+    }
+
+    #[test]
+    fn test_generate()
+    {
+        let source = generate("int add(int a, int b) { return a + b; }");
+        assert!(source.contains("\
+f_add:
+    pushq %rbp
+    movq %rsp, %rbp
+    subq $16, %rsp
+    movl %edi, -8(%rbp)
+    movl %esi, -16(%rbp)
+    movl -8(%rbp), %eax
+    pushq %rax
+    movl -16(%rbp), %eax
+    movl %eax, %ecx
+    popq %rax
+    addl %ecx, %eax
+    jmp .L1
+.L2:
+    movl $1, %edi
+    call exit@PLT
+.L1:
+    leave
+    ret
+"), "{}", source);
+        assert!(source.contains("    .globl main\nmain:\n"));
+    }
+
+    #[test]
+    fn test_int_programs()
+    {
+        assert_same("
+int fib = 0;
+int fibo(int n) {
+    if (n < 2) {
+        return n;
+    }
+    return fibo(n - 1) + fibo(n - 2);
+}
+int main(void) {
+    fib = fibo(12);
+    return fib % 200 + (7 / 2) * (0 - 7 / 2) + -7 % 3;
+}");
+
+        // 既定の折り返しモードと同じく, 符号付き整数の最小値 / -1 や最大値 + 1 でも停止しない
+        assert_same("
+int m = -2147483647 - 1;
+long lm = -9223372036854775807 - 1;
+int divide(int a, int b) { return a / b; }
+long modulo(long a, long b) { return a % b; }
+int main(void) {
+    int big = 2147483647;
+    long lbig = 9223372036854775807;
+    int q = m / -1;
+    int r = m % -1;
+    int over = big + 1;
+    int under = m - 1;
+    int product = big * 2;
+    int negated = -m;
+    long lq = lm / -1;
+    long lover = lbig + 1;
+    return (q == m && divide(m, -1) == m) + (r == 0) * 2 + (over == m) * 4 + (under == big) * 8
+        + (product == -2) * 16 + (negated == m) * 32 + (lq == lm && -lm == lm) * 64
+        + (modulo(lm, -1) == 0 && lover == lm) * 128;
+}");
+
+        assert_same("
+int x = (10 + 20) * 3 - 4 / 2;
+int sum_odd(int n) {
+    int i = 0;
+    int sum = 0;
+    while (1) {
+        i = i + 1;
+        if (i > n) {
+            break;
+        }
+        if (i % 2 == 0) {
+            continue;
+        }
+        int odd = i;
+        sum = sum + odd;
+    }
+    return sum;
+}
+int many(int a, int b, int c, int d, int e, int f, int g, int h) {
+    return a - b + c - d + e - f + g * h;
+}
+int main(void) {
+    int shadow = 3;
+    {
+        int shadow = 100;
+        x = x + shadow;
+    }
+    int z = (x > 15 && shadow < 2) || x == 188 || !shadow;
+    return sum_odd(9) + many(1, 2, 3, 4, 5, 6, 7, 8) + z + (x >= 188) + (x <= 100) + (x != 1);
+}");
+    }
+
+    #[test]
+    fn test_numeric_programs()
+    {
+        assert_same("
+unsigned int big = 4000000000u;
+long wide = 3000000000;
+unsigned long huge = 18000000000000000000ul;
+char small = 200;
+unsigned char byte = 300;
+short half = 40000;
+_Bool flag = 5;
+double ratio = 1.0 / 3;
+float third = 1.0f / 3;
+long mix(char c, unsigned int u, long l, float f, double d) {
+    return c + u + l + f + d;
+}
+unsigned char low(int n) {
+    return n;
+}
+int main(void) {
+    int score = 0;
+    unsigned int u = 1;
+    long l = wide * 3;
+    double d = huge;
+    unsigned long back = d;
+    char c = 127;
+    unsigned short us = 0;
+    c = c + 1;
+    us = us - 1;
+    if (-1 < u) {
+        score = score + 100;
+    }
+    if (u - 2 > u) {
+        score = score + 1;
+    }
+    if (l / 7 == 1285714285 && -wide % 7 == -4) {
+        score = score + 1;
+    }
+    if (huge / 1000000000000000000ul == 18 && back == huge && d > 17000000000000000000.0) {
+        score = score + 1;
+    }
+    if (small == -56 && byte == 44 && half == -25536 && flag == 1) {
+        score = score + 1;
+    }
+    if (c == -128 && us == 65535 && (u - 2) + 0L > 0) {
+        score = score + 1;
+    }
+    if (mix(-3, 10u, 100, 0.5f, 0.25) == 107 && low(513) == 1) {
+        score = score + 1;
+    }
+    if (third + ratio > 0.66 && big / 3 == 1333333333u && big % 7 == 3) {
+        score = score + 1;
+    }
+    return score;
+}");
+
+        assert_same("
+double ratio = (double)7 / 2;
+int truncated = (int)3.99;
+unsigned char low = (unsigned char)300;
+long size = sizeof(long) + sizeof ratio;
+int main(void) {
+    int n = -7;
+    unsigned int u = (unsigned int)n;
+    float f = (float)n / 2;
+    _Bool b = (_Bool)0.5;
+    return (int)(u / 2u % 100) + (int)f + b + (char)(n * 40) + sizeof(n + 1L);
+}");
+    }
+
+    #[test]
+    fn test_float_programs()
+    {
+        assert_same("
+float y = 1.5;
+float scale(float x, int n) {
+    return x * n;
+}
+float mixed(int a, float b, int c, float d, float e, float f, float g, float h, float i, float j, float k, int l) {
+    return a + b + c + d + e + f + g + h + i + j + k + l;
+}
+int truncate(float value) {
+    return value;
+}
+int main(void) {
+    float z = scale(y, 3) - 0.25;
+    int flags = (z > 4.0) + (z < 4.0) * 2 + (z == 4.25) * 4 + (z != 4.25) * 8 + (-z <= -4.25) * 16 + !z * 32;
+    int rest = 7.5 % 2 + 7 % 2.5 + truncate(-z) + (1 == 1.5) * 2 + (2 < 2.5) * 4 + (2.5 > 2) * 8;
+    return flags + rest * 10 + truncate(mixed(1, 0.5, 2, 0.25, 1, 1, 1, 1, 1, 1, 1.25, 3)) + (y && 0.0) + (0.0 || y);
+}");
+    }
+}
@@ -0,0 +1,415 @@
+use crate::lexical::{Constant, Operator, UnaryOperator, ValueType};
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+/// アリーナ内のノードを指す ID
+pub trait NodeId: Copy
+{
+    fn new(index: usize) -> Self;
+    fn index(self) -> usize;
+}
+
+/// 式ノードの ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ExprId(u32);
+
+/// 文ノードの ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StmtId(u32);
+
+impl NodeId for ExprId
+{
+    fn new(index: usize) -> Self
+    {
+        ExprId(index as u32)
+    }
+
+    fn index(self) -> usize
+    {
+        self.0 as usize
+    }
+}
+
+impl NodeId for StmtId
+{
+    fn new(index: usize) -> Self
+    {
+        StmtId(index as u32)
+    }
+
+    fn index(self) -> usize
+    {
+        self.0 as usize
+    }
+}
+
+/// NodeId をキーにした付加情報のテーブル. 型や名前解決の結果などを AST の外に持つ
+#[derive(Debug, Clone, PartialEq)]
+pub struct SideTable<I: NodeId, T>
+{
+    values: Vec<Option<T>>,
+    _marker: PhantomData<I>,
+}
+
+impl<I: NodeId, T> Default for SideTable<I, T>
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl<I: NodeId, T> SideTable<I, T>
+{
+    pub fn new() -> Self
+    {
+        SideTable {
+            values: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn insert(&mut self, id: I, value: T)
+    {
+        let index = id.index();
+        if index >= self.values.len()
+        {
+            self.values.resize_with(index + 1, || None);
+        }
+        self.values[index] = Some(value);
+    }
+
+    pub fn get(&self, id: I) -> Option<&T>
+    {
+        self.values.get(id.index()).and_then(|value| value.as_ref())
+    }
+
+    pub fn clear(&mut self)
+    {
+        self.values.clear();
+    }
+}
+
+/// 式
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr
+{
+    // 定数
+    Constant(Constant),
+
+    // 変数の参照
+    Identifier(String),
+
+    // 単項演算
+    Unary {
+        op: UnaryOperator,
+        operand: ExprId,
+    },
+
+    // 二項演算
+    Binary {
+        op: Operator,
+        lhs: ExprId,
+        rhs: ExprId,
+    },
+
+    // 明示的な型変換 `(int)x`
+    Cast {
+        value_type: ValueType,
+        operand: ExprId,
+    },
+
+    // 関数呼び出し
+    Call {
+        name: String,
+        arguments: Vec<ExprId>,
+    },
+
+    // 配列の要素アクセス. `a[]` の場合は index が None
+    ArrayAccess {
+        name: String,
+        index: Option<ExprId>,
+    },
+}
+
+/// 文
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt
+{
+    // ローカル変数の宣言
+    Declaration(Decl),
+
+    // 代入
+    Assignment {
+        name: String,
+        value: ExprId,
+    },
+
+    // 式文. 関数呼び出しなど
+    Expression(ExprId),
+
+    // { ... }
+    Block(Vec<StmtId>),
+
+    // if 文. else if は else_branch に If が入る
+    If {
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
+    },
+
+    // while 文
+    While {
+        condition: ExprId,
+        body: StmtId,
+    },
+
+    // return 文
+    Return(Option<ExprId>),
+    Break,
+    Continue,
+}
+
+/// 関数の仮引数
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter
+{
+    pub type_specifier: ValueType,
+    pub name: String,
+}
+
+/// 関数定義
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function
+{
+    pub return_type: ValueType,
+    pub name: String,
+    pub parameters: Vec<Parameter>,
+    pub body: Vec<StmtId>,
+}
+
+/// 宣言
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decl
+{
+    // 変数宣言
+    Variable {
+        type_specifier: ValueType,
+        name: String,
+        initializer: Option<ExprId>,
+    },
+
+    // 関数定義. 呼び出しのたびに複製しないよう Rc で共有する
+    Function(Rc<Function>),
+
+    // 関数のプロトタイプ宣言. 本体は空で, 名前のない引数は空文字列になる
+    Prototype(Rc<Function>),
+}
+
+/// 式と文を Vec に格納し, ID で参照する構文木
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Ast
+{
+    exprs: Vec<Expr>,
+    stmts: Vec<Stmt>,
+
+    // トップレベルの宣言
+    declarations: Vec<Decl>,
+
+    // ソース上の行番号. 字句解析器から行番号が渡された場合のみ記録される
+    stmt_lines: SideTable<StmtId, usize>,
+    expr_lines: SideTable<ExprId, usize>,
+}
+
+impl Ast
+{
+    pub fn new() -> Self
+    {
+        Ast {
+            exprs: Vec::new(),
+            stmts: Vec::new(),
+            declarations: Vec::new(),
+            stmt_lines: SideTable::new(),
+            expr_lines: SideTable::new(),
+        }
+    }
+
+    pub fn add_expr(&mut self, expr: Expr) -> ExprId
+    {
+        self.exprs.push(expr);
+        ExprId::new(self.exprs.len() - 1)
+    }
+
+    pub fn add_stmt(&mut self, stmt: Stmt) -> StmtId
+    {
+        self.stmts.push(stmt);
+        StmtId::new(self.stmts.len() - 1)
+    }
+
+    pub fn add_declaration(&mut self, declaration: Decl)
+    {
+        self.declarations.push(declaration);
+    }
+
+    pub fn expr(&self, id: ExprId) -> &Expr
+    {
+        &self.exprs[id.index()]
+    }
+
+    pub fn stmt(&self, id: StmtId) -> &Stmt
+    {
+        &self.stmts[id.index()]
+    }
+
+    /// 式を置き換える. 最適化で使う
+    pub fn replace_expr(&mut self, id: ExprId, expr: Expr)
+    {
+        self.exprs[id.index()] = expr;
+    }
+
+    /// 文を置き換える. 最適化で使う
+    pub fn replace_stmt(&mut self, id: StmtId, stmt: Stmt)
+    {
+        self.stmts[id.index()] = stmt;
+    }
+
+    pub fn declarations(&self) -> &Vec<Decl>
+    {
+        &self.declarations
+    }
+
+    pub fn set_stmt_line(&mut self, id: StmtId, line: usize)
+    {
+        self.stmt_lines.insert(id, line);
+    }
+
+    pub fn set_expr_line(&mut self, id: ExprId, line: usize)
+    {
+        self.expr_lines.insert(id, line);
+    }
+
+    /// 文が始まる行
+    pub fn stmt_line(&self, id: StmtId) -> Option<usize>
+    {
+        self.stmt_lines.get(id).copied()
+    }
+
+    /// 式が終わる行
+    pub fn expr_line(&self, id: ExprId) -> Option<usize>
+    {
+        self.expr_lines.get(id).copied()
+    }
+
+    pub fn expr_count(&self) -> usize
+    {
+        self.exprs.len()
+    }
+
+    pub fn stmt_count(&self) -> usize
+    {
+        self.stmts.len()
+    }
+
+    /// 式を S 式風の文字列にする. デバッグやテストで使う
+    pub fn format_expr(&self, id: ExprId) -> String
+    {
+        match self.expr(id)
+        {
+            Expr::Constant(constant) => constant.to_string(),
+            Expr::Identifier(name) => name.clone(),
+            Expr::Unary { op, operand } =>
+                {
+                    let op = match op
+                    {
+                        UnaryOperator::Minus => "-",
+                        UnaryOperator::LogicalNot => "!",
+                    };
+                    format!("({} {})", op, self.format_expr(*operand))
+                }
+            Expr::Binary { op, lhs, rhs } =>
+                format!("({} {} {})", op.as_str(), self.format_expr(*lhs), self.format_expr(*rhs)),
+            Expr::Cast { value_type, operand } =>
+                format!("(({}) {})", value_type.as_str(), self.format_expr(*operand)),
+            Expr::Call { name, arguments } =>
+                {
+                    let arguments: Vec<String> = arguments.iter().map(|argument| self.format_expr(*argument)).collect();
+                    format!("{}({})", name, arguments.join(", "))
+                }
+            Expr::ArrayAccess { name, index } =>
+                {
+                    match index
+                    {
+                        Some(index) => format!("{}[{}]", name, self.format_expr(*index)),
+                        None => format!("{}[]", name),
+                    }
+                }
+        }
+    }
+
+    /// 文を S 式風の文字列にする. デバッグやテストで使う
+    pub fn format_stmt(&self, id: StmtId) -> String
+    {
+        match self.stmt(id)
+        {
+            Stmt::Declaration(decl) => self.format_decl(decl),
+            Stmt::Assignment { name, value } => format!("(= {} {})", name, self.format_expr(*value)),
+            Stmt::Expression(expr) => self.format_expr(*expr),
+            Stmt::Block(stmts) => self.format_block(stmts),
+            Stmt::If { condition, then_branch, else_branch } =>
+                {
+                    match else_branch
+                    {
+                        Some(else_branch) => format!("(if {} {} {})",
+                                                     self.format_expr(*condition),
+                                                     self.format_stmt(*then_branch),
+                                                     self.format_stmt(*else_branch)),
+                        None => format!("(if {} {})",
+                                        self.format_expr(*condition),
+                                        self.format_stmt(*then_branch)),
+                    }
+                }
+            Stmt::While { condition, body } =>
+                format!("(while {} {})", self.format_expr(*condition), self.format_stmt(*body)),
+            Stmt::Return(Some(value)) => format!("(return {})", self.format_expr(*value)),
+            Stmt::Return(None) => "(return)".to_string(),
+            Stmt::Break => "(break)".to_string(),
+            Stmt::Continue => "(continue)".to_string(),
+        }
+    }
+
+    fn format_block(&self, stmts: &[StmtId]) -> String
+    {
+        let stmts: Vec<String> = stmts.iter().map(|stmt| self.format_stmt(*stmt)).collect();
+        format!("{{{}}}", stmts.join(" "))
+    }
+
+    /// 宣言を S 式風の文字列にする. デバッグやテストで使う
+    pub fn format_decl(&self, decl: &Decl) -> String
+    {
+        match decl
+        {
+            Decl::Variable { type_specifier, name, initializer: Some(initializer) } =>
+                format!("({:?} {} {})", type_specifier, name, self.format_expr(*initializer)),
+            Decl::Variable { type_specifier, name, initializer: None } =>
+                format!("({:?} {})", type_specifier, name),
+            Decl::Function(function) =>
+                {
+                    let parameters: Vec<String> = function.parameters.iter()
+                        .map(|parameter| format!("{:?} {}", parameter.type_specifier, parameter.name))
+                        .collect();
+                    format!("({:?} {}({}) {})",
+                            function.return_type,
+                            function.name,
+                            parameters.join(", "),
+                            self.format_block(&function.body))
+                }
+            Decl::Prototype(function) =>
+                {
+                    let parameters: Vec<String> = function.parameters.iter()
+                        .map(|parameter| format!("{:?} {}", parameter.type_specifier, parameter.name).trim_end().to_string())
+                        .collect();
+                    format!("({:?} {}({}))", function.return_type, function.name, parameters.join(", "))
+                }
+        }
+    }
+}
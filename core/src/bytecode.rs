@@ -0,0 +1,779 @@
+use crate::ast::{Ast, Decl, Expr, ExprId, Function, Parameter, Stmt, StmtId};
+use crate::interpreter::{Interpreter, VariableType};
+use crate::lexical::{Operator, UnaryOperator, ValueType};
+use std::collections::HashMap;
+
+/// スタックマシンの命令
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction
+{
+    // 定数プールの値を積む
+    Constant(usize),
+
+    // スタックの先頭を捨てる
+    Pop,
+
+    // ローカル変数の値を積む
+    LoadLocal(usize),
+
+    // ローカル変数を宣言された型で定義する
+    DefineLocal {
+        slot: usize,
+        value_type: ValueType,
+        name: usize,
+    },
+
+    // 初期化子のないローカル変数を宣言された型の 0 で定義する. 厳密モードでは代入されるまで読み出せない
+    DeclareLocal {
+        slot: usize,
+        value_type: ValueType,
+        name: usize,
+    },
+
+    // ローカル変数へ代入する. 値は変数の現在の型に変換される
+    StoreLocal {
+        slot: usize,
+        name: usize,
+    },
+
+    // グローバル変数の値を積む. 未定義の場合はエラー
+    LoadGlobal(usize),
+
+    // グローバル変数を宣言された型で定義する
+    DefineGlobal {
+        slot: usize,
+        value_type: ValueType,
+    },
+
+    // グローバル変数へ代入する
+    StoreGlobal(usize),
+
+    Unary(UnaryOperator),
+    Binary(Operator),
+
+    // 先頭を指定した型に変換する. 明示的なキャストに使う
+    Cast(ValueType),
+
+    // 先頭が偽なら Int(0) に置き換えて飛ぶ. '&&' の短絡評価
+    ShortCircuitAnd(usize),
+
+    // 先頭が真なら Int(1) に置き換えて飛ぶ. '||' の短絡評価
+    ShortCircuitOr(usize),
+
+    Jump(usize),
+
+    // 先頭を取り出し, 偽なら飛ぶ
+    JumpIfFalse(usize),
+
+    // 関数を呼び出す. 引数はスタックに積まれている
+    Call(usize),
+
+    // 戻り値の型が同じ関数を呼び出し, 今のフレームを呼び出し先で置き換える
+    TailCall(usize),
+
+    // 先頭が void ならエラー. 式の中での関数呼び出しの結果に使う
+    CheckValue(usize),
+
+    // 先頭を戻り値として関数から戻る
+    Return,
+
+    // 値を返さずに関数から戻る
+    ReturnVoid,
+
+    // 名前が解決できなかった識別子と関数. 実行時にエラーになる
+    UndefinedVariable(usize),
+    UndefinedFunction(usize),
+    ArgumentCountMismatch {
+        name: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+/// コンパイル済みの関数
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionCode
+{
+    pub name: String,
+    pub return_type: ValueType,
+    pub parameters: Vec<Parameter>,
+
+    // 引数を含むローカル変数のスロット数
+    pub local_count: usize,
+    pub code: Vec<Instruction>,
+
+    // 命令ごとのソース上の行番号. 不明な場合は 0
+    pub lines: Vec<usize>,
+}
+
+/// グローバル変数の情報
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global
+{
+    pub name: String,
+    pub value_type: ValueType,
+}
+
+/// コンパイル済みのプログラム
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program
+{
+    // 定数プール
+    pub constants: Vec<VariableType>,
+
+    // エラー表示などに使う名前
+    pub names: Vec<String>,
+
+    pub globals: Vec<Global>,
+    pub functions: Vec<FunctionCode>,
+
+    // グローバル変数を初期化するコード
+    pub initializer: FunctionCode,
+}
+
+impl Program
+{
+    /// 関数名から関数番号を求める. 同名の関数は後に定義されたものが優先される
+    pub fn function_index(&self, name: &str) -> Option<usize>
+    {
+        self.functions.iter().rposition(|function| function.name == name)
+    }
+}
+
+/// while 文のジャンプ先
+struct Loop
+{
+    // continue で戻る条件式の位置
+    start: usize,
+
+    // 後で書き換える break の Jump 命令の位置
+    breaks: Vec<usize>,
+}
+
+/// 構文木をバイトコードに変換する
+pub struct Compiler<'a>
+{
+    ast: &'a Ast,
+
+    constants: Vec<VariableType>,
+    names: Vec<String>,
+    globals: Vec<Global>,
+
+    // 関数名と関数番号
+    functions: HashMap<String, usize>,
+
+    // 関数番号ごとの引数の数と戻り値の型
+    arities: Vec<usize>,
+    return_types: Vec<ValueType>,
+
+    // コンパイル中の関数の戻り値の型
+    return_type: ValueType,
+
+    // 関数本体から見えるグローバル変数
+    global_slots: HashMap<String, usize>,
+
+    // グローバル変数の初期化中に, その時点で定義済みのグローバル変数と関数
+    defined_globals: HashMap<String, usize>,
+    defined_functions: HashMap<String, usize>,
+
+    // 初期化コードのコンパイル中かどうか
+    in_initializer: bool,
+
+    code: Vec<Instruction>,
+    lines: Vec<usize>,
+
+    // 生成中の命令に対応する行番号
+    current_line: usize,
+
+    // ブロックごとのローカル変数とスロット番号
+    scopes: Vec<HashMap<String, usize>>,
+    next_slot: usize,
+    local_count: usize,
+
+    loops: Vec<Loop>,
+}
+
+impl<'a> Compiler<'a>
+{
+    pub fn new(ast: &'a Ast) -> Self
+    {
+        Compiler {
+            ast,
+            constants: Vec::new(),
+            names: Vec::new(),
+            globals: Vec::new(),
+            functions: HashMap::new(),
+            arities: Vec::new(),
+            return_types: Vec::new(),
+            return_type: ValueType::Void,
+            global_slots: HashMap::new(),
+            defined_globals: HashMap::new(),
+            defined_functions: HashMap::new(),
+            in_initializer: false,
+            code: Vec::new(),
+            lines: Vec::new(),
+            current_line: 0,
+            scopes: Vec::new(),
+            next_slot: 0,
+            local_count: 0,
+            loops: Vec::new(),
+        }
+    }
+
+    pub fn compile(mut self) -> Program
+    {
+        let ast = self.ast;
+
+        // 関数とグローバル変数の番号を先に決めておく.
+        let mut function_definitions: Vec<&Function> = Vec::new();
+        for declaration in ast.declarations()
+        {
+            match declaration
+            {
+                Decl::Function(function) =>
+                    {
+                        self.functions.insert(function.name.clone(), function_definitions.len());
+                        self.arities.push(function.parameters.len());
+                        self.return_types.push(function.return_type.clone());
+                        function_definitions.push(function);
+                    }
+                Decl::Prototype(_) => {}
+                Decl::Variable { type_specifier, name, .. } =>
+                    {
+                        match self.global_slots.get(name)
+                        {
+                            Some(slot) => self.globals[*slot].value_type = type_specifier.clone(),
+                            None =>
+                                {
+                                    self.global_slots.insert(name.clone(), self.globals.len());
+                                    self.globals.push(Global { name: name.clone(), value_type: type_specifier.clone() });
+                                }
+                        }
+                    }
+            }
+        }
+
+        // 初期化コードはトップレベルの宣言順に実行される
+        self.in_initializer = true;
+        let mut function_index = 0;
+        for declaration in ast.declarations()
+        {
+            match declaration
+            {
+                Decl::Function(function) =>
+                    {
+                        self.defined_functions.insert(function.name.clone(), function_index);
+                        function_index += 1;
+                    }
+                Decl::Prototype(_) => {}
+                Decl::Variable { type_specifier, name, initializer: Some(initializer) } =>
+                    {
+                        self.current_line = ast.expr_line(*initializer).unwrap_or(0);
+                        self.value_expression(*initializer);
+                        let slot = self.global_slots[name];
+                        self.emit(Instruction::DefineGlobal { slot, value_type: type_specifier.clone() });
+                        self.defined_globals.insert(name.clone(), slot);
+                    }
+                Decl::Variable { type_specifier, name, initializer: None } =>
+                    {
+                        // C と同じく 0 で初期化する. すでに定義されている場合は値を保持する
+                        if !self.defined_globals.contains_key(name)
+                        {
+                            let index = self.constant(VariableType::Int(0));
+                            self.emit(Instruction::Constant(index));
+                            let slot = self.global_slots[name];
+                            self.emit(Instruction::DefineGlobal { slot, value_type: type_specifier.clone() });
+                            self.defined_globals.insert(name.clone(), slot);
+                        }
+                    }
+            }
+        }
+        self.current_line = 0;
+        self.emit(Instruction::ReturnVoid);
+        self.in_initializer = false;
+        let initializer = FunctionCode {
+            name: "<init>".to_string(),
+            return_type: ValueType::Void,
+            parameters: Vec::new(),
+            local_count: 0,
+            code: std::mem::take(&mut self.code),
+            lines: std::mem::take(&mut self.lines),
+        };
+
+        let functions = function_definitions.iter()
+            .map(|function| self.function_definition(function))
+            .collect();
+
+        Program {
+            constants: self.constants,
+            names: self.names,
+            globals: self.globals,
+            functions,
+            initializer,
+        }
+    }
+
+    fn function_definition(&mut self, function: &Function) -> FunctionCode
+    {
+        // 引数は先頭のスロットに入る
+        let mut parameters = HashMap::new();
+        for (slot, parameter) in function.parameters.iter().enumerate()
+        {
+            parameters.insert(parameter.name.clone(), slot);
+        }
+        self.scopes.push(parameters);
+        self.next_slot = function.parameters.len();
+        self.local_count = self.next_slot;
+        self.return_type = function.return_type.clone();
+
+        for stmt in function.body.iter()
+        {
+            self.statement(*stmt);
+        }
+        self.current_line = 0;
+        self.emit(Instruction::ReturnVoid);
+
+        self.scopes.pop();
+
+        FunctionCode {
+            name: function.name.clone(),
+            return_type: function.return_type.clone(),
+            parameters: function.parameters.clone(),
+            local_count: self.local_count,
+            code: std::mem::take(&mut self.code),
+            lines: std::mem::take(&mut self.lines),
+        }
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> usize
+    {
+        self.code.push(instruction);
+        self.lines.push(self.current_line);
+        self.code.len() - 1
+    }
+
+    /// 式の行を記録して命令を追加する. 0 除算やオーバーフローはこの行で報告される
+    fn emit_at(&mut self, instruction: Instruction, expr: ExprId) -> usize
+    {
+        let previous_line = self.current_line;
+        if let Some(line) = self.ast.expr_line(expr)
+        {
+            self.current_line = line;
+        }
+        let position = self.emit(instruction);
+        self.current_line = previous_line;
+        position
+    }
+
+    /// 飛び先を現在の位置に書き換える
+    fn patch(&mut self, position: usize)
+    {
+        let target = self.code.len();
+        match &mut self.code[position]
+        {
+            Instruction::Jump(to)
+            | Instruction::JumpIfFalse(to)
+            | Instruction::ShortCircuitAnd(to)
+            | Instruction::ShortCircuitOr(to) => *to = target,
+            instruction => panic!("ジャンプ命令ではありません : {:?}", instruction),
+        }
+    }
+
+    fn constant(&mut self, value: VariableType) -> usize
+    {
+        match self.constants.iter().position(|constant| *constant == value)
+        {
+            Some(index) => index,
+            None =>
+                {
+                    self.constants.push(value);
+                    self.constants.len() - 1
+                }
+        }
+    }
+
+    fn name(&mut self, name: &str) -> usize
+    {
+        match self.names.iter().position(|n| n == name)
+        {
+            Some(index) => index,
+            None =>
+                {
+                    self.names.push(name.to_string());
+                    self.names.len() - 1
+                }
+        }
+    }
+
+    fn statement(&mut self, stmt: StmtId)
+    {
+        // 入れ子の文を抜けたら外側の文の行に戻す
+        let previous_line = self.current_line;
+        if let Some(line) = self.ast.stmt_line(stmt)
+        {
+            self.current_line = line;
+        }
+        self.compile_statement(stmt);
+        self.current_line = previous_line;
+    }
+
+    fn compile_statement(&mut self, stmt: StmtId)
+    {
+        let ast = self.ast;
+        match ast.stmt(stmt)
+        {
+            Stmt::Declaration(Decl::Variable { type_specifier, name, initializer }) =>
+                {
+                    if let Some(initializer) = initializer
+                    {
+                        self.value_expression(*initializer);
+                    }
+
+                    let slot = self.next_slot;
+                    self.next_slot += 1;
+                    self.local_count = self.local_count.max(self.next_slot);
+
+                    let name_index = self.name(name);
+                    let value_type = type_specifier.clone();
+                    let instruction = match initializer
+                    {
+                        Some(_) => Instruction::DefineLocal { slot, value_type, name: name_index },
+                        None => Instruction::DeclareLocal { slot, value_type, name: name_index },
+                    };
+                    self.emit(instruction);
+                    self.scopes.last_mut().unwrap().insert(name.clone(), slot);
+                }
+            Stmt::Declaration(Decl::Function(_) | Decl::Prototype(_)) => {}
+            Stmt::Assignment { name, value } =>
+                {
+                    self.value_expression(*value);
+                    match self.lookup_local(name)
+                    {
+                        Some(slot) =>
+                            {
+                                let name = self.name(name);
+                                self.emit(Instruction::StoreLocal { slot, name });
+                            }
+                        None => match self.lookup_global(name)
+                        {
+                            Some(slot) =>
+                                {
+                                    self.emit(Instruction::StoreGlobal(slot));
+                                }
+                            None =>
+                                {
+                                    let name = self.name(name);
+                                    self.emit(Instruction::UndefinedVariable(name));
+                                }
+                        },
+                    }
+                }
+            Stmt::Expression(expr) =>
+                {
+                    // 文としての関数呼び出しは void でもよい
+                    if let Expr::Call { name, arguments } = ast.expr(*expr)
+                    {
+                        self.function_call(name, arguments);
+                    } else {
+                        self.value_expression(*expr);
+                    }
+                    self.emit(Instruction::Pop);
+                }
+            Stmt::Block(stmts) =>
+                {
+                    self.scopes.push(HashMap::new());
+                    let next_slot = self.next_slot;
+                    for stmt in stmts.iter()
+                    {
+                        self.statement(*stmt);
+                    }
+
+                    // ブロックを抜けたらスロットを再利用する
+                    self.next_slot = next_slot;
+                    self.scopes.pop();
+                }
+            Stmt::If { condition, then_branch, else_branch } =>
+                {
+                    self.value_expression(*condition);
+                    let jump_to_else = self.emit(Instruction::JumpIfFalse(0));
+                    self.statement(*then_branch);
+
+                    match else_branch
+                    {
+                        Some(else_branch) =>
+                            {
+                                let jump_to_end = self.emit(Instruction::Jump(0));
+                                self.patch(jump_to_else);
+                                self.statement(*else_branch);
+                                self.patch(jump_to_end);
+                            }
+                        None =>
+                            {
+                                self.patch(jump_to_else);
+                            }
+                    }
+                }
+            Stmt::While { condition, body } =>
+                {
+                    let start = self.code.len();
+                    self.value_expression(*condition);
+                    let jump_to_end = self.emit(Instruction::JumpIfFalse(0));
+
+                    self.loops.push(Loop { start, breaks: Vec::new() });
+                    self.statement(*body);
+                    self.emit(Instruction::Jump(start));
+
+                    self.patch(jump_to_end);
+                    let while_loop = self.loops.pop().unwrap();
+                    for position in while_loop.breaks
+                    {
+                        self.patch(position);
+                    }
+                }
+            Stmt::Return(value) =>
+                {
+                    match value
+                    {
+                        Some(value) =>
+                            {
+                                if !self.tail_call(*value)
+                                {
+                                    self.value_expression(*value);
+                                    self.emit(Instruction::Return);
+                                }
+                            }
+                        None =>
+                            {
+                                self.emit(Instruction::ReturnVoid);
+                            }
+                    }
+                }
+            Stmt::Break =>
+                {
+                    // ループの外の break は関数から抜ける
+                    if self.loops.is_empty()
+                    {
+                        self.emit(Instruction::ReturnVoid);
+                    } else {
+                        let position = self.emit(Instruction::Jump(0));
+                        self.loops.last_mut().unwrap().breaks.push(position);
+                    }
+                }
+            Stmt::Continue =>
+                {
+                    match self.loops.last()
+                    {
+                        Some(while_loop) =>
+                            {
+                                let start = while_loop.start;
+                                self.emit(Instruction::Jump(start));
+                            }
+                        None =>
+                            {
+                                self.emit(Instruction::ReturnVoid);
+                            }
+                    }
+                }
+        }
+    }
+
+    fn lookup_local(&self, name: &str) -> Option<usize>
+    {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name).copied())
+    }
+
+    fn lookup_global(&self, name: &str) -> Option<usize>
+    {
+        if self.in_initializer
+        {
+            self.defined_globals.get(name).copied()
+        } else {
+            self.global_slots.get(name).copied()
+        }
+    }
+
+    /// 値として使う式をコンパイルする. 関数呼び出しの結果が void ならエラーにする
+    fn value_expression(&mut self, expr: ExprId)
+    {
+        let ast = self.ast;
+        match ast.expr(expr)
+        {
+            Expr::Constant(value) =>
+                {
+                    let index = self.constant(Interpreter::constant(value));
+                    self.emit(Instruction::Constant(index));
+                }
+            Expr::Identifier(identifier) =>
+                {
+                    match self.lookup_local(identifier)
+                    {
+                        Some(slot) =>
+                            {
+                                self.emit(Instruction::LoadLocal(slot));
+                            }
+                        None => match self.lookup_global(identifier)
+                        {
+                            Some(slot) =>
+                                {
+                                    self.emit(Instruction::LoadGlobal(slot));
+                                }
+                            None =>
+                                {
+                                    let name = self.name(identifier);
+                                    self.emit(Instruction::UndefinedVariable(name));
+                                }
+                        },
+                    }
+                }
+            Expr::Unary { op, operand } =>
+                {
+                    self.value_expression(*operand);
+                    self.emit_at(Instruction::Unary(*op), expr);
+                }
+            Expr::Binary { op, lhs, rhs } =>
+                {
+                    self.value_expression(*lhs);
+
+                    // '&&' と '||' は左辺で結果が決まる場合は右辺を評価しない
+                    let short_circuit = match op
+                    {
+                        Operator::LogicalAnd => Some(self.emit(Instruction::ShortCircuitAnd(0))),
+                        Operator::LogicalOr => Some(self.emit(Instruction::ShortCircuitOr(0))),
+                        _ => None,
+                    };
+
+                    self.value_expression(*rhs);
+                    self.emit_at(Instruction::Binary(*op), expr);
+
+                    if let Some(position) = short_circuit
+                    {
+                        self.patch(position);
+                    }
+                }
+            Expr::Cast { value_type, operand } =>
+                {
+                    self.value_expression(*operand);
+                    self.emit(Instruction::Cast(value_type.clone()));
+                }
+            Expr::Call { name, arguments } =>
+                {
+                    self.function_call(name, arguments);
+                    let name = self.name(name);
+                    self.emit(Instruction::CheckValue(name));
+                }
+            Expr::ArrayAccess { .. } =>
+                {
+                    unimplemented!("配列は未実装です");
+                }
+        }
+    }
+
+    /// インタプリタと同じ条件で `return f(...)` を末尾呼び出しにする
+    fn tail_call(&mut self, value: ExprId) -> bool
+    {
+        let ast = self.ast;
+        let Expr::Call { name, arguments } = ast.expr(value) else {
+            return false;
+        };
+        let Some(function) = self.functions.get(name).copied() else {
+            return false;
+        };
+        if self.return_types[function] == ValueType::Void
+            || self.return_types[function] != self.return_type
+            || self.arities[function] != arguments.len()
+        {
+            return false;
+        }
+
+        for argument in arguments.iter()
+        {
+            self.value_expression(*argument);
+        }
+        self.emit(Instruction::TailCall(function));
+        true
+    }
+
+    fn function_call(&mut self, name: &str, arguments: &[ExprId])
+    {
+        let function = if self.in_initializer
+        {
+            self.defined_functions.get(name).copied()
+        } else {
+            self.functions.get(name).copied()
+        };
+
+        let Some(function) = function else {
+            let name = self.name(name);
+            self.emit(Instruction::UndefinedFunction(name));
+            return;
+        };
+
+        // 引数の数は引数を計算する前に確認する
+        let expected = self.arities[function];
+        if arguments.len() != expected
+        {
+            let name = self.name(name);
+            self.emit(Instruction::ArgumentCountMismatch { name, expected, found: arguments.len() });
+            return;
+        }
+
+        for argument in arguments.iter()
+        {
+            self.value_expression(*argument);
+        }
+        self.emit(Instruction::Call(function));
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::bytecode::{Compiler, Instruction};
+    use crate::interpreter::VariableType;
+    use crate::lexical::{Lexer, Operator, ValueType};
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_compile()
+    {
+        let mut lexer = Lexer::new("
+        int twice(int n) {
+            if (n > 0) {
+                int a = n * 2;
+                return a;
+            }
+            int b = 0;
+            return b;
+        }
+        ".to_string());
+        lexer.tokenize();
+
+        let mut parser = Parser::new(lexer.tokens().clone());
+        parser.parse();
+
+        let program = Compiler::new(parser.ast()).compile();
+        let function = &program.functions[0];
+
+        // n と, ブロックを抜けた後に再利用される a, b のスロット
+        assert_eq!(function.local_count, 2);
+        assert_eq!(program.constants, vec![VariableType::Int(0), VariableType::Int(2)]);
+        assert_eq!(program.names, vec!["a", "b"]);
+        assert_eq!(function.code, vec![
+            Instruction::LoadLocal(0),
+            Instruction::Constant(0),
+            Instruction::Binary(Operator::GreaterThan),
+            Instruction::JumpIfFalse(10),
+            Instruction::LoadLocal(0),
+            Instruction::Constant(1),
+            Instruction::Binary(Operator::Multiply),
+            Instruction::DefineLocal { slot: 1, value_type: ValueType::Int, name: 0 },
+            Instruction::LoadLocal(1),
+            Instruction::Return,
+            Instruction::Constant(0),
+            Instruction::DefineLocal { slot: 1, value_type: ValueType::Int, name: 1 },
+            Instruction::LoadLocal(1),
+            Instruction::Return,
+            Instruction::ReturnVoid,
+        ]);
+    }
+}
@@ -0,0 +1,783 @@
+use crate::ast::Parameter;
+use crate::bytecode::{FunctionCode, Global, Instruction, Program};
+use crate::interpreter::{Interpreter, VariableType};
+use crate::lexical::{Operator, UnaryOperator, ValueType};
+use std::io::{Read, Write};
+
+/// バイトコードファイルの先頭に置く識別子
+pub const MAGIC: &[u8; 4] = b"NAGB";
+
+/// ファイル形式のバージョン. 形式を変更したら上げる.
+/// 2: 末尾呼び出し命令を追加
+/// 3: 初期化子のないローカル変数の宣言命令を追加
+/// 4: char, short, long, unsigned, double, _Bool を追加し, float を 32 bit にした
+/// 5: キャスト命令を追加
+pub const VERSION: u16 = 5;
+
+/// float を 32 bit にしたバージョン
+const FLOAT32_VERSION: u16 = 4;
+
+/// バージョン 3 までの型のタグ. float は 64 bit だったので double として読む
+const OLD_TYPE_TAGS: [ValueType; 3] = [ValueType::Void, ValueType::Int, ValueType::Double];
+
+/// 型と定数の種類を表すタグ. 0 は void
+const TYPE_TAGS: [ValueType; 12] = [
+    ValueType::Void,
+    ValueType::Int,
+    ValueType::Float,
+    ValueType::Double,
+    ValueType::Bool,
+    ValueType::Char,
+    ValueType::UnsignedChar,
+    ValueType::Short,
+    ValueType::UnsignedShort,
+    ValueType::UnsignedInt,
+    ValueType::Long,
+    ValueType::UnsignedLong,
+];
+
+/// バイトコードファイルの読み込みエラー
+#[derive(Debug)]
+pub enum FormatError
+{
+    Io(std::io::Error),
+
+    // 先頭の識別子が一致しない
+    BadMagic,
+
+    // 対応していないバージョン
+    UnsupportedVersion(u16),
+
+    // 内容が壊れている
+    Invalid(String),
+}
+
+impl std::fmt::Display for FormatError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        match self
+        {
+            FormatError::Io(error) =>
+                write!(f, "読み込みに失敗しました : {}", error),
+            FormatError::BadMagic =>
+                write!(f, "バイトコードファイルではありません"),
+            FormatError::UnsupportedVersion(version) =>
+                write!(f, "対応していないバージョンです : {} (対応 {})", version, VERSION),
+            FormatError::Invalid(message) =>
+                write!(f, "バイトコードが壊れています : {}", message),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+impl From<std::io::Error> for FormatError
+{
+    fn from(error: std::io::Error) -> Self
+    {
+        match error.kind()
+        {
+            std::io::ErrorKind::UnexpectedEof => FormatError::Invalid("ファイルが途中で終わっています".to_string()),
+            _ => FormatError::Io(error),
+        }
+    }
+}
+
+/// データがバイトコードファイルの識別子で始まるかどうか
+pub fn is_bytecode(data: &[u8]) -> bool
+{
+    data.starts_with(MAGIC)
+}
+
+impl Program
+{
+    /// バイトコードファイルとして書き出す. 数値はすべてリトルエンディアン
+    pub fn write_to(&self, writer: &mut impl Write) -> std::io::Result<()>
+    {
+        let mut encoder = Encoder { buffer: Vec::new() };
+        encoder.program(self);
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&encoder.buffer)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8>
+    {
+        let mut bytes = Vec::new();
+        self.write_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    /// バイトコードファイルを読み込み, 参照先の番号がすべて範囲内か確認する
+    pub fn read_from(reader: &mut impl Read) -> Result<Program, FormatError>
+    {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC
+        {
+            return Err(FormatError::BadMagic);
+        }
+
+        let mut version = [0; 2];
+        reader.read_exact(&mut version)?;
+        let version = u16::from_le_bytes(version);
+        // バージョン 4 で型のタグが変わったので, それより古いファイルは当時のタグで読む.
+        // それ以外の変更は命令の追加だけなので, 古いバージョンのファイルもそのまま実行できる
+        if !(1..=VERSION).contains(&version)
+        {
+            return Err(FormatError::UnsupportedVersion(version));
+        }
+
+        let mut decoder = Decoder { reader, version };
+        let program = decoder.program()?;
+        validate(&program)?;
+        Ok(program)
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Program, FormatError>
+    {
+        let program = Self::read_from(&mut bytes)?;
+        if !bytes.is_empty()
+        {
+            return Err(FormatError::Invalid("末尾に余分なデータがあります".to_string()));
+        }
+        Ok(program)
+    }
+}
+
+struct Encoder
+{
+    buffer: Vec<u8>,
+}
+
+impl Encoder
+{
+    fn u8(&mut self, value: u8)
+    {
+        self.buffer.push(value);
+    }
+
+    fn u32(&mut self, value: usize)
+    {
+        let value = u32::try_from(value).expect("値が大きすぎて書き出せません");
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str)
+    {
+        self.u32(value.len());
+        self.buffer.extend_from_slice(value.as_bytes());
+    }
+
+    fn value_type(&mut self, value_type: &ValueType)
+    {
+        let tag = TYPE_TAGS.iter().position(|tag| tag == value_type).unwrap();
+        self.u8(tag as u8);
+    }
+
+    fn program(&mut self, program: &Program)
+    {
+        // 定数プール
+        self.u32(program.constants.len());
+        for constant in program.constants.iter()
+        {
+            // 型のタグに続けて値をその型の大きさのリトルエンディアンで書く
+            self.value_type(&Interpreter::value_type(constant));
+            let bytes = match constant
+            {
+                VariableType::Void => Vec::new(),
+                VariableType::Bool(val) => vec![*val as u8],
+                VariableType::Char(val) => val.to_le_bytes().to_vec(),
+                VariableType::UnsignedChar(val) => val.to_le_bytes().to_vec(),
+                VariableType::Short(val) => val.to_le_bytes().to_vec(),
+                VariableType::UnsignedShort(val) => val.to_le_bytes().to_vec(),
+                VariableType::Int(val) => val.to_le_bytes().to_vec(),
+                VariableType::UnsignedInt(val) => val.to_le_bytes().to_vec(),
+                VariableType::Long(val) => val.to_le_bytes().to_vec(),
+                VariableType::UnsignedLong(val) => val.to_le_bytes().to_vec(),
+                VariableType::Float(val) => val.to_le_bytes().to_vec(),
+                VariableType::Double(val) => val.to_le_bytes().to_vec(),
+            };
+            self.buffer.extend_from_slice(&bytes);
+        }
+
+        self.u32(program.names.len());
+        for name in program.names.iter()
+        {
+            self.string(name);
+        }
+
+        self.u32(program.globals.len());
+        for global in program.globals.iter()
+        {
+            self.string(&global.name);
+            self.value_type(&global.value_type);
+        }
+
+        self.u32(program.functions.len());
+        for function in program.functions.iter()
+        {
+            self.function(function);
+        }
+        self.function(&program.initializer);
+    }
+
+    fn function(&mut self, function: &FunctionCode)
+    {
+        self.string(&function.name);
+        self.value_type(&function.return_type);
+
+        self.u32(function.parameters.len());
+        for parameter in function.parameters.iter()
+        {
+            self.value_type(&parameter.type_specifier);
+            self.string(&parameter.name);
+        }
+
+        self.u32(function.local_count);
+
+        self.u32(function.code.len());
+        for (instruction, line) in function.code.iter().zip(function.lines.iter())
+        {
+            self.instruction(instruction);
+            self.u32(*line);
+        }
+    }
+
+    fn instruction(&mut self, instruction: &Instruction)
+    {
+        self.u8(opcode(instruction));
+        match instruction
+        {
+            Instruction::Constant(index)
+            | Instruction::LoadLocal(index)
+            | Instruction::LoadGlobal(index)
+            | Instruction::StoreGlobal(index)
+            | Instruction::ShortCircuitAnd(index)
+            | Instruction::ShortCircuitOr(index)
+            | Instruction::Jump(index)
+            | Instruction::JumpIfFalse(index)
+            | Instruction::Call(index)
+            | Instruction::TailCall(index)
+            | Instruction::CheckValue(index)
+            | Instruction::UndefinedVariable(index)
+            | Instruction::UndefinedFunction(index) => self.u32(*index),
+            Instruction::DefineLocal { slot, value_type, name }
+            | Instruction::DeclareLocal { slot, value_type, name } =>
+                {
+                    self.u32(*slot);
+                    self.value_type(value_type);
+                    self.u32(*name);
+                }
+            Instruction::StoreLocal { slot, name } =>
+                {
+                    self.u32(*slot);
+                    self.u32(*name);
+                }
+            Instruction::DefineGlobal { slot, value_type } =>
+                {
+                    self.u32(*slot);
+                    self.value_type(value_type);
+                }
+            Instruction::Unary(op) => self.u8(unary_operator_code(op)),
+            Instruction::Binary(op) => self.u8(operator_code(op)),
+            Instruction::Cast(value_type) => self.value_type(value_type),
+            Instruction::ArgumentCountMismatch { name, expected, found } =>
+                {
+                    self.u32(*name);
+                    self.u32(*expected);
+                    self.u32(*found);
+                }
+            Instruction::Pop | Instruction::Return | Instruction::ReturnVoid => {}
+        }
+    }
+}
+
+fn opcode(instruction: &Instruction) -> u8
+{
+    match instruction
+    {
+        Instruction::Constant(_) => 0,
+        Instruction::Pop => 1,
+        Instruction::LoadLocal(_) => 2,
+        Instruction::DefineLocal { .. } => 3,
+        Instruction::StoreLocal { .. } => 4,
+        Instruction::LoadGlobal(_) => 5,
+        Instruction::DefineGlobal { .. } => 6,
+        Instruction::StoreGlobal(_) => 7,
+        Instruction::Unary(_) => 8,
+        Instruction::Binary(_) => 9,
+        Instruction::ShortCircuitAnd(_) => 10,
+        Instruction::ShortCircuitOr(_) => 11,
+        Instruction::Jump(_) => 12,
+        Instruction::JumpIfFalse(_) => 13,
+        Instruction::Call(_) => 14,
+        Instruction::CheckValue(_) => 15,
+        Instruction::Return => 16,
+        Instruction::ReturnVoid => 17,
+        Instruction::UndefinedVariable(_) => 18,
+        Instruction::UndefinedFunction(_) => 19,
+        Instruction::ArgumentCountMismatch { .. } => 20,
+        Instruction::TailCall(_) => 21,
+        Instruction::DeclareLocal { .. } => 22,
+        Instruction::Cast(_) => 23,
+    }
+}
+
+const OPERATORS: [Operator; 13] = [
+    Operator::Plus,
+    Operator::Minus,
+    Operator::Multiply,
+    Operator::Divide,
+    Operator::Modulo,
+    Operator::LessThan,
+    Operator::GreaterThan,
+    Operator::LessThanOrEqual,
+    Operator::GreaterThanOrEqual,
+    Operator::Equal,
+    Operator::NotEqual,
+    Operator::LogicalOr,
+    Operator::LogicalAnd,
+];
+
+const UNARY_OPERATORS: [UnaryOperator; 2] = [UnaryOperator::Minus, UnaryOperator::LogicalNot];
+
+fn operator_code(op: &Operator) -> u8
+{
+    OPERATORS.iter().position(|operator| operator == op).unwrap() as u8
+}
+
+fn unary_operator_code(op: &UnaryOperator) -> u8
+{
+    UNARY_OPERATORS.iter().position(|operator| operator == op).unwrap() as u8
+}
+
+struct Decoder<'a, R: Read>
+{
+    reader: &'a mut R,
+
+    // 読んでいるファイルのバージョン
+    version: u16,
+}
+
+impl<R: Read> Decoder<'_, R>
+{
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N], FormatError>
+    {
+        let mut bytes = [0; N];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, FormatError>
+    {
+        Ok(self.bytes::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<usize, FormatError>
+    {
+        Ok(u32::from_le_bytes(self.bytes()?) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, FormatError>
+    {
+        let length = self.u32()?;
+        let mut bytes = Vec::new();
+        self.reader.by_ref().take(length as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != length
+        {
+            return Err(FormatError::Invalid("ファイルが途中で終わっています".to_string()));
+        }
+        String::from_utf8(bytes).map_err(|_| FormatError::Invalid("名前が UTF-8 ではありません".to_string()))
+    }
+
+    fn value_type(&mut self) -> Result<ValueType, FormatError>
+    {
+        let tag = self.u8()?;
+        let tags: &[ValueType] = if self.version < FLOAT32_VERSION { &OLD_TYPE_TAGS } else { &TYPE_TAGS };
+        tags.get(tag as usize).cloned()
+            .ok_or_else(|| FormatError::Invalid(format!("不明な型です : {}", tag)))
+    }
+
+    fn program(&mut self) -> Result<Program, FormatError>
+    {
+        let mut constants = Vec::new();
+        for _ in 0..self.u32()?
+        {
+            constants.push(match self.value_type()?
+            {
+                ValueType::Void => VariableType::Void,
+                ValueType::Bool => VariableType::Bool(u8::from_le_bytes(self.bytes()?) != 0),
+                ValueType::Char => VariableType::Char(i8::from_le_bytes(self.bytes()?)),
+                ValueType::UnsignedChar => VariableType::UnsignedChar(u8::from_le_bytes(self.bytes()?)),
+                ValueType::Short => VariableType::Short(i16::from_le_bytes(self.bytes()?)),
+                ValueType::UnsignedShort => VariableType::UnsignedShort(u16::from_le_bytes(self.bytes()?)),
+                ValueType::Int => VariableType::Int(i32::from_le_bytes(self.bytes()?)),
+                ValueType::UnsignedInt => VariableType::UnsignedInt(u32::from_le_bytes(self.bytes()?)),
+                ValueType::Long => VariableType::Long(i64::from_le_bytes(self.bytes()?)),
+                ValueType::UnsignedLong => VariableType::UnsignedLong(u64::from_le_bytes(self.bytes()?)),
+                ValueType::Float => VariableType::Float(f32::from_le_bytes(self.bytes()?)),
+                ValueType::Double => VariableType::Double(f64::from_le_bytes(self.bytes()?)),
+            });
+        }
+
+        let mut names = Vec::new();
+        for _ in 0..self.u32()?
+        {
+            names.push(self.string()?);
+        }
+
+        let mut globals = Vec::new();
+        for _ in 0..self.u32()?
+        {
+            let name = self.string()?;
+            let value_type = self.value_type()?;
+            globals.push(Global { name, value_type });
+        }
+
+        let mut functions = Vec::new();
+        for _ in 0..self.u32()?
+        {
+            functions.push(self.function()?);
+        }
+        let initializer = self.function()?;
+
+        Ok(Program { constants, names, globals, functions, initializer })
+    }
+
+    fn function(&mut self) -> Result<FunctionCode, FormatError>
+    {
+        let name = self.string()?;
+        let return_type = self.value_type()?;
+
+        let mut parameters = Vec::new();
+        for _ in 0..self.u32()?
+        {
+            let type_specifier = self.value_type()?;
+            let name = self.string()?;
+            parameters.push(Parameter { type_specifier, name });
+        }
+
+        let local_count = self.u32()?;
+
+        let mut code = Vec::new();
+        let mut lines = Vec::new();
+        for _ in 0..self.u32()?
+        {
+            code.push(self.instruction()?);
+            lines.push(self.u32()?);
+        }
+
+        Ok(FunctionCode { name, return_type, parameters, local_count, code, lines })
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, FormatError>
+    {
+        let instruction = match self.u8()?
+        {
+            0 => Instruction::Constant(self.u32()?),
+            1 => Instruction::Pop,
+            2 => Instruction::LoadLocal(self.u32()?),
+            3 => Instruction::DefineLocal { slot: self.u32()?, value_type: self.value_type()?, name: self.u32()? },
+            4 => Instruction::StoreLocal { slot: self.u32()?, name: self.u32()? },
+            5 => Instruction::LoadGlobal(self.u32()?),
+            6 => Instruction::DefineGlobal { slot: self.u32()?, value_type: self.value_type()? },
+            7 => Instruction::StoreGlobal(self.u32()?),
+            8 => match UNARY_OPERATORS.get(self.u8()? as usize)
+            {
+                Some(op) => Instruction::Unary(*op),
+                None => return Err(FormatError::Invalid("不明な単項演算子です".to_string())),
+            },
+            9 => match OPERATORS.get(self.u8()? as usize)
+            {
+                Some(op) => Instruction::Binary(*op),
+                None => return Err(FormatError::Invalid("不明な演算子です".to_string())),
+            },
+            10 => Instruction::ShortCircuitAnd(self.u32()?),
+            11 => Instruction::ShortCircuitOr(self.u32()?),
+            12 => Instruction::Jump(self.u32()?),
+            13 => Instruction::JumpIfFalse(self.u32()?),
+            14 => Instruction::Call(self.u32()?),
+            15 => Instruction::CheckValue(self.u32()?),
+            16 => Instruction::Return,
+            17 => Instruction::ReturnVoid,
+            18 => Instruction::UndefinedVariable(self.u32()?),
+            19 => Instruction::UndefinedFunction(self.u32()?),
+            20 => Instruction::ArgumentCountMismatch { name: self.u32()?, expected: self.u32()?, found: self.u32()? },
+            21 => Instruction::TailCall(self.u32()?),
+            22 => Instruction::DeclareLocal { slot: self.u32()?, value_type: self.value_type()?, name: self.u32()? },
+            23 => Instruction::Cast(self.value_type()?),
+            code => return Err(FormatError::Invalid(format!("不明な命令です : {}", code))),
+        };
+        Ok(instruction)
+    }
+}
+
+/// 読み込んだプログラムの番号が範囲内かと, スタックの値が足りなくならないかを確認する.
+/// VM が範囲外を参照したり空のスタックから取り出したりして panic しないようにする
+fn validate(program: &Program) -> Result<(), FormatError>
+{
+    let functions = program.functions.iter().chain(std::iter::once(&program.initializer));
+    for function in functions
+    {
+        let check = |valid: bool, what: &str, index: usize| {
+            if valid
+            {
+                Ok(())
+            } else {
+                Err(FormatError::Invalid(format!("{} の {} が範囲外です : {}", function.name, what, index)))
+            }
+        };
+
+        check(function.parameters.len() <= function.local_count, "ローカル変数の数", function.local_count)?;
+
+        // 最後の命令から次へ進むことはできない
+        match function.code.last()
+        {
+            Some(Instruction::Return | Instruction::ReturnVoid | Instruction::Jump(_)) => {}
+            _ => return Err(FormatError::Invalid(format!("{} が return で終わっていません", function.name))),
+        }
+
+        for instruction in function.code.iter()
+        {
+            match instruction
+            {
+                Instruction::Constant(index) => check(*index < program.constants.len(), "定数", *index)?,
+                Instruction::LoadLocal(slot)
+                | Instruction::StoreLocal { slot, .. }
+                | Instruction::DefineLocal { slot, .. }
+                | Instruction::DeclareLocal { slot, .. } =>
+                    check(*slot < function.local_count, "ローカル変数", *slot)?,
+                Instruction::LoadGlobal(slot) | Instruction::StoreGlobal(slot) | Instruction::DefineGlobal { slot, .. } =>
+                    check(*slot < program.globals.len(), "グローバル変数", *slot)?,
+                Instruction::ShortCircuitAnd(target)
+                | Instruction::ShortCircuitOr(target)
+                | Instruction::Jump(target)
+                | Instruction::JumpIfFalse(target) => check(*target < function.code.len(), "飛び先", *target)?,
+                Instruction::Call(index) | Instruction::TailCall(index) => check(*index < program.functions.len(), "関数", *index)?,
+                _ => {}
+            }
+
+            match instruction
+            {
+                Instruction::DefineLocal { name, .. }
+                | Instruction::DeclareLocal { name, .. }
+                | Instruction::StoreLocal { name, .. }
+                | Instruction::CheckValue(name)
+                | Instruction::UndefinedVariable(name)
+                | Instruction::UndefinedFunction(name)
+                | Instruction::ArgumentCountMismatch { name, .. } => check(*name < program.names.len(), "名前", *name)?,
+                _ => {}
+            }
+        }
+
+        check_stack(program, function)?;
+    }
+    Ok(())
+}
+
+/// 命令ごとにスタックの深さを求め, 値が足りない命令と, 合流する経路で深さが食い違う命令がないかを確認する.
+/// 深さはローカル変数の領域より上に積まれた値の数で, 番号が範囲内であることは確認済みとする
+fn check_stack(program: &Program, function: &FunctionCode) -> Result<(), FormatError>
+{
+    let mut depths: Vec<Option<usize>> = vec![None; function.code.len()];
+    let mut pending = vec![(0, 0)];
+    while let Some((ip, depth)) = pending.pop()
+    {
+        match depths[ip]
+        {
+            Some(known) if known == depth => continue,
+            Some(_) => return Err(FormatError::Invalid(
+                format!("{} の {} 番目の命令でスタックの深さが一致しません", function.name, ip))),
+            None => depths[ip] = Some(depth),
+        }
+
+        let instruction = &function.code[ip];
+        let (pops, pushes) = stack_effect(program, instruction);
+        if depth < pops
+        {
+            return Err(FormatError::Invalid(format!("{} の {} 番目の命令でスタックが足りません", function.name, ip)));
+        }
+        let depth = depth - pops + pushes;
+
+        match instruction
+        {
+            Instruction::Jump(target) => pending.push((*target, depth)),
+            Instruction::JumpIfFalse(target)
+            | Instruction::ShortCircuitAnd(target)
+            | Instruction::ShortCircuitOr(target) =>
+                {
+                    pending.push((*target, depth));
+                    pending.push((ip + 1, depth));
+                }
+            Instruction::Return
+            | Instruction::ReturnVoid
+            | Instruction::TailCall(_)
+            | Instruction::UndefinedVariable(_)
+            | Instruction::UndefinedFunction(_)
+            | Instruction::ArgumentCountMismatch { .. } => {}
+            // 最後の命令は return か jump なので, 次の命令は必ずある
+            _ => pending.push((ip + 1, depth)),
+        }
+    }
+    Ok(())
+}
+
+/// 命令がスタックから取り出す値の数と積む値の数
+fn stack_effect(program: &Program, instruction: &Instruction) -> (usize, usize)
+{
+    match instruction
+    {
+        Instruction::Constant(_) | Instruction::LoadLocal(_) | Instruction::LoadGlobal(_) => (0, 1),
+        Instruction::Pop
+        | Instruction::DefineLocal { .. }
+        | Instruction::StoreLocal { .. }
+        | Instruction::DefineGlobal { .. }
+        | Instruction::StoreGlobal(_)
+        | Instruction::JumpIfFalse(_)
+        | Instruction::Return => (1, 0),
+        Instruction::Unary(_)
+        | Instruction::Cast(_)
+        | Instruction::ShortCircuitAnd(_)
+        | Instruction::ShortCircuitOr(_)
+        | Instruction::CheckValue(_) => (1, 1),
+        Instruction::Binary(_) => (2, 1),
+        Instruction::Call(index) => (program.functions[*index].parameters.len(), 1),
+        Instruction::TailCall(index) => (program.functions[*index].parameters.len(), 0),
+        Instruction::DeclareLocal { .. }
+        | Instruction::Jump(_)
+        | Instruction::ReturnVoid
+        | Instruction::UndefinedVariable(_)
+        | Instruction::UndefinedFunction(_)
+        | Instruction::ArgumentCountMismatch { .. } => (0, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::bytecode::{Compiler, Instruction, Program};
+    use crate::bytecode_file::{FormatError, FLOAT32_VERSION, VERSION};
+    use crate::interpreter::VariableType::{Double, Float, Int};
+    use crate::lexical::{Lexer, Operator};
+    use crate::parser::Parser;
+    use crate::vm::Vm;
+
+    #[test]
+    fn test_round_trip()
+    {
+        let mut lexer = Lexer::new("
+        float ratio = 0.5;
+        int fibo(int n) {
+            if (n < 2 || n == 0) {
+                return n;
+            }
+            return fibo(n - 1) + fibo(n - 2);
+        }
+        float scale(float x) { return -x * ratio + (int)(x / 2); }
+        ".to_string());
+        lexer.tokenize();
+
+        let mut parser = Parser::with_lines(lexer.tokens().clone(), lexer.lines().clone());
+        parser.parse();
+
+        let program = Compiler::new(parser.ast()).compile();
+        let bytes = program.to_bytes();
+        assert_eq!(&bytes[..4], b"NAGB");
+
+        // 読み込んだプログラムは元と同じで, 構文解析せずに実行できる
+        let loaded = Program::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded, program);
+
+        let mut vm = Vm::new(loaded);
+        assert_eq!(vm.call("fibo", &[Int(10)]), Ok(Int(55)));
+        assert_eq!(vm.call("scale", &[Int(3)]), Ok(Float(-0.5)));
+    }
+
+    // 'float ratio = 0.5; float scale(float x) { return x * ratio; } int twice(int n) { return n * 2; }' を
+    // バージョン 1 の compile で書き出したもの. バージョン 2, 3 でもバージョン番号以外は同じになる
+    const VERSION1_IMAGE: &[u8] = b"\
+          NAGB\x01\x00\x02\x00\x00\x00\x02\x00\x00\x00\x00\x00\x00\xe0?\x01\x02\x00\x00\x00\
+          \x00\x00\x00\x00\x01\x00\x00\x00\x05\x00\x00\x00ratio\x02\x02\x00\x00\x00\x05\x00\
+          \x00\x00scale\x02\x01\x00\x00\x00\x02\x01\x00\x00\x00x\x01\x00\x00\x00\x05\x00\
+          \x00\x00\x02\x00\x00\x00\x00\x02\x00\x00\x00\x05\x00\x00\x00\x00\x02\x00\x00\x00\x09\x02\x02\x00\
+          \x00\x00\x10\x02\x00\x00\x00\x11\x00\x00\x00\x00\x05\x00\x00\x00twice\x01\x01\x00\
+          \x00\x00\x01\x01\x00\x00\x00n\x01\x00\x00\x00\x05\x00\x00\x00\x02\x00\x00\x00\x00\x03\x00\x00\
+          \x00\x00\x01\x00\x00\x00\x03\x00\x00\x00\x09\x02\x03\x00\x00\x00\x10\x03\x00\x00\x00\x11\x00\x00\
+          \x00\x00\x06\x00\x00\x00<init>\x00\x00\x00\x00\x00\x00\x00\x00\x00\x03\x00\x00\
+          \x00\x00\x00\x00\x00\x00\x01\x00\x00\x00\x06\x00\x00\x00\x00\x02\x01\x00\x00\x00\x11\x00\x00\x00\
+          \x00";
+
+    #[test]
+    fn test_old_versions()
+    {
+        // バージョン 3 までは float が 64 bit だったので, 定数と型のタグ 2 は double として読む
+        for version in 1..FLOAT32_VERSION
+        {
+            let mut bytes = VERSION1_IMAGE.to_vec();
+            bytes[4..6].copy_from_slice(&version.to_le_bytes());
+
+            let mut vm = Vm::new(Program::from_bytes(&bytes).unwrap());
+            assert_eq!(vm.call("scale", &[Double(3.0)]), Ok(Double(1.5)), "バージョン {}", version);
+            assert_eq!(vm.call("twice", &[Int(4)]), Ok(Int(8)), "バージョン {}", version);
+        }
+
+        // バージョン 4 では 0.5 は double の定数 (タグ 3) になり, float はタグ 2 の 32 bit になる
+        let mut bytes = VERSION1_IMAGE.to_vec();
+        bytes[4..6].copy_from_slice(&FLOAT32_VERSION.to_le_bytes());
+        bytes[10] = 3;
+
+        let mut vm = Vm::new(Program::from_bytes(&bytes).unwrap());
+        assert_eq!(vm.call("scale", &[Float(3.0)]), Ok(Float(1.5)));
+        assert_eq!(vm.call("twice", &[Int(4)]), Ok(Int(8)));
+    }
+
+    #[test]
+    fn test_invalid_file()
+    {
+        assert!(matches!(Program::from_bytes(b"ELF!"), Err(FormatError::BadMagic)));
+
+        let mut lexer = Lexer::new("int one(void) { return 1; }".to_string());
+        lexer.tokenize();
+        let mut parser = Parser::new(lexer.tokens().clone());
+        parser.parse();
+        let bytes = Compiler::new(parser.ast()).compile().to_bytes();
+
+        let mut newer = bytes.clone();
+        newer[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(Program::from_bytes(&newer), Err(FormatError::UnsupportedVersion(_))));
+
+        assert!(matches!(Program::from_bytes(&bytes[..bytes.len() - 3]), Err(FormatError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_stack_underflow()
+    {
+        let mut lexer = Lexer::new("int add(int a, int b) { return a + b; }\n".to_string());
+        lexer.tokenize();
+        let mut parser = Parser::new(lexer.tokens().clone());
+        parser.parse();
+        let program = Compiler::new(parser.ast()).compile();
+
+        // 値が足りない命令と, 経路によって深さが変わる命令を含むファイルは読み込まない
+        let codes = [
+            vec![Instruction::Pop, Instruction::ReturnVoid],
+            vec![Instruction::Constant(0), Instruction::Binary(Operator::Plus), Instruction::Return],
+            vec![Instruction::LoadLocal(0), Instruction::TailCall(0)],
+            vec![Instruction::LoadLocal(0), Instruction::JumpIfFalse(3), Instruction::LoadLocal(0), Instruction::Return],
+            vec![Instruction::LoadLocal(0), Instruction::Jump(0)],
+        ];
+        for code in codes
+        {
+            let mut crafted = program.clone();
+            crafted.constants.push(Int(1));
+            crafted.functions[0].lines = vec![0; code.len()];
+            crafted.functions[0].code = code;
+
+            let result = Program::from_bytes(&crafted.to_bytes());
+            assert!(matches!(result, Err(FormatError::Invalid(_))), "{:?}", crafted.functions[0].code);
+        }
+    }
+}
@@ -0,0 +1,927 @@
+use crate::ast::{Ast, Decl, Expr, ExprId, Function, NodeId, SideTable, Stmt, StmtId};
+use crate::lexical::{Constant, Operator, UnaryOperator, ValueType};
+use crate::parser::static_local_name;
+use crate::resolver::{Resolution, Resolutions, Resolver};
+use crate::semantic::{Binding, Diagnostic, SemanticAnalyzer};
+
+/// 生成する C ソースの先頭に置く補助関数. 剰余はインタプリタと同じく浮動小数点数が混ざっても int になる.
+/// 符号付き整数の除算と剰余は, インタプリタの折り返しと同じく最小値 / -1 を最小値, 最小値 % -1 を 0 にする
+const PRELUDE: &str = "\
+#include <math.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+static inline int nagato_rem(double lhs, double rhs) { return (int)fmod(lhs, rhs); }
+
+static inline int nagato_div_int(int lhs, int rhs) { return rhs == -1 ? (int)(0u - (unsigned int)lhs) : lhs / rhs; }
+static inline int nagato_rem_int(int lhs, int rhs) { return rhs == -1 ? 0 : lhs % rhs; }
+static inline long long nagato_div_long(long long lhs, long long rhs)
+{
+    return rhs == -1 ? (long long)(0ull - (unsigned long long)lhs) : lhs / rhs;
+}
+static inline long long nagato_rem_long(long long lhs, long long rhs) { return rhs == -1 ? 0 : lhs % rhs; }
+
+static inline void nagato_missing_return(const char *name)
+{
+    fprintf(stderr, \"関数が値を返さずに終了しました : %s\\n\", name);
+    exit(1);
+}
+";
+
+/// 構文木から単独でコンパイルできる C ソースを生成する.
+/// 暗黙の型変換はすべてキャストとして書き出し, 識別子は C の予約語やライブラリと衝突しないよう接頭辞を付ける
+pub struct CGenerator<'a>
+{
+    ast: &'a Ast,
+    resolutions: Resolutions,
+
+    // 意味解析で求めた式の型と, 代入先の宣言
+    types: SideTable<ExprId, ValueType>,
+    assignment_bindings: SideTable<StmtId, Binding>,
+
+    // グローバル変数の型. 同じ名前で複数回宣言された場合は最後の宣言の型
+    globals: Vec<(String, ValueType)>,
+
+    output: String,
+    indent: usize,
+
+    // 生成中の関数と, その中の while 文の深さ
+    function: Option<&'a Function>,
+    loop_depth: usize,
+}
+
+impl<'a> CGenerator<'a>
+{
+    pub fn new(ast: &'a Ast) -> Self
+    {
+        CGenerator {
+            ast,
+            resolutions: Resolutions::default(),
+            types: SideTable::new(),
+            assignment_bindings: SideTable::new(),
+            globals: Vec::new(),
+            output: String::new(),
+            indent: 0,
+            function: None,
+            loop_depth: 0,
+        }
+    }
+
+    /// C ソースを生成する. 意味解析でエラーがある場合は生成しない
+    pub fn generate(mut self) -> Result<String, Vec<Diagnostic>>
+    {
+        let ast = self.ast;
+
+        let mut analyzer = SemanticAnalyzer::new(ast);
+        // 定義のない関数は別の翻訳単位でリンクする
+        analyzer.set_external_functions(true);
+        let mut errors: Vec<Diagnostic> = analyzer.analyze().into_iter()
+            .filter(|diagnostic| diagnostic.is_error())
+            .collect();
+
+        // C の main から呼び出すので, main 関数は引数を取れない
+        let main = Self::functions(ast).into_iter().find(|function| function.name == "main");
+        if let Some(main) = main
+        {
+            if !main.parameters.is_empty()
+            {
+                errors.push(Diagnostic::error("main 関数は引数を取れません".to_string()));
+            }
+        }
+        if !errors.is_empty()
+        {
+            return Err(errors);
+        }
+
+        for index in 0..ast.expr_count()
+        {
+            let id = ExprId::new(index);
+            if let Some(value_type) = analyzer.expr_type(id)
+            {
+                self.types.insert(id, value_type.clone());
+            }
+        }
+        for index in 0..ast.stmt_count()
+        {
+            let id = StmtId::new(index);
+            if let Some(binding) = analyzer.assignment_binding(id)
+            {
+                self.assignment_bindings.insert(id, *binding);
+            }
+        }
+        self.resolutions = Resolver::new(ast).resolve().clone();
+
+        for declaration in ast.declarations()
+        {
+            if let Decl::Variable { type_specifier, name, .. } = declaration
+            {
+                match self.globals.iter_mut().find(|(global, _)| global == name)
+                {
+                    Some(global) => global.1 = type_specifier.clone(),
+                    None => self.globals.push((name.clone(), type_specifier.clone())),
+                }
+            }
+        }
+
+        self.output.push_str("/* NagatoInterpreter が生成した C ソース */\n");
+        self.output.push_str(PRELUDE);
+
+        self.line("");
+        for (name, value_type) in self.globals.clone().iter()
+        {
+            self.line(&format!("{} g_{};", c_type(value_type), name));
+        }
+
+        // 定義のない関数は別の翻訳単位でリンクする
+        let functions = Self::functions(ast);
+        self.line("");
+        for function in functions.iter().chain(Self::prototypes(ast).iter())
+        {
+            self.line(&format!("{};", self.signature(function)));
+        }
+        for function in functions.iter()
+        {
+            self.function_definition(function);
+        }
+
+        self.initializer();
+        self.main(main);
+
+        Ok(self.output)
+    }
+
+    /// 定義された関数. 同じ名前の関数は最後の定義だけを残す
+    fn functions(ast: &Ast) -> Vec<&Function>
+    {
+        let mut functions: Vec<&Function> = Vec::new();
+        for declaration in ast.declarations()
+        {
+            if let Decl::Function(function) = declaration
+            {
+                functions.retain(|defined| defined.name != function.name);
+                functions.push(function);
+            }
+        }
+        functions
+    }
+
+    /// プロトタイプだけが宣言され, 定義のない関数
+    fn prototypes(ast: &Ast) -> Vec<&Function>
+    {
+        let functions = Self::functions(ast);
+        let mut prototypes: Vec<&Function> = Vec::new();
+        for declaration in ast.declarations()
+        {
+            if let Decl::Prototype(function) = declaration
+            {
+                let defined = functions.iter().chain(prototypes.iter()).any(|defined| defined.name == function.name);
+                if !defined
+                {
+                    prototypes.push(function);
+                }
+            }
+        }
+        prototypes
+    }
+
+    fn line(&mut self, text: &str)
+    {
+        if !text.is_empty()
+        {
+            for _ in 0..self.indent
+            {
+                self.output.push_str("    ");
+            }
+        }
+        self.output.push_str(text);
+        self.output.push('\n');
+    }
+
+    fn signature(&self, function: &Function) -> String
+    {
+        let parameters: Vec<String> = function.parameters.iter()
+            .enumerate()
+            .map(|(slot, parameter)| format!("{} {}", c_type(&parameter.type_specifier), local_name(&parameter.name, 0, slot)))
+            .collect();
+        let parameters = if parameters.is_empty() { "void".to_string() } else { parameters.join(", ") };
+        format!("{} f_{}({})", c_type(&function.return_type), function.name, parameters)
+    }
+
+    fn function_definition(&mut self, function: &'a Function)
+    {
+        self.function = Some(function);
+
+        self.line("");
+        let signature = self.signature(function);
+        self.line(&signature);
+        self.line("{");
+        self.indent += 1;
+        for stmt in function.body.iter()
+        {
+            self.statement(*stmt);
+        }
+        self.indent -= 1;
+        self.line("}");
+
+        self.function = None;
+    }
+
+    /// グローバル変数の初期化子を宣言順に実行する関数
+    fn initializer(&mut self)
+    {
+        let ast = self.ast;
+        self.line("");
+        self.line("static void nagato_initialize(void)");
+        self.line("{");
+        self.indent += 1;
+        for declaration in ast.declarations()
+        {
+            if let Decl::Variable { name, initializer: Some(initializer), .. } = declaration
+            {
+                let value_type = self.global_type(name);
+                let value = self.converted(*initializer, &value_type);
+                self.line(&format!("g_{} = {};", name, value));
+            }
+        }
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    /// 初期化の後に main 関数を呼び出し, インタプリタと同じく定義済みのグローバル変数と戻り値を表示する
+    fn main(&mut self, main: Option<&Function>)
+    {
+        let ast = self.ast;
+        self.line("");
+        self.line("int main(void)");
+        self.line("{");
+        self.indent += 1;
+        self.line("nagato_initialize();");
+
+        let result = match main
+        {
+            Some(main) if main.return_type != ValueType::Void =>
+                {
+                    self.line(&format!("{} result = f_main();", c_type(&main.return_type)));
+                    Some(main.return_type.clone())
+                }
+            Some(_) =>
+                {
+                    self.line("f_main();");
+                    None
+                }
+            None => None,
+        };
+
+        let mut printed: Vec<&String> = Vec::new();
+        for declaration in ast.declarations()
+        {
+            if let Decl::Variable { name, .. } = declaration
+            {
+                // static なローカル変数は関数の外からは見えないので表示しない
+                if !printed.contains(&name) && static_local_name(name).is_none()
+                {
+                    printed.push(name);
+                    let format = print_format(&self.global_type(name));
+                    self.line(&format!("printf(\"{} = {}\\n\", g_{});", name, format, name));
+                }
+            }
+        }
+        if let Some(result) = result
+        {
+            self.line(&format!("printf(\"result = {}\\n\", result);", print_format(&result)));
+        }
+
+        self.line("return 0;");
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    fn global_type(&self, name: &str) -> ValueType
+    {
+        self.globals.iter()
+            .find(|(global, _)| global == name)
+            .map(|(_, value_type)| value_type.clone())
+            .unwrap_or(ValueType::Int)
+    }
+
+    fn statement(&mut self, id: StmtId)
+    {
+        let ast = self.ast;
+        match ast.stmt(id)
+        {
+            Stmt::Declaration(Decl::Variable { type_specifier, name, initializer }) =>
+                {
+                    let variable = self.variable(self.resolutions.stmt(id), name);
+                    // 初期化子がない変数は VM と同じく 0 で初期化しておく
+                    let value = match initializer
+                    {
+                        Some(initializer) => self.converted(*initializer, type_specifier),
+                        None => "0".to_string(),
+                    };
+                    self.line(&format!("{} {} = {};", c_type(type_specifier), variable, value));
+                }
+            Stmt::Declaration(Decl::Function(_) | Decl::Prototype(_)) => {}
+            Stmt::Assignment { name, value } =>
+                {
+                    let variable = self.variable(self.resolutions.stmt(id), name);
+                    let value_type = self.assignment_type(id, name);
+                    let value = self.converted(*value, &value_type);
+                    self.line(&format!("{} = {};", variable, value));
+                }
+            Stmt::Expression(expr) =>
+                {
+                    let text = self.expression(*expr);
+                    if let Expr::Call { .. } = ast.expr(*expr)
+                    {
+                        self.line(&format!("{};", text));
+                    } else {
+                        self.line(&format!("(void){};", text));
+                    }
+                }
+            Stmt::Block(stmts) =>
+                {
+                    self.line("{");
+                    self.indent += 1;
+                    for stmt in stmts.iter()
+                    {
+                        self.statement(*stmt);
+                    }
+                    self.indent -= 1;
+                    self.line("}");
+                }
+            Stmt::If { condition, then_branch, else_branch } =>
+                {
+                    let condition = self.expression(*condition);
+                    self.line(&format!("if ({})", condition));
+                    self.nested_statement(*then_branch);
+                    if let Some(else_branch) = else_branch
+                    {
+                        self.line("else");
+                        self.nested_statement(*else_branch);
+                    }
+                }
+            Stmt::While { condition, body } =>
+                {
+                    let condition = self.expression(*condition);
+                    self.line(&format!("while ({})", condition));
+                    self.loop_depth += 1;
+                    self.nested_statement(*body);
+                    self.loop_depth -= 1;
+                }
+            Stmt::Return(value) =>
+                {
+                    let return_type = self.function.map(|function| function.return_type.clone()).unwrap_or(ValueType::Void);
+                    match value
+                    {
+                        Some(value) =>
+                            {
+                                let value = self.converted(*value, &return_type);
+                                self.line(&format!("return {};", value));
+                            }
+                        None => self.line("return;"),
+                    }
+                }
+            Stmt::Break | Stmt::Continue if self.loop_depth == 0 =>
+                {
+                    // ループの外の break と continue はインタプリタでは関数から抜ける
+                    self.fall_off();
+                }
+            Stmt::Break => self.line("break;"),
+            Stmt::Continue => self.line("continue;"),
+        }
+    }
+
+    /// if や while の本体. ブロック以外の文は字下げして書く
+    fn nested_statement(&mut self, id: StmtId)
+    {
+        if let Stmt::Block(_) = self.ast.stmt(id)
+        {
+            self.statement(id);
+        } else {
+            self.indent += 1;
+            self.statement(id);
+            self.indent -= 1;
+        }
+    }
+
+    /// return を通らずに関数の終わりに達した場合
+    fn fall_off(&mut self)
+    {
+        match self.function
+        {
+            Some(function) if function.return_type != ValueType::Void =>
+                {
+                    let name = function.name.clone();
+                    self.line(&format!("nagato_missing_return(\"{}\");", name));
+                }
+            _ => self.line("return;"),
+        }
+    }
+
+    /// 代入先の変数の宣言された型
+    fn assignment_type(&self, id: StmtId, name: &str) -> ValueType
+    {
+        match self.assignment_bindings.get(id)
+        {
+            Some(Binding::Local(stmt)) => match self.ast.stmt(*stmt)
+            {
+                Stmt::Declaration(Decl::Variable { type_specifier, .. }) => type_specifier.clone(),
+                _ => ValueType::Int,
+            },
+            Some(Binding::Parameter(index)) => self.function
+                .map(|function| function.parameters[*index].type_specifier.clone())
+                .unwrap_or(ValueType::Int),
+            _ => self.global_type(name),
+        }
+    }
+
+    fn variable(&self, resolution: Resolution, name: &str) -> String
+    {
+        match resolution
+        {
+            Resolution::Local { depth, slot } => local_name(name, depth, slot),
+            Resolution::Global => format!("g_{}", name),
+        }
+    }
+
+    fn expr_type(&self, id: ExprId) -> ValueType
+    {
+        self.types.get(id).cloned().unwrap_or(ValueType::Int)
+    }
+
+    /// 式を指定した型に変換する. 型が異なる場合は明示的にキャストする
+    fn converted(&self, id: ExprId, value_type: &ValueType) -> String
+    {
+        let text = self.expression(id);
+        if self.expr_type(id) == *value_type
+        {
+            text
+        } else {
+            format!("({}){}", c_type(value_type), text)
+        }
+    }
+
+    fn expression(&self, id: ExprId) -> String
+    {
+        match self.ast.expr(id)
+        {
+            Expr::Constant(Constant::Integer(val)) =>
+                {
+                    // INT_MIN はリテラルとして書けないので式にする
+                    match *val
+                    {
+                        i32::MIN => "(-2147483647 - 1)".to_string(),
+                        val if val < 0 => format!("({})", val),
+                        val => val.to_string(),
+                    }
+                }
+            Expr::Constant(Constant::UnsignedInt(val)) => format!("{}u", val),
+            Expr::Constant(Constant::Long(val)) =>
+                {
+                    match *val
+                    {
+                        i64::MIN => "(-9223372036854775807LL - 1)".to_string(),
+                        val if val < 0 => format!("({}LL)", val),
+                        val => format!("{}LL", val),
+                    }
+                }
+            Expr::Constant(Constant::UnsignedLong(val)) => format!("{}ULL", val),
+            Expr::Constant(Constant::Float(val)) =>
+                {
+                    let text = format!("{:?}f", val);
+                    if *val < 0.0 { format!("({})", text) } else { text }
+                }
+            Expr::Constant(Constant::Double(val)) =>
+                {
+                    let text = format!("{:?}", val);
+                    if *val < 0.0 { format!("({})", text) } else { text }
+                }
+            Expr::Identifier(name) => self.variable(self.resolutions.expr(id), name),
+            // 符号付き整数の符号反転は C では未定義動作になりうるので, 符号なしで計算して折り返す
+            Expr::Unary { op: UnaryOperator::Minus, operand } if wrapping_type(&self.expr_type(id)).is_some() =>
+                {
+                    let value_type = self.expr_type(id);
+                    let unsigned = wrapping_type(&value_type).unwrap();
+                    format!("(({})(0 - ({}){}))", c_type(&value_type), unsigned, self.expression(*operand))
+                }
+            Expr::Unary { op, operand } =>
+                {
+                    let op = match op
+                    {
+                        UnaryOperator::Minus => "-",
+                        UnaryOperator::LogicalNot => "!",
+                    };
+                    format!("({}{})", op, self.expression(*operand))
+                }
+            Expr::Binary { op, lhs, rhs } => self.binary(*op, *lhs, *rhs),
+            Expr::Call { name, arguments } =>
+                {
+                    let ast = self.ast;
+                    let function = Self::functions(ast).into_iter()
+                        .chain(Self::prototypes(ast))
+                        .find(|function| function.name == *name);
+                    let arguments: Vec<String> = match function
+                    {
+                        Some(function) => arguments.iter()
+                            .zip(function.parameters.iter())
+                            .map(|(argument, parameter)| self.converted(*argument, &parameter.type_specifier))
+                            .collect(),
+                        None => arguments.iter().map(|argument| self.expression(*argument)).collect(),
+                    };
+                    format!("f_{}({})", name, arguments.join(", "))
+                }
+            Expr::Cast { value_type, operand } => self.converted(*operand, value_type),
+            Expr::ArrayAccess { .. } =>
+                {
+                    unimplemented!("配列は未実装です");
+                }
+        }
+    }
+
+    fn binary(&self, op: Operator, lhs: ExprId, rhs: ExprId) -> String
+    {
+        let lhs_type = self.expr_type(lhs);
+        let rhs_type = self.expr_type(rhs);
+
+        let common = ValueType::common(&lhs_type, &rhs_type);
+
+        match op
+        {
+            // 剰余は浮動小数点数が混ざると補助関数で計算する
+            Operator::Modulo if common.is_floating() =>
+                format!("nagato_rem({}, {})", self.converted(lhs, &ValueType::Double), self.converted(rhs, &ValueType::Double)),
+            // 論理演算子は型を揃えずに真偽だけを見る
+            Operator::LogicalAnd | Operator::LogicalOr =>
+                format!("({} {} {})", self.expression(lhs), op.as_str(), self.expression(rhs)),
+            // 符号付き整数の除算と剰余は最小値 / -1 を扱う補助関数で計算する
+            Operator::Divide | Operator::Modulo if wrapping_type(&common).is_some() =>
+                {
+                    let name = if op == Operator::Divide { "div" } else { "rem" };
+                    let suffix = if common == ValueType::Int { "int" } else { "long" };
+                    format!("nagato_{}_{}({}, {})", name, suffix, self.converted(lhs, &common), self.converted(rhs, &common))
+                }
+            // 符号付き整数の加減乗算はインタプリタと同じく折り返すよう, 符号なしで計算して戻す
+            Operator::Plus | Operator::Minus | Operator::Multiply if wrapping_type(&common).is_some() =>
+                {
+                    let unsigned = wrapping_type(&common).unwrap();
+                    format!("(({})(({}){} {} ({}){}))", c_type(&common), unsigned, self.converted(lhs, &common),
+                            op.as_str(), unsigned, self.converted(rhs, &common))
+                }
+            // 算術演算と比較は通常の算術変換による共通の型に両辺をキャストする
+            _ => format!("({} {} {})", self.converted(lhs, &common), op.as_str(), self.converted(rhs, &common)),
+        }
+    }
+}
+
+/// 折り返して計算する符号付き整数の型に対応する符号なしの型. 整数拡張後の型だけを扱う
+fn wrapping_type(value_type: &ValueType) -> Option<&'static str>
+{
+    match value_type
+    {
+        ValueType::Int => Some("unsigned int"),
+        ValueType::Long => Some("unsigned long long"),
+        _ => None,
+    }
+}
+
+fn c_type(value_type: &ValueType) -> &'static str
+{
+    match value_type
+    {
+        ValueType::Void => "void",
+        ValueType::Bool => "_Bool",
+        // インタプリタの char は符号付き
+        ValueType::Char => "signed char",
+        ValueType::UnsignedChar => "unsigned char",
+        ValueType::Short => "short",
+        ValueType::UnsignedShort => "unsigned short",
+        ValueType::Int => "int",
+        ValueType::UnsignedInt => "unsigned int",
+        // long は 64 bit なので long long にする
+        ValueType::Long => "long long",
+        ValueType::UnsignedLong => "unsigned long long",
+        ValueType::Float => "float",
+        ValueType::Double => "double",
+    }
+}
+
+fn print_format(value_type: &ValueType) -> &'static str
+{
+    match value_type
+    {
+        // 読み戻したときに同じ値になる桁数で表示する. float は double に拡張されて渡される
+        ValueType::Float | ValueType::Double => "%.17g",
+        ValueType::UnsignedInt => "%u",
+        ValueType::Long => "%lld",
+        ValueType::UnsignedLong => "%llu",
+        // int より小さい型は int に拡張されて渡される
+        _ => "%d",
+    }
+}
+
+/// ローカル変数の C での名前. 同じ名前の変数を区別するためスコープの深さとスロットを付ける
+fn local_name(name: &str, depth: usize, slot: usize) -> String
+{
+    format!("l_{}_{}_{}", name, depth, slot)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::c_backend::CGenerator;
+    use crate::interpreter::{Interpreter, Variable, VariableType};
+    use crate::lexical::Lexer;
+    use crate::parser::Parser;
+    use std::collections::HashMap;
+    use std::process::Command;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn generate(program: &str) -> String
+    {
+        let mut lexer = Lexer::new(program.to_string());
+        lexer.tokenize();
+
+        let mut parser = Parser::new(lexer.tokens().clone());
+        parser.parse();
+        CGenerator::new(parser.ast()).generate().unwrap()
+    }
+
+    /// C コンパイラが使えない環境では差分テストを省略する
+    fn cc_available() -> bool
+    {
+        Command::new("cc").arg("--version").output().is_ok_and(|output| output.status.success())
+    }
+
+    /// 生成した C をコンパイルして実行し, 表示された "名前 = 値" を返す
+    fn run_c(program: &str) -> Vec<(String, String)>
+    {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let directory = std::env::temp_dir()
+            .join(format!("nagato_c_{}_{}", std::process::id(), COUNT.fetch_add(1, Ordering::SeqCst)));
+        std::fs::create_dir_all(&directory).unwrap();
+        let source = directory.join("program.c");
+        let executable = directory.join("program");
+        std::fs::write(&source, generate(program)).unwrap();
+
+        let compile = Command::new("cc")
+            .args(["-std=c99", "-O2", "-Wall", "-Werror", "-o"])
+            .arg(&executable)
+            .arg(&source)
+            .arg("-lm")
+            .output()
+            .unwrap();
+        assert!(compile.status.success(), "{}", String::from_utf8_lossy(&compile.stderr));
+
+        let output = Command::new(&executable).output().unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(output.status.success());
+
+        String::from_utf8(output.stdout).unwrap()
+            .lines()
+            .map(|line| {
+                let (name, value) = line.split_once(" = ").unwrap();
+                (name.to_string(), value.to_string())
+            })
+            .collect()
+    }
+
+    /// インタプリタと生成した C の実行結果が一致することを確認する
+    fn assert_same(program: &str)
+    {
+        if !cc_available()
+        {
+            return;
+        }
+
+        let mut interpreter = Interpreter::load(program).unwrap();
+        let result = interpreter.call("main", &[]).unwrap();
+        let output = run_c(program);
+
+        let mut expected: HashMap<&str, Variable> = interpreter.global_variables()
+            .map(|(name, variable)| (name.as_str(), variable.clone()))
+            .collect();
+        expected.insert("result", Variable::Value(result));
+        assert_eq!(output.len(), expected.len(), "{:?}", output);
+
+        for (name, value) in output.iter()
+        {
+            match expected.get(name.as_str())
+            {
+                Some(Variable::Value(expected @ (VariableType::Float(_) | VariableType::Double(_)))) =>
+                    assert_eq!(value.parse::<f64>().unwrap(), Interpreter::to_f64(expected), "{}", name),
+                Some(Variable::Value(expected)) if *expected != VariableType::Void =>
+                    assert_eq!(value, &expected.to_string(), "{}", name),
+                other => panic!("{} の値が一致しません : {} / {:?}", name, value, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate()
+    {
+        let source = generate("
+int limit = 3;
+float scale(float x, int n) {
+    int limit = n;
+    return x * limit % 2;
+}
+int main(void) {
+    int count;
+    count = scale(limit, 2) + 0.5;
+    return count;
+}");
+        let body = source.split_once("int g_limit;").unwrap().1;
+        assert_eq!(body, "
+
+float f_scale(float l_x_0_0, int l_n_0_1);
+int f_main(void);
+
+float f_scale(float l_x_0_0, int l_n_0_1)
+{
+    int l_limit_0_2 = l_n_0_1;
+    return (float)nagato_rem((double)(l_x_0_0 * (float)l_limit_0_2), (double)2);
+}
+
+int f_main(void)
+{
+    int l_count_0_0 = 0;
+    l_count_0_0 = (int)((double)f_scale((float)g_limit, 2) + 0.5);
+    return l_count_0_0;
+}
+
+static void nagato_initialize(void)
+{
+    g_limit = 3;
+}
+
+int main(void)
+{
+    nagato_initialize();
+    int result = f_main();
+    printf(\"limit = %d\\n\", g_limit);
+    printf(\"result = %d\\n\", result);
+    return 0;
+}
+");
+    }
+
+    #[test]
+    fn test_same_result()
+    {
+        assert_same("
+int x = (10 + 20) * 3 - 4 / 2;
+float y = (x + 1) * 1.5;
+int z = ((x > 15) && (y < 50.0)) || (x == 26);
+int fib = 0;
+int fibo(int n) {
+    if (n < 2) {
+        return n;
+    }
+    return fibo(n - 1) + fibo(n - 2);
+}
+int main(void) {
+    fib = fibo(15);
+    return fib + z;
+}");
+
+        // 既定の折り返しモードと同じく, 符号付き整数の最小値 / -1 や最大値 + 1 でも停止しない
+        assert_same("
+int m = -2147483647 - 1;
+long lm = -9223372036854775807 - 1;
+int divide(int a, int b) { return a / b; }
+long modulo(long a, long b) { return a % b; }
+int main(void) {
+    int big = 2147483647;
+    long lbig = 9223372036854775807;
+    int q = m / -1;
+    int r = m % -1;
+    int over = big + 1;
+    int under = m - 1;
+    int product = big * 2;
+    int negated = -m;
+    long lq = lm / -1;
+    long lover = lbig + 1;
+    return (q == m && divide(m, -1) == m) + (r == 0) * 2 + (over == m) * 4 + (under == big) * 8
+        + (product == -2) * 16 + (negated == m) * 32 + (lq == lm && -lm == lm) * 64
+        + (modulo(lm, -1) == 0 && lover == lm) * 128;
+}");
+
+        assert_same("
+float total = 0;
+int truncate(float value) {
+    return value;
+}
+float average(int a, int b) {
+    return (a + b) / 2.0;
+}
+void accumulate(float value) {
+    total = total + value;
+}
+int main(void) {
+    int i = 0;
+    int odd = 0;
+    while (1) {
+        i = i + 1;
+        if (i > 20) {
+            break;
+        }
+        if (i % 2 == 0) {
+            continue;
+        }
+        int odd = i;
+        accumulate(odd / 3);
+    }
+    accumulate(7.5 % 2 + 7 % 2.5 + 7.5 % 2.5);
+    int compared = (1 == 1.5) + (2 < 2.5) * 2 + (2.5 > 2) * 4;
+    return truncate(average(i, -3)) * 100 + truncate(total) + odd + compared * 1000;
+}");
+
+        assert_same("
+float rate = 0.1;
+float step(float value, int n) {
+    if (n == 0) {
+        return value;
+    }
+    return step(value * (1 + rate), n - 1);
+}
+int main(void) {
+    float x = step(1, 10);
+    int shadow = 5;
+    {
+        float shadow = x * -2;
+        x = shadow;
+    }
+    return !x + (x < shadow) * 10 - -shadow;
+}");
+    }
+
+    #[test]
+    fn test_numeric_types()
+    {
+        assert_same("
+unsigned int big = 4000000000u;
+long wide = 3000000000;
+unsigned long huge = 18000000000000000000ul;
+char small = 200;
+unsigned char byte = 300;
+short half = 40000;
+_Bool flag = 5;
+double ratio = 1.0 / 3;
+float third = 1.0f / 3;
+long mix(char c, unsigned int u, long l, float f, double d) {
+    return c + u + l + f + d;
+}
+unsigned char low(int n) {
+    return n;
+}
+int main(void) {
+    int score = 0;
+    unsigned int u = 1;
+    long l = wide * 3;
+    double d = huge;
+    unsigned long back = d;
+    char c = 127;
+    unsigned short us = 0;
+    c = c + 1;
+    us = us - 1;
+    if (-1 < u) {
+        score = score + 100;
+    }
+    if (u - 2 > u) {
+        score = score + 1;
+    }
+    if (l / 7 == 1285714285 && -wide % 7 == -4) {
+        score = score + 1;
+    }
+    if (huge / 1000000000000000000ul == 18 && back == huge && d > 17000000000000000000.0) {
+        score = score + 1;
+    }
+    if (small == -56 && byte == 44 && half == -25536 && flag == 1) {
+        score = score + 1;
+    }
+    if (c == -128 && us == 65535 && (u - 2) + 0L > 0) {
+        score = score + 1;
+    }
+    if (mix(-3, 10u, 100, 0.5f, 0.25) == 107 && low(513) == 1) {
+        score = score + 1;
+    }
+    if (third + ratio > 0.66 && big / 3 == 1333333333u && big % 7 == 3) {
+        score = score + 1;
+    }
+    return score;
+}");
+
+        assert_same("
+double ratio = (double)7 / 2;
+int truncated = (int)3.99;
+unsigned char low = (unsigned char)300;
+long size = sizeof(long) + sizeof ratio;
+int main(void) {
+    int n = -7;
+    unsigned int u = (unsigned int)n;
+    float f = (float)n / 2;
+    _Bool b = (_Bool)0.5;
+    return (int)(u / 2u % 100) + (int)f + b + (char)(n * 40) + sizeof(n + 1L);
+}");
+    }
+}
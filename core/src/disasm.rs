@@ -0,0 +1,180 @@
+use crate::bytecode::{FunctionCode, Instruction, Program};
+use crate::lexical::UnaryOperator;
+use std::fmt::Write;
+
+impl Program
+{
+    /// 人が読める命令の一覧を返す. source を渡すと命令の前に対応するソースの行を表示する
+    pub fn disassemble(&self, source: Option<&str>) -> String
+    {
+        let source_lines: Vec<&str> = source.map(|source| source.lines().collect()).unwrap_or_default();
+        let mut output = String::new();
+
+        writeln!(output, "constants:").unwrap();
+        for (index, constant) in self.constants.iter().enumerate()
+        {
+            writeln!(output, "    {:>4}  {:?}", index, constant).unwrap();
+        }
+
+        writeln!(output, "globals:").unwrap();
+        for (index, global) in self.globals.iter().enumerate()
+        {
+            writeln!(output, "    {:>4}  {} {}", index, global.value_type.as_str(), global.name).unwrap();
+        }
+
+        self.disassemble_function(&mut output, &self.initializer, &source_lines);
+        for function in self.functions.iter()
+        {
+            self.disassemble_function(&mut output, function, &source_lines);
+        }
+
+        output
+    }
+
+    fn disassemble_function(&self, output: &mut String, function: &FunctionCode, source_lines: &[&str])
+    {
+        let parameters: Vec<String> = function.parameters.iter()
+            .map(|parameter| format!("{} {}", parameter.type_specifier.as_str(), parameter.name))
+            .collect();
+        writeln!(output).unwrap();
+        writeln!(output, "{} {}({}) locals={}",
+                 function.return_type.as_str(),
+                 function.name,
+                 parameters.join(", "),
+                 function.local_count).unwrap();
+
+        let mut previous_line = 0;
+        for (position, instruction) in function.code.iter().enumerate()
+        {
+            // 行が変わったところでソースの行を表示する
+            let line = function.lines.get(position).copied().unwrap_or(0);
+            if line != 0 && line != previous_line
+            {
+                match source_lines.get(line - 1)
+                {
+                    Some(text) => writeln!(output, "    ; {:>4} | {}", line, text.trim()).unwrap(),
+                    None => writeln!(output, "    ; {:>4}", line).unwrap(),
+                }
+            }
+            previous_line = line;
+
+            let (mnemonic, operands, comment) = self.describe(instruction);
+            let text = format!("{:<16} {}", mnemonic, operands);
+            match comment
+            {
+                Some(comment) => writeln!(output, "    {:04}  {:<28} ; {}", position, text.trim_end(), comment).unwrap(),
+                None => writeln!(output, "    {:04}  {}", position, text.trim_end()).unwrap(),
+            }
+        }
+    }
+
+    /// 命令名, オペランド, 参照先の説明
+    fn describe(&self, instruction: &Instruction) -> (&'static str, String, Option<String>)
+    {
+        let name = |index: &usize| self.names.get(*index).cloned();
+        let global = |slot: &usize| self.globals.get(*slot).map(|global| global.name.clone());
+
+        match instruction
+        {
+            Instruction::Constant(index) =>
+                ("CONST", index.to_string(), self.constants.get(*index).map(|constant| format!("{:?}", constant))),
+            Instruction::Pop => ("POP", String::new(), None),
+            Instruction::LoadLocal(slot) => ("LOAD_LOCAL", slot.to_string(), None),
+            Instruction::DefineLocal { slot, value_type, name: index } =>
+                ("DEFINE_LOCAL", format!("{} {}", slot, value_type.as_str()), name(index)),
+            Instruction::DeclareLocal { slot, value_type, name: index } =>
+                ("DECLARE_LOCAL", format!("{} {}", slot, value_type.as_str()), name(index)),
+            Instruction::StoreLocal { slot, name: index } => ("STORE_LOCAL", slot.to_string(), name(index)),
+            Instruction::LoadGlobal(slot) => ("LOAD_GLOBAL", slot.to_string(), global(slot)),
+            Instruction::DefineGlobal { slot, value_type } =>
+                ("DEFINE_GLOBAL", format!("{} {}", slot, value_type.as_str()), global(slot)),
+            Instruction::StoreGlobal(slot) => ("STORE_GLOBAL", slot.to_string(), global(slot)),
+            Instruction::Unary(op) =>
+                {
+                    let op = match op
+                    {
+                        UnaryOperator::Minus => "-",
+                        UnaryOperator::LogicalNot => "!",
+                    };
+                    ("UNARY", op.to_string(), None)
+                }
+            Instruction::Binary(op) => ("BINARY", op.as_str().to_string(), None),
+            Instruction::Cast(value_type) => ("CAST", value_type.as_str().to_string(), None),
+            Instruction::ShortCircuitAnd(target) => ("AND", format!("{:04}", target), None),
+            Instruction::ShortCircuitOr(target) => ("OR", format!("{:04}", target), None),
+            Instruction::Jump(target) => ("JUMP", format!("{:04}", target), None),
+            Instruction::JumpIfFalse(target) => ("JUMP_IF_FALSE", format!("{:04}", target), None),
+            Instruction::Call(index) =>
+                ("CALL", index.to_string(), self.functions.get(*index).map(|function| function.name.clone())),
+            Instruction::TailCall(index) =>
+                ("TAIL_CALL", index.to_string(), self.functions.get(*index).map(|function| function.name.clone())),
+            Instruction::CheckValue(index) => ("CHECK_VALUE", String::new(), name(index)),
+            Instruction::Return => ("RETURN", String::new(), None),
+            Instruction::ReturnVoid => ("RETURN_VOID", String::new(), None),
+            Instruction::UndefinedVariable(index) => ("UNDEFINED_VARIABLE", String::new(), name(index)),
+            Instruction::UndefinedFunction(index) => ("UNDEFINED_FUNCTION", String::new(), name(index)),
+            Instruction::ArgumentCountMismatch { name: index, expected, found } =>
+                ("ARGUMENT_COUNT", format!("{} {}", expected, found), name(index)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::bytecode::Compiler;
+    use crate::lexical::Lexer;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_disassemble()
+    {
+        let source = "int limit = 3;
+int count(int n) {
+    while (n < limit) {
+        n = n + 1;
+    }
+    return n;
+}";
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.tokenize();
+
+        let mut parser = Parser::with_lines(lexer.tokens().clone(), lexer.lines().clone());
+        parser.parse();
+
+        let program = Compiler::new(parser.ast()).compile();
+        let listing = program.disassemble(Some(source));
+
+        assert_eq!(listing, "\
+constants:
+       0  Int(3)
+       1  Int(1)
+globals:
+       0  int limit
+
+void <init>() locals=0
+    ;    1 | int limit = 3;
+    0000  CONST            0           ; Int(3)
+    0001  DEFINE_GLOBAL    0 int       ; limit
+    0002  RETURN_VOID
+
+int count(int n) locals=1
+    ;    3 | while (n < limit) {
+    0000  LOAD_LOCAL       0
+    0001  LOAD_GLOBAL      0           ; limit
+    0002  BINARY           <
+    0003  JUMP_IF_FALSE    0009
+    ;    4 | n = n + 1;
+    0004  LOAD_LOCAL       0
+    0005  CONST            1           ; Int(1)
+    0006  BINARY           +
+    0007  STORE_LOCAL      0           ; n
+    ;    3 | while (n < limit) {
+    0008  JUMP             0000
+    ;    6 | return n;
+    0009  LOAD_LOCAL       0
+    0010  RETURN
+    0011  RETURN_VOID
+");
+    }
+}
@@ -1,9 +1,10 @@
 use crate::interpreter::VariableType::Int;
-use crate::lexical::{Constant, Operator, UnaryOperator, ValueType};
-use crate::parser::{FunctionCall, FunctionDefinition, Leaf, Node};
-use std::cell::RefCell;
+use crate::ast::{Ast, Decl, Expr, ExprId, Function, Stmt, StmtId};
+use crate::lexical::{Constant, Lexer, Operator, UnaryOperator, ValueType};
+use crate::parser::{static_local_name, Parser};
+use crate::resolver::{Resolution, Resolutions, Resolver};
+use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::hash::Hash;
 use std::rc::Rc;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -16,8 +17,17 @@ pub struct Array
 #[derive(Debug, Clone, PartialEq)]
 pub enum VariableType
 {
+    Bool(bool),
+    Char(i8),
+    UnsignedChar(u8),
+    Short(i16),
+    UnsignedShort(u16),
     Int(i32),
-    Float(f64),
+    UnsignedInt(u32),
+    Long(i64),
+    UnsignedLong(u64),
+    Float(f32),
+    Double(f64),
     Void,
 }
 
@@ -28,8 +38,17 @@ impl std::fmt::Display for VariableType
     {
         match self
         {
+            VariableType::Bool(val) => write!(f, "{}", *val as i32),
+            VariableType::Char(val) => write!(f, "{}", val),
+            VariableType::UnsignedChar(val) => write!(f, "{}", val),
+            VariableType::Short(val) => write!(f, "{}", val),
+            VariableType::UnsignedShort(val) => write!(f, "{}", val),
             VariableType::Int(val) => write!(f, "{}", val),
+            VariableType::UnsignedInt(val) => write!(f, "{}", val),
+            VariableType::Long(val) => write!(f, "{}", val),
+            VariableType::UnsignedLong(val) => write!(f, "{}", val),
             VariableType::Float(val) => write!(f, "{}", val),
+            VariableType::Double(val) => write!(f, "{}", val),
             VariableType::Void => write!(f, "void"),
         }
     }
@@ -41,6 +60,174 @@ pub enum Variable {
     Array(Array),
 }
 
+/// 実行時エラー
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError
+{
+    // 関数が定義されていない
+    UndefinedFunction(String),
+
+    // 引数の数が一致しない
+    ArgumentCountMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+
+    // void の値を使用した, または void に値を渡した
+    VoidMisuse(String),
+
+    // 非 void 関数が値を返さずに終了した
+    MissingReturnValue(String),
+
+    // 変数が定義されていない
+    UndefinedVariable(String),
+
+    // 厳密モードで, 初期化子のないローカル変数を代入前に読み出した
+    UninitializedVariable(String),
+
+    // 呼び出しの深さが上限を超えた. 呼び出し元から順に並べた関数名を持つ
+    StackOverflow {
+        limit: usize,
+        chain: Vec<String>,
+    },
+
+    // トラップモードで, 符号付き整数の演算結果が型の範囲を超えた. 行番号は分かる場合のみ持つ.
+    // 位置は列まで持たない. 字句解析器とバイトコードの行番号表は行単位でしか記録しておらず,
+    // 同じ行の中のどの演算かは operation の "2147483647 + 1" のような式で区別できる
+    IntegerOverflow {
+        operation: String,
+        line: Option<usize>,
+    },
+
+    // 0 で割った
+    DivisionByZero {
+        line: Option<usize>,
+    },
+
+    // 同じスコープで変数が再宣言されている. 読み込みの時点で報告する
+    Redeclaration(String),
+
+    // 字句解析か構文解析に失敗した. 読み込みの時点で報告する
+    SyntaxError(String),
+}
+
+impl std::fmt::Display for RuntimeError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        match self
+        {
+            RuntimeError::UndefinedFunction(name) =>
+                write!(f, "関数が見つかりません : {}", name),
+            RuntimeError::ArgumentCountMismatch { name, expected, found } =>
+                write!(f, "引数の数が一致しません : {} (期待値 {}, 実際 {})", name, expected, found),
+            RuntimeError::VoidMisuse(message) =>
+                write!(f, "{}", message),
+            RuntimeError::MissingReturnValue(name) =>
+                write!(f, "関数が値を返さずに終了しました : {}", name),
+            RuntimeError::UndefinedVariable(name) =>
+                write!(f, "未定義の変数です : {}", name),
+            RuntimeError::UninitializedVariable(name) =>
+                write!(f, "初期化されていない変数を使用しています : {}", name),
+            RuntimeError::StackOverflow { limit, chain } =>
+                write!(f, "呼び出しの深さが上限 {} を超えました : {}", limit, format_call_chain(chain)),
+            RuntimeError::IntegerOverflow { operation, line } =>
+                write!(f, "整数のオーバーフローです : {}{}", operation, format_line(line)),
+            RuntimeError::DivisionByZero { line } =>
+                write!(f, "0で割ることはできません{}", format_line(line)),
+            RuntimeError::Redeclaration(name) =>
+                write!(f, "同じスコープで再宣言されています : {}", name),
+            RuntimeError::SyntaxError(message) =>
+                write!(f, "構文エラーです : {}", message),
+        }
+    }
+}
+
+/// エラーの発生した行の表示. 行番号が分からない場合は空文字列
+fn format_line(line: &Option<usize>) -> String
+{
+    match line
+    {
+        Some(line) => format!(" ({} 行目)", line),
+        None => String::new(),
+    }
+}
+
+/// 呼び出しの連鎖を表示する. 同じ関数が続く部分は回数にまとめる
+fn format_call_chain(chain: &[String]) -> String
+{
+    let mut parts: Vec<String> = Vec::new();
+    let mut index = 0;
+    while index < chain.len()
+    {
+        let count = chain[index..].iter().take_while(|name| **name == chain[index]).count();
+        if count == 1
+        {
+            parts.push(chain[index].clone());
+        } else {
+            parts.push(format!("{} x{}", chain[index], count));
+        }
+        index += count;
+    }
+    parts.join(" -> ")
+}
+
+/// ソースコードを字句解析・構文解析する. 同じスコープで再宣言された変数は参照先が決まらないのでエラーにする.
+/// 字句解析器と構文解析器は誤りを panic で報告するので, 捕まえてエラーとして返す
+pub(crate) fn parse_program(program: &str) -> Result<Parser, RuntimeError>
+{
+    let parser = std::panic::catch_unwind(|| {
+        let mut lexer = Lexer::new(program.to_string());
+        lexer.tokenize();
+
+        let mut parser = Parser::with_lines(lexer.tokens().clone(), lexer.lines().clone());
+        parser.parse();
+        parser
+    });
+    let parser = parser.map_err(|payload| {
+        let message = match payload.downcast::<String>()
+        {
+            Ok(message) => *message,
+            Err(payload) => payload.downcast_ref::<&str>().map_or_else(String::new, |message| message.to_string()),
+        };
+        RuntimeError::SyntaxError(message)
+    })?;
+
+    let mut resolver = Resolver::new(parser.ast());
+    resolver.resolve();
+    if let Some(name) = resolver.redeclarations().first()
+    {
+        return Err(RuntimeError::Redeclaration(name.clone()));
+    }
+    Ok(parser)
+}
+
+/// 呼び出しの深さの上限の既定値
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// 木構造インタプリタは C の関数呼び出し 1 段ごとに Rust のスタックを消費する
+/// (デバッグビルドで 1 段あたりおよそ 12 KiB). 残りがこれより少なくなったら新しい領域に切り替える
+const STACK_RED_ZONE: usize = 128 * 1024;
+
+/// 切り替える際に確保するスタックの大きさ
+const STACK_SEGMENT_SIZE: usize = 2 * 1024 * 1024;
+
+/// 符号付き整数の演算結果が型の範囲を超えた場合の扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArithmeticMode
+{
+    // 2 の補数で折り返す. INT_MIN / -1 は INT_MIN, INT_MIN % -1 は 0 になる
+    #[default]
+    Wrapping,
+
+    // RuntimeError::IntegerOverflow にする. 符号なし整数は C と同じく常に折り返す
+    Trapping,
+}
+
+impl std::error::Error for RuntimeError {}
+
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Scope
 {
@@ -48,76 +235,191 @@ pub enum Scope
     Local,
 }
 
+/// ローカル変数のスロット
+#[derive(Debug, Clone, PartialEq)]
+enum Local
+{
+    // 初期化子なしで宣言され, まだ値が代入されていない変数
+    Uninitialized(ValueType),
+    Value(VariableType),
+}
+
+/// 文を実行した後の制御の流れ
+#[derive(Debug, Clone, PartialEq)]
+enum Flow
+{
+    // 次の文へ進む
+    Normal,
+    Return(VariableType),
+
+    // 戻り値の型が同じ関数の呼び出しを return する. フレームを積まずに呼び出し先を実行する
+    TailCall(Rc<Function>, Vec<VariableType>),
+    Break,
+    Continue,
+}
+
 pub struct Interpreter
 {
-    roots: Vec<Rc<RefCell<Node>>>,
+    // 構文木. 関数本体を実行中でも借用が続かないよう Rc で共有する
+    ast: Rc<Ast>,
 
     // すべての領域からアクセス可能な変数
     global_variables: HashMap<String, Variable>,
 
-    // 関数の中でのみアクセス可能な変数
-    local_variables: Vec<Vec<HashMap<String, Variable>>>,
+    // 関数の中でのみアクセス可能な変数. 呼び出しごとのフレーム, スコープの深さ, スロットの順に並ぶ
+    local_variables: Vec<Vec<Vec<Local>>>,
+
+    // 識別子の参照先. 実行前に解決しておく
+    resolutions: Resolutions,
+
+    function_definition: HashMap<String, Rc<Function>>,
+
+    // 実行中の関数. 末尾呼び出しでは最後の要素を呼び出し先に置き換える
+    call_stack: Vec<Rc<Function>>,
+    max_call_depth: usize,
+
+    // 代入前のローカル変数の読み出しをエラーにするか
+    strict: bool,
+
+    // 符号付き整数のオーバーフローの扱い
+    arithmetic_mode: ArithmeticMode,
 
-    function_definition: HashMap<String, FunctionDefinition>,
-    
     scope: Scope,
+
+    // グローバル変数と関数定義の登録が済んでいるか
+    initialized: bool,
 }
 
 impl Interpreter
 {
-    pub fn new(roots: &Vec<Rc<RefCell<Node>>>) -> Self
+    pub fn new(ast: &Ast) -> Self
     {
+        let resolutions = Resolver::new(ast).resolve().clone();
+
         Interpreter
         {
-            roots: roots.clone(),
+            ast: Rc::new(ast.clone()),
             global_variables: HashMap::new(),
             local_variables: Vec::new(),
+            resolutions,
             function_definition: HashMap::new(),
+            call_stack: Vec::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            strict: false,
+            arithmetic_mode: ArithmeticMode::Wrapping,
             scope: Scope::Global,
+            initialized: false,
         }
     }
 
-    pub fn global_variables(&self) -> &HashMap<String, Variable>
+    /// ソースコードを字句解析・構文解析し, グローバル変数と関数定義を登録した状態で返す
+    pub fn load(program: &str) -> Result<Self, RuntimeError>
     {
-        &self.global_variables
+        let parser = parse_program(program)?;
+        let mut interpreter = Interpreter::new(parser.ast());
+        interpreter.initialize()?;
+        Ok(interpreter)
     }
 
-    pub fn run(&mut self) -> VariableType
+    /// トップレベルの宣言を評価してグローバル変数と関数定義を登録する.
+    /// 2回目以降の呼び出しでは何もしないので, グローバル変数の値は保持される
+    pub fn initialize(&mut self) -> Result<(), RuntimeError>
     {
-        let mut roots = self.roots.clone();
-        for root in roots.iter()
+        if self.initialized
         {
-            self.interpret_node(root);
+            return Ok(());
         }
 
-        // main 関数を呼び出し実行する
-        if let Some(main) = self.function_definition.get("main")
+        let ast = Rc::clone(&self.ast);
+        for declaration in ast.declarations().iter()
+        {
+            self.declaration(declaration, None)?;
+        }
+        self.initialized = true;
+        Ok(())
+    }
+
+    /// 任意の関数を引数付きで呼び出す. グローバル変数の状態は呼び出し間で保持される
+    pub fn call(&mut self, name: &str, arguments: &[VariableType]) -> Result<VariableType, RuntimeError>
+    {
+        self.initialize()?;
+
+        let function_definition = match self.function_definition.get(name)
         {
-            // main の function_call を作成
-            let function_call = FunctionCall::new("main".to_string());
+            Some(function_definition) => function_definition.clone(),
+            None => return Err(RuntimeError::UndefinedFunction(name.to_string())),
+        };
 
-            // main 関数を呼び出し
-            self.scope = Scope::Local;
-            let val = self.function_call(&function_call);
-            self.scope = Scope::Global;
+        if arguments.len() != function_definition.parameters.len()
+        {
+            return Err(RuntimeError::ArgumentCountMismatch {
+                name: name.to_string(),
+                expected: function_definition.parameters.len(),
+                found: arguments.len(),
+            });
+        }
 
-            return val;
-        } else {
-            panic!("main 関数が見つかりません");
+        let previous_scope = self.scope.clone();
+        self.scope = Scope::Local;
+        let val = self.invoke(function_definition, arguments.to_vec());
+        self.scope = previous_scope;
+
+        val
+    }
+
+    /// 同時に実行できる関数呼び出しの数を設定する. 末尾呼び出しは深さに数えない
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize)
+    {
+        self.max_call_depth = max_call_depth;
+    }
+
+    /// 厳密モードを設定する. 有効な場合, 初期化子のないローカル変数を代入前に読むとエラーになる.
+    /// 無効な場合は宣言された型の 0 を読み出す
+    pub fn set_strict(&mut self, strict: bool)
+    {
+        self.strict = strict;
+    }
+
+    /// 符号付き整数のオーバーフローを折り返すか, 実行時エラーにするかを設定する
+    pub fn set_arithmetic_mode(&mut self, arithmetic_mode: ArithmeticMode)
+    {
+        self.arithmetic_mode = arithmetic_mode;
+    }
+
+    /// 定義済みのグローバル変数. static なローカル変数を置き換えたものは含めない
+    pub fn global_variables(&self) -> impl Iterator<Item = (&String, &Variable)>
+    {
+        self.global_variables.iter().filter(|(name, _)| static_local_name(name).is_none())
+    }
+
+    /// 名前でグローバル変数を探す. static なローカル変数を置き換えたものは見つからない
+    pub fn global_variable(&self, name: &str) -> Option<&Variable>
+    {
+        self.global_variables.get(name).filter(|_| static_local_name(name).is_none())
+    }
+
+    pub fn run(&mut self) -> VariableType
+    {
+        // main 関数を呼び出し実行する
+        match self.call("main", &[])
+        {
+            Ok(val) => val,
+            Err(RuntimeError::UndefinedFunction(_)) => panic!("main 関数が見つかりません"),
+            Err(error) => panic!("{}", error),
         }
     }
 
     pub fn show_variables(&self)
     {
-        for (name, variable) in &self.global_variables
+        for (name, variable) in self.global_variables()
         {
             match variable
             {
                 Variable::Value(value) =>
                     {
-                        //println!("{} = {:?}", name, value);
+                        println!("{} = {}", name, value);
                     }
-                Variable::Array(array) =>
+                Variable::Array(_array) =>
                     {
                         unimplemented!("配列の表示は未実装です");
                     }
@@ -125,950 +427,915 @@ impl Interpreter
         }
     }
 
-    fn interpret_node(&mut self, node: &Rc<RefCell<Node>>) -> VariableType
+    fn declaration(&mut self, declaration: &Decl, stmt: Option<StmtId>) -> Result<(), RuntimeError>
     {
-        if let Some(val) = node.clone().borrow().val()
+        match declaration
         {
-            println!("interpret_node val: {}", val);
-            match val
-            {
-                Leaf::Declaration(variable_type) =>
-                    {
-                        // node の左側から変数名を取得
-                        if let Some(lhs) = node.borrow().lhs()
-                        {
-                            let identifier = self.identifier_name(lhs);
-
-                            // node の右側から値を取得
-                            if let Some(rhs) = node.borrow().rhs()
-                            {
-                                let value = self.statement(rhs);
-                                self.variable_definition(variable_type, identifier, value);
-                            }
-                        }
-                    }
-                Leaf::FunctionDefinition(function_definition) =>
-                    {
-                        let name = function_definition.name();
-                        self.function_definition.insert(name.clone(), function_definition.clone());
-                    }
-
-                // 関数呼び出し
-                Leaf::FunctionCall(function_call) =>
-                    {
-                        self.function_call(function_call);
-                    }
-
-                // return 文
-                Leaf::Return =>
-                    {
-                        if let Some(lhs) = node.borrow().lhs()
-                        {
-                            let value = self.statement(lhs);
-                            //println!("return {:?}", value);
-
-                            return value;
-                        }
-                    }
-                Leaf::Assignment =>
+            Decl::Variable { type_specifier, name, initializer } =>
+                {
+                    let value = match initializer
                     {
-                        return self.variable_assignment(node);
-                    }
-                Leaf::IfStatement(_) =>
+                        Some(initializer) => Some(self.expression(*initializer)?),
+                        None => None,
+                    };
+                    let resolution = match stmt
                     {
-                        return self.selection_statement(node);
-                    }
-                _ => {
-                    panic!("未対応のノードです : {:?}", val);
+                        Some(stmt) => self.resolutions.stmt(stmt),
+                        None => Resolution::Global,
+                    };
+                    self.variable_definition(resolution, type_specifier, name.clone(), value)?;
                 }
-            }
+            Decl::Function(function) =>
+                {
+                    self.function_definition.insert(function.name.clone(), Rc::clone(function));
+                }
+            // 定義のない関数を呼び出すと実行時に UndefinedFunction になる
+            Decl::Prototype(_) => {}
         }
-        VariableType::Void
+        Ok(())
     }
 
-    fn selection_statement(&mut self, node: &Rc<RefCell<Node>>) -> VariableType
+    fn statement(&mut self, stmt: StmtId) -> Result<Flow, RuntimeError>
     {
-        // if 文の条件式を取得
-        if let Some(Leaf::IfStatement(expression)) = node.borrow().val()
+        let ast = Rc::clone(&self.ast);
+        match ast.stmt(stmt)
         {
-            let condition = self.statement(expression);
-
-            // condition != 0 の場合は if 文の中身を実行
-            let mut condition_value: i32 = 0;
-            match condition
-            {
-                VariableType::Int(val) => condition_value = val,
-                VariableType::Float(val) => condition_value = val as i32,
-                _ => {
-                    panic!("if 文の条件式が対応していません");
+            Stmt::Declaration(declaration) =>
+                {
+                    self.declaration(declaration, Some(stmt))?;
                 }
-            }
-
-            if condition_value != 0
-            {
-                if let Some(lhs) = node.borrow().lhs()
+            Stmt::Assignment { name, value } =>
                 {
-                    if let Some(Leaf::BlockItem(nodes)) = lhs.borrow().val()
-                    {
-                        return self.compound_statement(nodes, true);
-                    }
+                    let value = self.expression(*value)?;
+                    self.variable_assignment(self.resolutions.stmt(stmt), name, value)?;
                 }
-            } else {
-                if let Some(rhs) = node.borrow().rhs()
+            Stmt::Expression(expr) =>
                 {
-                    // else のときと, else if のとき
-                    if let Some(Leaf::IfStatement(_)) = rhs.borrow().val()
+                    if let Expr::Call { name, arguments } = ast.expr(*expr)
                     {
-                        return self.selection_statement(rhs);
+                        // 文としての関数呼び出しは void でもよい
+                        self.function_call(name, arguments)?;
                     } else {
-                        if let Some(Leaf::BlockItem(nodes)) = rhs.borrow().val()
-                        {
-                            return self.compound_statement(nodes, true);
-                        }
+                        self.expression(*expr)?;
                     }
                 }
-            }
-        } else {
-            panic!("if 文の条件式が取得できません");
-        }
-
-        VariableType::Void
-    }
-
-    fn variable_assignment(&mut self, node: &Rc<RefCell<Node>>) -> VariableType
-    {
-        // 左辺に識別子があり, 変数として登録されていることを確認する
-        if let Some(lhs) = node.borrow().lhs()
-        {
-            let identifier = self.identifier_name(lhs);
-
-            if let Some(rhs) = node.borrow().rhs()
-            {
-                let value = self.statement(rhs);
-                // ローカル変数から検索
-                if let Some(local_variables) = self.local_variables.last_mut()
+            Stmt::Block(stmts) =>
+                {
+                    return self.compound_statement(stmts, true);
+                }
+            Stmt::If { condition, then_branch, else_branch } =>
                 {
-                    println!("local_variables : {:?} = {:?}", local_variables, value);
-                    // 最後のスコープから検索
-                    for local_variable in local_variables.iter_mut().rev()
+                    return self.selection_statement(*condition, *then_branch, *else_branch);
+                }
+            Stmt::While { condition, body } =>
+                {
+                    return self.iteration_statement(*condition, *body);
+                }
+            Stmt::Return(value) =>
+                {
+                    if let Some(value) = value
                     {
-                        if let Some(variable) = local_variable.get_mut(&identifier)
+                        if let Some(flow) = self.tail_call(*value)?
                         {
-                            if let Variable::Value(variable) = variable
-                            {
-                                *variable = value;
-                                return VariableType::Void;
-                            }
+                            return Ok(flow);
                         }
                     }
-                    println!("global_variables : {:?}", self.global_variables);
-                    // グローバル変数から検索
-                    if let Some(variable) = self.global_variables.get_mut(&identifier)
+
+                    let value = match value
                     {
-                        if let Variable::Value(variable) = variable
-                        {
-                            *variable = value;
-                        }
-                        else {
-                            panic!("Global 変数が見つかりません : {}", identifier);
-                        }
-                    }
-                    else {
-                        panic!("Global 変数が見つかりません : {}", identifier);
-                    }
+                        Some(value) => self.expression(*value)?,
+                        None => VariableType::Void,
+                    };
+                    return Ok(Flow::Return(value));
                 }
-            }
-            else { 
-                panic!("右辺に識別子がありません");
-            }
-        }
-        else { 
-            panic!("左辺に識別子がありません");
-        }
-
-        VariableType::Void
-    }
-
-    fn variable_definition(&mut self, value_type: &ValueType, identifier: String, value: VariableType)
-    {
-        match value_type
-        {
-            ValueType::Int =>
+            Stmt::Break =>
                 {
-                    if let VariableType::Float(val) = value
-                    {
-                        self.insert_variable(identifier, VariableType::Int(val as i32));
-                    } else {
-                        self.insert_variable(identifier, value);
-                    }
+                    return Ok(Flow::Break);
                 }
-            ValueType::Float =>
+            Stmt::Continue =>
                 {
-                    if let VariableType::Int(val) = value
-                    {
-                        self.insert_variable(identifier, VariableType::Float(val as f64));
-                    } else {
-                        self.insert_variable(identifier, value);
-                    }
+                    return Ok(Flow::Continue);
                 }
-            _ => {
-                panic!("未対応の型です : {:?}", value_type);
-            }
         }
+        Ok(Flow::Normal)
     }
 
-    fn insert_variable(&mut self, identifier: String, value: VariableType)
+    fn selection_statement(&mut self, condition: ExprId, then_branch: StmtId, else_branch: Option<StmtId>)
+        -> Result<Flow, RuntimeError>
     {
-        match self.scope
+        // condition != 0 の場合は if 文の中身を実行
+        if self.condition(condition)?
         {
-            Scope::Global =>
-                {
-                    self.global_variables.insert(identifier, Variable::Value(value));
-                }
-            Scope::Local =>
-                {
-                    if let Some(local_variables) = self.local_variables.last_mut()
-                    {
-                        local_variables.last_mut().unwrap().insert(identifier, Variable::Value(value));
-                    }
-                }
+            self.statement(then_branch)
+        } else if let Some(else_branch) = else_branch {
+            // else のときと, else if のとき
+            self.statement(else_branch)
+        } else {
+            Ok(Flow::Normal)
         }
     }
 
-    fn statement(&mut self, node: &Rc<RefCell<Node>>) -> VariableType
+    fn iteration_statement(&mut self, condition: ExprId, body: StmtId) -> Result<Flow, RuntimeError>
     {
-        if let Some(val) = node.borrow().val()
+        while self.condition(condition)?
         {
-            match val
+            match self.statement(body)?
             {
-                // 演算子
-                Leaf::Operator(op) =>
-                    {
-                        if let Some((lhs, rhs))
-                            = node.borrow().get_lhs_and_rhs()
-                        {
-                            return self.operator(op, lhs, rhs);
-                        }
-                    }
-
-                // 定数
-                Leaf::Constant(value) =>
-                    {
-                        return self.constant(value);
-                    }
-
-                // 識別子
-                Leaf::Identifier(identifier) =>
-                    {
-                        return self.identifier(identifier);
-                    }
-
-                // 単項演算子
-                Leaf::UnaryExpression(op) =>
-                    {
-                        if let Some(lhs) = node.borrow().lhs()
-                        {
-                            return self.unary_expression(op, lhs);
-                        }
-                    }
-
-                // 括弧で囲まれた式
-                Leaf::ParenthesizedExpression =>
-                    {
-                        if let Some(lhs) = node.borrow().lhs()
-                        {
-                            return self.statement(lhs);
-                        }
-                    }
-                Leaf::FunctionCall(function_call) =>
-                    {
-                        let value = self.function_call(function_call);
-
-                        // statement で void の場合はエラー
-                        if let VariableType::Void = value
-                        {
-                            panic!("void は代入できません");
-                        }
-
-                        return value;
-                    }
-                _ => {
-                    panic!("未対応のノードです : {:?}", val);
-                }
+                Flow::Break => break,
+                Flow::Normal | Flow::Continue => {}
+                flow => return Ok(flow),
             }
         }
-
-        panic!("未対応のノードです");
+        Ok(Flow::Normal)
     }
 
-    fn function_call(&mut self, function_call: &FunctionCall) -> VariableType
+    /// 条件式を評価し, 0 以外なら true を返す
+    fn condition(&mut self, condition: ExprId) -> Result<bool, RuntimeError>
     {
-        let name = function_call.name();
-        let function_definitions = self.function_definition.clone();
-        //println!("function_call : {}", name);
+        let value = self.expression(condition)?;
+        Self::truthy(value)
+    }
 
-        if let Some(function_definition) = function_definitions.get(name)
+    /// 条件式の値を真偽値にする. void は条件式に使用できない
+    pub(crate) fn truthy(value: VariableType) -> Result<bool, RuntimeError>
+    {
+        match value
         {
-
-            
-            let mut new_variables: HashMap<String, Variable> = HashMap::new();
-
-            // 引数がある場合計算する
-            let function_arguments = function_call.arguments();
-
-            // 引数がある場合は引数を計算してローカル変数に追加
-            if !function_arguments.is_empty()
-            {
-                //println!("function_call 引数の数 : {}", function_arguments.len());
-                // 引数の数と function-definition の引数リストの数が一致することを確認する
-                if function_arguments.len() != function_definition.arguments().len()
-                {
-                    panic!("引数の数が一致しません");
-                }
-
-                for (i, argument) in function_arguments.iter().enumerate()
-                {
-                    let argument_value = self.statement(argument);
-                    let argument_name = function_definition.arguments()[i].identify().clone();
-                    //println!("argument_name : {}", argument_name);
-                    // 引数をローカル変数に追加
-                    if let Some(local_variables) = self.local_variables.last_mut()
-                    {
-                        new_variables.insert(argument_name, Variable::Value(argument_value));
-                    }
-                }
-            }
-
-            // 新しくローカル変数を追加
-            self.local_variables.push(Vec::new());
-            self.local_variables.last_mut().unwrap().push(new_variables);
-
-            let return_value = self.compound_statement(function_definition.body(),
-                                                           false);
-
-            // ローカル変数を削除
-            self.local_variables.pop();
-
-            return_value
-        } else {
-            panic!("関数が見つかりません : {}", name);
+            VariableType::Void => Err(RuntimeError::VoidMisuse("void は条件式に使用できません".to_string())),
+            value => Ok(!Self::is_zero(&value)),
         }
     }
 
-    fn compound_statement(&mut self, nodes: &Vec<Rc<RefCell<Node>>>,
-                          is_generate_local_variables: bool) -> VariableType
+    fn variable_assignment(&mut self, resolution: Resolution, identifier: &String, value: VariableType)
+        -> Result<(), RuntimeError>
     {
-        if is_generate_local_variables
+        if let Some(local) = self.local(resolution)
         {
-            if let Some(local_variables) = self.local_variables.last_mut()
+            let value_type = match local
             {
-                local_variables.push(HashMap::new());
-            }
+                Local::Uninitialized(value_type) => value_type.clone(),
+                Local::Value(variable) => Self::value_type(variable),
+            };
+            *local = Local::Value(Self::convert(&value_type, value, identifier)?);
+            return Ok(());
         }
 
-        let mut return_value = VariableType::Void;
-        for statement in nodes.iter()
+        // グローバル変数から検索
+        if let Some(Variable::Value(variable)) = self.global_variables.get_mut(identifier)
         {
-            return_value = self.interpret_node(statement);
+            *variable = Self::convert(&Self::value_type(variable), value, identifier)?;
+            return Ok(());
         }
 
-        if is_generate_local_variables
+        Err(RuntimeError::UndefinedVariable(identifier.clone()))
+    }
+
+    fn variable_definition(&mut self, resolution: Resolution, value_type: &ValueType, identifier: String,
+                           value: Option<VariableType>) -> Result<(), RuntimeError>
+    {
+        if *value_type == ValueType::Void
         {
-            if let Some(local_variables) = self.local_variables.last_mut()
-            {
-                local_variables.pop();
-            }
+            return Err(RuntimeError::VoidMisuse(format!("変数を void 型で宣言することはできません : {}", identifier)));
         }
 
-        return_value
-    }
+        let value = match value
+        {
+            Some(value) => Some(Self::convert(value_type, value, &identifier)?),
+            None => None,
+        };
 
-    fn unary_expression(&mut self, op: &UnaryOperator, lhs: &Rc<RefCell<Node>>) -> VariableType
-    {
-        let lhs = self.statement(lhs);
-        match op
+        match (&self.scope, resolution)
         {
-            UnaryOperator::Minus =>
+            (Scope::Local, Resolution::Local { depth, slot }) =>
                 {
-                    match lhs
+                    let local = match value
+                    {
+                        Some(value) => Local::Value(value),
+                        None => Local::Uninitialized(value_type.clone()),
+                    };
+                    let frame = self.local_variables.last_mut().expect("ローカル変数のフレームがありません");
+                    let slots = &mut frame[depth];
+                    if slots.len() <= slot
+                    {
+                        slots.resize(slot + 1, Local::Uninitialized(value_type.clone()));
+                    }
+                    slots[slot] = local;
+                }
+            _ =>
+                {
+                    match value
                     {
-                        VariableType::Int(val) =>
+                        Some(value) =>
                             {
-                                Int(-val)
+                                self.global_variables.insert(identifier, Variable::Value(value));
                             }
-                        VariableType::Float(val) =>
+                        None =>
                             {
-                                VariableType::Float(-val)
+                                // C と同じく 0 で初期化する. すでに定義されている場合は値を保持する
+                                let zero = Self::convert(value_type, VariableType::Int(0), &identifier)?;
+                                self.global_variables.entry(identifier).or_insert(Variable::Value(zero));
                             }
-                        _ => {
-                            panic!("未対応の型です");
-                        }
                     }
                 }
-            UnaryOperator::LogicalNot =>
-                {
-                    match lhs
-                    {
-                        VariableType::Int(val) =>
-                            {
-                                Int(if val == 0 { 1 } else { 0 })
-                            }
-                        VariableType::Float(val) =>
-                            {
-                                Int(if val == 0.0 { 1 } else { 0 })
-                            }
-                        _ => {
-                            panic!("未対応の型です");
-                        }
-                    }
-                }
-            _ => {
-                panic!("未対応の演算子です : {:?}", op);
-            }
         }
+        Ok(())
     }
 
-    fn identifier(&mut self, identifier: &String) -> VariableType
+    /// 解決済みのローカル変数のスロット
+    fn local(&mut self, resolution: Resolution) -> Option<&mut Local>
     {
-        // ローカル変数から検索.最後のスコープから検索する
-        if let Some(local_variables) = self.local_variables.last_mut()
+        match resolution
         {
-            for variable in local_variables.iter().rev()
-            {
-                if let Some(variable) = variable.get(identifier)
-                {
-                    match variable
-                    {
-                        Variable::Value(value) => return value.clone(),
-                        _ => {
-                            panic!("未対応の変数です : {:?}", variable);
-                        }
-                    }
-                }
-            }
+            Resolution::Local { depth, slot } => self.local_variables.last_mut()?.get_mut(depth)?.get_mut(slot),
+            Resolution::Global => None,
         }
+    }
 
-        // グローバル変数から検索
-        if let Some(Variable::Value(value)) = self.global_variables.get(identifier) {
-            return value.clone();
+    /// 格納されている値から変数の型を求める
+    pub(crate) fn value_type(value: &VariableType) -> ValueType
+    {
+        match value
+        {
+            VariableType::Bool(_) => ValueType::Bool,
+            VariableType::Char(_) => ValueType::Char,
+            VariableType::UnsignedChar(_) => ValueType::UnsignedChar,
+            VariableType::Short(_) => ValueType::Short,
+            VariableType::UnsignedShort(_) => ValueType::UnsignedShort,
+            VariableType::Int(_) => ValueType::Int,
+            VariableType::UnsignedInt(_) => ValueType::UnsignedInt,
+            VariableType::Long(_) => ValueType::Long,
+            VariableType::UnsignedLong(_) => ValueType::UnsignedLong,
+            VariableType::Float(_) => ValueType::Float,
+            VariableType::Double(_) => ValueType::Double,
+            VariableType::Void => ValueType::Void,
         }
+    }
 
-        panic!("未定義の変数です : {}", identifier);
+    /// C の変換規則に従って値を宣言された型に変換する. 整数への変換は 0 方向に切り捨てる
+    pub(crate) fn convert(value_type: &ValueType, value: VariableType, name: &str) -> Result<VariableType, RuntimeError>
+    {
+        match (value_type, value)
+        {
+            (ValueType::Void, VariableType::Void) => Ok(VariableType::Void),
+            (ValueType::Void, _) => Err(RuntimeError::VoidMisuse(
+                format!("void に値を渡すことはできません : {}", name))),
+            (_, VariableType::Void) => Err(RuntimeError::VoidMisuse(
+                format!("void は値として使用できません : {}", name))),
+            (value_type, value) => Ok(Self::cast(value_type, value)),
+        }
     }
 
-    fn constant(&mut self, value: &Constant) -> VariableType
+    /// 数値を別の型に変換する. 整数どうしは 2 の補数で切り詰め, 浮動小数点数から整数へは 0 方向に切り捨てて飽和させる.
+    /// _Bool への変換は 0 以外を 1 にする
+    pub(crate) fn cast(value_type: &ValueType, value: VariableType) -> VariableType
     {
         match value
         {
-            Constant::Integer(val) =>
-                {
-                    VariableType::Int(*val)
-                }
-            Constant::Float(val) =>
-                {
-                    VariableType::Float(*val)
-                }
-            _ => {
-                panic!("未対応の定数です : {:?}", value);
-            }
+            VariableType::Float(_) | VariableType::Double(_) => Self::from_floating(value_type, Self::to_f64(&value)),
+            value => Self::from_integer(value_type, Self::to_i128(&value)),
         }
     }
 
+    /// 整数の値を i128 で取り出す. 浮動小数点数は 0 方向に切り捨てる
+    pub(crate) fn to_i128(value: &VariableType) -> i128
+    {
+        match value
+        {
+            VariableType::Bool(val) => *val as i128,
+            VariableType::Char(val) => *val as i128,
+            VariableType::UnsignedChar(val) => *val as i128,
+            VariableType::Short(val) => *val as i128,
+            VariableType::UnsignedShort(val) => *val as i128,
+            VariableType::Int(val) => *val as i128,
+            VariableType::UnsignedInt(val) => *val as i128,
+            VariableType::Long(val) => *val as i128,
+            VariableType::UnsignedLong(val) => *val as i128,
+            VariableType::Float(val) => *val as i128,
+            VariableType::Double(val) => *val as i128,
+            VariableType::Void => panic!("未対応の型です"),
+        }
+    }
 
-    fn operator(&mut self, op: &Operator, lhs: &Rc<RefCell<Node>>, rhs: &Rc<RefCell<Node>>) -> VariableType
+    /// 値を f64 で取り出す
+    pub(crate) fn to_f64(value: &VariableType) -> f64
     {
-        let lhs = self.statement(lhs);
-        let rhs = self.statement(rhs);
-        let mut result: VariableType = Int(0);
-        match op
+        match value
         {
-            Operator::LogicalOr =>
-                {
-                    result = self.logical_or(lhs, rhs);
-                }
-            Operator::LogicalAnd =>
-                {
-                    result = self.logical_and(lhs, rhs);
-                }
-            Operator::Equal =>
-                {
-                    result = self.equal(lhs, rhs);
-                }
-            Operator::NotEqual =>
-                {
-                    result = self.not_equal(lhs, rhs);
-                }
-            Operator::LessThan =>
-                {
-                    result = self.less_than(lhs, rhs);
-                }
-            Operator::GreaterThan =>
-                {
-                    result = self.greater_than(lhs, rhs);
-                }
-            Operator::LessThanOrEqual =>
-                {
-                    result = self.less_than_or_equal(lhs, rhs);
-                }
-            Operator::GreaterThanOrEqual =>
-                {
-                    result = self.greater_than_or_equal(lhs, rhs);
-                }
-            Operator::Plus =>
-                {
-                    result = self.add(lhs, rhs);
-                }
-            Operator::Minus =>
-                {
-                    result = self.sub(lhs, rhs);
-                }
-            Operator::Multiply =>
-                {
-                    result = self.mul(lhs, rhs);
-                }
-            Operator::Divide =>
-                {
-                    result = self.div(lhs, rhs);
-                }
-            Operator::Modulo =>
-                {
-                    result = self.remainder(lhs, rhs);
-                }
-            _ => {
-                panic!("未対応の演算子です : {:?}", op);
-            }
+            VariableType::Float(val) => *val as f64,
+            VariableType::Double(val) => *val,
+            value => Self::to_i128(value) as f64,
         }
+    }
 
-        result
+    /// i128 の整数から指定した型の値を作る. 収まらない場合は下位のビットだけを残す
+    pub(crate) fn from_integer(value_type: &ValueType, value: i128) -> VariableType
+    {
+        match value_type
+        {
+            ValueType::Bool => VariableType::Bool(value != 0),
+            ValueType::Char => VariableType::Char(value as i8),
+            ValueType::UnsignedChar => VariableType::UnsignedChar(value as u8),
+            ValueType::Short => VariableType::Short(value as i16),
+            ValueType::UnsignedShort => VariableType::UnsignedShort(value as u16),
+            ValueType::Int => VariableType::Int(value as i32),
+            ValueType::UnsignedInt => VariableType::UnsignedInt(value as u32),
+            ValueType::Long => VariableType::Long(value as i64),
+            ValueType::UnsignedLong => VariableType::UnsignedLong(value as u64),
+            ValueType::Float => VariableType::Float(value as f32),
+            ValueType::Double => VariableType::Double(value as f64),
+            ValueType::Void => VariableType::Void,
+        }
     }
 
-    // 加算演算子　'+'
-    fn add(&mut self, lhs: VariableType, rhs: VariableType) -> VariableType
+    /// f64 の値から指定した型の値を作る. 整数型へは 0 方向に切り捨て, 範囲外の値は飽和させる
+    pub(crate) fn from_floating(value_type: &ValueType, value: f64) -> VariableType
     {
-        match (lhs, rhs)
+        match value_type
         {
-            (VariableType::Int(lhs), VariableType::Int(rhs)) =>
-                {
-                    Int(lhs + rhs)
-                }
-            (VariableType::Int(lhs), VariableType::Float(rhs)) =>
-                {
-                    VariableType::Float(lhs as f64 + rhs)
-                }
-            (VariableType::Float(lhs), VariableType::Int(rhs)) =>
-                {
-                    VariableType::Float(lhs + rhs as f64)
-                }
-            (VariableType::Float(lhs), VariableType::Float(rhs)) =>
-                {
-                    VariableType::Float(lhs + rhs)
-                }
-            _ => {
-                panic!("未対応の型です");
-            }
+            ValueType::Bool => VariableType::Bool(value != 0.0),
+            ValueType::Char => VariableType::Char(value as i8),
+            ValueType::UnsignedChar => VariableType::UnsignedChar(value as u8),
+            ValueType::Short => VariableType::Short(value as i16),
+            ValueType::UnsignedShort => VariableType::UnsignedShort(value as u16),
+            ValueType::Int => VariableType::Int(value as i32),
+            ValueType::UnsignedInt => VariableType::UnsignedInt(value as u32),
+            ValueType::Long => VariableType::Long(value as i64),
+            ValueType::UnsignedLong => VariableType::UnsignedLong(value as u64),
+            ValueType::Float => VariableType::Float(value as f32),
+            ValueType::Double => VariableType::Double(value),
+            ValueType::Void => VariableType::Void,
         }
     }
 
-    // 減算演算子　'-'
-    fn sub(&mut self, lhs: VariableType, rhs: VariableType) -> VariableType
+    /// 値が 0 かどうか
+    fn is_zero(value: &VariableType) -> bool
     {
-        match (lhs, rhs)
+        match value
         {
-            (VariableType::Int(lhs), VariableType::Int(rhs)) =>
-                {
-                    Int(lhs - rhs)
-                }
-            (VariableType::Int(lhs), VariableType::Float(rhs)) =>
-                {
-                    VariableType::Float(lhs as f64 - rhs)
-                }
-            (VariableType::Float(lhs), VariableType::Int(rhs)) =>
-                {
-                    VariableType::Float(lhs - rhs as f64)
-                }
-            (VariableType::Float(lhs), VariableType::Float(rhs)) =>
-                {
-                    VariableType::Float(lhs - rhs)
-                }
-            _ => {
-                panic!("未対応の型です");
-            }
+            VariableType::Float(_) | VariableType::Double(_) => Self::to_f64(value) == 0.0,
+            value => Self::to_i128(value) == 0,
         }
     }
 
-    // 乗算演算子　'*'
-    fn mul(&mut self, lhs: VariableType, rhs: VariableType) -> VariableType
+    fn expression(&mut self, expr: ExprId) -> Result<VariableType, RuntimeError>
     {
-        match (lhs, rhs)
+        let ast = Rc::clone(&self.ast);
+        match ast.expr(expr)
         {
-            (VariableType::Int(lhs), VariableType::Int(rhs)) =>
-                {
-                    Int(lhs * rhs)
-                }
-            (VariableType::Int(lhs), VariableType::Float(rhs)) =>
+            // 定数
+            Expr::Constant(value) => Ok(Self::constant(value)),
+
+            // 識別子
+            Expr::Identifier(identifier) => self.identifier(self.resolutions.expr(expr), identifier),
+
+            // 単項演算子
+            Expr::Unary { op, operand } => self.unary_expression(expr, op, *operand),
+
+            // 演算子
+            Expr::Binary { op, lhs, rhs } => self.operator(expr, op, *lhs, *rhs),
+
+            // キャスト
+            Expr::Cast { value_type, operand } =>
                 {
-                    VariableType::Float(lhs as f64 * rhs)
+                    let value = self.expression(*operand)?;
+                    Self::convert(value_type, value, "キャスト")
                 }
-            (VariableType::Float(lhs), VariableType::Int(rhs)) =>
+
+            Expr::Call { name, arguments } =>
                 {
-                    VariableType::Float(lhs * rhs as f64)
+                    let value = self.function_call(name, arguments)?;
+
+                    // 式の中で void の場合はエラー
+                    if let VariableType::Void = value
+                    {
+                        return Err(RuntimeError::VoidMisuse(
+                            format!("void 関数の結果は値として使用できません : {}", name)));
+                    }
+
+                    Ok(value)
                 }
-            (VariableType::Float(lhs), VariableType::Float(rhs)) =>
+            Expr::ArrayAccess { .. } =>
                 {
-                    VariableType::Float(lhs * rhs)
+                    unimplemented!("配列は未実装です");
                 }
-            _ => {
-                panic!("未対応の型です");
+        }
+    }
+
+    fn function_call(&mut self, name: &String, arguments: &[ExprId]) -> Result<VariableType, RuntimeError>
+    {
+        if let Some(function_definition) = self.function_definition.get(name).cloned()
+        {
+            // 引数の数と関数定義の引数リストの数が一致することを確認する
+            if arguments.len() != function_definition.parameters.len()
+            {
+                return Err(RuntimeError::ArgumentCountMismatch {
+                    name: name.clone(),
+                    expected: function_definition.parameters.len(),
+                    found: arguments.len(),
+                });
+            }
+
+            // 引数を計算する
+            let mut argument_values = Vec::new();
+            for argument in arguments.iter()
+            {
+                argument_values.push(self.expression(*argument)?);
             }
+
+            self.invoke(function_definition, argument_values)
+        } else {
+            Err(RuntimeError::UndefinedFunction(name.clone()))
         }
     }
 
-    // 除算演算子　'/'
-    fn div(&mut self, lhs: VariableType, rhs: VariableType) -> VariableType
+    /// `return f(...)` の f が定義済みで, 戻り値の型が実行中の関数と同じ非 void 型なら末尾呼び出しにする.
+    /// 戻り値の変換は呼び出し先で済むので, 呼び出し元のフレームを残す必要がない
+    fn tail_call(&mut self, value: ExprId) -> Result<Option<Flow>, RuntimeError>
     {
-        // 右辺値が0の場合はエラー
-        match rhs
+        let ast = Rc::clone(&self.ast);
+        let Expr::Call { name, arguments } = ast.expr(value) else {
+            return Ok(None);
+        };
+        let (Some(callee), Some(caller)) = (self.function_definition.get(name), self.call_stack.last()) else {
+            return Ok(None);
+        };
+        if callee.return_type == ValueType::Void
+            || callee.return_type != caller.return_type
+            || callee.parameters.len() != arguments.len()
         {
-            VariableType::Int(val) if val == 0 =>
-                {
-                    panic!("0で割ることはできません");
-                }
-            VariableType::Float(val) if val == 0.0 =>
-                {
-                    panic!("0で割ることはできません");
-                }
-            _ => {}
+            return Ok(None);
         }
 
-        match (lhs, rhs)
+        let callee = Rc::clone(callee);
+        let mut argument_values = Vec::new();
+        for argument in arguments.iter()
         {
-            (VariableType::Int(lhs), VariableType::Int(rhs)) =>
-                {
-                    Int(lhs / rhs)
-                }
-            (VariableType::Int(lhs), VariableType::Float(rhs)) =>
-                {
-                    VariableType::Float(lhs as f64 / rhs)
-                }
-            (VariableType::Float(lhs), VariableType::Int(rhs)) =>
-                {
-                    VariableType::Float(lhs / rhs as f64)
-                }
-            (VariableType::Float(lhs), VariableType::Float(rhs)) =>
-                {
-                    VariableType::Float(lhs / rhs)
-                }
-            _ => {
-                panic!("未対応の型です");
-            }
+            argument_values.push(self.expression(*argument)?);
         }
+        Ok(Some(Flow::TailCall(callee, argument_values)))
     }
 
-    // 余り演算子　'%'
-    fn remainder(&mut self, lhs: VariableType, rhs: VariableType) -> VariableType
+    /// 呼び出しの深さを確認してから関数を実行する
+    fn invoke(&mut self, function_definition: Rc<Function>, argument_values: Vec<VariableType>)
+        -> Result<VariableType, RuntimeError>
     {
-        // 右辺値が0の場合はエラー
-        match rhs
+        if self.call_stack.len() >= self.max_call_depth
         {
-            VariableType::Int(val) if val == 0 =>
-                {
-                    panic!("0で割ることはできません");
-                }
-            VariableType::Float(val) if val == 0.0 =>
-                {
-                    panic!("0で割ることはできません");
-                }
-            _ => {}
+            let mut chain: Vec<String> = self.call_stack.iter().map(|function| function.name.clone()).collect();
+            chain.push(function_definition.name.clone());
+            return Err(RuntimeError::StackOverflow { limit: self.max_call_depth, chain });
         }
 
-        match (lhs, rhs)
+        // 呼び出しの深さが実行中のスレッドのスタックの大きさに左右されないよう, 足りなくなったらスタックを継ぎ足す
+        self.call_stack.push(function_definition);
+        let val = stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT_SIZE, || self.execute_function(argument_values));
+        self.call_stack.pop();
+        val
+    }
+
+    /// 計算済みの引数をローカル変数として登録し, 実行中の関数の本体を実行する.
+    /// 末尾呼び出しの場合はフレームを入れ替えて呼び出し先を続けて実行する
+    fn execute_function(&mut self, mut argument_values: Vec<VariableType>) -> Result<VariableType, RuntimeError>
+    {
+        loop
         {
-            (VariableType::Int(lhs), VariableType::Int(rhs)) =>
-                {
-                    Int(lhs % rhs)
-                }
-            (VariableType::Int(lhs), VariableType::Float(rhs)) =>
-                {
-                    Int(lhs % rhs as i32)
-                }
-            (VariableType::Float(lhs), VariableType::Int(rhs)) =>
-                {
-                    Int((lhs % rhs as f64) as i32)
-                }
-            (VariableType::Float(lhs), VariableType::Float(rhs)) =>
-                {
-                    Int((lhs % rhs) as i32)
-                }
-            _ => {
-                panic!("未対応の型です");
+            let function_definition = Rc::clone(self.call_stack.last().unwrap());
+            let mut new_variables = Vec::with_capacity(function_definition.parameters.len());
+
+            for (parameter, argument_value) in function_definition.parameters.iter().zip(argument_values)
+            {
+                // 引数は宣言された型に変換してから先頭のスロットに追加
+                let name = format!("{} の引数 {}", function_definition.name, parameter.name);
+                let argument_value = Self::convert(&parameter.type_specifier, argument_value, &name)?;
+                new_variables.push(Local::Value(argument_value));
             }
+
+            // 新しくローカル変数のフレームを追加
+            self.local_variables.push(vec![new_variables]);
+
+            let flow = self.compound_statement(&function_definition.body, false);
+
+            // ローカル変数を削除
+            self.local_variables.pop();
+
+            let return_value = match flow?
+            {
+                Flow::TailCall(callee, arguments) =>
+                    {
+                        *self.call_stack.last_mut().unwrap() = callee;
+                        argument_values = arguments;
+                        continue;
+                    }
+                Flow::Return(value) => value,
+                _ => VariableType::Void,
+            };
+
+            return Self::return_value(&function_definition, return_value);
         }
     }
 
-    // 同値演算子　'=='
-    fn equal(&mut self, lhs: VariableType, rhs: VariableType) -> VariableType
+    /// 戻り値を関数の型に変換する
+    fn return_value(function_definition: &Function, return_value: VariableType) -> Result<VariableType, RuntimeError>
     {
-        match (lhs, rhs)
+        let name = format!("{} の戻り値", function_definition.name);
+        match (&function_definition.return_type, return_value)
         {
-            (VariableType::Int(lhs), VariableType::Int(rhs)) =>
-                {
-                    let result = lhs == rhs;
-                    Int(if result { 1 } else { 0 })
-                }
-            (VariableType::Int(lhs), VariableType::Float(rhs)) =>
-                {
-                    let result = lhs == rhs as i32;
-                    Int(if result { 1 } else { 0 })
-                }
-            (VariableType::Float(lhs), VariableType::Int(rhs)) =>
-                {
-                    let result = lhs == rhs as f64;
-                    Int(if result { 1 } else { 0 })
-                }
-            (VariableType::Float(lhs), VariableType::Float(rhs)) =>
-                {
-                    let result = lhs == rhs;
-                    Int(if result { 1 } else { 0 })
-                }
-            _ => {
-                panic!("未対応の型です");
+            (ValueType::Void, VariableType::Void) => Ok(VariableType::Void),
+            (ValueType::Void, _) => Err(RuntimeError::VoidMisuse(
+                format!("void 関数から値を返しています : {}", function_definition.name))),
+            (_, VariableType::Void) => Err(RuntimeError::MissingReturnValue(function_definition.name.clone())),
+            (return_type, value) => Self::convert(return_type, value, &name),
+        }
+    }
+
+    fn compound_statement(&mut self, stmts: &[StmtId],
+                          is_generate_local_variables: bool) -> Result<Flow, RuntimeError>
+    {
+        if is_generate_local_variables
+        {
+            if let Some(local_variables) = self.local_variables.last_mut()
+            {
+                local_variables.push(Vec::new());
             }
         }
+
+        let mut flow = Ok(Flow::Normal);
+        for stmt in stmts.iter()
+        {
+            flow = self.statement(*stmt);
+
+            // return, break, continue とエラーはブロックの残りを実行しない
+            if !matches!(flow, Ok(Flow::Normal))
+            {
+                break;
+            }
+        }
+
+        if is_generate_local_variables
+        {
+            if let Some(local_variables) = self.local_variables.last_mut()
+            {
+                local_variables.pop();
+            }
+        }
+
+        flow
     }
 
-    // 否定演算子　'!='
-    fn not_equal(&mut self, lhs: VariableType, rhs: VariableType) -> VariableType
+    fn unary_expression(&mut self, expr: ExprId, op: &UnaryOperator, operand: ExprId) -> Result<VariableType, RuntimeError>
     {
-        match (lhs, rhs)
+        let lhs = self.expression(operand)?;
+        Self::unary_operation(op, lhs, self.arithmetic_mode, self.ast.expr_line(expr))
+    }
+
+    /// 評価済みの値に単項演算子を適用する. '-' は整数拡張した型で計算する.
+    /// line はオーバーフローを報告するときの行番号
+    pub(crate) fn unary_operation(op: &UnaryOperator, lhs: VariableType, mode: ArithmeticMode, line: Option<usize>)
+        -> Result<VariableType, RuntimeError>
+    {
+        if *op == UnaryOperator::Minus && mode == ArithmeticMode::Trapping
         {
-            (VariableType::Int(lhs), VariableType::Int(rhs)) =>
-                {
-                    let result = lhs != rhs;
-                    Int(if result { 1 } else { 0 })
-                }
-            (VariableType::Int(lhs), VariableType::Float(rhs)) =>
-                {
-                    let result = lhs != rhs as i32;
-                    Int(if result { 1 } else { 0 })
-                }
-            (VariableType::Float(lhs), VariableType::Int(rhs)) =>
+            let value_type = Self::value_type(&lhs).promoted();
+            if Self::is_signed_integer(&value_type) && !Self::fits(&value_type, -Self::to_i128(&lhs))
+            {
+                return Err(RuntimeError::IntegerOverflow { operation: format!("-({})", lhs), line });
+            }
+        }
+
+        let value = match op
+        {
+            UnaryOperator::Minus =>
                 {
-                    let result = lhs != rhs as f64;
-                    Int(if result { 1 } else { 0 })
+                    match lhs
+                    {
+                        VariableType::Float(val) =>
+                            {
+                                VariableType::Float(-val)
+                            }
+                        VariableType::Double(val) =>
+                            {
+                                VariableType::Double(-val)
+                            }
+                        VariableType::Void => {
+                            panic!("未対応の型です");
+                        }
+                        value =>
+                            {
+                                let value_type = Self::value_type(&value).promoted();
+                                Self::from_integer(&value_type, Self::to_i128(&value).wrapping_neg())
+                            }
+                    }
                 }
-            (VariableType::Float(lhs), VariableType::Float(rhs)) =>
+            UnaryOperator::LogicalNot =>
                 {
-                    let result = lhs != rhs;
-                    Int(if result { 1 } else { 0 })
+                    Int(if Self::is_zero(&lhs) { 1 } else { 0 })
                 }
-            _ => {
-                panic!("未対応の型です");
-            }
+        };
+        Ok(value)
+    }
+
+    fn identifier(&mut self, resolution: Resolution, identifier: &String) -> Result<VariableType, RuntimeError>
+    {
+        let strict = self.strict;
+        if let Some(local) = self.local(resolution)
+        {
+            return match local
+            {
+                Local::Value(value) => Ok(value.clone()),
+                // 厳密モードでは値を代入する前に読むことはできない
+                Local::Uninitialized(_) if strict => Err(RuntimeError::UninitializedVariable(identifier.clone())),
+                Local::Uninitialized(value_type) => Self::convert(value_type, VariableType::Int(0), identifier),
+            };
+        }
+
+        // グローバル変数から検索
+        if let Some(Variable::Value(value)) = self.global_variables.get(identifier) {
+            return Ok(value.clone());
+        }
+
+        Err(RuntimeError::UndefinedVariable(identifier.clone()))
+    }
+
+    /// 定数の値
+    pub(crate) fn constant(value: &Constant) -> VariableType
+    {
+        match value
+        {
+            Constant::Integer(val) => VariableType::Int(*val),
+            Constant::UnsignedInt(val) => VariableType::UnsignedInt(*val),
+            Constant::Long(val) => VariableType::Long(*val),
+            Constant::UnsignedLong(val) => VariableType::UnsignedLong(*val),
+            Constant::Float(val) => VariableType::Float(*val),
+            Constant::Double(val) => VariableType::Double(*val),
         }
     }
 
-    // 小なり演算子　'<'
-    fn less_than(&mut self, lhs: VariableType, rhs: VariableType) -> VariableType
+    /// 値を定数にする. 整数拡張で現れない char, short, _Bool と void は定数で表せない
+    pub(crate) fn to_constant(value: &VariableType) -> Option<Constant>
+    {
+        match value
+        {
+            VariableType::Int(val) => Some(Constant::Integer(*val)),
+            VariableType::UnsignedInt(val) => Some(Constant::UnsignedInt(*val)),
+            VariableType::Long(val) => Some(Constant::Long(*val)),
+            VariableType::UnsignedLong(val) => Some(Constant::UnsignedLong(*val)),
+            VariableType::Float(val) => Some(Constant::Float(*val)),
+            VariableType::Double(val) => Some(Constant::Double(*val)),
+            _ => None,
+        }
+    }
+
+
+    fn operator(&mut self, expr: ExprId, op: &Operator, lhs: ExprId, rhs: ExprId) -> Result<VariableType, RuntimeError>
+    {
+        let lhs = self.expression(lhs)?;
+
+        // '&&' と '||' は左辺で結果が決まる場合は右辺を評価しない
+        match op
+        {
+            Operator::LogicalAnd if Self::truthy(lhs.clone()) == Ok(false) => return Ok(Int(0)),
+            Operator::LogicalOr if Self::truthy(lhs.clone()) == Ok(true) => return Ok(Int(1)),
+            _ => {}
+        }
+
+        let rhs = self.expression(rhs)?;
+        Self::binary_operation(op, lhs, rhs, self.arithmetic_mode, self.ast.expr_line(expr))
+    }
+
+    /// 評価済みの値に二項演算子を適用する. '&&' と '||' の短絡評価は呼び出し側で行う.
+    /// 論理演算子以外は通常の算術変換で両辺を共通の型にそろえてから計算する.
+    /// line は 0 除算とオーバーフローを報告するときの行番号
+    pub(crate) fn binary_operation(op: &Operator, lhs: VariableType, rhs: VariableType, mode: ArithmeticMode,
+                                   line: Option<usize>) -> Result<VariableType, RuntimeError>
     {
-        match (lhs, rhs)
+        match op
         {
-            (VariableType::Int(lhs), VariableType::Int(rhs)) =>
-                {
-                    let result = lhs < rhs;
-                    Int(if result { 1 } else { 0 })
-                }
-            (VariableType::Int(lhs), VariableType::Float(rhs)) =>
-                {
-                    let result = lhs < rhs as i32;
-                    Int(if result { 1 } else { 0 })
-                }
-            (VariableType::Float(lhs), VariableType::Int(rhs)) =>
+            Operator::LogicalOr =>
                 {
-                    let result = lhs < rhs as f64;
-                    Int(if result { 1 } else { 0 })
+                    return Ok(Self::logical_or(lhs, rhs));
                 }
-            (VariableType::Float(lhs), VariableType::Float(rhs)) =>
+            Operator::LogicalAnd =>
                 {
-                    let result = lhs < rhs;
-                    Int(if result { 1 } else { 0 })
+                    return Ok(Self::logical_and(lhs, rhs));
                 }
-            _ => {
-                panic!("未対応の型です");
-            }
+            _ => {}
         }
-    }
 
-    // 大なり演算子　'>'
-    fn greater_than(&mut self, lhs: VariableType, rhs: VariableType) -> VariableType
-    {
-        match (lhs, rhs)
+        if matches!(op, Operator::Divide | Operator::Modulo) && Self::is_zero(&rhs)
         {
-            (VariableType::Int(lhs), VariableType::Int(rhs)) =>
-                {
-                    let result = lhs > rhs;
-                    Int(if result { 1 } else { 0 })
-                }
-            (VariableType::Int(lhs), VariableType::Float(rhs)) =>
+            return Err(RuntimeError::DivisionByZero { line });
+        }
+        if mode == ArithmeticMode::Trapping && Self::overflows(op, &lhs, &rhs)
+        {
+            return Err(RuntimeError::IntegerOverflow { operation: format!("{} {} {}", lhs, op.as_str(), rhs), line });
+        }
+
+        let common = ValueType::common(&Self::value_type(&lhs), &Self::value_type(&rhs));
+        let lhs = Self::cast(&common, lhs);
+        let rhs = Self::cast(&common, rhs);
+
+        let value = match op
+        {
+            Operator::Equal =>
                 {
-                    let result = lhs > rhs as i32;
-                    Int(if result { 1 } else { 0 })
+                    Self::equal(lhs, rhs)
                 }
-            (VariableType::Float(lhs), VariableType::Int(rhs)) =>
+            Operator::NotEqual =>
                 {
-                    let result = lhs > rhs as f64;
-                    Int(if result { 1 } else { 0 })
+                    Self::not_equal(lhs, rhs)
                 }
-            (VariableType::Float(lhs), VariableType::Float(rhs)) =>
+            Operator::LessThan =>
                 {
-                    let result = lhs > rhs;
-                    Int(if result { 1 } else { 0 })
+                    Self::less_than(lhs, rhs)
                 }
-            _ => {
-                panic!("未対応の型です");
-            }
-        }
-    }
-
-    // 小なりイコール演算子　'<='
-    fn less_than_or_equal(&mut self, lhs: VariableType, rhs: VariableType) -> VariableType
-    {
-        match (lhs, rhs)
-        {
-            (VariableType::Int(lhs), VariableType::Int(rhs)) =>
+            Operator::GreaterThan =>
                 {
-                    let result = lhs <= rhs;
-                    Int(if result { 1 } else { 0 })
+                    Self::greater_than(lhs, rhs)
                 }
-            (VariableType::Int(lhs), VariableType::Float(rhs)) =>
+            Operator::LessThanOrEqual =>
                 {
-                    let result = lhs <= rhs as i32;
-                    Int(if result { 1 } else { 0 })
+                    Self::less_than_or_equal(lhs, rhs)
                 }
-            (VariableType::Float(lhs), VariableType::Int(rhs)) =>
+            Operator::GreaterThanOrEqual =>
                 {
-                    let result = lhs <= rhs as f64;
-                    Int(if result { 1 } else { 0 })
+                    Self::greater_than_or_equal(lhs, rhs)
                 }
-            (VariableType::Float(lhs), VariableType::Float(rhs)) =>
+            Operator::Plus =>
                 {
-                    let result = lhs <= rhs;
-                    Int(if result { 1 } else { 0 })
+                    Self::add(lhs, rhs)
                 }
-            _ => {
-                panic!("未対応の型です");
-            }
-        }
-    }
-
-    // 大なりイコール演算子　'>='
-    fn greater_than_or_equal(&mut self, lhs: VariableType, rhs: VariableType) -> VariableType
-    {
-        match (lhs, rhs)
-        {
-            (VariableType::Int(lhs), VariableType::Int(rhs)) =>
+            Operator::Minus =>
                 {
-                    let result = lhs >= rhs;
-                    Int(if result { 1 } else { 0 })
+                    Self::sub(lhs, rhs)
                 }
-            (VariableType::Int(lhs), VariableType::Float(rhs)) =>
+            Operator::Multiply =>
                 {
-                    let result = lhs >= rhs as i32;
-                    Int(if result { 1 } else { 0 })
+                    Self::mul(lhs, rhs)
                 }
-            (VariableType::Float(lhs), VariableType::Int(rhs)) =>
+            Operator::Divide =>
                 {
-                    let result = lhs >= rhs as f64;
-                    Int(if result { 1 } else { 0 })
+                    Self::div(lhs, rhs)
                 }
-            (VariableType::Float(lhs), VariableType::Float(rhs)) =>
+            Operator::Modulo =>
                 {
-                    let result = lhs >= rhs;
-                    Int(if result { 1 } else { 0 })
+                    Self::remainder(lhs, rhs)
                 }
-            _ => {
-                panic!("未対応の型です");
-            }
+            Operator::LogicalOr | Operator::LogicalAnd => unreachable!(),
+        };
+        Ok(value)
+    }
+
+    /// 共通の型が符号付き整数で, 算術演算の正確な結果が型に収まらないかどうか.
+    /// INT_MIN % -1 も商が収まらないのでオーバーフローとする. 除数は 0 でないこと
+    pub(crate) fn overflows(op: &Operator, lhs: &VariableType, rhs: &VariableType) -> bool
+    {
+        let common = ValueType::common(&Self::value_type(lhs), &Self::value_type(rhs));
+        if !Self::is_signed_integer(&common)
+        {
+            return false;
         }
+
+        let lhs = Self::to_i128(lhs);
+        let rhs = Self::to_i128(rhs);
+        let exact = match op
+        {
+            Operator::Plus => lhs + rhs,
+            Operator::Minus => lhs - rhs,
+            Operator::Multiply => lhs * rhs,
+            Operator::Divide | Operator::Modulo => lhs / rhs,
+            _ => return false,
+        };
+        !Self::fits(&common, exact)
     }
 
-    // 論理和　'||'
-    fn logical_or(&mut self, lhs: VariableType, rhs: VariableType) -> VariableType
+    pub(crate) fn is_signed_integer(value_type: &ValueType) -> bool
+    {
+        value_type.is_integer() && !value_type.is_unsigned()
+    }
+
+    /// 整数の値が型に収まるかどうか
+    pub(crate) fn fits(value_type: &ValueType, value: i128) -> bool
+    {
+        Self::to_i128(&Self::from_integer(value_type, value)) == value
+    }
+
+    /// 共通の型にそろえた両辺に算術演算を適用する. 整数は i128 で計算して共通の型に切り詰め,
+    /// 浮動小数点数は f64 で計算して共通の型に丸める
+    fn arithmetic(lhs: VariableType, rhs: VariableType,
+                  integer: fn(i128, i128) -> i128, floating: fn(f64, f64) -> f64) -> VariableType
     {
-        match (lhs, rhs)
+        let value_type = Self::value_type(&lhs);
+        if value_type.is_floating()
         {
-            (VariableType::Int(lhs), VariableType::Int(rhs)) =>
-                {
-                    let result = lhs != 0 || rhs != 0;
-                    Int(if result { 1 } else { 0 })
-                }
-            (VariableType::Int(lhs), VariableType::Float(rhs)) =>
-                {
-                    let result = lhs != 0 || rhs != 0.0;
-                    Int(if result { 1 } else { 0 })
-                }
-            (VariableType::Float(lhs), VariableType::Int(rhs)) =>
-                {
-                    let result = lhs != 0.0 || rhs != 0;
-                    Int(if result { 1 } else { 0 })
-                }
-            (VariableType::Float(lhs), VariableType::Float(rhs)) =>
-                {
-                    let result = lhs != 0.0 || rhs != 0.0;
-                    Int(if result { 1 } else { 0 })
-                }
-            _ => {
-                panic!("未対応の型です");
-            }
+            Self::from_floating(&value_type, floating(Self::to_f64(&lhs), Self::to_f64(&rhs)))
+        }
+        else
+        {
+            Self::from_integer(&value_type, integer(Self::to_i128(&lhs), Self::to_i128(&rhs)))
         }
     }
 
-    // 論理積　'&&'
-    fn logical_and(&mut self, lhs: VariableType, rhs: VariableType) -> VariableType
+    /// 共通の型にそろえた両辺を比較する. NaN を含む場合は None
+    fn compare(lhs: &VariableType, rhs: &VariableType) -> Option<Ordering>
     {
-        match (lhs, rhs)
+        if Self::value_type(lhs).is_floating()
         {
-            (VariableType::Int(lhs), VariableType::Int(rhs)) =>
-                {
-                    let result = lhs != 0 && rhs != 0;
-                    Int(if result { 1 } else { 0 })
-                }
-            (VariableType::Int(lhs), VariableType::Float(rhs)) =>
-                {
-                    let result = lhs != 0 && rhs != 0.0;
-                    Int(if result { 1 } else { 0 })
-                }
-            (VariableType::Float(lhs), VariableType::Int(rhs)) =>
-                {
-                    let result = lhs != 0.0 && rhs != 0;
-                    Int(if result { 1 } else { 0 })
-                }
-            (VariableType::Float(lhs), VariableType::Float(rhs)) =>
-                {
-                    let result = lhs != 0.0 && rhs != 0.0;
-                    Int(if result { 1 } else { 0 })
-                }
-            _ => {
-                panic!("未対応の型です");
-            }
+            Self::to_f64(lhs).partial_cmp(&Self::to_f64(rhs))
+        }
+        else
+        {
+            Some(Self::to_i128(lhs).cmp(&Self::to_i128(rhs)))
         }
     }
 
-    fn identifier_name(&self, node: &Rc<RefCell<Node>>) -> String
+    // 加算演算子　'+'
+    fn add(lhs: VariableType, rhs: VariableType) -> VariableType
+    {
+        Self::arithmetic(lhs, rhs, i128::wrapping_add, |lhs, rhs| lhs + rhs)
+    }
+
+    // 減算演算子　'-'
+    fn sub(lhs: VariableType, rhs: VariableType) -> VariableType
+    {
+        Self::arithmetic(lhs, rhs, i128::wrapping_sub, |lhs, rhs| lhs - rhs)
+    }
+
+    // 乗算演算子　'*'
+    fn mul(lhs: VariableType, rhs: VariableType) -> VariableType
+    {
+        Self::arithmetic(lhs, rhs, i128::wrapping_mul, |lhs, rhs| lhs * rhs)
+    }
+
+    // 除算演算子　'/'. 右辺が 0 でないことは呼び出し側で確認する
+    fn div(lhs: VariableType, rhs: VariableType) -> VariableType
+    {
+        Self::arithmetic(lhs, rhs, i128::wrapping_div, |lhs, rhs| lhs / rhs)
+    }
+
+    // 余り演算子　'%'. 浮動小数点数の場合は剰余を int に切り捨てる. 右辺が 0 でないことは呼び出し側で確認する
+    fn remainder(lhs: VariableType, rhs: VariableType) -> VariableType
     {
-        let mut identifier = String::new();
-        if let Some(val) = node.borrow().val()
+        if Self::value_type(&lhs).is_floating()
         {
-            match val
-            {
-                Leaf::Identifier(name) => {
-                    identifier = name.clone();
-                }
-                _ => {
-                    panic!("識別子ではありません : {:?}", val);
-                }
-            }
+            return Int((Self::to_f64(&lhs) % Self::to_f64(&rhs)) as i32);
         }
-        identifier
+        Self::arithmetic(lhs, rhs, i128::wrapping_rem, |lhs, rhs| lhs % rhs)
+    }
+
+    // 同値演算子　'=='
+    fn equal(lhs: VariableType, rhs: VariableType) -> VariableType
+    {
+        let result = Self::compare(&lhs, &rhs) == Some(Ordering::Equal);
+        Int(if result { 1 } else { 0 })
+    }
+
+    // 否定演算子　'!='
+    fn not_equal(lhs: VariableType, rhs: VariableType) -> VariableType
+    {
+        let result = Self::compare(&lhs, &rhs) != Some(Ordering::Equal);
+        Int(if result { 1 } else { 0 })
+    }
+
+    // 小なり演算子　'<'
+    fn less_than(lhs: VariableType, rhs: VariableType) -> VariableType
+    {
+        let result = Self::compare(&lhs, &rhs) == Some(Ordering::Less);
+        Int(if result { 1 } else { 0 })
+    }
+
+    // 大なり演算子　'>'
+    fn greater_than(lhs: VariableType, rhs: VariableType) -> VariableType
+    {
+        let result = Self::compare(&lhs, &rhs) == Some(Ordering::Greater);
+        Int(if result { 1 } else { 0 })
+    }
+
+    // 小なりイコール演算子　'<='
+    fn less_than_or_equal(lhs: VariableType, rhs: VariableType) -> VariableType
+    {
+        let result = matches!(Self::compare(&lhs, &rhs), Some(Ordering::Less | Ordering::Equal));
+        Int(if result { 1 } else { 0 })
+    }
+
+    // 大なりイコール演算子　'>='
+    fn greater_than_or_equal(lhs: VariableType, rhs: VariableType) -> VariableType
+    {
+        let result = matches!(Self::compare(&lhs, &rhs), Some(Ordering::Greater | Ordering::Equal));
+        Int(if result { 1 } else { 0 })
+    }
+
+    // 論理和　'||'
+    fn logical_or(lhs: VariableType, rhs: VariableType) -> VariableType
+    {
+        let result = !Self::is_zero(&lhs) || !Self::is_zero(&rhs);
+        Int(if result { 1 } else { 0 })
+    }
+
+    // 論理積　'&&'
+    fn logical_and(lhs: VariableType, rhs: VariableType) -> VariableType
+    {
+        let result = !Self::is_zero(&lhs) && !Self::is_zero(&rhs);
+        Int(if result { 1 } else { 0 })
     }
 }
 
@@ -1076,8 +1343,9 @@ impl Interpreter
 mod tests
 {
     use crate::interpreter::VariableType::{Float, Int};
-    use crate::interpreter::{Interpreter, Variable, VariableType};
+    use crate::interpreter::{ArithmeticMode, Interpreter, DEFAULT_MAX_CALL_DEPTH, RuntimeError, Variable, VariableType};
     use crate::parser::Parser;
+    use crate::vm::Vm;
     use std::collections::HashMap;
     use crate::lexical::Lexer;
 
@@ -1117,13 +1385,13 @@ mod tests
         let mut parser = Parser::new(tokens);
         parser.parse();
 
-        let mut interpreter = Interpreter::new(parser.roots());
+        let mut interpreter = Interpreter::new(parser.ast());
         let val = interpreter.run();
 
         assert_eq!(val, Int(103));
 
         // global 変数の値を確認する
-        let global_variables = interpreter.global_variables();
+        let global_variables: Vec<_> = interpreter.global_variables().collect();
         assert_eq!(global_variables.len(), 2);
         
         let mut variables = HashMap::new();
@@ -1146,4 +1414,366 @@ mod tests
         }
 
     }
+
+    #[test]
+    fn test_call_function()
+    {
+        let program = "
+        int count = 0;
+        int fibo(int n) {
+            count = count + 1;
+            if (n == 0) {
+                return 0;
+            } else if (n == 1) {
+                return 1;
+            } else {
+                return fibo(n - 1) + fibo(n - 2);
+            }
+        }
+        float scale(float x, int n) { return x * n; }
+        ";
+
+        let mut interpreter = Interpreter::load(program).unwrap();
+
+        assert_eq!(interpreter.call("fibo", &[Int(10)]), Ok(Int(55)));
+        assert_eq!(interpreter.call("scale", &[Float(1.5), Int(4)]), Ok(Float(6.0)));
+
+        // グローバル変数は呼び出し間で保持される
+        assert_eq!(interpreter.global_variable("count"), Some(&Variable::Value(Int(177))));
+        assert_eq!(interpreter.call("fibo", &[Int(1)]), Ok(Int(1)));
+        assert_eq!(interpreter.global_variable("count"), Some(&Variable::Value(Int(178))));
+    }
+
+    #[test]
+    fn test_type_conversion()
+    {
+        let program = "
+        float ratio = 0.0;
+        int half(float x) { return x / 2; }
+        float widen(int n) { return n; }
+        int store(float x) {
+            ratio = x;
+            int n = 0;
+            n = x * 3;
+            return n;
+        }
+        ";
+
+        let mut interpreter = Interpreter::load(program).unwrap();
+
+        // 引数は宣言された型へ, 戻り値は関数の型へ変換される
+        assert_eq!(interpreter.call("half", &[Int(5)]), Ok(Int(2)));
+        assert_eq!(interpreter.call("widen", &[Float(2.7)]), Ok(Float(2.0)));
+
+        // 代入でも変数の型に変換される
+        assert_eq!(interpreter.call("store", &[Int(3)]), Ok(Int(9)));
+        assert_eq!(interpreter.global_variable("ratio"), Some(&Variable::Value(Float(3.0))));
+        assert_eq!(interpreter.call("store", &[Float(1.5)]), Ok(Int(4)));
+    }
+
+    #[test]
+    fn test_void_misuse()
+    {
+        let program = "
+        void give(int n) { return n; }
+        void nothing(int n) { n = 1; }
+        int use_void(int n) {
+            int x = nothing(n);
+            return x;
+        }
+        int no_return(int n) { n = n + 1; }
+        ";
+
+        let mut interpreter = Interpreter::load(program).unwrap();
+
+        assert_eq!(interpreter.call("nothing", &[Int(1)]), Ok(VariableType::Void));
+        assert!(matches!(interpreter.call("give", &[Int(1)]), Err(RuntimeError::VoidMisuse(_))));
+        assert!(matches!(interpreter.call("use_void", &[Int(1)]), Err(RuntimeError::VoidMisuse(_))));
+        assert_eq!(interpreter.call("no_return", &[Int(1)]),
+                   Err(RuntimeError::MissingReturnValue("no_return".to_string())));
+    }
+
+    #[test]
+    fn test_control_flow()
+    {
+        let program = "
+        int sum_odd(int n) {
+            int i = 0;
+            int sum = 0;
+            while (1) {
+                i = i + 1;
+                if (i > n) {
+                    break;
+                }
+                if (i % 2 == 0) {
+                    continue;
+                }
+                sum = sum + i;
+            }
+            return sum;
+        }
+        int first_multiple(int n, int k) {
+            int i = 1;
+            while (i < 100) {
+                if (i * k >= n) {
+                    return i * k;
+                }
+                i = i + 1;
+            }
+            return 0 - 1;
+        }
+        ";
+
+        let mut interpreter = Interpreter::load(program).unwrap();
+
+        assert_eq!(interpreter.call("sum_odd", &[Int(10)]), Ok(Int(25)));
+        assert_eq!(interpreter.call("first_multiple", &[Int(20), Int(7)]), Ok(Int(21)));
+    }
+
+    #[test]
+    fn test_scopes()
+    {
+        let program = "
+        int x = 100;
+        int shadow(int n) {
+            int x = n;
+            while (n > 0) {
+                int x = 1;
+                n = n - x;
+            }
+            return x;
+        }
+        float assign_later(int n) {
+            float y;
+            y = n;
+            return y / 2;
+        }
+        int read_uninitialized() {
+            int z;
+            return z;
+        }
+        ";
+
+        let mut interpreter = Interpreter::load(program).unwrap();
+
+        assert_eq!(interpreter.call("shadow", &[Int(3)]), Ok(Int(3)));
+        assert_eq!(interpreter.call("assign_later", &[Int(3)]), Ok(VariableType::Float(1.5)));
+        assert_eq!(interpreter.call("read_uninitialized", &[]), Ok(Int(0)));
+        interpreter.set_strict(true);
+        assert_eq!(interpreter.call("read_uninitialized", &[]),
+                   Err(RuntimeError::UninitializedVariable("z".to_string())));
+        assert_eq!(interpreter.global_variable("x"), Some(&Variable::Value(Int(100))));
+    }
+
+    #[test]
+    fn test_enum()
+    {
+        let program = "
+        enum Color { RED, GREEN = 5, BLUE };
+        enum Color favorite = GREEN;
+        int brightness(enum Color c) {
+            if (c == RED) {
+                return 0;
+            }
+            return c * 10 + favorite;
+        }
+        ";
+
+        let mut interpreter = Interpreter::load(program).unwrap();
+
+        assert_eq!(interpreter.call("brightness", &[Int(0)]), Ok(Int(0)));
+        assert_eq!(interpreter.call("brightness", &[Int(6)]), Ok(Int(65)));
+        assert_eq!(interpreter.global_variable("favorite"), Some(&Variable::Value(Int(5))));
+    }
+
+    #[test]
+    fn test_block_scopes()
+    {
+        let program = "
+        int x = 100;
+        int outer_then_inner(void) {
+            int x = 1;
+            {
+                x = x + 1;
+                int x = 10;
+                x = x + 1;
+            }
+            return x;
+        }
+        int shadow_parameter(int n) {
+            {
+                int n = 5;
+                n = n * 2;
+            }
+            return n;
+        }
+        int global_until_declared(void) {
+            x = x + 1;
+            int x = 7;
+            return x;
+        }
+        ";
+
+        // 宣言より前の代入は外側の変数に, 宣言の後は内側の変数に行う
+        let mut interpreter = Interpreter::load(program).unwrap();
+        assert_eq!(interpreter.call("outer_then_inner", &[]), Ok(Int(2)));
+        assert_eq!(interpreter.call("shadow_parameter", &[Int(3)]), Ok(Int(3)));
+        assert_eq!(interpreter.call("global_until_declared", &[]), Ok(Int(7)));
+        assert_eq!(interpreter.global_variable("x"), Some(&Variable::Value(Int(101))));
+
+        // 同じスコープでの再宣言は読み込みの時点でエラーになる. 引数は本体の一番外側と同じスコープ
+        let redeclared = [
+            ("int f(void) { int x = 1; int x = 2; return x; }\n", "x"),
+            ("int f(int a) { int a = 2; return a; }\n", "a"),
+            ("int f(int n) { while (n > 0) { int y = n; int y = 0; n = y; } return n; }\n", "y"),
+        ];
+        for (program, name) in redeclared
+        {
+            let expected = RuntimeError::Redeclaration(name.to_string());
+            assert_eq!(Interpreter::load(program).err(), Some(expected.clone()), "{}", program);
+            assert_eq!(Vm::load(program).err(), Some(expected), "{}", program);
+        }
+    }
+
+    #[test]
+    fn test_integer_overflow()
+    {
+        let program = "
+        int add(int a, int b) {
+            return a + b;
+        }
+        int divide(int a, int b) { return a / b; }
+        int modulo(int a, int b) { return a % b; }
+        int negate(int n) { return -n; }
+        long widen(int n) { return n * 2L; }
+        unsigned int wrap(unsigned int n) { return n + 1; }
+        char narrow(char c) { return c + c; }
+        ";
+
+        let mut interpreter = Interpreter::load(program).unwrap();
+
+        // 既定では 2 の補数で折り返す. 0 除算はどちらのモードでもエラーになる
+        assert_eq!(interpreter.call("add", &[Int(i32::MAX), Int(1)]), Ok(Int(i32::MIN)));
+        assert_eq!(interpreter.call("divide", &[Int(i32::MIN), Int(-1)]), Ok(Int(i32::MIN)));
+        assert_eq!(interpreter.call("modulo", &[Int(i32::MIN), Int(-1)]), Ok(Int(0)));
+        assert_eq!(interpreter.call("negate", &[Int(i32::MIN)]), Ok(Int(i32::MIN)));
+        assert_eq!(interpreter.call("divide", &[Int(1), Int(0)]), Err(RuntimeError::DivisionByZero { line: Some(5) }));
+        assert_eq!(interpreter.call("modulo", &[Int(1), Int(0)]), Err(RuntimeError::DivisionByZero { line: Some(6) }));
+
+        interpreter.set_arithmetic_mode(ArithmeticMode::Trapping);
+        let overflow = |operation: &str, line| Err(RuntimeError::IntegerOverflow { operation: operation.to_string(), line: Some(line) });
+        assert_eq!(interpreter.call("add", &[Int(i32::MAX), Int(1)]), overflow("2147483647 + 1", 3));
+        assert_eq!(interpreter.call("divide", &[Int(i32::MIN), Int(-1)]), overflow("-2147483648 / -1", 5));
+        assert_eq!(interpreter.call("modulo", &[Int(i32::MIN), Int(-1)]), overflow("-2147483648 % -1", 6));
+        assert_eq!(interpreter.call("negate", &[Int(i32::MIN)]), overflow("-(-2147483648)", 7));
+        assert_eq!(interpreter.call("add", &[Int(i32::MAX), Int(1)]).unwrap_err().to_string(),
+                   "整数のオーバーフローです : 2147483647 + 1 (3 行目)");
+
+        // long に広げた計算, 符号なし整数, 狭い型への代入は折り返す
+        assert_eq!(interpreter.call("widen", &[Int(i32::MAX)]), Ok(VariableType::Long(4294967294)));
+        assert_eq!(interpreter.call("wrap", &[VariableType::UnsignedInt(u32::MAX)]), Ok(VariableType::UnsignedInt(0)));
+        assert_eq!(interpreter.call("narrow", &[Int(100)]), Ok(VariableType::Char(-56)));
+    }
+
+    #[test]
+    fn test_call_depth()
+    {
+        let program = "
+        int depth(int n) {
+            if (n == 0) {
+                return 0;
+            }
+            return depth(n - 1) + 1;
+        }
+        int count_down(int n, int sum) {
+            if (n == 0) {
+                return sum;
+            }
+            return count_down(n - 1, sum + 1);
+        }
+        int is_even(int n) {
+            if (n == 0) {
+                return 1;
+            }
+            return is_odd(n - 1);
+        }
+        int is_odd(int n) {
+            if (n == 0) {
+                return 0;
+            }
+            return is_even(n - 1);
+        }
+        int main(void) {
+            int result = depth(5);
+            return result;
+        }
+        ";
+
+        let mut interpreter = Interpreter::load(program).unwrap();
+        interpreter.set_max_call_depth(4);
+
+        let error = interpreter.call("main", &[]).unwrap_err();
+        assert_eq!(error, RuntimeError::StackOverflow {
+            limit: 4,
+            chain: vec!["main", "depth", "depth", "depth", "depth"].into_iter().map(String::from).collect(),
+        });
+        assert_eq!(error.to_string(), "呼び出しの深さが上限 4 を超えました : main -> depth x4");
+
+        // エラーの後も続けて呼び出せる
+        assert_eq!(interpreter.call("depth", &[Int(3)]), Ok(Int(3)));
+
+        // 末尾呼び出しは深さに数えない
+        assert_eq!(interpreter.call("count_down", &[Int(100000), Int(0)]), Ok(Int(100000)));
+        assert_eq!(interpreter.call("is_even", &[Int(10001)]), Ok(Int(0)));
+    }
+
+    #[test]
+    fn test_default_call_depth()
+    {
+        // 既定の上限は VM と同じで, テストスレッドの小さなスタックでも上限まで呼び出せる
+        let program = "int f(int n) { if (n > 0) { return 1 + f(n - 1); } return 0; }\n";
+        let mut interpreter = Interpreter::load(program).unwrap();
+        let mut vm = Vm::load(program).unwrap();
+        let depth = DEFAULT_MAX_CALL_DEPTH as i32;
+
+        assert_eq!(interpreter.call("f", &[Int(depth - 1)]), Ok(Int(depth - 1)));
+        assert_eq!(vm.call("f", &[Int(depth - 1)]), Ok(Int(depth - 1)));
+        for n in [depth, depth + 5]
+        {
+            assert!(matches!(interpreter.call("f", &[Int(n)]),
+                             Err(RuntimeError::StackOverflow { limit: DEFAULT_MAX_CALL_DEPTH, .. })));
+            assert!(matches!(vm.call("f", &[Int(n)]),
+                             Err(RuntimeError::StackOverflow { limit: DEFAULT_MAX_CALL_DEPTH, .. })));
+        }
+    }
+
+    #[test]
+    fn test_load_error()
+    {
+        // 読み込みに失敗するプログラムは panic せずにエラーを返す
+        let expected = RuntimeError::VoidMisuse("変数を void 型で宣言することはできません : x".to_string());
+        assert_eq!(Interpreter::load("void x;\n").err(), Some(expected.clone()));
+        assert_eq!(Vm::load("void x;\n").err(), Some(expected));
+
+        for program in ["int f(void) { return 1 +; }\n", "int x = 1 $ 2;\n", "int x = 0x10;\n"]
+        {
+            assert!(matches!(Interpreter::load(program), Err(RuntimeError::SyntaxError(_))), "{}", program);
+            assert!(matches!(Vm::load(program), Err(RuntimeError::SyntaxError(_))), "{}", program);
+        }
+    }
+
+    #[test]
+    fn test_call_error()
+    {
+        let mut interpreter = Interpreter::load("int add(int a, int b) { return a + b; }").unwrap();
+
+        assert_eq!(interpreter.call("main", &[]),
+                   Err(RuntimeError::UndefinedFunction("main".to_string())));
+        assert_eq!(interpreter.call("add", &[Int(1)]),
+                   Err(RuntimeError::ArgumentCountMismatch {
+                       name: "add".to_string(),
+                       expected: 2,
+                       found: 1,
+                   }));
+    }
 }
\ No newline at end of file
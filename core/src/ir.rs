@@ -0,0 +1,1026 @@
+use crate::ast::{Ast, Decl, Expr, ExprId, Function, NodeId, SideTable, Stmt, StmtId};
+use crate::interpreter::{Interpreter, VariableType};
+use crate::lexical::{Constant, Operator, UnaryOperator, ValueType};
+use crate::resolver::{Resolution, Resolutions, Resolver};
+use crate::semantic::{Binding, Diagnostic, SemanticAnalyzer};
+use petgraph::dot::Dot;
+use petgraph::graph::{Graph, NodeIndex};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+
+/// 基本ブロックの番号. 関数ごとに 0 から振り, 0 が入口になる
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockId(pub usize);
+
+/// 一時変数. 関数の中で一度だけ代入される
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Temp(pub usize);
+
+/// 名前の付いた変数. 読み書きは load と store で行い, 一時変数だけが SSA の形になる
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Variable
+{
+    // 名前解決で割り当てた (深さ, スロット) を持つローカル変数
+    Local { name: String, depth: usize, slot: usize },
+
+    Global(String),
+}
+
+/// 命令の引数
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand
+{
+    Temp(Temp),
+    Constant(Constant),
+}
+
+/// 三番地コードの命令
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction
+{
+    // 初期化子のないローカル変数の宣言. 値は代入されるまで未定義
+    Declare(Variable),
+
+    Load { dest: Temp, variable: Variable },
+    Store { variable: Variable, value: Operand },
+    Unary { dest: Temp, op: UnaryOperator, operand: Operand },
+
+    // 比較と算術演算. 引数は変換命令で型を揃えてある. float の剰余の結果は int になる
+    Binary { dest: Temp, op: Operator, lhs: Operand, rhs: Operand },
+
+    // 数値の型の変換. 浮動小数点数から整数へは 0 方向に切り捨てる
+    Convert { dest: Temp, to: ValueType, operand: Operand },
+
+    // void 関数の呼び出しは結果を持たない
+    Call { dest: Option<Temp>, name: String, arguments: Vec<Operand> },
+
+    // '&&' と '||' の合流点で, 直前に通ったブロックに応じて値を選ぶ
+    Phi { dest: Temp, incoming: Vec<(BlockId, Operand)> },
+}
+
+/// 基本ブロックの最後の命令
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator
+{
+    Jump(BlockId),
+
+    // 条件が 0 以外なら then_block に進む
+    Branch { condition: Operand, then_block: BlockId, else_block: BlockId },
+
+    Return(Option<Operand>),
+
+    // 非 void 関数が値を返さずに終わる場所. インタプリタでは実行時エラーになる
+    Unreachable,
+}
+
+impl Terminator
+{
+    pub fn successors(&self) -> Vec<BlockId>
+    {
+        match self
+        {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch { then_block, else_block, .. } => vec![*then_block, *else_block],
+            Terminator::Return(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock
+{
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+/// 基本ブロックに分けた関数. グローバル変数の初期化子は名前が INITIALIZER の関数にまとめる
+#[derive(Debug, Clone, PartialEq)]
+pub struct IrFunction
+{
+    pub name: String,
+    pub return_type: ValueType,
+    pub parameters: Vec<(Variable, ValueType)>,
+    pub blocks: Vec<BasicBlock>,
+
+    // 一時変数の型. 一時変数の番号で引く
+    pub temp_types: Vec<ValueType>,
+}
+
+impl IrFunction
+{
+    /// グローバル変数の初期化子をまとめた関数の名前. 識別子には使えない文字で始める
+    pub const INITIALIZER: &'static str = "@initialize";
+
+    pub fn block(&self, id: BlockId) -> &BasicBlock
+    {
+        &self.blocks[id.0]
+    }
+
+    /// ブロックごとの直前のブロック
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>>
+    {
+        let mut predecessors = vec![Vec::new(); self.blocks.len()];
+        for (index, block) in self.blocks.iter().enumerate()
+        {
+            for successor in block.terminator.successors()
+            {
+                predecessors[successor.0].push(BlockId(index));
+            }
+        }
+        predecessors
+    }
+
+    /// 入口から辿り着けるブロック. return や break の後の文は辿り着けないブロックに置かれる
+    pub fn reachable(&self) -> Vec<bool>
+    {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut stack = vec![BlockId(0)];
+        while let Some(id) = stack.pop()
+        {
+            if reachable[id.0]
+            {
+                continue;
+            }
+            reachable[id.0] = true;
+            stack.extend(self.block(id).terminator.successors());
+        }
+        reachable
+    }
+
+    /// 制御フローグラフ. ノードはブロック, 辺の重みは分岐の向き (true / false) で, 無条件の移動は空文字列
+    pub fn cfg(&self) -> Graph<BlockId, &'static str>
+    {
+        let mut graph = Graph::new();
+        let nodes: Vec<NodeIndex> = (0..self.blocks.len()).map(|index| graph.add_node(BlockId(index))).collect();
+        for (index, block) in self.blocks.iter().enumerate()
+        {
+            match &block.terminator
+            {
+                Terminator::Jump(target) =>
+                    {
+                        graph.add_edge(nodes[index], nodes[target.0], "");
+                    }
+                Terminator::Branch { then_block, else_block, .. } =>
+                    {
+                        graph.add_edge(nodes[index], nodes[then_block.0], "true");
+                        graph.add_edge(nodes[index], nodes[else_block.0], "false");
+                    }
+                Terminator::Return(_) | Terminator::Unreachable => {}
+            }
+        }
+        graph
+    }
+}
+
+/// 構文木を変換した結果
+#[derive(Debug, Clone, PartialEq)]
+pub struct IrProgram
+{
+    // グローバル変数の型. 同じ名前で複数回宣言された場合は最後の宣言の型
+    pub globals: Vec<(String, ValueType)>,
+
+    // 先頭はグローバル変数の初期化子. 続いて同じ名前の最後の定義だけを宣言順に並べる
+    pub functions: Vec<IrFunction>,
+}
+
+impl IrProgram
+{
+    /// すべての関数の制御フローグラフを 1 つの DOT にまとめる. ノードのラベルはブロックの命令の一覧
+    pub fn to_dot(&self) -> String
+    {
+        let mut graph = Graph::<String, &'static str>::new();
+        for function in self.functions.iter()
+        {
+            let cfg = function.cfg();
+            let nodes: Vec<NodeIndex> = cfg.node_indices()
+                .map(|node| graph.add_node(format!("{} {}:\n{}", function.name, cfg[node], format_block(function.block(cfg[node])))))
+                .collect();
+            for edge in cfg.raw_edges()
+            {
+                graph.add_edge(nodes[edge.source().index()], nodes[edge.target().index()], edge.weight);
+            }
+        }
+
+        let dot = Dot::with_attr_getters(&graph, &[], &|_, _| String::new(), &|_, _| "shape = box".to_string());
+        format!("{}", dot)
+    }
+
+    pub fn output_dot(&self, file_name: &str)
+    {
+        let mut file = File::create(file_name).unwrap();
+        file.write_all(self.to_dot().as_bytes()).unwrap();
+    }
+}
+
+/// ブロックの命令を 1 行ずつ並べる
+fn format_block(block: &BasicBlock) -> String
+{
+    let mut text = String::new();
+    for instruction in block.instructions.iter()
+    {
+        text.push_str(&format!("{}\n", instruction));
+    }
+    text.push_str(&format!("{}\n", block.terminator));
+    text
+}
+
+impl fmt::Display for BlockId
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl fmt::Display for Temp
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "t{}", self.0)
+    }
+}
+
+impl fmt::Display for Variable
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            Variable::Local { name, depth, slot } => write!(f, "{}.{}.{}", name, depth, slot),
+            Variable::Global(name) => write!(f, "@{}", name),
+        }
+    }
+}
+
+impl fmt::Display for Operand
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            Operand::Temp(temp) => write!(f, "{}", temp),
+            Operand::Constant(constant) => write!(f, "{}", constant),
+        }
+    }
+}
+
+fn join(operands: &[Operand]) -> String
+{
+    operands.iter().map(|operand| operand.to_string()).collect::<Vec<String>>().join(", ")
+}
+
+impl fmt::Display for Instruction
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            Instruction::Declare(variable) => write!(f, "declare {}", variable),
+            Instruction::Load { dest, variable } => write!(f, "{} = load {}", dest, variable),
+            Instruction::Store { variable, value } => write!(f, "store {}, {}", variable, value),
+            Instruction::Unary { dest, op, operand } =>
+                {
+                    let op = match op
+                    {
+                        UnaryOperator::Minus => "-",
+                        UnaryOperator::LogicalNot => "!",
+                    };
+                    write!(f, "{} = {}{}", dest, op, operand)
+                }
+            Instruction::Binary { dest, op, lhs, rhs } => write!(f, "{} = {} {} {}", dest, lhs, op.as_str(), rhs),
+            Instruction::Convert { dest, to, operand } => write!(f, "{} = ({}) {}", dest, to.as_str(), operand),
+            Instruction::Call { dest: Some(dest), name, arguments } => write!(f, "{} = call {}({})", dest, name, join(arguments)),
+            Instruction::Call { dest: None, name, arguments } => write!(f, "call {}({})", name, join(arguments)),
+            Instruction::Phi { dest, incoming } =>
+                {
+                    let incoming: Vec<String> = incoming.iter()
+                        .map(|(block, operand)| format!("[{}: {}]", block, operand))
+                        .collect();
+                    write!(f, "{} = phi {}", dest, incoming.join(", "))
+                }
+        }
+    }
+}
+
+impl fmt::Display for Terminator
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            Terminator::Jump(target) => write!(f, "jump {}", target),
+            Terminator::Branch { condition, then_block, else_block } =>
+                write!(f, "branch {}, {}, {}", condition, then_block, else_block),
+            Terminator::Return(Some(value)) => write!(f, "return {}", value),
+            Terminator::Return(None) => write!(f, "return"),
+            Terminator::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+impl fmt::Display for IrFunction
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        let parameters: Vec<String> = self.parameters.iter()
+            .map(|(variable, value_type)| format!("{} {}", value_type.as_str(), variable))
+            .collect();
+        writeln!(f, "function {} {}({})", self.return_type.as_str(), self.name, parameters.join(", "))?;
+        for (index, block) in self.blocks.iter().enumerate()
+        {
+            writeln!(f, "{}:", BlockId(index))?;
+            for line in format_block(block).lines()
+            {
+                writeln!(f, "    {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for IrProgram
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        for (name, value_type) in self.globals.iter()
+        {
+            writeln!(f, "global {} @{}", value_type.as_str(), name)?;
+        }
+        for function in self.functions.iter()
+        {
+            writeln!(f)?;
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
+
+/// 構文木を基本ブロックに分けた三番地コードに変換する
+pub struct IrBuilder<'a>
+{
+    ast: &'a Ast,
+    resolutions: Resolutions,
+
+    // 意味解析で求めた式の型と, 代入先の宣言
+    types: SideTable<ExprId, ValueType>,
+    assignment_bindings: SideTable<StmtId, Binding>,
+
+    globals: Vec<(String, ValueType)>,
+
+    // 関数名と定義. 定義のない関数はプロトタイプを持つ
+    functions: HashMap<String, &'a Function>,
+
+    // 変換中の関数
+    function: Option<&'a Function>,
+    blocks: Vec<(Vec<Instruction>, Option<Terminator>)>,
+    current: BlockId,
+    temp_types: Vec<ValueType>,
+
+    // break と continue の飛び先
+    loops: Vec<(BlockId, BlockId)>,
+}
+
+impl<'a> IrBuilder<'a>
+{
+    pub fn new(ast: &'a Ast) -> Self
+    {
+        IrBuilder {
+            ast,
+            resolutions: Resolutions::default(),
+            types: SideTable::new(),
+            assignment_bindings: SideTable::new(),
+            globals: Vec::new(),
+            functions: HashMap::new(),
+            function: None,
+            blocks: Vec::new(),
+            current: BlockId(0),
+            temp_types: Vec::new(),
+            loops: Vec::new(),
+        }
+    }
+
+    /// 中間表現を生成する. 意味解析でエラーがある場合は生成しない
+    pub fn build(mut self) -> Result<IrProgram, Vec<Diagnostic>>
+    {
+        let ast = self.ast;
+
+        let mut analyzer = SemanticAnalyzer::new(ast);
+        // 定義のない関数は外部の関数として呼び出す
+        analyzer.set_external_functions(true);
+        let errors: Vec<Diagnostic> = analyzer.analyze().into_iter()
+            .filter(|diagnostic| diagnostic.is_error())
+            .collect();
+        if !errors.is_empty()
+        {
+            return Err(errors);
+        }
+
+        let mut functions: Vec<&'a Function> = Vec::new();
+        for declaration in ast.declarations()
+        {
+            match declaration
+            {
+                Decl::Function(function) =>
+                    {
+                        functions.retain(|defined| defined.name != function.name);
+                        functions.push(function);
+                        self.functions.insert(function.name.clone(), function);
+                    }
+                Decl::Prototype(function) =>
+                    {
+                        self.functions.entry(function.name.clone()).or_insert(function);
+                    }
+                Decl::Variable { type_specifier, name, .. } =>
+                    {
+                        match self.globals.iter_mut().find(|(global, _)| global == name)
+                        {
+                            Some(global) => global.1 = type_specifier.clone(),
+                            None => self.globals.push((name.clone(), type_specifier.clone())),
+                        }
+                    }
+            }
+        }
+
+        for index in 0..ast.expr_count()
+        {
+            let id = ExprId::new(index);
+            if let Some(value_type) = analyzer.expr_type(id)
+            {
+                self.types.insert(id, value_type.clone());
+            }
+        }
+        for index in 0..ast.stmt_count()
+        {
+            let id = StmtId::new(index);
+            if let Some(binding) = analyzer.assignment_binding(id)
+            {
+                self.assignment_bindings.insert(id, *binding);
+            }
+        }
+        self.resolutions = Resolver::new(ast).resolve().clone();
+
+        let mut ir_functions = vec![self.initializer()];
+        for function in functions.iter()
+        {
+            ir_functions.push(self.function_definition(function));
+        }
+
+        Ok(IrProgram { globals: self.globals, functions: ir_functions })
+    }
+
+    fn begin_function(&mut self)
+    {
+        self.blocks.clear();
+        self.temp_types.clear();
+        self.current = self.new_block();
+    }
+
+    /// 終端のないブロックを閉じて関数を組み立てる
+    fn end_function(&mut self, name: &str, return_type: &ValueType, parameters: Vec<(Variable, ValueType)>) -> IrFunction
+    {
+        let end = if *return_type == ValueType::Void { Terminator::Return(None) } else { Terminator::Unreachable };
+        let blocks = self.blocks.drain(..)
+            .map(|(instructions, terminator)| BasicBlock { instructions, terminator: terminator.unwrap_or(end.clone()) })
+            .collect();
+        let mut function = IrFunction {
+            name: name.to_string(),
+            return_type: return_type.clone(),
+            parameters,
+            blocks,
+            temp_types: std::mem::take(&mut self.temp_types),
+        };
+        Self::remove_empty_blocks(&mut function);
+        function
+    }
+
+    /// return や break の後に作った空のブロックのうち, どこからも辿り着けないものを取り除いて番号を詰める.
+    /// 命令を持つブロックと, 残したブロックから飛ぶ先のブロックは残す
+    fn remove_empty_blocks(function: &mut IrFunction)
+    {
+        let reachable = function.reachable();
+        let mut kept: Vec<bool> = function.blocks.iter()
+            .enumerate()
+            .map(|(index, block)| reachable[index] || !block.instructions.is_empty())
+            .collect();
+        let mut stack: Vec<BlockId> = (0..kept.len()).filter(|index| kept[*index]).map(BlockId).collect();
+        while let Some(id) = stack.pop()
+        {
+            for successor in function.block(id).terminator.successors()
+            {
+                if !kept[successor.0]
+                {
+                    kept[successor.0] = true;
+                    stack.push(successor);
+                }
+            }
+        }
+
+        let mut numbers = Vec::new();
+        let mut count = 0;
+        for kept in kept.iter()
+        {
+            numbers.push(BlockId(count));
+            if *kept
+            {
+                count += 1;
+            }
+        }
+        let renumber = |id: &mut BlockId| *id = numbers[id.0];
+
+        let blocks = std::mem::take(&mut function.blocks);
+        for (mut block, kept) in blocks.into_iter().zip(kept.iter())
+        {
+            if !kept
+            {
+                continue;
+            }
+            for instruction in block.instructions.iter_mut()
+            {
+                if let Instruction::Phi { incoming, .. } = instruction
+                {
+                    incoming.iter_mut().for_each(|(id, _)| renumber(id));
+                }
+            }
+            match &mut block.terminator
+            {
+                Terminator::Jump(target) => renumber(target),
+                Terminator::Branch { then_block, else_block, .. } =>
+                    {
+                        renumber(then_block);
+                        renumber(else_block);
+                    }
+                Terminator::Return(_) | Terminator::Unreachable => {}
+            }
+            function.blocks.push(block);
+        }
+    }
+
+    fn initializer(&mut self) -> IrFunction
+    {
+        let ast = self.ast;
+        self.begin_function();
+        for declaration in ast.declarations()
+        {
+            if let Decl::Variable { name, initializer: Some(initializer), .. } = declaration
+            {
+                let value_type = self.global_type(name);
+                let value = self.converted(*initializer, &value_type);
+                self.emit(Instruction::Store { variable: Variable::Global(name.clone()), value });
+            }
+        }
+        self.end_function(IrFunction::INITIALIZER, &ValueType::Void, Vec::new())
+    }
+
+    fn function_definition(&mut self, function: &'a Function) -> IrFunction
+    {
+        self.function = Some(function);
+        self.begin_function();
+        for stmt in function.body.iter()
+        {
+            self.statement(*stmt);
+        }
+
+        let parameters = function.parameters.iter()
+            .enumerate()
+            .map(|(slot, parameter)| (
+                Variable::Local { name: parameter.name.clone(), depth: 0, slot },
+                parameter.type_specifier.clone(),
+            ))
+            .collect();
+        let ir_function = self.end_function(&function.name, &function.return_type, parameters);
+        self.function = None;
+        ir_function
+    }
+
+    fn global_type(&self, name: &str) -> ValueType
+    {
+        self.globals.iter()
+            .find(|(global, _)| global == name)
+            .map(|(_, value_type)| value_type.clone())
+            .unwrap_or(ValueType::Int)
+    }
+
+    fn new_block(&mut self) -> BlockId
+    {
+        self.blocks.push((Vec::new(), None));
+        BlockId(self.blocks.len() - 1)
+    }
+
+    fn new_temp(&mut self, value_type: ValueType) -> Temp
+    {
+        self.temp_types.push(value_type);
+        Temp(self.temp_types.len() - 1)
+    }
+
+    fn emit(&mut self, instruction: Instruction)
+    {
+        self.blocks[self.current.0].0.push(instruction);
+    }
+
+    /// 現在のブロックを閉じて次のブロックに移る
+    fn terminate(&mut self, terminator: Terminator, next: BlockId)
+    {
+        self.blocks[self.current.0].1 = Some(terminator);
+        self.current = next;
+    }
+
+    /// return や break の後ろの文は, どこからも飛んでこない新しいブロックに置く
+    fn terminate_unreachable(&mut self, terminator: Terminator)
+    {
+        let next = self.new_block();
+        self.terminate(terminator, next);
+    }
+
+    fn variable(resolution: Resolution, name: &str) -> Variable
+    {
+        match resolution
+        {
+            Resolution::Local { depth, slot } => Variable::Local { name: name.to_string(), depth, slot },
+            Resolution::Global => Variable::Global(name.to_string()),
+        }
+    }
+
+    fn statement(&mut self, id: StmtId)
+    {
+        let ast = self.ast;
+        match ast.stmt(id)
+        {
+            Stmt::Declaration(Decl::Variable { type_specifier, name, initializer }) =>
+                {
+                    let variable = Self::variable(self.resolutions.stmt(id), name);
+                    match initializer
+                    {
+                        Some(initializer) =>
+                            {
+                                let value = self.converted(*initializer, type_specifier);
+                                self.emit(Instruction::Store { variable, value });
+                            }
+                        None => self.emit(Instruction::Declare(variable)),
+                    }
+                }
+            Stmt::Declaration(Decl::Function(_) | Decl::Prototype(_)) => {}
+            Stmt::Assignment { name, value } =>
+                {
+                    let value_type = self.assignment_type(id, name);
+                    let value = self.converted(*value, &value_type);
+                    let variable = Self::variable(self.resolutions.stmt(id), name);
+                    self.emit(Instruction::Store { variable, value });
+                }
+            Stmt::Expression(expr) =>
+                {
+                    self.expression(*expr);
+                }
+            Stmt::Block(stmts) =>
+                {
+                    for stmt in stmts.iter()
+                    {
+                        self.statement(*stmt);
+                    }
+                }
+            Stmt::If { condition, then_branch, else_branch } =>
+                {
+                    let condition = self.expression(*condition);
+                    let then_block = self.new_block();
+                    let end_block = self.new_block();
+                    let else_block = if else_branch.is_some() { self.new_block() } else { end_block };
+                    self.terminate(Terminator::Branch { condition, then_block, else_block }, then_block);
+
+                    self.statement(*then_branch);
+                    if let Some(else_branch) = else_branch
+                    {
+                        self.terminate(Terminator::Jump(end_block), else_block);
+                        self.statement(*else_branch);
+                    }
+                    self.terminate(Terminator::Jump(end_block), end_block);
+                }
+            Stmt::While { condition, body } =>
+                {
+                    let header = self.new_block();
+                    let body_block = self.new_block();
+                    let exit = self.new_block();
+                    self.terminate(Terminator::Jump(header), header);
+
+                    let condition = self.expression(*condition);
+                    self.terminate(Terminator::Branch { condition, then_block: body_block, else_block: exit }, body_block);
+
+                    self.loops.push((exit, header));
+                    self.statement(*body);
+                    self.loops.pop();
+                    self.terminate(Terminator::Jump(header), exit);
+                }
+            Stmt::Return(value) =>
+                {
+                    let return_type = self.function.map(|function| function.return_type.clone()).unwrap_or(ValueType::Void);
+                    let value = match value
+                    {
+                        Some(value) if return_type != ValueType::Void => Some(self.converted(*value, &return_type)),
+                        _ => None,
+                    };
+                    self.terminate_unreachable(Terminator::Return(value));
+                }
+            Stmt::Break | Stmt::Continue =>
+                {
+                    // ループの外の break と continue はインタプリタでは関数から抜ける
+                    let returns_value = self.function.is_some_and(|function| function.return_type != ValueType::Void);
+                    let terminator = match (ast.stmt(id), self.loops.last())
+                    {
+                        (Stmt::Break, Some((exit, _))) => Terminator::Jump(*exit),
+                        (Stmt::Continue, Some((_, header))) => Terminator::Jump(*header),
+                        _ if returns_value => Terminator::Unreachable,
+                        _ => Terminator::Return(None),
+                    };
+                    self.terminate_unreachable(terminator);
+                }
+        }
+    }
+
+    /// 代入先の変数の宣言された型
+    fn assignment_type(&self, id: StmtId, name: &str) -> ValueType
+    {
+        match self.assignment_bindings.get(id)
+        {
+            Some(Binding::Local(stmt)) => match self.ast.stmt(*stmt)
+            {
+                Stmt::Declaration(Decl::Variable { type_specifier, .. }) => type_specifier.clone(),
+                _ => ValueType::Int,
+            },
+            Some(Binding::Parameter(index)) => self.function
+                .map(|function| function.parameters[*index].type_specifier.clone())
+                .unwrap_or(ValueType::Int),
+            _ => self.global_type(name),
+        }
+    }
+
+    fn operand_type(&self, operand: &Operand) -> ValueType
+    {
+        match operand
+        {
+            Operand::Temp(temp) => self.temp_types[temp.0].clone(),
+            Operand::Constant(constant) => constant.value_type(),
+        }
+    }
+
+    /// 値を指定した型に変換する. 定数で表せる型への定数の変換はその場で行う
+    fn convert(&mut self, operand: Operand, to: &ValueType) -> Operand
+    {
+        if self.operand_type(&operand) == *to || *to == ValueType::Void
+        {
+            return operand;
+        }
+        if let Operand::Constant(constant) = &operand
+        {
+            if let Some(constant) = Interpreter::to_constant(&Interpreter::cast(to, Interpreter::constant(constant)))
+            {
+                return Operand::Constant(constant);
+            }
+        }
+
+        let dest = self.new_temp(to.clone());
+        self.emit(Instruction::Convert { dest, to: to.clone(), operand });
+        Operand::Temp(dest)
+    }
+
+    /// 式を計算し, 指定した型に変換する
+    fn converted(&mut self, id: ExprId, value_type: &ValueType) -> Operand
+    {
+        let operand = self.expression(id);
+        self.convert(operand, value_type)
+    }
+
+    /// 式を計算して結果を返す. void 関数の呼び出しは 0 を返すが使われない
+    fn expression(&mut self, id: ExprId) -> Operand
+    {
+        let ast = self.ast;
+        match ast.expr(id)
+        {
+            Expr::Constant(constant) => Operand::Constant(constant.clone()),
+            Expr::Identifier(name) =>
+                {
+                    let value_type = self.types.get(id).cloned().unwrap_or(ValueType::Int);
+                    let dest = self.new_temp(value_type);
+                    let variable = Self::variable(self.resolutions.expr(id), name);
+                    self.emit(Instruction::Load { dest, variable });
+                    Operand::Temp(dest)
+                }
+            Expr::Unary { op, operand } =>
+                {
+                    let operand = self.expression(*operand);
+                    // '-' は整数拡張した型で計算する
+                    let (operand, value_type) = match op
+                    {
+                        UnaryOperator::Minus =>
+                            {
+                                let value_type = self.operand_type(&operand).promoted();
+                                (self.convert(operand, &value_type), value_type)
+                            }
+                        UnaryOperator::LogicalNot => (operand, ValueType::Int),
+                    };
+                    let dest = self.new_temp(value_type);
+                    self.emit(Instruction::Unary { dest, op: *op, operand });
+                    Operand::Temp(dest)
+                }
+            Expr::Binary { op, lhs, rhs } => self.binary(*op, *lhs, *rhs),
+            Expr::Cast { value_type, operand } => self.converted(*operand, value_type),
+            Expr::Call { name, arguments } =>
+                {
+                    let function = self.functions[name];
+                    let arguments = arguments.iter()
+                        .zip(function.parameters.iter())
+                        .map(|(argument, parameter)| self.converted(*argument, &parameter.type_specifier))
+                        .collect();
+                    let dest = match function.return_type
+                    {
+                        ValueType::Void => None,
+                        ref return_type => Some(self.new_temp(return_type.clone())),
+                    };
+                    self.emit(Instruction::Call { dest, name: name.clone(), arguments });
+                    dest.map(Operand::Temp).unwrap_or(Operand::Constant(Constant::Integer(0)))
+                }
+            Expr::ArrayAccess { .. } =>
+                {
+                    unimplemented!("配列は未実装です");
+                }
+        }
+    }
+
+    fn binary(&mut self, op: Operator, lhs: ExprId, rhs: ExprId) -> Operand
+    {
+        if let Operator::LogicalAnd | Operator::LogicalOr = op
+        {
+            return self.logical(op, lhs, rhs);
+        }
+
+        let lhs = self.expression(lhs);
+        let rhs = self.expression(rhs);
+        let lhs_type = self.operand_type(&lhs);
+        let rhs_type = self.operand_type(&rhs);
+
+        // 両辺を通常の算術変換による共通の型に揃える
+        let operand_type = ValueType::common(&lhs_type, &rhs_type);
+        let lhs = self.convert(lhs, &operand_type);
+        let rhs = self.convert(rhs, &operand_type);
+
+        let result_type = match op
+        {
+            Operator::Plus | Operator::Minus | Operator::Multiply | Operator::Divide => operand_type,
+            Operator::Modulo if !operand_type.is_floating() => operand_type,
+            _ => ValueType::Int,
+        };
+        let dest = self.new_temp(result_type);
+        self.emit(Instruction::Binary { dest, op, lhs, rhs });
+        Operand::Temp(dest)
+    }
+
+    /// '&&' と '||' を分岐に変換し, 合流点で phi により結果を選ぶ
+    fn logical(&mut self, op: Operator, lhs: ExprId, rhs: ExprId) -> Operand
+    {
+        let lhs = self.expression(lhs);
+        let lhs_block = self.current;
+        let rhs_block = self.new_block();
+        let end_block = self.new_block();
+        let (terminator, short_circuit) = match op
+        {
+            Operator::LogicalAnd =>
+                (Terminator::Branch { condition: lhs, then_block: rhs_block, else_block: end_block }, Operand::Constant(Constant::Integer(0))),
+            _ =>
+                (Terminator::Branch { condition: lhs, then_block: end_block, else_block: rhs_block }, Operand::Constant(Constant::Integer(1))),
+        };
+        self.terminate(terminator, rhs_block);
+
+        // 右辺は 0 か 1 に揃える. 0 と比較するため整数拡張しておく
+        let rhs = self.expression(rhs);
+        let rhs_type = self.operand_type(&rhs).promoted();
+        let rhs = self.convert(rhs, &rhs_type);
+        let zero = Operand::Constant(Interpreter::to_constant(&Interpreter::cast(&rhs_type, VariableType::Int(0))).unwrap());
+        let boolean = self.new_temp(ValueType::Int);
+        self.emit(Instruction::Binary { dest: boolean, op: Operator::NotEqual, lhs: rhs, rhs: zero });
+        let rhs_end = self.current;
+        self.terminate(Terminator::Jump(end_block), end_block);
+
+        let dest = self.new_temp(ValueType::Int);
+        self.emit(Instruction::Phi {
+            dest,
+            incoming: vec![(lhs_block, short_circuit), (rhs_end, Operand::Temp(boolean))],
+        });
+        Operand::Temp(dest)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::ir::{BlockId, IrBuilder, IrProgram};
+    use crate::lexical::Lexer;
+    use crate::parser::Parser;
+    use petgraph::algo::is_cyclic_directed;
+
+    fn build(program: &str) -> IrProgram
+    {
+        let mut lexer = Lexer::new(program.to_string());
+        lexer.tokenize();
+
+        let mut parser = Parser::new(lexer.tokens().clone());
+        parser.parse();
+        IrBuilder::new(parser.ast()).build().unwrap()
+    }
+
+    #[test]
+    fn test_lowering()
+    {
+        let program = build("
+float scale = 1.5;
+int count(int n) {
+    int sum;
+    sum = 0;
+    while (n > 0 && sum < 100) {
+        sum = sum + n * scale;
+        n = n - 1;
+    }
+    return sum;
+}");
+        assert_eq!(program.to_string(), "\
+global float @scale
+
+function void @initialize()
+bb0:
+    store @scale, 1.5f
+    return
+
+function int count(int n.0.0)
+bb0:
+    declare sum.0.1
+    store sum.0.1, 0
+    jump bb1
+bb1:
+    t0 = load n.0.0
+    t1 = t0 > 0
+    branch t1, bb4, bb5
+bb2:
+    t6 = load sum.0.1
+    t7 = load n.0.0
+    t8 = load @scale
+    t9 = (float) t7
+    t10 = t9 * t8
+    t11 = (float) t6
+    t12 = t11 + t10
+    t13 = (int) t12
+    store sum.0.1, t13
+    t14 = load n.0.0
+    t15 = t14 - 1
+    store n.0.0, t15
+    jump bb1
+bb3:
+    t16 = load sum.0.1
+    return t16
+bb4:
+    t2 = load sum.0.1
+    t3 = t2 < 100
+    t4 = t3 != 0
+    jump bb5
+bb5:
+    t5 = phi [bb1: 0], [bb4: t4]
+    branch t5, bb2, bb3
+");
+    }
+
+    #[test]
+    fn test_cfg()
+    {
+        let program = build("
+int f(int n) {
+    if (n < 0) {
+        return 0;
+        n = 1;
+    } else {
+        n = n * 2;
+    }
+    while (n > 10) {
+        if (n == 20) {
+            break;
+        }
+        n = n - 1;
+    }
+    return n;
+}");
+        let function = &program.functions[1];
+        let cfg = function.cfg();
+
+        // return の後ろの代入だけがどこからも辿り着けないブロックとして残る
+        let reachable = function.reachable();
+        let unreachable: Vec<usize> = (0..reachable.len()).filter(|index| !reachable[*index]).collect();
+        assert_eq!(unreachable.len(), 1);
+        assert_eq!(function.block(BlockId(unreachable[0])).instructions.len(), 1);
+
+        // ループの本体から条件に戻る辺がある
+        assert!(is_cyclic_directed(&cfg));
+        assert_eq!(function.predecessors()[0].len(), 0);
+
+        let dot = program.to_dot();
+        assert!(dot.starts_with("digraph {"));
+        assert!(dot.contains("label = \"true\""));
+        assert!(dot.contains("f bb0:\\lt0 = load n.0.0\\l"));
+        assert!(dot.contains("shape = box"));
+    }
+}
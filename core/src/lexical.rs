@@ -58,14 +58,219 @@ impl Operator {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ValueType {
     Void,
-    Int,
-    Float,
+    Bool,           // _Bool
+    Char,           // char (符号付きとして扱う)
+    UnsignedChar,   // unsigned char
+    Short,          // short
+    UnsignedShort,  // unsigned short
+    Int,            // int
+    UnsignedInt,    // unsigned int
+    Long,           // long, long long (64 bit)
+    UnsignedLong,   // unsigned long, unsigned long long
+    Float,          // float (32 bit)
+    Double,         // double, long double (64 bit)
+}
+
+impl ValueType {
+    /// 型に対応するキーワードを返す
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ValueType::Void => "void",
+            ValueType::Bool => "_Bool",
+            ValueType::Char => "char",
+            ValueType::UnsignedChar => "unsigned char",
+            ValueType::Short => "short",
+            ValueType::UnsignedShort => "unsigned short",
+            ValueType::Int => "int",
+            ValueType::UnsignedInt => "unsigned int",
+            ValueType::Long => "long",
+            ValueType::UnsignedLong => "unsigned long",
+            ValueType::Float => "float",
+            ValueType::Double => "double",
+        }
+    }
+
+    /// 整数型かどうか. _Bool も整数型に含む
+    pub fn is_integer(&self) -> bool {
+        !matches!(self, ValueType::Void | ValueType::Float | ValueType::Double)
+    }
+
+    /// 浮動小数点型かどうか
+    pub fn is_floating(&self) -> bool {
+        matches!(self, ValueType::Float | ValueType::Double)
+    }
+
+    /// 符号なし整数型かどうか
+    pub fn is_unsigned(&self) -> bool {
+        matches!(self, ValueType::Bool | ValueType::UnsignedChar | ValueType::UnsignedShort
+            | ValueType::UnsignedInt | ValueType::UnsignedLong)
+    }
+
+    /// 型の大きさ (バイト数)
+    pub fn size(&self) -> usize {
+        match self {
+            ValueType::Void => 0,
+            ValueType::Bool | ValueType::Char | ValueType::UnsignedChar => 1,
+            ValueType::Short | ValueType::UnsignedShort => 2,
+            ValueType::Int | ValueType::UnsignedInt | ValueType::Float => 4,
+            ValueType::Long | ValueType::UnsignedLong | ValueType::Double => 8,
+        }
+    }
+
+    /// 整数変換の順位
+    fn rank(&self) -> usize {
+        match self {
+            ValueType::Bool => 1,
+            ValueType::Char | ValueType::UnsignedChar => 2,
+            ValueType::Short | ValueType::UnsignedShort => 3,
+            ValueType::Int | ValueType::UnsignedInt => 4,
+            ValueType::Long | ValueType::UnsignedLong => 5,
+            ValueType::Void | ValueType::Float | ValueType::Double => 0,
+        }
+    }
+
+    /// 整数拡張. int より順位の低い整数型は int になる
+    pub fn promoted(&self) -> ValueType {
+        if self.is_integer() && self.rank() < ValueType::Int.rank() {
+            ValueType::Int
+        } else {
+            self.clone()
+        }
+    }
+
+    /// 同じ順位の符号なし整数型
+    fn to_unsigned(&self) -> ValueType {
+        match self {
+            ValueType::Char => ValueType::UnsignedChar,
+            ValueType::Short => ValueType::UnsignedShort,
+            ValueType::Int => ValueType::UnsignedInt,
+            ValueType::Long => ValueType::UnsignedLong,
+            _ => self.clone(),
+        }
+    }
+
+    /// 通常の算術変換. 二項演算の両辺をそろえる共通の型を返す
+    pub fn common(lhs: &ValueType, rhs: &ValueType) -> ValueType {
+        if *lhs == ValueType::Double || *rhs == ValueType::Double {
+            return ValueType::Double;
+        }
+        if *lhs == ValueType::Float || *rhs == ValueType::Float {
+            return ValueType::Float;
+        }
+
+        let (lhs, rhs) = (lhs.promoted(), rhs.promoted());
+        if lhs == rhs {
+            return lhs;
+        }
+        if lhs.is_unsigned() == rhs.is_unsigned() {
+            return if lhs.rank() >= rhs.rank() { lhs } else { rhs };
+        }
+
+        let (unsigned, signed) = if lhs.is_unsigned() { (lhs, rhs) } else { (rhs, lhs) };
+        if unsigned.rank() >= signed.rank() {
+            unsigned
+        } else if signed.size() > unsigned.size() {
+            // 符号付きの型で符号なしの型のすべての値を表せる
+            signed
+        } else {
+            signed.to_unsigned()
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Constant {
     Integer(i32),
-    Float(f64),
+    UnsignedInt(u32),
+    Long(i64),
+    UnsignedLong(u64),
+    Float(f32),
+    Double(f64),
+}
+
+impl Constant {
+    /// 定数の型
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            Constant::Integer(_) => ValueType::Int,
+            Constant::UnsignedInt(_) => ValueType::UnsignedInt,
+            Constant::Long(_) => ValueType::Long,
+            Constant::UnsignedLong(_) => ValueType::UnsignedLong,
+            Constant::Float(_) => ValueType::Float,
+            Constant::Double(_) => ValueType::Double,
+        }
+    }
+
+    /// 数値リテラルを読む. 数値でなければ None を返す
+    ///
+    /// 整数は接尾辞 u, l, ul, ll を, 浮動小数点数は f, l を受け付ける.
+    /// 接尾辞のない整数は値の収まる最初の型 (int, long, unsigned long) になり, 浮動小数点数は double になる
+    pub fn parse(literal: &str) -> Option<Constant> {
+        if !literal.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+            return None;
+        }
+
+        // 数字で始まるトークンは識別子にならないので, 数値として読めなければ誤り
+        let constant = Self::parse_number(literal);
+        if constant.is_none() && literal.starts_with(|c: char| c.is_ascii_digit()) {
+            panic!("不正な数値リテラル : {}", literal);
+        }
+        constant
+    }
+
+    fn parse_number(literal: &str) -> Option<Constant> {
+        let lower = literal.to_ascii_lowercase();
+        if lower.contains('.') || lower.contains('e') {
+            return if let Some(body) = lower.strip_suffix('f') {
+                body.parse::<f32>().ok().map(Constant::Float)
+            } else {
+                lower.strip_suffix('l').unwrap_or(&lower).parse::<f64>().ok().map(Constant::Double)
+            };
+        }
+
+        let body = lower.trim_end_matches(['u', 'l']);
+        let suffix = &lower[body.len()..];
+        let unsigned = suffix.contains('u');
+        let long = suffix.contains('l');
+        if !matches!(suffix, "" | "u" | "l" | "ul" | "lu" | "ll" | "ull" | "llu") {
+            return None;
+        }
+        // ll は大文字と小文字を混ぜられない
+        let original = &literal[body.len()..];
+        if original.contains("lL") || original.contains("Ll") {
+            return None;
+        }
+
+        let value = match body.parse::<u64>() {
+            Ok(value) => value,
+            Err(_) if !body.is_empty() && body.bytes().all(|b| b.is_ascii_digit()) =>
+                panic!("整数定数が大きすぎます : {}", literal),
+            Err(_) => return None,
+        };
+
+        let constant = match (unsigned, long) {
+            (false, false) if value <= i32::MAX as u64 => Constant::Integer(value as i32),
+            (true, false) if value <= u32::MAX as u64 => Constant::UnsignedInt(value as u32),
+            (false, _) if value <= i64::MAX as u64 => Constant::Long(value as i64),
+            _ => Constant::UnsignedLong(value),
+        };
+        Some(constant)
+    }
+}
+
+// 定数の format. 型が分かるよう C の接尾辞を付ける
+impl std::fmt::Display for Constant {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Constant::Integer(val) => write!(f, "{}", val),
+            Constant::UnsignedInt(val) => write!(f, "{}u", val),
+            Constant::Long(val) => write!(f, "{}L", val),
+            Constant::UnsignedLong(val) => write!(f, "{}uL", val),
+            // int の定数と区別するため常に小数点を付ける
+            Constant::Float(val) => write!(f, "{:?}f", val),
+            Constant::Double(val) => write!(f, "{:?}", val),
+        }
+    }
 }
 
 /// トークン
@@ -77,6 +282,12 @@ pub enum Token {
 
     // 型指定子
     Type(ValueType),                // 型指定子
+    Signed,                    // `signed`
+    Unsigned,                  // `unsigned`
+    Enum,                      // `enum`
+    Typedef,                   // `typedef`
+    Const,                     // `const`
+    Static,                    // `static`
 
     // 区切り記号やその他の構造
     Comma,                     // `,`
@@ -98,6 +309,7 @@ pub enum Token {
     UnaryOperator(UnaryOperator), // 単項演算子
     // 代入演算子
     Assign,                    // `=`
+    Sizeof,                    // `sizeof`
 
     // jump
     Return,                    // `return`
@@ -115,19 +327,27 @@ impl Token
             "int" => Some(Token::Type(ValueType::Int)),
             "float" => Some(Token::Type(ValueType::Float)),
             "void" => Some(Token::Type(ValueType::Void)),
+            "_Bool" => Some(Token::Type(ValueType::Bool)),
+            "char" => Some(Token::Type(ValueType::Char)),
+            "short" => Some(Token::Type(ValueType::Short)),
+            "long" => Some(Token::Type(ValueType::Long)),
+            "double" => Some(Token::Type(ValueType::Double)),
+            "signed" => Some(Token::Signed),
+            "unsigned" => Some(Token::Unsigned),
+            "enum" => Some(Token::Enum),
+            "typedef" => Some(Token::Typedef),
+            "const" => Some(Token::Const),
+            "static" => Some(Token::Static),
             "if" => Some(Token::If),
             "else" => Some(Token::Else),
             "while" => Some(Token::While),
             "return" => Some(Token::Return),
             "continue" => Some(Token::Continue),
             "break" => Some(Token::Break),
+            "sizeof" => Some(Token::Sizeof),
 
             // 数値の場合
-            _ if keyword.parse::<i64>().is_ok() =>
-                Some(Token::Constant(Constant::Integer(keyword.parse::<i32>().unwrap()))),
-            _ if keyword.parse::<f64>().is_ok() =>
-                Some(Token::Constant(Constant::Float(keyword.parse::<f64>().unwrap()))),
-            _ => None,
+            _ => Constant::parse(keyword).map(Token::Constant),
         }
     }
 
@@ -142,6 +362,10 @@ pub struct Lexer {
     position: usize,
     tokens: Vec<Token>,
     token_str: String,
+
+    // トークンごとの行番号. 1 始まり
+    lines: Vec<usize>,
+    line: usize,
 }
 
 impl Lexer
@@ -152,17 +376,14 @@ impl Lexer
             position: 0,
             tokens: Vec::new(),
             token_str: String::new(),
+            lines: Vec::new(),
+            line: 1,
         }
     }
 
     pub fn tokenize(&mut self)
     {
-        loop {
-            let c = match self.next_char() {
-                Some(c) => c,
-                None => break,
-            };
-
+        while let Some(c) = self.next_char() {
             match c
             {
                 'a'..='z' | 'A'..='Z' | '_' =>
@@ -185,46 +406,47 @@ impl Lexer
                     {
                         self.add_token();
 
-                        self.tokens.push(Token::LeftParen);
+                        self.push_token(Token::LeftParen);
                     }
                 ')' =>
                     {
                         self.add_token();
-                        self.tokens.push(Token::RightParen);
+                        self.push_token(Token::RightParen);
                     }
                 '{' =>
                     {
                         self.add_token();
-                        self.tokens.push(Token::LeftBrace);
+                        self.push_token(Token::LeftBrace);
                     }
                 '}' =>
                     {
                         self.add_token();
-                        self.tokens.push(Token::RightBrace);
+                        self.push_token(Token::RightBrace);
                     }
                 ';' =>
                     {
                         self.add_token();
-                        self.tokens.push(Token::Semicolon);
+                        self.push_token(Token::Semicolon);
                     }
                 ',' =>
                     {
                         self.add_token();
-                        self.tokens.push(Token::Comma);
+                        self.push_token(Token::Comma);
                     }
                 '[' =>
                     {
                         self.add_token();
-                        self.tokens.push(Token::LeftBracket);
+                        self.push_token(Token::LeftBracket);
                     }
                 ']' =>
                     {
                         self.add_token();
-                        self.tokens.push(Token::RightBracket);
+                        self.push_token(Token::RightBracket);
                     }
                 '\n' =>
                     {
                         self.add_token();
+                        self.line += 1;
                     }
                 '=' =>
                     {
@@ -234,13 +456,15 @@ impl Lexer
                             Some('=') =>
                                 {
                                     self.add_token();
-                                    self.tokens.push(Token::Operator(Operator::Equal));
+                                    self.push_token(Token::Operator(Operator::Equal));
                                 }
                             _ =>
                                 {
                                     self.add_token();
-                                    self.tokens.push(Token::Assign);
-                                    self.back_char();
+                                    self.push_token(Token::Assign);
+                                    if next_char.is_some() {
+                                        self.back_char();
+                                    }
                                 }
                         }
                     }
@@ -252,7 +476,7 @@ impl Lexer
                             Some('|') =>
                                 {
                                     self.add_token();
-                                    self.tokens.push(Token::Operator(Operator::LogicalOr));
+                                    self.push_token(Token::Operator(Operator::LogicalOr));
                                 }
                             _ =>
                                 {
@@ -268,19 +492,22 @@ impl Lexer
                             Some('=') =>
                                 {
                                     self.add_token();
-                                    self.tokens.push(Token::Operator(Operator::NotEqual));
+                                    self.push_token(Token::Operator(Operator::NotEqual));
                                 }
                             _ =>
                                 {
                                     self.add_token();
-                                    self.tokens.push(Token::UnaryOperator(UnaryOperator::LogicalNot));
+                                    self.push_token(Token::UnaryOperator(UnaryOperator::LogicalNot));
+                                    if next_char.is_some() {
+                                        self.back_char();
+                                    }
                                 }
                         }
                     }
                 '+' | '*' | '/' | '%' =>
                     {
                         self.add_token();
-                        self.tokens.push(Token::Operator(match c {
+                        self.push_token(Token::Operator(match c {
                             '+' => Operator::Plus,
                             '*' => Operator::Multiply,
                             '/' => Operator::Divide,
@@ -292,18 +519,15 @@ impl Lexer
                     {
                         self.add_token();
 
-                        // 一個前のトークンが Identifier か定数の場合は Operator::Minus
-                        if let Some(token) = self.tokens.last() {
-                            match token {
-                                Token::Identifier(_) | Token::Constant(_) => {
-                                    self.tokens.push(Token::Operator(Operator::Minus));
-                                }
-                                _ => {
-                                    self.tokens.push(Token::UnaryOperator(UnaryOperator::Minus));
-                                }
+                        // 一個前のトークンが Identifier か定数か閉じ括弧の場合は Operator::Minus
+                        match self.tokens.last() {
+                            Some(Token::Identifier(_)) | Some(Token::Constant(_))
+                            | Some(Token::RightParen) | Some(Token::RightBracket) => {
+                                self.push_token(Token::Operator(Operator::Minus));
+                            }
+                            _ => {
+                                self.push_token(Token::UnaryOperator(UnaryOperator::Minus));
                             }
-                        } else {
-                            self.tokens.push(Token::UnaryOperator(UnaryOperator::Minus));
                         }
                     }
                 '&' =>
@@ -314,7 +538,7 @@ impl Lexer
                             Some('&') =>
                                 {
                                     self.add_token();
-                                    self.tokens.push(Token::Operator(Operator::LogicalAnd));
+                                    self.push_token(Token::Operator(Operator::LogicalAnd));
                                 }
                             _ =>
                                 {
@@ -330,7 +554,7 @@ impl Lexer
                             Some('=') =>
                                 {
                                     self.add_token();
-                                    self.tokens.push(Token::Operator(match c {
+                                    self.push_token(Token::Operator(match c {
                                         '>' => Operator::GreaterThanOrEqual,
                                         '<' => Operator::LessThanOrEqual,
                                         _ => unreachable!(),
@@ -339,7 +563,7 @@ impl Lexer
                             _ =>
                                 {
                                     self.add_token();
-                                    self.tokens.push(Token::Operator(match c {
+                                    self.push_token(Token::Operator(match c {
                                         '>' => Operator::GreaterThan,
                                         '<' => Operator::LessThan,
                                         _ => unreachable!(),
@@ -360,9 +584,9 @@ impl Lexer
         // トークンを追加
         if !self.token_str.is_empty() {
             if let Some(token) = Token::from_keyword(&self.token_str) {
-                self.tokens.push(token);
+                self.push_token(token);
             } else {
-                self.tokens.push(Token::Identifier(self.token_str.clone()));
+                self.push_token(Token::Identifier(self.token_str.clone()));
             }
 
             // トークン文字列をクリア
@@ -370,6 +594,12 @@ impl Lexer
         }
     }
 
+    fn push_token(&mut self, token: Token)
+    {
+        self.tokens.push(token);
+        self.lines.push(self.line);
+    }
+
     fn add_char(&mut self, c: char)
     {
         self.token_str.push(c);
@@ -395,16 +625,6 @@ impl Lexer
         result
     }
 
-    /// 次の文字を取得するが、文字列を進めない
-    fn peek_char(&self) -> Option<char>
-    {
-        if self.position >= self.sentence.len() {
-            return None;
-        }
-
-        self.sentence.chars().nth(self.position)
-    }
-
     /// 文字を一つ戻す
     fn back_char(&mut self)
     {
@@ -414,11 +634,16 @@ impl Lexer
     pub fn tokens(&self) -> &Vec<Token> {
         &self.tokens
     }
+
+    /// トークンごとのソース上の行番号
+    pub fn lines(&self) -> &Vec<usize> {
+        &self.lines
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::lexical::Token::Identifier;
+    
     use super::*;
 
     #[test]
@@ -620,9 +845,9 @@ int main() {
             Token::Assign,
             Token::Identifier("multiply".to_string()),
             Token::LeftParen,
-            Token::Constant(Constant::Float(2.5)),
+            Token::Constant(Constant::Double(2.5)),
             Token::Comma,
-            Token::Constant(Constant::Float(4.0)),
+            Token::Constant(Constant::Double(4.0)),
             Token::RightParen,
             Token::Semicolon,
 
@@ -720,4 +945,60 @@ int main() {
             assert_eq!(token, &result[i]);
         }
     }
+
+    #[test]
+    fn test_numeric_literals() {
+        let mut lexer = Lexer::new("10 10L 3u 3ul 5ll 1.5 1.5f 2.0L 3000000000 unsigned;".to_string());
+        lexer.tokenize();
+
+        let result = vec![
+            Token::Constant(Constant::Integer(10)),
+            Token::Constant(Constant::Long(10)),
+            Token::Constant(Constant::UnsignedInt(3)),
+            Token::Constant(Constant::UnsignedLong(3)),
+            Token::Constant(Constant::Long(5)),
+            Token::Constant(Constant::Double(1.5)),
+            Token::Constant(Constant::Float(1.5)),
+            Token::Constant(Constant::Double(2.0)),
+            // int に収まらない 10 進数は long になる
+            Token::Constant(Constant::Long(3000000000)),
+            Token::Unsigned,
+            Token::Semicolon,
+        ];
+        assert_eq!(lexer.tokens(), &result);
+    }
+
+    #[test]
+    fn test_malformed_numeric_literals() {
+        for literal in ["0x10", "1.5ff", "10abc", "5lL", "5Ll", "3uu", "3lul", "1.5lf"] {
+            let result = std::panic::catch_unwind(|| {
+                let mut lexer = Lexer::new(format!("{};", literal));
+                lexer.tokenize();
+            });
+            let message = result.expect_err(literal).downcast::<String>().unwrap();
+            assert_eq!(*message, format!("不正な数値リテラル : {}", literal));
+        }
+
+        // 大文字と小文字をそろえた ll は受け付ける
+        let mut lexer = Lexer::new("5LL 5ll 5uLL;".to_string());
+        lexer.tokenize();
+        assert_eq!(lexer.tokens(), &vec![
+            Token::Constant(Constant::Long(5)),
+            Token::Constant(Constant::Long(5)),
+            Token::Constant(Constant::UnsignedLong(5)),
+            Token::Semicolon,
+        ]);
+    }
+
+    #[test]
+    fn test_usual_arithmetic_conversions() {
+        use ValueType::*;
+
+        assert_eq!(ValueType::common(&Char, &Short), Int);
+        assert_eq!(ValueType::common(&Int, &UnsignedInt), UnsignedInt);
+        assert_eq!(ValueType::common(&Long, &UnsignedInt), Long);
+        assert_eq!(ValueType::common(&Long, &UnsignedLong), UnsignedLong);
+        assert_eq!(ValueType::common(&UnsignedLong, &Float), Float);
+        assert_eq!(ValueType::common(&Float, &Double), Double);
+    }
 }
@@ -1,6 +1,19 @@
 pub mod lexical;
+pub mod ast;
 pub mod parser;
 pub mod interpreter;
+pub mod semantic;
+pub mod resolver;
+pub mod optimizer;
+pub mod bytecode;
+pub mod vm;
+pub mod bytecode_file;
+pub mod disasm;
+pub mod c_backend;
+pub mod asm_backend;
+pub mod wat_backend;
+pub mod ir;
+pub mod lint;
 pub mod tree_viewer;
 
 #[cfg(test)]
@@ -8,7 +21,6 @@ mod test
 {
     use std::collections::HashMap;
     use super::*;
-    use lexical::Lexer;
     use interpreter::Variable;
     use interpreter::VariableType;
 
@@ -22,15 +34,8 @@ int u = ((z != 0) && ((y - 10.0) >= 0.0)) + ((x != y) * 2);
 float v = ((x + z) * (y - 2.0)) + ((u + 3) / 2);
 ");
 
-        let mut lexer = Lexer::new(input);
-        lexer.tokenize();
-
-        let tokens = lexer.tokens().clone();
-        let mut parser = parser::Parser::new(tokens);
-        parser.parse();
-
-        let mut interpreter = interpreter::Interpreter::new(parser.roots());
-        interpreter.run();
+        // main 関数がないのでグローバル変数の登録だけを行う
+        let interpreter = interpreter::Interpreter::load(&input).unwrap();
 
         let variables = interpreter.global_variables();
 
@@ -75,7 +80,7 @@ float v = ((x + z) * (y - 2.0)) + ((u + 3) / 2);
 
         
         
-        for (i, (name, variable)) in variables.iter().enumerate() {
+        for (i, (name, variable)) in variables.enumerate() {
             match answer.get(name.as_str()) {
                 Some(ans) => {
                     println!("[{}] {}: {:?}", i, name, variable);
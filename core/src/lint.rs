@@ -0,0 +1,437 @@
+use crate::ast::{Ast, Decl, Function, Stmt, StmtId};
+use crate::ir::{BlockId, Instruction, IrBuilder, IrFunction, Variable};
+use crate::parser::static_local_name;
+use crate::resolver::{Resolution, Resolutions, Resolver};
+use crate::semantic::Diagnostic;
+use std::collections::HashSet;
+
+/// 実行しなくても分かる問題を警告する.
+/// 使われていない変数と引数, return や break の後の到達できないコード,
+/// 初期化されていない可能性のある変数の読み出し, 呼び出されない関数を報告する
+pub struct Linter<'a>
+{
+    ast: &'a Ast,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a>
+{
+    pub fn new(ast: &'a Ast) -> Self
+    {
+        Linter { ast, diagnostics: Vec::new() }
+    }
+
+    /// 診断を返す. 意味解析でエラーがある場合はエラーだけを返す
+    pub fn lint(&mut self) -> Vec<Diagnostic>
+    {
+        let ast = self.ast;
+        let program = match IrBuilder::new(ast).build()
+        {
+            Ok(program) => program,
+            Err(errors) => return errors,
+        };
+
+        // 中間表現と同じく, 同じ名前の関数は最後の定義を調べる.
+        // static なローカル変数はグローバル変数として関数の定義の直前に置かれるので, 直後の関数のものとする
+        let mut functions: Vec<(&'a Function, Vec<&'a String>)> = Vec::new();
+        let mut static_variables: Vec<&'a String> = Vec::new();
+        for declaration in ast.declarations()
+        {
+            match declaration
+            {
+                Decl::Function(function) =>
+                    {
+                        functions.retain(|(defined, _)| defined.name != function.name);
+                        functions.push((function, std::mem::take(&mut static_variables)));
+                    }
+                Decl::Variable { name, .. } if static_local_name(name).is_some() => static_variables.push(name),
+                _ => {}
+            }
+        }
+
+        let mut resolver = Resolver::new(ast);
+        let resolutions = resolver.resolve().clone();
+
+        for (function, static_variables) in functions.iter()
+        {
+            let ir_function = program.functions.iter()
+                .find(|ir_function| ir_function.name == function.name)
+                .unwrap();
+
+            // 引数とローカル変数を宣言された順に並べる
+            let mut variables: Vec<(Variable, bool)> = function.parameters.iter()
+                .enumerate()
+                .map(|(slot, parameter)| (Variable::Local { name: parameter.name.clone(), depth: 0, slot }, true))
+                .collect();
+            for stmt in function.body.iter()
+            {
+                self.collect_variables(*stmt, &resolutions, &mut variables);
+            }
+            variables.extend(static_variables.iter().map(|name| (Variable::Global(name.to_string()), false)));
+            self.unused_variables(ir_function, &variables);
+            self.uninitialized_reads(ir_function);
+
+            self.unreachable_code(&function.body, &function.name);
+        }
+
+        self.uncalled_functions(&program.functions);
+
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    fn warning(&mut self, message: String)
+    {
+        self.diagnostics.push(Diagnostic::warning(message));
+    }
+
+    fn collect_variables(&self, id: StmtId, resolutions: &Resolutions, variables: &mut Vec<(Variable, bool)>)
+    {
+        match self.ast.stmt(id)
+        {
+            Stmt::Declaration(Decl::Variable { name, .. }) =>
+                {
+                    if let Resolution::Local { depth, slot } = resolutions.stmt(id)
+                    {
+                        variables.push((Variable::Local { name: name.clone(), depth, slot }, false));
+                    }
+                }
+            Stmt::Block(stmts) =>
+                {
+                    for stmt in stmts.iter()
+                    {
+                        self.collect_variables(*stmt, resolutions, variables);
+                    }
+                }
+            Stmt::If { then_branch, else_branch, .. } =>
+                {
+                    self.collect_variables(*then_branch, resolutions, variables);
+                    if let Some(else_branch) = else_branch
+                    {
+                        self.collect_variables(*else_branch, resolutions, variables);
+                    }
+                }
+            Stmt::While { body, .. } => self.collect_variables(*body, resolutions, variables),
+            _ => {}
+        }
+    }
+
+    /// 一度も読み出されない変数と引数. 代入しかされない変数と static なローカル変数も含む
+    fn unused_variables(&mut self, function: &IrFunction, variables: &[(Variable, bool)])
+    {
+        let loaded: HashSet<&Variable> = function.blocks.iter()
+            .flat_map(|block| block.instructions.iter())
+            .filter_map(|instruction| match instruction
+            {
+                Instruction::Load { variable, .. } => Some(variable),
+                _ => None,
+            })
+            .collect();
+
+        for (variable, is_parameter) in variables.iter()
+        {
+            if loaded.contains(variable)
+            {
+                continue;
+            }
+            let name = match variable
+            {
+                Variable::Local { name, .. } => name.as_str(),
+                Variable::Global(name) => static_local_name(name).unwrap_or(name),
+            };
+            let kind = if *is_parameter { "引数" } else { "変数" };
+            self.warning(format!("使われていない{}です : {} (関数 {})", kind, name, function.name));
+        }
+    }
+
+    /// 宣言から読み出しまでの経路のどこかで代入されていない変数.
+    /// ブロックの入口で未初期化の可能性がある変数を, 直前のブロックの出口の和集合として求める
+    fn uninitialized_reads(&mut self, function: &IrFunction)
+    {
+        let reachable = function.reachable();
+        let predecessors = function.predecessors();
+
+        // 引数以外で初期化子のない宣言を持つ変数だけを調べる
+        let declared: HashSet<Variable> = function.blocks.iter()
+            .flat_map(|block| block.instructions.iter())
+            .filter_map(|instruction| match instruction
+            {
+                Instruction::Declare(variable) => Some(variable.clone()),
+                _ => None,
+            })
+            .collect();
+        if declared.is_empty()
+        {
+            return;
+        }
+
+        // 出口で未初期化の可能性がある変数の集合. 辿り着けないブロックは空とみなす
+        let mut outputs: Vec<HashSet<Variable>> = vec![HashSet::new(); function.blocks.len()];
+        let mut changed = true;
+        while changed
+        {
+            changed = false;
+            for index in 0..function.blocks.len()
+            {
+                if !reachable[index]
+                {
+                    continue;
+                }
+                let mut state = Self::block_input(&predecessors[index], &outputs);
+                Self::transfer(function, BlockId(index), &mut state, |_| {});
+                if state != outputs[index]
+                {
+                    outputs[index] = state;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut reported: Vec<Variable> = Vec::new();
+        for index in 0..function.blocks.len()
+        {
+            if !reachable[index]
+            {
+                continue;
+            }
+            let mut state = Self::block_input(&predecessors[index], &outputs);
+            Self::transfer(function, BlockId(index), &mut state, |variable| {
+                if !reported.contains(variable)
+                {
+                    reported.push(variable.clone());
+                }
+            });
+        }
+        for variable in reported.iter()
+        {
+            if let Variable::Local { name, .. } = variable
+            {
+                self.warning(format!("初期化されていない可能性のある変数を使用しています : {} (関数 {})", name, function.name));
+            }
+        }
+    }
+
+    /// ブロックの入口で未初期化の可能性がある変数. どれか 1 つの経路で未初期化なら含める
+    fn block_input(predecessors: &[BlockId], outputs: &[HashSet<Variable>]) -> HashSet<Variable>
+    {
+        let mut state = HashSet::new();
+        for predecessor in predecessors.iter()
+        {
+            state.extend(outputs[predecessor.0].iter().cloned());
+        }
+        state
+    }
+
+    /// ブロックの命令を順に適用する. 未初期化の可能性がある変数を読み出すたびに report を呼ぶ
+    fn transfer(function: &IrFunction, id: BlockId, state: &mut HashSet<Variable>, mut report: impl FnMut(&Variable))
+    {
+        for instruction in function.block(id).instructions.iter()
+        {
+            match instruction
+            {
+                Instruction::Declare(variable) =>
+                    {
+                        state.insert(variable.clone());
+                    }
+                Instruction::Store { variable, .. } =>
+                    {
+                        state.remove(variable);
+                    }
+                Instruction::Load { variable, .. } if state.contains(variable) => report(variable),
+                _ => {}
+            }
+        }
+    }
+
+    /// 文の並びのうち, return, break, continue の後にある文. 並びごとに最初の 1 つだけ報告する
+    fn unreachable_code(&mut self, stmts: &[StmtId], function_name: &str)
+    {
+        let mut terminated: Option<&'static str> = None;
+        for stmt in stmts.iter()
+        {
+            if let Some(kind) = terminated
+            {
+                let line = match self.ast.stmt_line(*stmt)
+                {
+                    Some(line) => format!(", {} 行目", line),
+                    None => String::new(),
+                };
+                self.warning(format!("到達できないコードです : {} の後 (関数 {}{})", kind, function_name, line));
+                break;
+            }
+            self.nested_unreachable_code(*stmt, function_name);
+            terminated = self.terminates(*stmt);
+        }
+    }
+
+    fn nested_unreachable_code(&mut self, id: StmtId, function_name: &str)
+    {
+        let ast = self.ast;
+        match ast.stmt(id)
+        {
+            Stmt::Block(stmts) => self.unreachable_code(stmts, function_name),
+            Stmt::If { then_branch, else_branch, .. } =>
+                {
+                    self.nested_unreachable_code(*then_branch, function_name);
+                    if let Some(else_branch) = else_branch
+                    {
+                        self.nested_unreachable_code(*else_branch, function_name);
+                    }
+                }
+            Stmt::While { body, .. } => self.nested_unreachable_code(*body, function_name),
+            _ => {}
+        }
+    }
+
+    /// 文の後ろに制御が進まない場合, その原因になった文の種類を返す
+    fn terminates(&self, id: StmtId) -> Option<&'static str>
+    {
+        match self.ast.stmt(id)
+        {
+            Stmt::Return(_) => Some("return"),
+            Stmt::Break => Some("break"),
+            Stmt::Continue => Some("continue"),
+            Stmt::Block(stmts) => stmts.iter().find_map(|stmt| self.terminates(*stmt)),
+            Stmt::If { then_branch, else_branch: Some(else_branch), .. } =>
+                {
+                    let then_kind = self.terminates(*then_branch)?;
+                    self.terminates(*else_branch)?;
+                    Some(then_kind)
+                }
+            _ => None,
+        }
+    }
+
+    /// main とグローバル変数の初期化子から呼び出されない関数. main がない場合は調べない
+    fn uncalled_functions(&mut self, functions: &[IrFunction])
+    {
+        if !functions.iter().any(|function| function.name == "main")
+        {
+            return;
+        }
+
+        let mut called: HashSet<&str> = HashSet::new();
+        let mut stack = vec!["main", IrFunction::INITIALIZER];
+        while let Some(name) = stack.pop()
+        {
+            if !called.insert(name)
+            {
+                continue;
+            }
+            let Some(function) = functions.iter().find(|function| function.name == name) else { continue };
+            for instruction in function.blocks.iter().flat_map(|block| block.instructions.iter())
+            {
+                if let Instruction::Call { name, .. } = instruction
+                {
+                    stack.push(name);
+                }
+            }
+        }
+
+        for function in functions.iter()
+        {
+            if !called.contains(function.name.as_str())
+            {
+                self.warning(format!("呼び出されていない関数です : {}", function.name));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::lexical::Lexer;
+    use crate::lint::Linter;
+    use crate::parser::Parser;
+
+    fn lint(program: &str) -> Vec<String>
+    {
+        let mut lexer = Lexer::new(program.to_string());
+        lexer.tokenize();
+
+        let mut parser = Parser::with_lines(lexer.tokens().clone(), lexer.lines().clone());
+        parser.parse();
+        Linter::new(parser.ast()).lint().iter().map(|diagnostic| diagnostic.to_string()).collect()
+    }
+
+    #[test]
+    fn test_lint()
+    {
+        assert_eq!(lint("
+int total = 0;
+int helper(int unused) {
+    return 1;
+}
+int twice(int n) {
+    return n * 2;
+    total = 3;
+}
+int main(void) {
+    int sum;
+    int i = 0;
+    int written;
+    written = 1;
+    while (i < 10) {
+        if (i == 5) {
+            break;
+            i = 0;
+        }
+        sum = sum + i;
+        i = i + 1;
+    }
+    if (i > 0) {
+        return sum;
+    } else {
+        return 0;
+    }
+    return total;
+}"), vec![
+            "warning: 使われていない引数です : unused (関数 helper)",
+            "warning: 到達できないコードです : return の後 (関数 twice, 8 行目)",
+            "warning: 使われていない変数です : written (関数 main)",
+            "warning: 初期化されていない可能性のある変数を使用しています : sum (関数 main)",
+            "warning: 到達できないコードです : break の後 (関数 main, 18 行目)",
+            "warning: 到達できないコードです : return の後 (関数 main, 28 行目)",
+            "warning: 呼び出されていない関数です : helper",
+            "warning: 呼び出されていない関数です : twice",
+        ]);
+    }
+
+    #[test]
+    fn test_initialized_on_every_path()
+    {
+        assert_eq!(lint("
+int f(int n) {
+    int x;
+    if (n > 0) {
+        x = 1;
+    } else {
+        x = 2;
+    }
+    int y;
+    if (n > 1) {
+        y = 1;
+    }
+    return x + y;
+}"), vec![
+            "warning: 初期化されていない可能性のある変数を使用しています : y (関数 f)",
+        ]);
+    }
+
+    #[test]
+    fn test_unused_static_local()
+    {
+        assert_eq!(lint("
+int counter(void) {
+    static int unused = 3;
+    static int count;
+    count = count + 1;
+    return count;
+}
+int main(void) {
+    return counter();
+}"), vec![
+            "warning: 使われていない変数です : unused (関数 counter)",
+        ]);
+    }
+}
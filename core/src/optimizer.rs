@@ -0,0 +1,361 @@
+use crate::ast::{Ast, Decl, Expr, ExprId, NodeId, SideTable, Stmt, StmtId};
+use crate::interpreter::{ArithmeticMode, Interpreter, VariableType};
+use crate::lexical::{Operator, UnaryOperator, ValueType};
+use crate::semantic::SemanticAnalyzer;
+
+/// 構文木を書き換えて実行前に計算できる部分を取り除く.
+/// 定数の畳み込み, 条件が定数の if/while の除去, 型が変わらない場合の代数的な簡約を行う
+pub struct Optimizer
+{
+    ast: Ast,
+
+    // 最適化前の式の型. 意味解析でエラーになった式は型が入っていない
+    types: SideTable<ExprId, ValueType>,
+}
+
+impl Optimizer
+{
+    pub fn new(ast: &Ast) -> Self
+    {
+        let mut analyzer = SemanticAnalyzer::new(ast);
+        analyzer.analyze();
+
+        let mut types = SideTable::new();
+        for index in 0..ast.expr_count()
+        {
+            let id = ExprId::new(index);
+            if let Some(value_type) = analyzer.expr_type(id)
+            {
+                types.insert(id, value_type.clone());
+            }
+        }
+
+        Optimizer { ast: ast.clone(), types }
+    }
+
+    /// 最適化した構文木を返す
+    pub fn optimize(mut self) -> Ast
+    {
+        let declarations = self.ast.declarations().clone();
+        for declaration in declarations.iter()
+        {
+            match declaration
+            {
+                Decl::Variable { initializer: Some(initializer), .. } =>
+                    {
+                        self.expression(*initializer);
+                    }
+                Decl::Variable { initializer: None, .. } | Decl::Prototype(_) => {}
+                Decl::Function(function) =>
+                    {
+                        for stmt in function.body.iter()
+                        {
+                            self.statement(*stmt);
+                        }
+                    }
+            }
+        }
+        self.ast
+    }
+
+    fn statement(&mut self, id: StmtId)
+    {
+        match self.ast.stmt(id).clone()
+        {
+            Stmt::Declaration(Decl::Variable { initializer: Some(initializer), .. }) =>
+                {
+                    self.expression(initializer);
+                }
+            Stmt::Declaration(_) => {}
+            Stmt::Assignment { value, .. } =>
+                {
+                    self.expression(value);
+                }
+            Stmt::Expression(expr) =>
+                {
+                    self.expression(expr);
+                }
+            Stmt::Block(stmts) =>
+                {
+                    for stmt in stmts.iter()
+                    {
+                        self.statement(*stmt);
+                    }
+                }
+            Stmt::If { condition, then_branch, else_branch } =>
+                {
+                    let condition = self.expression(condition);
+                    self.statement(then_branch);
+                    if let Some(else_branch) = else_branch
+                    {
+                        self.statement(else_branch);
+                    }
+
+                    // 条件が定数なら実行される側の文だけを残す
+                    if let Some(condition) = condition
+                    {
+                        let taken = if Self::is_true(&condition) { Some(then_branch) } else { else_branch };
+                        let stmt = match taken
+                        {
+                            Some(taken) => self.ast.stmt(taken).clone(),
+                            None => Stmt::Block(Vec::new()),
+                        };
+                        self.ast.replace_stmt(id, stmt);
+                    }
+                }
+            Stmt::While { condition, body } =>
+                {
+                    let condition = self.expression(condition);
+                    self.statement(body);
+
+                    // 一度も実行されないループは取り除く
+                    if let Some(condition) = condition
+                    {
+                        if !Self::is_true(&condition)
+                        {
+                            self.ast.replace_stmt(id, Stmt::Block(Vec::new()));
+                        }
+                    }
+                }
+            Stmt::Return(Some(value)) =>
+                {
+                    self.expression(value);
+                }
+            Stmt::Return(None) | Stmt::Break | Stmt::Continue => {}
+        }
+    }
+
+    /// 式を畳み込み, 定数になった場合はその値を返す
+    fn expression(&mut self, id: ExprId) -> Option<VariableType>
+    {
+        match self.ast.expr(id).clone()
+        {
+            Expr::Constant(value) => Some(Interpreter::constant(&value)),
+            Expr::Identifier(_) => None,
+            Expr::Unary { op, operand } =>
+                {
+                    let operand = self.expression(operand)?;
+                    // -INT_MIN は実行時と同じくオーバーフローさせるため畳み込まない
+                    let value_type = Interpreter::value_type(&operand).promoted();
+                    if op == UnaryOperator::Minus && Interpreter::is_signed_integer(&value_type)
+                        && !Interpreter::fits(&value_type, -Interpreter::to_i128(&operand))
+                    {
+                        return None;
+                    }
+                    self.fold(id, Interpreter::unary_operation(&op, operand, ArithmeticMode::Wrapping, None).ok()?)
+                }
+            Expr::Binary { op, lhs, rhs } => self.binary(id, op, lhs, rhs),
+            Expr::Cast { value_type, operand } =>
+                {
+                    let operand = self.expression(operand)?;
+                    if value_type == ValueType::Void
+                    {
+                        return None;
+                    }
+                    self.fold(id, Interpreter::cast(&value_type, operand))
+                }
+            Expr::Call { arguments, .. } =>
+                {
+                    for argument in arguments.iter()
+                    {
+                        self.expression(*argument);
+                    }
+                    None
+                }
+            Expr::ArrayAccess { index, .. } =>
+                {
+                    if let Some(index) = index
+                    {
+                        self.expression(index);
+                    }
+                    None
+                }
+        }
+    }
+
+    fn binary(&mut self, id: ExprId, op: Operator, lhs: ExprId, rhs: ExprId) -> Option<VariableType>
+    {
+        let lhs_value = self.expression(lhs);
+
+        // '&&' と '||' は左辺で結果が決まる場合は右辺ごと取り除く
+        match (&op, &lhs_value)
+        {
+            (Operator::LogicalAnd, Some(value)) if !Self::is_true(value) =>
+                return self.fold(id, VariableType::Int(0)),
+            (Operator::LogicalOr, Some(value)) if Self::is_true(value) =>
+                return self.fold(id, VariableType::Int(1)),
+            _ => {}
+        }
+
+        let rhs_value = self.expression(rhs);
+        match (lhs_value, rhs_value)
+        {
+            (Some(lhs_value), Some(rhs_value)) =>
+                {
+                    // 0 除算やオーバーフローは実行時のエラーとして残す
+                    if !Self::can_fold(&op, &lhs_value, &rhs_value)
+                    {
+                        return None;
+                    }
+                    self.fold(id, Interpreter::binary_operation(&op, lhs_value, rhs_value, ArithmeticMode::Wrapping, None).ok()?)
+                }
+            (None, Some(rhs_value)) =>
+                {
+                    if self.is_identity(&op, lhs, &rhs_value, false)
+                    {
+                        self.ast.replace_expr(id, self.ast.expr(lhs).clone());
+                    }
+                    None
+                }
+            (Some(lhs_value), None) =>
+                {
+                    if self.is_identity(&op, rhs, &lhs_value, true)
+                    {
+                        self.ast.replace_expr(id, self.ast.expr(rhs).clone());
+                    }
+                    None
+                }
+            (None, None) => None,
+        }
+    }
+
+    /// 式を定数に置き換える
+    fn fold(&mut self, id: ExprId, value: VariableType) -> Option<VariableType>
+    {
+        let constant = Interpreter::to_constant(&value)?;
+        self.ast.replace_expr(id, Expr::Constant(constant));
+        Some(value)
+    }
+
+    /// 実行時と同じ結果になる場合のみ畳み込む
+    fn can_fold(op: &Operator, lhs: &VariableType, rhs: &VariableType) -> bool
+    {
+        let common = ValueType::common(&Interpreter::value_type(lhs), &Interpreter::value_type(rhs));
+        let is_zero = Interpreter::truthy(Interpreter::cast(&common, rhs.clone())) == Ok(false);
+
+        match op
+        {
+            Operator::Divide | Operator::Modulo if is_zero => false,
+            // 符号付き整数のオーバーフローは実行時の算術モードに任せる
+            _ => !Interpreter::overflows(op, lhs, rhs),
+        }
+    }
+
+    /// `x + 0`, `x - 0`, `x * 1`, `x / 1` と `0 + x`, `1 * x` のように結果が x そのものになるか.
+    /// 型が変わる場合と, -0.0 + 0 のように float で値が変わる場合は簡約しない
+    fn is_identity(&self, op: &Operator, operand: ExprId, constant: &VariableType, constant_is_lhs: bool) -> bool
+    {
+        let operand_type = match self.types.get(operand)
+        {
+            Some(operand_type) => operand_type,
+            None => return false,
+        };
+        // 通常の算術変換で x の型が変わらない定数のみ簡約できる
+        if *operand_type == ValueType::Void
+            || ValueType::common(operand_type, &Interpreter::value_type(constant)) != *operand_type
+        {
+            return false;
+        }
+        let is_zero = Interpreter::to_f64(constant) == 0.0;
+        let is_one = Interpreter::to_f64(constant) == 1.0;
+
+        match (op, constant_is_lhs)
+        {
+            (Operator::Plus, _) => is_zero && operand_type.is_integer(),
+            (Operator::Minus, false) => is_zero,
+            (Operator::Multiply, _) | (Operator::Divide, false) => is_one,
+            _ => false,
+        }
+    }
+
+    fn is_true(value: &VariableType) -> bool
+    {
+        Interpreter::truthy(value.clone()).unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::interpreter::{Interpreter, VariableType};
+    use crate::lexical::Lexer;
+    use crate::optimizer::Optimizer;
+    use crate::parser::Parser;
+
+    fn optimize(program: &str) -> Vec<String>
+    {
+        let mut lexer = Lexer::new(program.to_string());
+        lexer.tokenize();
+
+        let mut parser = Parser::new(lexer.tokens().clone());
+        parser.parse();
+
+        let ast = Optimizer::new(parser.ast()).optimize();
+        ast.declarations().iter().map(|declaration| ast.format_decl(declaration)).collect()
+    }
+
+    #[test]
+    fn test_constant_folding()
+    {
+        assert_eq!(optimize("
+int x = (10 + 20) * 3 - 4 / 2;
+float y = 1 + 2.5;
+int z = 7 / 2 + !0;
+int w = 0 && f();
+int d = 1 / 0;
+int o = 2147483647 + 1;
+"), vec![
+            "(Int x 88)",
+            "(Float y 3.5)",
+            "(Int z 4)",
+            "(Int w 0)",
+            "(Int d (/ 1 0))",
+            "(Int o (+ 2147483647 1))",
+        ]);
+    }
+
+    #[test]
+    fn test_branches_and_simplification()
+    {
+        assert_eq!(optimize("
+int f(int n, float r) {
+    if (1 < 2) { n = n * 1; } else { n = 0; }
+    if (0) { n = 1; }
+    while (2 - 2) { n = n + 1; }
+    r = r + 0;
+    r = r - 0;
+    return 0 + n / 1;
+}"), vec![
+            "(Int f(Int n, Float r) {{(= n n)} {} {} (= r (+ r 0)) (= r r) (return n)})",
+        ]);
+    }
+
+    #[test]
+    fn test_same_result()
+    {
+        let program = "
+int g = 3 * 4;
+int f(int n) {
+    int sum = 0 * 5;
+    while (n > 0 || 0) {
+        if (n % 2 == 1 && 1) { sum = sum + n * 1; }
+        n = n - 1 + 0;
+    }
+    return sum + g;
+}";
+        let mut lexer = Lexer::new(program.to_string());
+        lexer.tokenize();
+
+        let mut parser = Parser::new(lexer.tokens().clone());
+        parser.parse();
+
+        let optimized = Optimizer::new(parser.ast()).optimize();
+        let mut interpreter = Interpreter::new(parser.ast());
+        let mut optimized_interpreter = Interpreter::new(&optimized);
+        for n in 0..6
+        {
+            assert_eq!(interpreter.call("f", &[VariableType::Int(n)]),
+                       optimized_interpreter.call("f", &[VariableType::Int(n)]));
+        }
+    }
+}
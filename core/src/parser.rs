@@ -1,349 +1,126 @@
-use crate::lexical::Operator;
-use std::cell::{Ref, RefCell};
-use std::rc::{Rc, Weak};
-use crate::lexical::{Constant, Token, ValueType, UnaryOperator};
-
+use crate::lexical::{Constant, Operator, UnaryOperator};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use crate::lexical::{Token, ValueType};
+use crate::ast::{Ast, Decl, Expr, ExprId, Function, Parameter, Stmt, StmtId};
+use crate::interpreter::{ArithmeticMode, Interpreter, VariableType};
+use crate::semantic::SemanticAnalyzer;
+
+/// 変数と列挙子が共有する名前空間に登録された名前
 #[derive(Debug, Clone)]
-pub struct FunctionCall {
-    // 呼び出す関数名
-    name: String,
-
-    // 引数のリスト, 0個以上の logical_or_expression が入る
-    arguments: Vec<Rc<RefCell<Node>>>,
-}
+enum Symbol {
+    // 宣言済みの変数. 型は sizeof の式の型を求めるのに使う.
+    // name は実際に参照する名前で, static なローカル変数ではグローバル変数としての名前になる.
+    // 定数式で初期化された const 変数は value に値を持ち, 定数式の中で使える
+    Variable { value_type: ValueType, name: String, is_const: bool, value: Option<VariableType> },
 
-impl FunctionCall
-{
-    pub fn new(name: String) -> Self {
-        FunctionCall {
-            name,
-            arguments: Vec::new(),
-        }
-    }
+    // 列挙子. 式の中では int の定数に置き換える
+    Enumerator(i32),
 
-    pub fn add_argument(&mut self, argument: Rc<RefCell<Node>>) {
-        self.arguments.push(argument);
-    }
-
-    pub fn arguments(&self) -> &Vec<Rc<RefCell<Node>>> {
-        &self.arguments
-    }
-
-    pub fn name(&self) -> &String {
-        &self.name
-    }
+    // typedef で定義した型名
+    Typedef(ValueType),
 }
 
-#[derive(Debug, Clone)]
-pub struct Declaration {
-    // 型
-    type_specifier: Token,
-
-    // 識別子
-    identify: Token,
+/// グローバルかブロックごとのスコープ
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    symbols: HashMap<String, Symbol>,
 
-    // 初期化子
-    initializer: Option<Rc<RefCell<Node>>>,
+    // 列挙型のタグは変数とは別の名前空間になる
+    tags: HashSet<String>,
 }
 
 #[derive(Debug, Clone)]
-pub struct Argument {
-    // 型
-    type_specifier: ValueType,
-
-    // 識別子
-    identify: String,
-}
-
-impl Argument {
-    pub fn new(type_specifier: ValueType, identify: String) -> Self {
-        Argument {
-            type_specifier,
-            identify,
-        }
-    }
-
-    pub fn type_specifier(&self) -> &ValueType {
-        &self.type_specifier
-    }
+pub struct Parser {
+    tokens: Vec<Token>,
 
-    pub fn identify(&self) -> &String {
-        &self.identify
-    }
-}
+    // トークンごとの行番号. 空の場合は行番号を記録しない
+    lines: Vec<usize>,
+    ast: Ast,
+    token_index: usize,
 
-#[derive(Debug, Clone)]
-pub struct FunctionDefinition {
-    // 型
-    type_specifier: ValueType,
+    // 宣言済みの変数, 列挙子と列挙型のタグ. 先頭がグローバル, 以降はブロックごとに積む
+    scopes: Vec<Scope>,
 
-    // 識別子
-    identify: String,
+    // 宣言済みの関数の戻り値の型
+    function_types: HashMap<String, ValueType>,
 
-    // 引数のリスト
-    arguments: Vec<Argument>,
+    // グローバル変数に置き換えた static なローカル変数. 関数の定義より前に宣言として追加する
+    static_variables: Vec<Decl>,
 
-    // 関数の中身
-    body: Vec<Rc<RefCell<Node>>>,
+    // 置き換えた static なローカル変数の数. グローバル変数の名前を一意にするのに使う
+    static_count: usize,
 }
 
-impl FunctionDefinition {
-    pub fn new() -> Self {
-        FunctionDefinition {
-            type_specifier: ValueType::Void,
-            identify: String::new(),
-            arguments: Vec::new(),
-            body: Vec::new(),
-        }
-    }
-
-    pub fn body(&self) -> &Vec<Rc<RefCell<Node>>> {
-        &self.body
-    }
-
-    pub fn name(&self) -> &String {
-        &self.identify
-    }
-
-    pub fn arguments(&self) -> &Vec<Argument> {
-        &self.arguments
-    }
-
-    pub fn set_type_specifier(&mut self, type_specifier: ValueType) {
-        self.type_specifier = type_specifier;
-    }
+/// static なローカル変数を置き換えたグローバル変数の名前の接頭辞. '__' で始まる名前は C では予約されている
+const STATIC_LOCAL_PREFIX: &str = "__static";
 
-    pub fn set_identify(&mut self, identify: String) {
-        self.identify = identify;
-    }
-
-    pub fn add_argument(&mut self, type_specifier: ValueType, identify: String) {
-        self.arguments.push(Argument::new(type_specifier, identify));
-    }
-
-    pub fn add_body(&mut self, body: Rc<RefCell<Node>>) {
-        self.body.push(body);
+/// static なローカル変数を置き換えたグローバル変数の名前から, ソース上の変数名を返す.
+/// それ以外の名前の場合は None を返す
+pub fn static_local_name(storage: &str) -> Option<&str> {
+    let (count, name) = storage.strip_prefix(STATIC_LOCAL_PREFIX)?.split_once('_')?;
+    if count.is_empty() || !count.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
     }
+    Some(name)
 }
 
-#[derive(Debug, Clone)]
-pub enum Leaf
-{
-    Node(Rc<RefCell<Node>>),
-    Declaration(ValueType),
-    FunctionDefinition(FunctionDefinition),
-    UnaryExpression(UnaryOperator),
-    FunctionCall(FunctionCall),
-    ArrayAccess,
-    ParenthesizedExpression,
-
-    // {, }
-    BlockItem(Vec<Rc<RefCell<Node>>>),
-
-    // 分岐
-    IfStatement(Rc<RefCell<Node>>),
-    
-    // ループ
-    WhileStatement,
-
-    // 代入
-    Assignment,
-
-    // jump系の文
-    Return,
-    Break,
-    Continue,
-
-    // 識別子
-    Identifier(String),
-
-    // 演算子
-    Operator(Operator),
-
-    // 定数
-    Constant(Constant),
+/// 宣言の先頭の 'const', 'static' 修飾子
+#[derive(Debug, Clone, Copy, Default)]
+struct Qualifiers {
+    is_const: bool,
+    is_static: bool,
 }
 
-// Leaf の format 出力
-impl std::fmt::Display for Leaf
+impl Parser
 {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
-    {
-        match self
-        {
-            Leaf::Node(node) => write!(f, "Node [{:?}]", node.borrow().val()),
-            Leaf::Declaration(declaration) => write!(f, "Declaration [{:?}]", declaration),
-            Leaf::FunctionDefinition(function_definition) => write!(f, "FunctionDefinition [{:?}]", function_definition.name()),
-            Leaf::UnaryExpression(operator) => write!(f, "UnaryExpression [{:?}]", operator),
-            Leaf::FunctionCall(function_call) => write!(f, "FunctionCall [{:?}]", function_call),
-            Leaf::ArrayAccess => write!(f, "ArrayAccess"),
-            Leaf::ParenthesizedExpression => write!(f, "ParenthesizedExpression"),
-            Leaf::BlockItem(_) => write!(f, "BlockItem"),
-            Leaf::IfStatement(_) => write!(f, "IfStatement"),
-            Leaf::Assignment => write!(f, "Assignment"),
-            Leaf::Return => write!(f, "Return"),
-            Leaf::Break => write!(f, "Break"),
-            Leaf::Continue => write!(f, "Continue"),
-            Leaf::Identifier(identifier) => write!(f, "Identifier [{:?}]", identifier),
-            Leaf::Operator(operator) => write!(f, "Operator [{:?}]", operator),
-            Leaf::Constant(constant) => write!(f, "Constant [{:?}]", constant),
-            Leaf::WhileStatement => write!(f, "WhileStatement"),
-        }
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Self::with_lines(tokens, Vec::new())
     }
-}
-
-/// 構文木
-#[derive(Debug, Clone)]
-pub struct Node {
-    lhs: Option<Rc<RefCell<Node>>>,
-    rhs: Option<Rc<RefCell<Node>>>,
-    val: Option<Leaf>,
-    parent: Weak<RefCell<Node>>,
-}
 
-impl Node {
-    pub fn new() -> Self {
-        Node {
-            lhs: None,
-            rhs: None,
-            val: None,
-            parent: Weak::new(),
+    /// 行番号付きのトークン列から構文解析器を作る. 行番号は文と式ごとに Ast に記録される
+    pub fn with_lines(tokens: Vec<Token>, lines: Vec<usize>) -> Self {
+        Parser {
+            tokens,
+            lines,
+            ast: Ast::new(),
+            token_index: 0,
+            scopes: vec![Scope::default()],
+            function_types: HashMap::new(),
+            static_variables: Vec::new(),
+            static_count: 0,
         }
     }
 
-    pub fn val(&self) -> Option<&Leaf> {
-        self.val.as_ref()
-    }
-
-    pub fn lhs(&self) -> Option<&Rc<RefCell<Node>>> {
-        self.lhs.as_ref()
-    }
-
-    pub fn rhs(&self) -> Option<&Rc<RefCell<Node>>> {
-        self.rhs.as_ref()
+    /// 構文木. ノードは Ast 内に格納され ID で参照する
+    pub fn ast(&self) -> &Ast {
+        &self.ast
     }
 
-    pub fn get_lhs_and_rhs(&self)
-                           -> Option<(&Rc<RefCell<Node>>, &Rc<RefCell<Node>>)>
-    {
-        self.lhs().zip(self.rhs())
+    /// トップレベルの宣言
+    pub fn declarations(&self) -> &Vec<Decl> {
+        self.ast.declarations()
     }
 
-    pub fn show_node(root: &Node)
-    {
-        if let Some(leaf) = &root.val {
-            match leaf {
-                Leaf::Declaration(declaration) => {
-                    println!("Declaration [{:?}]", declaration);
-                }
-                Leaf::FunctionDefinition(function_definition) => {
-                    println!("FunctionDefinition [{:?}]", function_definition.name());
-                }
-                Leaf::UnaryExpression(operator) => {
-                    println!("UnaryExpression [{:?}]", operator);
-                }
-                Leaf::FunctionCall(function_call) => {
-                    println!("FunctionCall [{:?}]", function_call);
-                }
-                Leaf::ArrayAccess => {
-                    println!("ArrayAccess [{:?}]", leaf);
-                }
-                Leaf::ParenthesizedExpression => {
-                    println!("ParenthesizedExpression");
-                }
-                Leaf::Node(node) => {
-                    Node::show_node(&node.borrow());
-                }
-                Leaf::Identifier(identifier) => {
-                    println!("Identifier [{:?}]", identifier);
-                }
-                Leaf::Operator(operator) => {
-                    println!("Operator [{:?}]", operator);
-                }
-                Leaf::Constant(constant) => {
-                    println!("Constant [{:?}]", constant);
-                }
-                Leaf::Return => {
-                    println!("Return");
-                }
-                Leaf::Break => {
-                    println!("Break");
-                }
-                Leaf::Continue => {
-                    println!("Continue");
-                }
-                Leaf::Assignment => {
-                    println!("Assignment");
-                }
-                Leaf::IfStatement(_) => {
-                    println!("IfStatement");
-                }
-                Leaf::BlockItem(_) => {
-                    println!("BlockItem");
-                }
-                Leaf::WhileStatement => {
-                    println!("WhileStatement");
-                }
-            }
-        }
-
-        if let Some(lhs) = &root.lhs {
-            print!("lhs : ");
-            Node::show_node(&lhs.borrow());
-        }
-
-        if let Some(rhs) = &root.rhs {
-            print!("rhs : ");
-            Node::show_node(&rhs.borrow());
+    /// 式を追加し, 直前に読んだトークンの行を記録する
+    fn add_expr(&mut self, expr: Expr) -> ExprId {
+        let id = self.ast.add_expr(expr);
+        if let Some(line) = self.token_index.checked_sub(1).and_then(|index| self.lines.get(index)) {
+            self.ast.set_expr_line(id, *line);
         }
+        id
     }
 
-    pub fn set_parent(&mut self, parent: &Rc<RefCell<Node>>) {
-        self.parent = Rc::downgrade(&parent);
-    }
-
-    pub fn set_lhs(&mut self, node: Rc<RefCell<Node>>) {
-        self.lhs = Some(node);
-    }
-
-    pub fn set_rhs(&mut self, node: Rc<RefCell<Node>>) {
-        self.rhs = Some(node);
-    }
-
-    pub fn set_val(&mut self, leaf: Leaf) {
-        self.val = Some(leaf);
-    }
-}
-
-#[derive(Debug, Clone)]
-pub struct Parser {
-    tokens: Vec<Token>,
-    roots: Vec<Rc<RefCell<Node>>>,
-    token_index: usize,
-}
-
-impl Parser
-{
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Parser {
-            tokens,
-            roots: Vec::new(),
-            token_index: 0,
+    /// 文の開始位置のトークンの行を記録する
+    fn set_stmt_line(&mut self, id: StmtId, start: usize) {
+        if let Some(line) = self.lines.get(start) {
+            self.ast.set_stmt_line(id, *line);
         }
     }
 
-    pub fn roots(&self) -> &Vec<Rc<RefCell<Node>>> {
-        &self.roots
-    }
-
-    pub fn root(&self) -> &Rc<RefCell<Node>> {
-        self.roots.first().unwrap()
-    }
-
     fn get_next_token(&mut self) -> Option<Token>
     {
         if self.token_index < self.tokens.len() {
-            println!("token_index: {}, Token : {:?}", self.token_index, self.tokens[self.token_index]);
             let result = Some(self.tokens[self.token_index].clone());
             self.token_index += 1;
             result
@@ -355,9 +132,6 @@ impl Parser
     fn get_next_token_without_increment(&self) -> Option<Token>
     {
         if self.token_index < self.tokens.len() {
-            println!("token_index without: {}, Token : {:?}", self.token_index, self
-                .tokens[self
-                .token_index]);
             Some(self.tokens[self.token_index].clone())
         } else {
             None
@@ -369,6 +143,22 @@ impl Parser
         self.token_index += 1;
     }
 
+    /// エラー表示用に現在のトークンを取得する
+    fn current_token(&self) -> Option<&Token>
+    {
+        self.tokens.get(self.token_index)
+    }
+
+    /// 次のトークンが expected であることを確認して読み進める
+    fn expect(&mut self, expected: Token, name: &str)
+    {
+        if self.get_next_token_without_increment() == Some(expected) {
+            self.token_index_increment();
+        } else {
+            panic!("'{}' が見つかりませんでした : {:?}", name, self.current_token());
+        }
+    }
+
     pub fn parse(&mut self)
     {
         self.translation_unit();
@@ -376,388 +166,301 @@ impl Parser
 
     fn translation_unit(&mut self)
     {
+        // トークンがなくなるまで繰り返す
         while self.token_index < self.tokens.len() {
-            println!("TranslationUnit token_index: {}", self.token_index);
-            // トークンがなくなるまで繰り返す
-            self.external_declaration();
+            let declarations = self.external_declaration();
+            for declaration in self.static_variables.drain(..).chain(declarations) {
+                self.ast.add_declaration(declaration);
+            }
         }
     }
 
-    /// 関数定義かグローバル変数定義かを判定する
-    fn external_declaration(&mut self)
+    /// 関数定義かグローバル変数定義かを判定する. 変数定義は宣言子ごとに分けて返す
+    fn external_declaration(&mut self) -> Vec<Decl>
     {
-        // 関数の場合は type_specifier, identify, ( となり '(' が続く場合は関数として処理する
-
-        let type_specifier = self.tokens[self.token_index].clone();
-        let identify = self.tokens[self.token_index + 1].clone();
-        let next_token = self.tokens[self.token_index + 2].clone();
-
-        if next_token == Token::LeftParen {
-            println!("function_definition");
-            self.function_definition();
+        // 関数の場合は type_specifier, identify, ( となり '(' が続く場合は関数として処理する.
+        // typedef は関数にならない
+        let index = self.skip_type_specifier(self.token_index);
+        if self.get_next_token_without_increment() != Some(Token::Typedef)
+            && self.tokens.get(index + 1) == Some(&Token::LeftParen)
+        {
+            vec![self.function_definition()]
         } else {
-            println!("declaration : {}", self.token_index);
-            let root = self.declaration();
-            self.roots.push(root);
+            self.declaration()
         }
     }
 
-    fn function_definition(&mut self)
+    /// 関数定義かプロトタイプ宣言. ')' の後に ';' が続く場合はプロトタイプになる
+    fn function_definition(&mut self) -> Decl
     {
-        let mut function_definition = FunctionDefinition::new();
-
-        // 関数定義の型を取得
-        if let Some(Token::Type(type_specifier)) = self.get_next_token() {
-            function_definition.set_type_specifier(type_specifier);
-        } else {
-            panic!("型が見つかりませんでした : {:?}", self.tokens[self.token_index]);
-        }
+        // 関数定義の型を取得. プログラムは 1 ファイルだけなので static による内部結合は区別しない
+        let (return_type, _) = self.declaration_specifiers();
 
         // 関数定義の識別子を取得
-        if let Some(Token::Identifier(identifier)) = self.get_next_token() {
-            function_definition.set_identify(identifier);
+        let name = if let Some(Token::Identifier(identifier)) = self.get_next_token() {
+            identifier
         } else {
-            panic!("識別子が見つかりませんでした : {:?}", self.tokens[self.token_index]);
-        }
+            panic!("識別子が見つかりませんでした : {:?}", self.current_token());
+        };
+        Self::check_reserved(&name);
 
         // 関数定義の引数リストを取得
-        if let Some(Token::LeftParen) = self.get_next_token() {
-            // 引数がない場合は ')' が来る
-            if let Some(Token::RightParen) = self.get_next_token_without_increment() {
-                self.token_index_increment();
-            } else {
-                self.parameter_list(&mut function_definition);
-                // ')' が来ることを確認
-                if let Some(Token::RightParen) = self.get_next_token() {
-                    // 何もしない
-                } else {
-                    panic!("')' が見つかりませんでした : {:?}", self.tokens[self.token_index]);
-                }
-            }
+        let mut parameters = Vec::new();
+        self.expect(Token::LeftParen, "(");
+        let constants = self.parameter_list(&mut parameters);
+        self.expect(Token::RightParen, ")");
+        self.function_types.insert(name.clone(), return_type.clone());
+
+        if self.get_next_token_without_increment() == Some(Token::Semicolon) {
+            self.token_index_increment();
+            return Decl::Prototype(Rc::new(Function {
+                return_type,
+                name,
+                parameters,
+                body: Vec::new(),
+            }));
         }
 
-        // 関数定義の本体を取得. '{', '}' の処理は compound_statement 内部で行う
-        let roots = self.compound_statement();
-        function_definition.body = roots;
+        // 関数定義の本体を取得. 引数は本体の一番外側のブロックと同じスコープに入る
+        self.scopes.push(Scope::default());
+        for (parameter, is_const) in parameters.iter().zip(constants) {
+            self.declare(&parameter.name, Symbol::Variable {
+                value_type: parameter.type_specifier.clone(),
+                name: parameter.name.clone(),
+                is_const,
+                value: None,
+            });
+        }
+        let body = self.block();
+        self.scopes.pop();
 
-        let root = Rc::new(RefCell::new(Node::new()));
-        root.borrow_mut().set_val(Leaf::FunctionDefinition(function_definition));
+        Decl::Function(Rc::new(Function {
+            return_type,
+            name,
+            parameters,
+            body,
+        }))
+    }
 
-        self.roots.push(root);
+    fn compound_statement(&mut self) -> Vec<StmtId>
+    {
+        self.scopes.push(Scope::default());
+        let stmts = self.block();
+        self.scopes.pop();
+        stmts
     }
 
-    fn compound_statement(&mut self) -> Vec<Rc<RefCell<Node>>>
+    /// '{' から '}' までの文の並び. スコープは呼び出し側で用意する
+    fn block(&mut self) -> Vec<StmtId>
     {
-        let mut roots: Vec<Rc<RefCell<Node>>> = Vec::new();
-        println!("compound_statement");
+        let mut stmts = Vec::new();
+
         // '{' が来ることを確認
-        if let Some(Token::LeftBrace) = self.get_next_token() {
-            // 何もしない
-        } else {
-            panic!("'{{' が見つかりませんでした : {:?}", self.tokens[self.token_index]);
-        }
+        self.expect(Token::LeftBrace, "{");
 
         // '}' が来るまで繰り返す
         loop {
-            if let Some(Token::RightBrace) = self.get_next_token_without_increment() {
-                break;
+            match self.get_next_token_without_increment() {
+                Some(Token::RightBrace) => break,
+                None => panic!("'}}' が見つかりませんでした : {:?}", self.current_token()),
+                _ => self.block_item(&mut stmts),
             }
-
-            let root = self.block_item();
-            roots.push(root);
         }
 
         // '}' が来ることを確認
-        if let Some(Token::RightBrace) = self.get_next_token() {
-            // 何もしない
-        } else {
-            panic!("'}}' が見つかりませんでした : {:?}", self.tokens[self.token_index]);
-        }
+        self.expect(Token::RightBrace, "}");
 
-        roots
+        stmts
     }
 
-    fn block_item(&mut self) -> Rc<RefCell<Node>>
+    fn block_item(&mut self, stmts: &mut Vec<StmtId>)
     {
-        let mut root = Rc::new(RefCell::new(Node::new()));
-
-        if let Some(next_token) = self.get_next_token_without_increment()
+        match self.get_next_token_without_increment()
         {
-            match next_token
-            {
-                // 変数定義の場合
-                Token::Type(type_specifier) => {
-                    root = self.declaration();
-                }
-                _ => {
-                    root = self.statement();
+            // 変数定義の場合. 宣言子ごとに 1 つの文にする
+            Some(token) if self.is_declaration_specifier(&token) => {
+                let start = self.token_index;
+                for declaration in self.declaration() {
+                    let stmt = self.ast.add_stmt(Stmt::Declaration(declaration));
+                    self.set_stmt_line(stmt, start);
+                    stmts.push(stmt);
                 }
             }
+            _ => stmts.push(self.statement()),
         }
-
-        root
     }
 
-    fn statement(&mut self) -> Rc<RefCell<Node>>
+    fn statement(&mut self) -> StmtId
     {
-        let mut root = Rc::new(RefCell::new(Node::new()));
-
-        if let Some(next_token) = self.get_next_token_without_increment()
+        let start = self.token_index;
+        let stmt = match self.get_next_token_without_increment()
         {
-            match next_token
-            {
-                Token::LeftBrace => {
-                    // compound_statement の場合
-                    let roots = self.compound_statement();
-                    root.borrow_mut().set_val(Leaf::BlockItem(roots));
-                }
-                Token::If => {
-                    // if_statement の場合
-                    root = self.selection_statement();
-                }
-                Token::While => {
-                    // while_statement の場合
-                    root = self.iteration_statement();
-                }
-                Token::Return => {
-                    // jump_statement の場合
-                    root = self.jump_statement();
-                }
-                Token::Identifier(identifier) => {
-                    // expression_statement の場合
-                    root = self.expression_statement();
-                }
-                _ => {
-                    // expression_statement の場合
-                    unimplemented!("expression_statement");
-                }
+            // compound_statement の場合
+            Some(Token::LeftBrace) => {
+                let stmts = self.compound_statement();
+                self.ast.add_stmt(Stmt::Block(stmts))
             }
-        }
 
-        root
+            // if_statement の場合
+            Some(Token::If) => self.selection_statement(),
+
+            // while_statement の場合
+            Some(Token::While) => self.iteration_statement(),
+
+            // jump_statement の場合
+            Some(Token::Return) | Some(Token::Break) | Some(Token::Continue) => self.jump_statement(),
+
+            // expression_statement の場合
+            Some(_) => self.expression_statement(),
+            None => panic!("文が見つかりませんでした"),
+        };
+        self.set_stmt_line(stmt, start);
+        stmt
     }
 
-    fn iteration_statement(&mut self) -> Rc<RefCell<Node>>
+    fn iteration_statement(&mut self) -> StmtId
     {
-        let mut root = Rc::new(RefCell::new(Node::new()));
-        root.borrow_mut().set_val(Leaf::WhileStatement);
-        
         // 最初の while トークンを取得
-        if let Some(Token::While) = self.get_next_token()
-        {
-            // 何もしない
-        } else {
-            panic!("'while' が見つかりませんでした : {:?}", self.tokens[self.token_index]);
-        }
-        
-        // 次のトークンが '(' かどうか
-        if let Some(Token::LeftParen) = self.get_next_token()
-        {
-            // 何もしない
-        } else {
-            panic!("'(' が見つかりませんでした : {:?}", self.tokens[self.token_index]);
-        }
-        
+        self.expect(Token::While, "while");
+
         // 条件式を取得
-        if let Some(condition) = self.logical_or_expression(&root)
-        {
-            root.borrow_mut().set_lhs(condition);
-        } else {
-            panic!("条件式が見つかりませんでした : {:?}", self.tokens[self.token_index]);
-        }
-        
-        // 次のトークンが ')' かどうか
-        if let Some(Token::RightParen) = self.get_next_token()
-        {
-            // 何もしない
-        } else {
-            panic!("')' が見つかりませんでした : {:?}", self.tokens[self.token_index]);
-        }
-        
+        self.expect(Token::LeftParen, "(");
+        let condition = self.logical_or_expression();
+        self.expect(Token::RightParen, ")");
+
         // while の中身を取得
-        let statement = self.statement();
-        root.borrow_mut().set_rhs(statement);
+        let body = self.statement();
 
-        root
+        self.ast.add_stmt(Stmt::While { condition, body })
     }
 
-    fn selection_statement(&mut self) -> Rc<RefCell<Node>>
+    fn selection_statement(&mut self) -> StmtId
     {
-        let mut root = Rc::new(RefCell::new(Node::new()));
-
         // 最初の if トークンを取得
-        if let Some(Token::If) = self.get_next_token()
-        {
-            // 何もしない
-        } else {
-            panic!("'if' が見つかりませんでした : {:?}", self.tokens[self.token_index]);
-        }
-
-        // 次のトークンが '(' かどうか
-        if let Some(Token::LeftParen) = self.get_next_token()
-        {
-            // 何もしない
-        } else {
-            panic!("'(' が見つかりませんでした : {:?}", self.tokens[self.token_index]);
-        }
+        self.expect(Token::If, "if");
 
         // 条件式を取得
-        if let Some(condition) = self.logical_or_expression(&root)
-        {
-            root.borrow_mut().set_val(Leaf::IfStatement(condition));
-        } else {
-            panic!("条件式が見つかりませんでした : {:?}", self.tokens[self.token_index]);
-        }
-
-        // 次のトークンが ')' かどうか
-        if let Some(Token::RightParen) = self.get_next_token()
-        {
-            // 何もしない
-        } else {
-            panic!("')' が見つかりませんでした : {:?}", self.tokens[self.token_index]);
-        }
+        self.expect(Token::LeftParen, "(");
+        let condition = self.logical_or_expression();
+        self.expect(Token::RightParen, ")");
 
         // if の中身を取得
-        let true_statement = self.statement();
-        root.borrow_mut().set_lhs(true_statement);
+        let then_branch = self.statement();
 
         // else がある場合
-        if let Some(Token::Else) = self.get_next_token_without_increment()
-        {
+        let else_branch = if let Some(Token::Else) = self.get_next_token_without_increment() {
             self.token_index_increment();
+            Some(self.statement())
+        } else {
+            None
+        };
 
-            // else の中身を取得
-            let false_statement = self.statement();
-            root.borrow_mut().set_rhs(false_statement);
-        }
-
-        root
+        self.ast.add_stmt(Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        })
     }
 
     fn semicolon(&mut self) {
-        if let Some(Token::Semicolon) = self.get_next_token() {
-            // 何もしない
-        } else {
-            panic!("';' が見つかりませんでした : {:?}", self.tokens[self.token_index]);
-        }
+        self.expect(Token::Semicolon, ";");
     }
 
-    fn expression_statement(&mut self) -> Rc<RefCell<Node>>
+    fn expression_statement(&mut self) -> StmtId
     {
-        let mut root = Rc::new(RefCell::new(Node::new()));
-        root.borrow_mut().set_val(Leaf::Assignment);
-
-        // 左辺に識別子を設定
-        if let Some(Token::Identifier(identifier)) = self.get_next_token()
-        {
-            let left_node = Rc::new(RefCell::new(Node::new()));
-            left_node.borrow_mut().set_val(Leaf::Identifier(identifier));
-            root.borrow_mut().set_lhs(left_node);
-        } else {
-            panic!("識別子が見つかりませんでした : {:?}", self.tokens[self.token_index]);
+        // 空の文
+        if let Some(Token::Semicolon) = self.get_next_token_without_increment() {
+            self.token_index_increment();
+            return self.ast.add_stmt(Stmt::Block(Vec::new()));
         }
 
-        // 次のトークンが '=' かどうか
-        if let Some(next_token) = self.get_next_token()
-        {
-            match next_token
-            {
-                Token::Assign => {
-                    // '=' の場合は initializer をパースする
-                    if let Some(initializer) = self.logical_or_expression(&root) {
-                        println!("initializer");
-                        root.borrow_mut().set_rhs(initializer);
-                    }
-                }
-                _ => {
-                    panic!("初期化子が見つかりませんでした : {:?}", self.tokens[self.token_index]);
-                }
+        // 識別子の次が '=' の場合は代入文
+        let stmt = match (self.get_next_token_without_increment(), self.tokens.get(self.token_index + 1)) {
+            (Some(Token::Identifier(name)), Some(Token::Assign)) => {
+                // static なローカル変数はグローバル変数としての名前に代入する
+                let name = match self.lookup(&name) {
+                    Some(Symbol::Enumerator(_)) => panic!("列挙子には代入できません : {}", name),
+                    Some(Symbol::Variable { is_const: true, .. }) => panic!("const 変数には代入できません : {}", name),
+                    Some(Symbol::Variable { name, .. }) => name.clone(),
+                    _ => name,
+                };
+                self.token_index += 2;
+                let value = self.logical_or_expression();
+                Stmt::Assignment { name, value }
             }
-        } else {
-            panic!("トークンがありません");
-        }
+            _ => Stmt::Expression(self.logical_or_expression()),
+        };
 
         // ';' が来ることを確認
-        if let Some(Token::Semicolon) = self.get_next_token() {
-            println!("semicolon");
-            // 何もしない
-        } else {
-            panic!("';' が見つかりませんでした : {:?}", self.tokens[self.token_index]);
-        }
+        self.semicolon();
 
-        root
+        self.ast.add_stmt(stmt)
     }
 
-    fn jump_statement(&mut self) -> Rc<RefCell<Node>>
+    fn jump_statement(&mut self) -> StmtId
     {
-        let mut root = Rc::new(RefCell::new(Node::new()));
-
-        // 次のトークンを取得
-        if let Some(jump_token) = self.get_next_token()
+        let stmt = match self.get_next_token()
         {
-            match jump_token
-            {
-                Token::Return => {
-                    println!("return");
-                    // valにReturnを設定
-                    root.borrow_mut().set_val(Leaf::Return);
-
-                    // return の場合は expression が続く
-                    if let Some(expression) = self.logical_or_expression(&root) {
-                        root.borrow_mut().set_lhs(expression);
-                    } else {
-                        panic!("return の後に式がありませんでした : {:?}", self.tokens[self.token_index]);
-                    }
-                }
-                Token::Break => {
-                    unimplemented!("break");
-                }
-                Token::Continue => {
-                    unimplemented!("continue");
-                }
-                _ => {
-                    panic!("ジャンプステートメントが見つかりませんでした : {:?}", self.tokens[self.token_index]);
+            Some(Token::Return) => {
+                // return の後に式が続く場合がある
+                if let Some(Token::Semicolon) = self.get_next_token_without_increment() {
+                    Stmt::Return(None)
+                } else {
+                    Stmt::Return(Some(self.logical_or_expression()))
                 }
             }
-        }
+            Some(Token::Break) => Stmt::Break,
+            Some(Token::Continue) => Stmt::Continue,
+            _ => {
+                panic!("ジャンプステートメントが見つかりませんでした : {:?}", self.current_token());
+            }
+        };
 
         // ';' が来ることを確認
         self.semicolon();
 
-        root
+        self.ast.add_stmt(stmt)
     }
 
     /// 関数の引数リストを取得する. ')' が来るまで繰り返す
-    fn parameter_list(&mut self, function_definition: &mut FunctionDefinition)
+    /// 引数リスト. 引数ごとに const 修飾されているかどうかを返す
+    fn parameter_list(&mut self, parameters: &mut Vec<Parameter>) -> Vec<bool>
     {
+        let mut constants = Vec::new();
+
         // ')' が来る場合は何もしない
         if let Some(Token::RightParen) = self.get_next_token_without_increment() {
-            return;
+            return constants;
         }
 
-        // 一個目の型が void の場合は何もせずに終了
-        if let Some(Token::Type(type_specifier)) = self.get_next_token_without_increment()
+        // 一個目の型が void で ')' が続く場合は引数なし
+        if let (Some(Token::Type(ValueType::Void)), Some(Token::RightParen))
+            = (self.get_next_token_without_increment(), self.tokens.get(self.token_index + 1))
         {
-            if type_specifier == ValueType::Void {
-                self.token_index_increment();
-                return;
-            }
+            self.token_index_increment();
+            return constants;
         }
 
         loop {
-            if let Some(Token::Type(type_specifier)) = self.get_next_token_without_increment()
-            {
-                self.token_index_increment();
+            let (type_specifier, qualifiers) = self.declaration_specifiers();
+            if qualifiers.is_static {
+                panic!("関数の引数に static は指定できません : {:?}", self.current_token());
+            }
+            constants.push(qualifiers.is_const);
 
-                // 型がある場合は識別子が続く
-                if let Some(Token::Identifier(identifier)) = self.get_next_token()
-                {
-                    function_definition.add_argument(type_specifier, identifier);
-                } else {
-                    panic!("関数の引数の識別子が見つかりませんでした : {:?}", self.tokens[self.token_index]);
+            // 型の後には識別子が続く. プロトタイプでは識別子を省略できる
+            match self.get_next_token_without_increment()
+            {
+                Some(Token::Identifier(name)) => {
+                    self.token_index_increment();
+                    parameters.push(Parameter { type_specifier, name });
+                }
+                Some(Token::Comma) | Some(Token::RightParen) => {
+                    parameters.push(Parameter { type_specifier, name: String::new() });
+                }
+                _ => {
+                    panic!("関数の引数の識別子が見つかりませんでした : {:?}", self.current_token());
                 }
-            } else {
-                panic!("関数の型が見つかりませんでした : {:?}", self.tokens[self.token_index]);
             }
 
             // 次のトークンが ',' か ')' かを調べて ',' なら次の引数を取得する
@@ -770,486 +473,918 @@ impl Parser
                     break;
                 }
                 _ => {
-                    panic!("次のトークンがありません : {:?}", self.tokens[self.token_index]);
+                    panic!("次のトークンがありません : {:?}", self.current_token());
                 }
             }
         }
-    }
-
-    fn declaration(&mut self) -> Rc<RefCell<Node>>
-    {
-        println!("declaration");
 
-        // グローバル変数定義をパースする
-        let mut root = Rc::new(RefCell::new(Node::new()));
+        constants
+    }
 
-        // declaration の値として型が入る
-        if let Some(Token::Type(type_specifier)) = self.get_next_token() {
-            root.borrow_mut().set_val(Leaf::Declaration(type_specifier));
-        } else {
-            panic!("型が見つかりませんでした : {:?}", self.tokens[self.token_index]);
+    /// 型指定子になるトークンかどうか. 識別子は typedef で定義した型名の場合に型指定子になる
+    fn is_type_specifier(&self, token: &Token) -> bool {
+        match token {
+            Token::Type(_) | Token::Signed | Token::Unsigned | Token::Enum => true,
+            Token::Identifier(name) => matches!(self.lookup(name), Some(Symbol::Typedef(_))),
+            _ => false,
         }
+    }
 
-        // declaration の左辺として識別子が入る
-        if let Some(Token::Identifier(identifier)) = self.get_next_token() {
-            let left_node = Rc::new(RefCell::new(Node::new()));
-            left_node.borrow_mut().set_val(Leaf::Identifier(identifier));
-            root.borrow_mut().set_lhs(left_node);
-        } else {
-            panic!("識別子が見つかりませんでした : {:?}", self.tokens[self.token_index]);
+    /// index から始まる型指定子を読み飛ばし, その直後の位置を返す.
+    /// 型指定子は 'unsigned long' や 'enum Color { RED }' のように複数のトークンになることがある
+    fn skip_type_specifier(&self, mut index: usize) -> usize {
+        // 型名は他の型指定子と組み合わせられない
+        while matches!(self.tokens.get(index), Some(Token::Typedef | Token::Const | Token::Static)) {
+            index += 1;
+        }
+        if let Some(token @ Token::Identifier(_)) = self.tokens.get(index) {
+            return if self.is_type_specifier(token) { index + 1 } else { index };
         }
 
-        // 次のトークンが '=' かどうか
-        if let Some(next_token) = self.get_next_token()
-        {
-            match next_token
-            {
-                Token::Assign => {
-                    // '=' の場合は initializer をパースする
-                    if let Some(initializer) = self.logical_or_expression(&root) {
-                        println!("initializer");
-                        root.borrow_mut().set_rhs(initializer);
+        while let Some(token) = self.tokens.get(index) {
+            match token {
+                Token::Const | Token::Static => index += 1,
+                Token::Enum => {
+                    index += 1;
+                    if matches!(self.tokens.get(index), Some(Token::Identifier(_))) {
+                        index += 1;
                     }
-
-                    // ';' が来ることを確認
-                    if let Some(Token::Semicolon) = self.get_next_token() {
-                        println!("semicolon");
-                        // 何もしない
-                    } else {
-                        panic!("';' が見つかりませんでした : {:?}", self.tokens[self.token_index]);
+                    if self.tokens.get(index) == Some(&Token::LeftBrace) {
+                        while self.tokens.get(index).is_some_and(|token| *token != Token::RightBrace) {
+                            index += 1;
+                        }
+                        index += 1;
                     }
                 }
-                Token::Semicolon => {
-                    // 何もしない
-                }
-                _ => {
-                    panic!("初期化子が見つかりませんでした : {:?}", self.tokens[self.token_index]);
-                }
+                Token::Identifier(_) => break,
+                token if self.is_type_specifier(token) => index += 1,
+                _ => break,
             }
-        } else {
-            panic!("トークンがありません");
         }
-        root
+        index
     }
 
-    fn logical_or_expression(&mut self, parent: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>>
-    {
-        println!("logical_or_expression");
-        let mut node = Rc::new(RefCell::new(Node::new()));
-        node.borrow_mut().set_parent(parent);
+    /// 宣言の先頭になるトークンかどうか
+    fn is_declaration_specifier(&self, token: &Token) -> bool {
+        matches!(token, Token::Typedef | Token::Const | Token::Static) || self.is_type_specifier(token)
+    }
 
-        if let Some(left_node) = self.logical_and_expression(&parent) {
+    /// 'static const int' や 'int const' のように型指定子の前後に修飾子の付いた型を読む
+    fn declaration_specifiers(&mut self) -> (ValueType, Qualifiers) {
+        let mut qualifiers = Qualifiers::default();
+        let mut read_qualifiers = |parser: &mut Self| {
+            loop {
+                match parser.get_next_token_without_increment() {
+                    Some(Token::Const) => qualifiers.is_const = true,
+                    Some(Token::Static) => qualifiers.is_static = true,
+                    _ => break,
+                }
+                parser.token_index_increment();
+            }
+        };
 
-            // 次のトークンが '||' の場合は logical_or_expression をパースする
-            if let Some(Token::Operator(Operator::LogicalOr)) = self.get_next_token_without_increment() {
-                node.borrow_mut().set_val(Leaf::Operator(Operator::LogicalOr));
-                node.borrow_mut().set_lhs(left_node);
-                self.token_index_increment();
+        read_qualifiers(self);
+        let value_type = self.type_specifier();
+        read_qualifiers(self);
+        (value_type, qualifiers)
+    }
 
-                if let Some(right_node) = self.logical_or_expression(&node) {
-                    node.borrow_mut().set_rhs(right_node);
-                } else {
-                    panic!("右のノードが見つかりませんでした : {:?}", self.tokens[self.token_index]);
+    /// 連続する型指定子を読んで 1 つの型にまとめる. 'unsigned long int' や 'long double' など.
+    /// 指定子の順序は問わない
+    fn type_specifier(&mut self) -> ValueType {
+        match self.get_next_token_without_increment() {
+            Some(Token::Enum) => return self.enum_specifier(),
+            // typedef で定義した型名はそれだけで 1 つの型になる
+            Some(Token::Identifier(name)) => {
+                if let Some(Symbol::Typedef(value_type)) = self.lookup(&name) {
+                    let value_type = value_type.clone();
+                    self.token_index_increment();
+                    return value_type;
                 }
+            }
+            _ => {}
+        }
 
-                Some(node)
-            } else {
-                // 演算子がない場合は左のノードをそのまま返す
-                Some(left_node)
+        let mut specifiers = Vec::new();
+        let mut signedness = None;
+        while let Some(token) = self.get_next_token_without_increment() {
+            match token {
+                Token::Type(value_type) => specifiers.push(value_type),
+                Token::Signed | Token::Unsigned if signedness.is_none() =>
+                    signedness = Some(token == Token::Unsigned),
+                Token::Signed | Token::Unsigned =>
+                    panic!("signed, unsigned は 1 つしか指定できません : {:?}", token),
+                _ => break,
             }
-        } else {
-            panic!("識別子が見つかりませんでした : {:?}", self.tokens[self.token_index]);
+            self.token_index_increment();
         }
-    }
 
-    fn logical_and_expression(&mut self, parent: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>>
-    {
-        println!("logical_and_expression");
-        let mut node = Rc::new(RefCell::new(Node::new()));
-        node.borrow_mut().set_parent(parent);
+        if specifiers.is_empty() && signedness.is_none() {
+            panic!("型が見つかりませんでした : {:?}", self.current_token());
+        }
 
-        if let Some(left_node) = self.equality_expression(&node)
-        {
-            // 次のトークンが '&&' の場合は logical_and_expression をパースする
-            if let Some(Token::Operator(Operator::LogicalAnd)) = self.get_next_token_without_increment() {
-                node.borrow_mut().set_val(Leaf::Operator(Operator::LogicalAnd));
-                node.borrow_mut().set_lhs(left_node);
-                self.token_index_increment();
+        let count = |value_type: ValueType| specifiers.iter().filter(|t| **t == value_type).count();
+        let (shorts, longs, ints) = (count(ValueType::Short), count(ValueType::Long), count(ValueType::Int));
+        let others: Vec<&ValueType> = specifiers.iter()
+            .filter(|t| !matches!(t, ValueType::Short | ValueType::Long | ValueType::Int))
+            .collect();
+
+        let base = match (others.as_slice(), shorts, longs, ints) {
+            // 'signed', 'unsigned' だけの場合は int
+            ([], 0, 0, 0..=1) => Some(ValueType::Int),
+            ([], 1, 0, 0..=1) => Some(ValueType::Short),
+            ([], 0, 1..=2, 0..=1) => Some(ValueType::Long),
+            ([ValueType::Double], 0, 0..=1, 0) => Some(ValueType::Double),
+            ([other], 0, 0, 0) => Some((*other).clone()),
+            _ => None,
+        };
+
+        let value_type = match (base, signedness) {
+            (Some(base), None) => Some(base),
+            (Some(base @ (ValueType::Char | ValueType::Short | ValueType::Int | ValueType::Long)), Some(false)) =>
+                Some(base),
+            (Some(ValueType::Char), Some(true)) => Some(ValueType::UnsignedChar),
+            (Some(ValueType::Short), Some(true)) => Some(ValueType::UnsignedShort),
+            (Some(ValueType::Int), Some(true)) => Some(ValueType::UnsignedInt),
+            (Some(ValueType::Long), Some(true)) => Some(ValueType::UnsignedLong),
+            _ => None,
+        };
+
+        value_type.unwrap_or_else(|| panic!("型指定子の組み合わせが正しくありません : {:?}", specifiers))
+    }
 
-                // 再度 logical_and_expression を呼び出す
-                if let Some(right_node) = self.logical_and_expression(&mut node) {
-                    node.borrow_mut().set_rhs(right_node);
-                } else {
-                    panic!("右のノードが見つかりませんでした : {:?}", self.tokens[self.token_index]);
-                }
+    /// 'enum Tag { A, B = 式, C }' か 'enum Tag'. 列挙型は int として扱い,
+    /// 列挙子は現在のスコープに int の定数として登録する
+    fn enum_specifier(&mut self) -> ValueType {
+        self.expect(Token::Enum, "enum");
+        let tag = if let Some(Token::Identifier(tag)) = self.get_next_token_without_increment() {
+            self.token_index_increment();
+            Some(tag)
+        } else {
+            None
+        };
 
-                Some(node)
-            } else {
-                // 演算子がない場合は左のノードをそのまま返す
-                Some(left_node)
+        // 本体がない場合は定義済みのタグを参照する
+        if self.get_next_token_without_increment() != Some(Token::LeftBrace) {
+            let tag = tag.unwrap_or_else(|| panic!("'{{' が見つかりませんでした : {:?}", self.current_token()));
+            if !self.scopes.iter().any(|scope| scope.tags.contains(&tag)) {
+                panic!("未定義の列挙型です : {}", tag);
             }
-        } else {
-            panic!("識別子が見つかりませんでした : {:?}", self.tokens[self.token_index]);
+            return ValueType::Int;
         }
-    }
+        self.token_index_increment();
 
-    fn equality_expression(&mut self, parent: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>> {
-        println!("equality_expression");
-        let mut node = Rc::new(RefCell::new(Node::new()));
-        node.borrow_mut().set_parent(parent);
+        if let Some(tag) = tag {
+            let scope = self.scopes.last_mut().expect("スコープがありません");
+            if !scope.tags.insert(tag.clone()) {
+                panic!("列挙型が再定義されています : {}", tag);
+            }
+        }
 
-        if let Some(left_node) = self.relational_expression(&node) {
-            // '==' または '!=' の演算子を取得
-            if let Some(operator) = self.get_next_token_without_increment()
-                .and_then(|token| match token {
-                    Token::Operator(Operator::Equal) => Some(Operator::Equal),
-                    Token::Operator(Operator::NotEqual) => Some(Operator::NotEqual),
-                    _ => None,
-                })
-            {
-                node.borrow_mut().set_val(Leaf::Operator(operator));
-                node.borrow_mut().set_lhs(left_node);
+        // 値を省略した列挙子は直前の値 + 1 になる
+        let mut next = Some(0);
+        loop {
+            let name = if let Some(Token::Identifier(name)) = self.get_next_token() {
+                name
+            } else {
+                panic!("列挙子が見つかりませんでした : {:?}", self.current_token());
+            };
+
+            let value = if self.get_next_token_without_increment() == Some(Token::Assign) {
                 self.token_index_increment();
+                let expr = self.logical_or_expression();
+                self.constant_expression(expr)
+            } else {
+                next.unwrap_or_else(|| panic!("列挙子の値が int の範囲を超えています : {}", name))
+            };
 
-                // 再帰的に equality_expression を呼び出す
-                if let Some(right_node) = self.equality_expression(&node) {
-                    node.borrow_mut().set_rhs(right_node);
-                } else {
-                    panic!(
-                        "右のノードが見つかりませんでした : {:?}",
-                        self.tokens[self.token_index]
-                    );
-                }
+            // 列挙子のスコープは自身の定義の直後から始まる
+            self.declare(&name, Symbol::Enumerator(value));
+            next = value.checked_add(1);
 
-                Some(node)
-            } else {
-                // 演算子がない場合は左のノードをそのまま返す
-                Some(left_node)
+            match self.get_next_token()
+            {
+                // 最後の列挙子の後の ',' は省略できる
+                Some(Token::Comma) if self.get_next_token_without_increment() == Some(Token::RightBrace) => {
+                    self.token_index_increment();
+                    break;
+                }
+                Some(Token::Comma) => {}
+                Some(Token::RightBrace) => break,
+                _ => panic!("'}}' が見つかりませんでした : {:?}", self.current_token()),
             }
-        } else {
-            panic!(
-                "識別子が見つかりませんでした : {:?}",
-                self.tokens[self.token_index]
-            );
         }
+
+        ValueType::Int
     }
 
+    /// 列挙子の値になる整数の定数式を評価する. 値は int の範囲に収まらなければならない.
+    /// 定数式で初期化された const 変数も定数として扱う
+    fn constant_expression(&self, id: ExprId) -> i32 {
+        let value = self.constant_value(id)
+            .filter(|value| Interpreter::value_type(value).is_integer())
+            .unwrap_or_else(|| panic!("列挙子の値が整数の定数式ではありません : {}", self.ast.format_expr(id)));
+        i32::try_from(Interpreter::to_i128(&value))
+            .unwrap_or_else(|_| panic!("列挙子の値が int の範囲を超えています : {}", value))
+    }
 
-    fn relational_expression(&mut self, parent: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>>
-    {
-        println!("relational_expression");
-        let mut node = Rc::new(RefCell::new(Node::new()));
-        node.borrow_mut().set_parent(parent);
-
-        if let Some(left_node) = self.additive_expression(&mut node) {
-            // '<' または '>' の演算子を取得
-            if let Some(operator) = self.get_next_token_without_increment()
-                .and_then(|token| match token {
-                    Token::Operator(Operator::LessThan) => Some(Operator::LessThan),
-                    Token::Operator(Operator::LessThanOrEqual) => Some(Operator::LessThanOrEqual),
-                    Token::Operator(Operator::GreaterThan) => Some(Operator::GreaterThan),
-                    Token::Operator(Operator::GreaterThanOrEqual) => Some(Operator::GreaterThanOrEqual),
-                    _ => None,
-                })
-            {
-                println!("relational_expression");
-                node.borrow_mut().set_val(Leaf::Operator(operator));
-                node.borrow_mut().set_lhs(left_node);
-                self.token_index_increment();
+    fn constant_value(&self, id: ExprId) -> Option<VariableType> {
+        match self.ast.expr(id) {
+            Expr::Constant(constant) => Some(Interpreter::constant(constant)),
+            Expr::Identifier(name) => match self.variable(name) {
+                Some(Symbol::Variable { value, .. }) => value.clone(),
+                _ => None,
+            },
+            Expr::Unary { op, operand } =>
+                Interpreter::unary_operation(op, self.constant_value(*operand)?, ArithmeticMode::Trapping, None).ok(),
+            Expr::Binary { op, lhs, rhs } => {
+                let (lhs, rhs) = (self.constant_value(*lhs)?, self.constant_value(*rhs)?);
+                Interpreter::binary_operation(op, lhs, rhs, ArithmeticMode::Trapping, None).ok()
+            }
+            Expr::Cast { value_type, operand } if *value_type != ValueType::Void =>
+                Some(Interpreter::cast(value_type, self.constant_value(*operand)?)),
+            _ => None,
+        }
+    }
 
-                // 再帰的に relational_expression を呼び出す
-                if let Some(right_node) = self.relational_expression(&mut node) {
-                    node.borrow_mut().set_rhs(right_node);
-                } else {
-                    panic!("右のノードが見つかりませんでした : {:?}", self.tokens[self.token_index]);
-                }
+    /// 現在のスコープに名前を登録する. 列挙子と型名は同じスコープの他の名前と重複できない.
+    /// ただし同じ型への typedef の再定義は許す. 変数どうしの再宣言は意味解析で報告するが,
+    /// static なローカル変数はグローバル変数に置き換えるので, ここで重複を調べる
+    fn declare(&mut self, name: &str, symbol: Symbol) {
+        Self::check_reserved(name);
+        let scope = self.scopes.last_mut().expect("スコープがありません");
+        let conflicts = match (scope.symbols.get(name), &symbol) {
+            (None, _) => false,
+            (Some(Symbol::Variable { name: previous, .. }), Symbol::Variable { name: storage, .. }) =>
+                previous != storage,
+            (Some(Symbol::Typedef(previous)), Symbol::Typedef(value_type)) => previous != value_type,
+            _ => true,
+        };
+        if conflicts {
+            panic!("識別子が再定義されています : {}", name);
+        }
+        scope.symbols.insert(name.to_string(), symbol);
+    }
 
-                Some(node)
-            } else {
-                // 演算子がない場合は左のノードをそのまま返す
-                Some(left_node)
-            }
-        } else {
-            panic!("識別子が見つかりませんでした : {:?}", self.tokens[self.token_index]);
+    /// static なローカル変数を置き換えたグローバル変数と衝突しないよう, 同じ形の名前の宣言を拒む
+    fn check_reserved(name: &str) {
+        if static_local_name(name).is_some() {
+            panic!("'{}' で始まる名前は予約されています : {}", STATIC_LOCAL_PREFIX, name);
         }
     }
 
+    /// 式の識別子が参照する変数を探す. 式の識別子は static なローカル変数ではグローバル変数としての名前になっている
+    fn variable(&self, name: &str) -> Option<&Symbol> {
+        self.scopes.iter().rev()
+            .flat_map(|scope| scope.symbols.values())
+            .find(|symbol| matches!(symbol, Symbol::Variable { name: storage, .. } if storage == name))
+    }
+
+    /// 内側のスコープから順に名前を探す
+    fn lookup(&self, name: &str) -> Option<&Symbol> {
+        self.scopes.iter().rev().find_map(|scope| scope.symbols.get(name))
+    }
 
-    fn additive_expression(&mut self, parent: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>>
+    /// 変数定義. 'int a, b = 2, c;' のように ',' で区切られた宣言子を 1 つずつの宣言にする
+    fn declaration(&mut self) -> Vec<Decl>
     {
-        println!("additive_expression");
-        let mut node = Rc::new(RefCell::new(Node::new()));
-        node.borrow_mut().set_parent(parent);
-
-        if let Some(left_node) = self.multiplicative_expression(&mut node) {
-            // '+' または '-' の演算子を取得
-            if let Some(operator) = self.get_next_token_without_increment()
-                .and_then(|token| match token {
-                    Token::Operator(Operator::Plus) => Some(Operator::Plus),
-                    Token::Operator(Operator::Minus) => Some(Operator::Minus),
-                    _ => None,
-                })
-            {
+        if self.get_next_token_without_increment() == Some(Token::Typedef) {
+            self.typedef_declaration();
+            return Vec::new();
+        }
+
+        // 型を取得
+        let (type_specifier, qualifiers) = self.declaration_specifiers();
+        // static なローカル変数は関数の呼び出しをまたいで値を保つので, グローバル変数に置き換える.
+        // グローバル変数の static はプログラムが 1 ファイルだけなので区別しない
+        let is_static_local = qualifiers.is_static && self.scopes.len() > 1;
+
+        // 'enum Color { RED };' のように宣言子のない宣言は型の定義だけを行う
+        if self.get_next_token_without_increment() == Some(Token::Semicolon) {
+            self.token_index_increment();
+            return Vec::new();
+        }
+
+        let mut declarations = Vec::new();
+        loop {
+            // 識別子を取得
+            let name = if let Some(Token::Identifier(identifier)) = self.get_next_token() {
+                identifier
+            } else {
+                panic!("識別子が見つかりませんでした : {:?}", self.current_token());
+            };
+
+            // 変数のスコープは宣言子の直後から始まる
+            let storage = if is_static_local {
+                self.static_count += 1;
+                format!("{}{}_{}", STATIC_LOCAL_PREFIX, self.static_count - 1, name)
+            } else {
+                name.clone()
+            };
+            self.declare(&name, Symbol::Variable {
+                value_type: type_specifier.clone(),
+                name: storage.clone(),
+                is_const: qualifiers.is_const,
+                value: None,
+            });
+
+            // 次のトークンが '=' の場合は initializer をパースする
+            let initializer = if self.get_next_token_without_increment() == Some(Token::Assign) {
                 self.token_index_increment();
-                node.borrow_mut().set_val(Leaf::Operator(operator));
-                node.borrow_mut().set_lhs(left_node);
+                Some(self.logical_or_expression())
+            } else {
+                None
+            };
+
+            // 定数式で初期化された const 変数は値を覚えておく
+            let value = initializer.filter(|_| qualifiers.is_const)
+                .and_then(|initializer| self.constant_value(initializer))
+                .map(|value| Interpreter::cast(&type_specifier, value));
+            if let Some(Symbol::Variable { value: constant, .. }) = self.scopes.last_mut()
+                .and_then(|scope| scope.symbols.get_mut(&name))
+            {
+                *constant = value;
+            }
 
-                // 再帰的に additive_expression を呼び出す
-                if let Some(right_node) = self.additive_expression(&mut node) {
-                    node.borrow_mut().set_rhs(right_node);
-                } else {
-                    panic!("右のノードが見つかりませんでした : {:?}", self.tokens[self.token_index]);
+            if is_static_local {
+                // 初期化は実行前に 1 度だけ行うので, 初期化子は定数式でなければならない.
+                // 初期化子はローカルな const 変数を参照できるので, 畳み込んだ定数に置き換える
+                if let Some(initializer) = initializer {
+                    let value = self.constant_value(initializer)
+                        .and_then(|value| {
+                            // char, short, _Bool は定数で表せないので int に拡張する
+                            Interpreter::to_constant(&value)
+                                .or_else(|| Interpreter::to_constant(&Interpreter::cast(&ValueType::Int, value)))
+                        })
+                        .unwrap_or_else(|| panic!("static 変数の初期化子は定数式でなければなりません : {}", name));
+                    self.ast.replace_expr(initializer, Expr::Constant(value));
                 }
-
-                Some(node)
+                self.static_variables.push(Decl::Variable {
+                    type_specifier: type_specifier.clone(),
+                    name: storage,
+                    initializer,
+                });
             } else {
-                // 演算子がない場合は左のノードをそのまま返す
-                Some(left_node)
+                declarations.push(Decl::Variable {
+                    type_specifier: type_specifier.clone(),
+                    name: storage,
+                    initializer,
+                });
+            }
+
+            // ',' の場合は次の宣言子へ, ';' の場合は終了
+            match self.get_next_token()
+            {
+                Some(Token::Comma) => {}
+                Some(Token::Semicolon) => break,
+                Some(_) => {
+                    panic!("';' が見つかりませんでした : {:?}", self.current_token());
+                }
+                None => {
+                    panic!("トークンがありません");
+                }
             }
-        } else {
-            panic!("識別子が見つかりませんでした : {:?}", self.tokens[self.token_index]);
         }
+
+        declarations
     }
 
-    fn multiplicative_expression(&mut self, parent: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>>
+    /// 'typedef unsigned long size_t, length_t;' のように宣言子を型名として現在のスコープに登録する
+    fn typedef_declaration(&mut self)
     {
-        println!("multiplicative_expression");
-        let mut node = Rc::new(RefCell::new(Node::new()));
-        node.borrow_mut().set_parent(parent);
-
-        if let Some(left_node) = self.unary_expression(&mut node) {
-            // '*' または '/' の演算子を取得
-            if let Some(operator) = self.get_next_token_without_increment()
-                .and_then(|token| match token {
-                    Token::Operator(Operator::Multiply) => Some(Operator::Multiply),
-                    Token::Operator(Operator::Divide) => Some(Operator::Divide),
-                    _ => None,
-                })
+        self.expect(Token::Typedef, "typedef");
+        let type_specifier = self.type_specifier();
+
+        loop {
+            let name = if let Some(Token::Identifier(identifier)) = self.get_next_token() {
+                identifier
+            } else {
+                panic!("型名が見つかりませんでした : {:?}", self.current_token());
+            };
+            self.declare(&name, Symbol::Typedef(type_specifier.clone()));
+
+            match self.get_next_token()
             {
-                self.token_index_increment();
-                node.borrow_mut().set_val(Leaf::Operator(operator));
-                node.borrow_mut().set_lhs(left_node);
+                Some(Token::Comma) => {}
+                Some(Token::Semicolon) => break,
+                Some(Token::Assign) => panic!("typedef には初期化子を指定できません : {}", name),
+                _ => panic!("';' が見つかりませんでした : {:?}", self.current_token()),
+            }
+        }
+    }
 
-                // 再帰的に multiplicative_expression を呼び出す
-                if let Some(right_node) = self.multiplicative_expression(&mut node) {
-                    node.borrow_mut().set_rhs(right_node);
-                } else {
-                    panic!("右のノードが見つかりませんでした : {:?}", self.tokens[self.token_index]);
-                }
+    /// 二項演算子を左結合で読み進める
+    fn binary_expression(&mut self, operand: fn(&mut Self) -> ExprId, operators: &[Operator]) -> ExprId
+    {
+        let mut lhs = operand(self);
 
-                Some(node)
-            } else {
-                // 演算子がない場合は左のノードをそのまま返す
-                Some(left_node)
+        while let Some(Token::Operator(op)) = self.get_next_token_without_increment() {
+            if !operators.contains(&op) {
+                break;
             }
-        } else {
-            panic!("識別子が見つかりませんでした : {:?}", self.tokens[self.token_index]);
+            self.token_index_increment();
+
+            let rhs = operand(self);
+            lhs = self.add_expr(Expr::Binary { op, lhs, rhs });
         }
+
+        lhs
     }
 
-    // 最終的にはpostfix_expression を呼び出すが関数呼び出しと配列は現状無視する.
-    fn unary_expression(&mut self, parent: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>>
+    fn logical_or_expression(&mut self) -> ExprId
     {
-        println!("unary_expression");
-        let mut node = Rc::new(RefCell::new(Node::new()));
-        node.borrow_mut().set_parent(parent);
+        self.binary_expression(Self::logical_and_expression, &[Operator::LogicalOr])
+    }
 
-        // 次のトークンを取得
-        let next_token = self.get_next_token_without_increment();
+    fn logical_and_expression(&mut self) -> ExprId
+    {
+        self.binary_expression(Self::equality_expression, &[Operator::LogicalAnd])
+    }
 
-        if let Some(Token::UnaryOperator(operator)) = next_token {
-            // 単項演算子の場合
-            node.borrow_mut().set_val(Leaf::UnaryExpression(operator));
-            self.token_index_increment();
-            let left_node = self.postfix_expression(&mut node);
-            if let Some(left_node) = left_node {
-                node.borrow_mut().set_lhs(left_node);
+    fn equality_expression(&mut self) -> ExprId
+    {
+        self.binary_expression(Self::relational_expression, &[Operator::Equal, Operator::NotEqual])
+    }
+
+    fn relational_expression(&mut self) -> ExprId
+    {
+        self.binary_expression(Self::additive_expression, &[
+            Operator::LessThan,
+            Operator::GreaterThan,
+            Operator::LessThanOrEqual,
+            Operator::GreaterThanOrEqual,
+        ])
+    }
+
+    fn additive_expression(&mut self) -> ExprId
+    {
+        self.binary_expression(Self::multiplicative_expression, &[Operator::Plus, Operator::Minus])
+    }
+
+    fn multiplicative_expression(&mut self) -> ExprId
+    {
+        self.binary_expression(Self::unary_expression, &[Operator::Multiply, Operator::Divide, Operator::Modulo])
+    }
+
+    fn unary_expression(&mut self) -> ExprId
+    {
+        match self.get_next_token_without_increment() {
+            Some(Token::UnaryOperator(op)) => {
+                // 単項演算子の場合
+                self.token_index_increment();
+                let operand = self.unary_expression();
+                self.add_expr(Expr::Unary { op, operand })
             }
-        } else {
-            // 単項演算子でない場合は postfix_expression をパースする
-            let postfix_node = self.postfix_expression(&mut node);
-            if let Some(postfix_node) = postfix_node {
-                node = postfix_node;
+            Some(Token::LeftParen) if self.is_type_name() => {
+                // '(' の後に型指定子が続く場合はキャスト
+                let value_type = self.type_name();
+
+                // 字句解析では ')' の後の '-' を二項演算子にするので, キャストの後では単項演算子に読み替える
+                if self.get_next_token_without_increment() == Some(Token::Operator(Operator::Minus)) {
+                    self.tokens[self.token_index] = Token::UnaryOperator(UnaryOperator::Minus);
+                }
+                let operand = self.unary_expression();
+                self.add_expr(Expr::Cast { value_type, operand })
+            }
+            Some(Token::Sizeof) => {
+                // sizeof はコンパイル時に unsigned long の定数にする. 式は評価せず型だけを求める
+                self.token_index_increment();
+                let value_type = if self.is_type_name() {
+                    self.type_name()
+                } else {
+                    let operand = self.unary_expression();
+                    self.expr_type(operand)
+                };
+                if value_type == ValueType::Void {
+                    panic!("void の大きさは求められません");
+                }
+                self.add_expr(Expr::Constant(Constant::UnsignedLong(value_type.size() as u64)))
             }
+            // 単項演算子でない場合は postfix_expression をパースする
+            _ => self.postfix_expression(),
         }
+    }
 
-        Some(node)
+    /// 次のトークンから '(' 型指定子 ')' の形の型名が始まるかどうか
+    fn is_type_name(&self) -> bool {
+        self.get_next_token_without_increment() == Some(Token::LeftParen)
+            && self.tokens.get(self.token_index + 1).is_some_and(|token| self.is_type_specifier(token))
     }
 
-    fn postfix_expression(&mut self, parent: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>>
+    /// '(' 型指定子 ')' を読んで型を返す
+    fn type_name(&mut self) -> ValueType {
+        self.expect(Token::LeftParen, "(");
+        let value_type = self.type_specifier();
+        self.expect(Token::RightParen, ")");
+        value_type
+    }
+
+    /// sizeof の対象になる式の型. 意味解析と同じ規則で, 宣言済みの変数と関数の型から求める
+    fn expr_type(&self, id: ExprId) -> ValueType {
+        match self.ast.expr(id) {
+            Expr::Constant(constant) => constant.value_type(),
+            Expr::Identifier(name) => match self.variable(name) {
+                Some(Symbol::Variable { value_type, .. }) => value_type.clone(),
+                _ => panic!("未定義の識別子です : {}", name),
+            },
+            Expr::Unary { op: UnaryOperator::Minus, operand } => self.expr_type(*operand).promoted(),
+            Expr::Unary { op: UnaryOperator::LogicalNot, .. } => ValueType::Int,
+            Expr::Binary { op, lhs, rhs } =>
+                SemanticAnalyzer::binary_type(*op, &self.expr_type(*lhs), &self.expr_type(*rhs)),
+            Expr::Cast { value_type, .. } => value_type.clone(),
+            Expr::Call { name, .. } => self.function_types.get(name).cloned()
+                .unwrap_or_else(|| panic!("未定義の関数です : {}", name)),
+            Expr::ArrayAccess { .. } => ValueType::Int,
+        }
+    }
+
+    fn postfix_expression(&mut self) -> ExprId
     {
-        println!("postfix_expression");
-        let mut node = Rc::new(RefCell::new(Node::new()));
-        node.borrow_mut().set_parent(parent);
+        let name = match self.get_next_token_without_increment()
+        {
+            Some(Token::Identifier(name)) => name,
+            // それ以外の場合は primary_expression を呼び出す
+            _ => return self.primary_expression(),
+        };
+        self.token_index_increment();
 
-        if let Some(next_token) = self.get_next_token_without_increment()
+        match self.get_next_token_without_increment()
         {
-            match next_token
-            {
-                Token::Identifier(identify) => {
-                    // index を進める
+            Some(Token::LeftParen) => {
+                // 関数呼び出しの場合
+                self.token_index_increment();
+                let mut arguments = Vec::new();
+
+                // ')' が来るまで引数を読む
+                if let Some(Token::RightParen) = self.get_next_token_without_increment() {
                     self.token_index_increment();
+                } else {
+                    loop {
+                        arguments.push(self.logical_or_expression());
 
-                    if let Some(next_identify) = self.get_next_token_without_increment()
-                    {
-                        match next_identify
+                        match self.get_next_token()
                         {
-                            Token::LeftParen => {
-                                self.token_index_increment();
-
-                                // 関数呼び出しの場合
-                                let mut function_call = FunctionCall::new(identify);
-
-                                // ')' が来るまで argument_expression_list を呼び出す
-                                loop {
-                                    let next_token = self.get_next_token_without_increment();
-
-                                    // 引数がない場合は ')' が来る
-                                    if let Some(Token::RightParen) = next_token {
-                                        self.token_index_increment();
-                                        break;
-                                    }
-
-                                    let logical_or_expression_node
-                                        = self.logical_or_expression(&node);
-
-                                    if let Some(arg) = logical_or_expression_node {
-                                        println!("arg : {:?}", arg);
-                                        function_call.add_argument(arg);
-                                    }
-
-                                    // ',' が来ることを確認
-                                    match self.get_next_token_without_increment()
-                                    {
-                                        Some(Token::Comma) => {
-                                            self.token_index_increment();
-                                        }
-                                        None => {
-                                            panic!("次のトークンがありません");
-                                        }
-                                        _ => {
-                                            // 何もしない
-                                            println!("skip : {:?}", self.tokens[self.token_index]);
-                                        }
-                                    }
-                                }
-
-                                node.borrow_mut().set_val(Leaf::FunctionCall(function_call));
-                            }
-                            Token::LeftBracket => {
-                                // 配列の場合
-                                node.borrow_mut().set_val(Leaf::ArrayAccess);
-
-                                // 左側に識別子を設定
-                                let left_node = Rc::new(RefCell::new(Node::new()));
-                                left_node.borrow_mut().set_val(Leaf::Identifier(identify));
-                                node.borrow_mut().set_lhs(left_node);
-
-                                // ']' のときはからの配列として扱う
-                                if let Some(Token::RightBracket) = self.get_next_token_without_increment() {
-                                    // 何もしない
-                                    self.token_index_increment();
-                                } else {
-                                    // 配列の index を取得
-                                    let logical_or_expression_node = self.logical_or_expression(&node);
-                                    if let Some(index) = logical_or_expression_node {
-                                        node.borrow_mut().set_rhs(index);
-                                    }
-
-                                    // ']' が来ることを確認
-                                    if let Some(Token::RightBracket) = self.get_next_token() {
-                                        // 何もしない
-                                    } else {
-                                        panic!("']' が見つかりませんでした : {:?}", self.tokens[self.token_index]);
-                                    }
-                                }
-                            }
+                            Some(Token::Comma) => {}
+                            Some(Token::RightParen) => break,
                             _ => {
-                                println!("postfix_expression : {:?}", identify);
-                                // それ以外の場合は identifier として処理する
-                                node.borrow_mut().set_val(Leaf::Identifier(identify));
+                                panic!("')' が見つかりませんでした : {:?}", self.current_token());
                             }
                         }
-
-                        return Some(node);
-                    }
-                }
-                // それ以外の場合
-                _ => {
-                    // primary_expression を呼び出す
-                    let primary_node = self.primary_expression(&node);
-                    if let Some(primary_node) = primary_node {
-                        node = primary_node;
                     }
                 }
+
+                self.add_expr(Expr::Call { name, arguments })
             }
+            Some(Token::LeftBracket) => {
+                // 配列の場合
+                self.token_index_increment();
 
-            return Some(node);
+                // ']' のときは空の配列として扱う
+                let index = if let Some(Token::RightBracket) = self.get_next_token_without_increment() {
+                    None
+                } else {
+                    Some(self.logical_or_expression())
+                };
+                self.expect(Token::RightBracket, "]");
+
+                self.add_expr(Expr::ArrayAccess { name, index })
+            }
+            // 列挙子は int の定数に置き換える
+            _ => match self.lookup(&name) {
+                Some(Symbol::Enumerator(value)) => {
+                    let value = *value;
+                    self.add_expr(Expr::Constant(Constant::Integer(value)))
+                }
+                Some(Symbol::Typedef(_)) => panic!("型名は式に使えません : {}", name),
+                // static なローカル変数はグローバル変数としての名前で参照する
+                Some(Symbol::Variable { name: storage, .. }) if *storage != name => {
+                    let storage = storage.clone();
+                    self.add_expr(Expr::Identifier(storage))
+                }
+                // それ以外の場合は identifier として処理する
+                _ => self.add_expr(Expr::Identifier(name)),
+            },
         }
+    }
 
-        None
+    fn primary_expression(&mut self) -> ExprId
+    {
+        match self.get_next_token()
+        {
+            // 定数の場合
+            Some(Token::Constant(constant)) => self.add_expr(Expr::Constant(constant)),
+            Some(Token::LeftParen) => {
+                // '(' が来た場合は logical_or_expression を呼び出す
+                let expr = self.logical_or_expression();
+                self.expect(Token::RightParen, ")");
+                expr
+            }
+            _ => {
+                panic!("primary_expression でエラーが発生しました : {:?}", self.tokens.get(self.token_index - 1));
+            }
+        }
     }
 
-    fn argument_expression_list(&mut self, parent: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>>
+    pub fn show_tree(&self)
     {
-        println!("argument_expression_list");
-        let mut node = Rc::new(RefCell::new(Node::new()));
-        node.borrow_mut().set_parent(parent);
+        for declaration in self.ast.declarations() {
+            println!("{}", self.ast.format_decl(declaration));
+        }
+    }
+}
 
-        Some(node)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexical::Lexer;
+
+    fn parse(program: &str) -> Vec<String> {
+        let mut lexer = Lexer::new(program.to_string());
+        lexer.tokenize();
+
+        let mut parser = Parser::new(lexer.tokens().clone());
+        parser.parse();
+        let ast = parser.ast();
+        ast.declarations().iter().map(|declaration| ast.format_decl(declaration)).collect()
     }
 
-    fn primary_expression(&mut self, parent: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>>
-    {
-        println!("primary_expression");
-        let mut node = Rc::new(RefCell::new(Node::new()));
-        node.borrow_mut().set_parent(parent);
+    /// 構文解析が panic することを確かめ, そのメッセージを返す
+    fn parse_error(program: &str) -> String {
+        let result = std::panic::catch_unwind(|| parse(program));
+        *result.expect_err(program).downcast::<String>().unwrap()
+    }
 
-        // 次のトークンを取得
-        if let Some(next_token) = self.get_next_token()
-        {
-            match next_token
-            {
-                Token::Constant(constant) => {
-                    // 定数の場合
-                    node.borrow_mut().set_val(Leaf::Constant(constant));
-                }
-                Token::LeftParen => {
-                    node.borrow_mut().set_val(Leaf::ParenthesizedExpression);
-
-                    // '(' が来た場合は logical_or_expression を呼び出す
-                    let logical_or_expression_node = self.logical_or_expression(&node);
-                    if let Some(logical_or_expression_node) = logical_or_expression_node {
-                        node.borrow_mut().set_lhs(logical_or_expression_node);
-                    } else {
-                        panic!("空の括弧が見つかりました : {:?}", self.tokens[self.token_index]);
-                    }
+    #[test]
+    fn test_left_associative() {
+        let declarations = parse("int x = 10 - 4 - 3;");
 
-                    // ')' が来ることを確認
-                    if let Some(Token::RightParen) = self.get_next_token() {
-                        // 何もしない
-                    } else {
-                        panic!("')' が見つかりませんでした : {:?}", self.tokens[self.token_index]);
-                    }
+        assert_eq!(declarations, vec!["(Int x (- (- 10 4) 3))"]);
+    }
 
-                    return Some(node);
-                }
-                _ => {
-                    panic!("primary_expression でエラーが発生しました : {:?}", self.tokens[self.token_index]);
-                }
-            }
-            return Some(node);
+    #[test]
+    fn test_statements() {
+        let declarations = parse("
+void count(int n) {
+    while (n > 0) {
+        if (n == 3) {
+            break;
+        } else {
+            n = n - 1;
         }
+        continue;
+    }
+    count(n);
+    return;
+}
+");
 
-        None
+        assert_eq!(declarations, vec![
+            "(Void count(Int n) {(while (> n 0) {(if (== n 3) {(break)} {(= n (- n 1))}) (continue)}) count(n) (return)})"
+        ]);
     }
 
-    pub fn show_tree(&self)
-    {
-        for root in &self.roots {
-            print!("root : ");
-            Node::show_node(&root.borrow());
+    #[test]
+    fn test_multiple_declarators() {
+        let declarations = parse("
+int a, b = 2, c;
+int main(void) {
+    float x = 1.5, y;
+    return b;
+}
+");
+
+        assert_eq!(declarations, vec![
+            "(Int a)",
+            "(Int b 2)",
+            "(Int c)",
+            "(Int main() {(Float x 1.5) (Float y) (return b)})",
+        ]);
+    }
+
+    #[test]
+    fn test_prototypes() {
+        let declarations = parse("
+int helper(int, float y);
+void log(void);
+int main(void) { return helper(1, 2.0); }
+");
+
+        assert_eq!(declarations, vec![
+            "(Int helper(Int, Float y))",
+            "(Void log())",
+            "(Int main() {(return helper(1, 2.0))})",
+        ]);
+    }
+
+    #[test]
+    fn test_type_specifiers() {
+        let declarations = parse("
+unsigned long a = 10L;
+short int b;
+long long c;
+unsigned d = 3u;
+signed char e;
+long double f = 1.5f;
+_Bool g;
+unsigned short h(unsigned char x);
+");
+
+        assert_eq!(declarations, vec![
+            "(UnsignedLong a 10L)",
+            "(Short b)",
+            "(Long c)",
+            "(UnsignedInt d 3u)",
+            "(Char e)",
+            "(Double f 1.5f)",
+            "(Bool g)",
+            "(UnsignedShort h(UnsignedChar x))",
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "型指定子の組み合わせが正しくありません")]
+    fn test_invalid_type_specifiers() {
+        parse("unsigned float x;");
+    }
+
+    #[test]
+    fn test_cast_and_sizeof() {
+        let declarations = parse("
+int n = 3;
+double d = (double)n / 2;
+long a = (long)-n * sizeof(int);
+unsigned long s = sizeof n + sizeof(unsigned short);
+double g(char c) {
+    long n = sizeof c + sizeof(c + c) + sizeof g(c) + sizeof -c;
+    return n;
+}
+");
+
+        // sizeof は型の大きさの unsigned long 定数になる. 内側のスコープの変数は外側を隠す
+        assert_eq!(declarations, vec![
+            "(Int n 3)",
+            "(Double d (/ ((double) n) 2))",
+            "(Long a (* ((long) (- n)) 4uL))",
+            "(UnsignedLong s (+ 4uL 2uL))",
+            "(Double g(Char c) {(Long n (+ (+ (+ 1uL 4uL) 8uL) 4uL)) (return n)})",
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "void の大きさは求められません")]
+    fn test_sizeof_void() {
+        parse("void f(void); int main(void) { return sizeof f(); }");
+    }
+
+    #[test]
+    fn test_enum() {
+        let declarations = parse("
+enum Color { RED, GREEN = 5, BLUE, };
+enum Color c = BLUE;
+enum { LOW = -1, HIGH = LOW + 2 * GREEN } level = HIGH;
+int f(enum Color c) {
+    enum Local { RED = 10, WHITE } d = RED;
+    return c + d + WHITE + (int)sizeof(enum Color);
+}
+int g(void) { int BLUE = 1; return BLUE + GREEN; }
+");
+
+        // 列挙子は int の定数になり, 内側のスコープで同じ名前を定義すると外側を隠す
+        assert_eq!(declarations, vec![
+            "(Int c 6)",
+            "(Int level 9)",
+            "(Int f(Int c) {(Int d 10) (return (+ (+ (+ c d) 11) ((int) 4uL)))})",
+            "(Int g() {(Int BLUE 1) (return (+ BLUE 5))})",
+        ]);
+    }
+
+    #[test]
+    fn test_enum_errors() {
+        let cases = [
+            ("enum Color c;", "未定義の列挙型です"),
+            ("enum Color { RED }; enum Color { BLUE };", "列挙型が再定義されています"),
+            ("enum { RED, RED };", "識別子が再定義されています"),
+            ("int RED; enum { RED };", "識別子が再定義されています"),
+            ("int x = 1; enum { A = x };", "列挙子の値が整数の定数式ではありません"),
+            ("enum { A = 2147483647, B };", "列挙子の値が int の範囲を超えています"),
+            ("enum { A }; void f(void) { A = 1; }", "列挙子には代入できません"),
+            ("void f(int n) { enum { n }; }", "識別子が再定義されています"),
+        ];
+
+        for (program, expected) in cases {
+            let message = parse_error(program);
+            assert!(message.contains(expected), "{} : {}", program, message);
         }
     }
+
+    #[test]
+    fn test_typedef() {
+        let declarations = parse("
+typedef int score_t;
+typedef unsigned long size_t, length_t;
+typedef enum { OFF, ON } switch_t;
+typedef score_t total_t;
+typedef int score_t;
+total_t best = ON;
+length_t size(score_t s) {
+    typedef double score_t;
+    score_t half = (score_t)s / 2;
+    return sizeof(score_t) + sizeof half;
+}
+int shadow(int n) {
+    int size_t = n;
+    return size_t;
+}
+");
+
+        // 型名は元の型に置き換わり, 内側のスコープの型名や変数は外側の型名を隠す
+        assert_eq!(declarations, vec![
+            "(Int best 1)",
+            "(UnsignedLong size(Int s) {(Double half (/ ((double) s) 2)) (return (+ 8uL 8uL))})",
+            "(Int shadow(Int n) {(Int size_t n) (return size_t)})",
+        ]);
+    }
+
+    #[test]
+    fn test_typedef_errors() {
+        let cases = [
+            ("typedef int T; typedef long T;", "識別子が再定義されています"),
+            ("int T; typedef int T;", "識別子が再定義されています"),
+            ("typedef int T = 1;", "typedef には初期化子を指定できません"),
+            ("typedef int T; int x = T + 1;", "型名は式に使えません"),
+        ];
+
+        for (program, expected) in cases {
+            let message = parse_error(program);
+            assert!(message.contains(expected), "{} : {}", program, message);
+        }
+    }
+
+    #[test]
+    fn test_const_and_static() {
+        let declarations = parse("
+const int limit = 10;
+static int total;
+enum { LAST = limit - 1 } last = LAST;
+static int next(int const n) {
+    const int step = 2;
+    static long count = limit - 1, calls;
+    static char scale = step * 3;
+    count = count + n * scale;
+    return count + sizeof count;
 }
+");
+
+        // 定数式で初期化された const 変数は定数式に使え, static なローカル変数は関数の前にグローバル変数として宣言される.
+        // static なローカル変数の初期化子はローカルな const 変数も参照できるよう, 畳み込んだ定数になる
+        assert_eq!(declarations, vec![
+            "(Int limit 10)",
+            "(Int total)",
+            "(Int last 9)",
+            "(Long __static0_count 9)",
+            "(Long __static1_calls)",
+            "(Char __static2_scale 6)",
+            "(Int next(Int n) {(Int step 2) (= __static0_count (+ __static0_count (* n __static2_scale))) (return (+ __static0_count 8uL))})",
+        ]);
+    }
 
+    #[test]
+    fn test_const_and_static_errors() {
+        let cases = [
+            ("const int x = 1; void f(void) { x = 2; }", "const 変数には代入できません"),
+            ("void f(const int n) { n = 2; }", "const 変数には代入できません"),
+            ("void f(static int n) { }", "関数の引数に static は指定できません"),
+            ("void f(void) { static int x; int x; }", "識別子が再定義されています"),
+            ("int g(void); void f(void) { static int x = g(); }", "static 変数の初期化子は定数式でなければなりません"),
+            ("int __static0_x; void f(void) { static int x; }", "'__static' で始まる名前は予約されています"),
+            ("int __static1_f(void) { return 0; }", "'__static' で始まる名前は予約されています"),
+        ];
+
+        for (program, expected) in cases {
+            let message = parse_error(program);
+            assert!(message.contains(expected), "{} : {}", program, message);
+        }
+    }
+
+    #[test]
+    fn test_node_ids() {
+        let mut lexer = Lexer::new("int f(int a) { return a * 2; }".to_string());
+        lexer.tokenize();
+
+        let mut parser = Parser::new(lexer.tokens().clone());
+        parser.parse();
+        let ast = parser.ast();
+
+        // a, 2, a * 2 の 3 つの式と return 文がアリーナに入る
+        assert_eq!(ast.expr_count(), 3);
+        assert_eq!(ast.stmt_count(), 1);
+
+        let Decl::Function(function) = &ast.declarations()[0] else {
+            panic!("関数定義ではありません");
+        };
+        let Stmt::Return(Some(value)) = ast.stmt(function.body[0]) else {
+            panic!("return 文ではありません");
+        };
+        assert_eq!(ast.format_expr(*value), "(* a 2)");
+    }
+}
@@ -0,0 +1,421 @@
+use crate::ast::{Ast, Decl, Expr, ExprId, Function, SideTable, Stmt, StmtId};
+use crate::semantic::Diagnostic;
+use std::collections::{HashMap, HashSet};
+
+/// 変数の参照先
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution
+{
+    // ローカル変数. 関数の一番外側のスコープを深さ 0 とし, スコープ内で宣言された順にスロットを割り当てる
+    Local { depth: usize, slot: usize },
+
+    // グローバル変数. 実行時に名前で検索する
+    Global,
+}
+
+/// 名前解決の結果
+#[derive(Debug, Clone, Default)]
+pub struct Resolutions
+{
+    // 識別子の式ごとの参照先
+    exprs: SideTable<ExprId, Resolution>,
+
+    // 代入文と宣言文ごとの参照先
+    stmts: SideTable<StmtId, Resolution>,
+}
+
+impl Resolutions
+{
+    /// 識別子の式の参照先. 解決していない式はグローバル変数として扱う
+    pub fn expr(&self, id: ExprId) -> Resolution
+    {
+        self.exprs.get(id).copied().unwrap_or(Resolution::Global)
+    }
+
+    /// 代入文の代入先, または宣言文で宣言された変数の場所
+    pub fn stmt(&self, id: StmtId) -> Resolution
+    {
+        self.stmts.get(id).copied().unwrap_or(Resolution::Global)
+    }
+}
+
+/// ローカル変数のスコープ
+#[derive(Debug, Default)]
+struct Scope
+{
+    // 識別のための番号
+    id: usize,
+
+    // 名前とスロット
+    slots: HashMap<String, usize>,
+
+    // 割り当て済みのスロット数. 同じ名前を再宣言しても新しいスロットを使う
+    slot_count: usize,
+}
+
+/// 宣言より前に使われた可能性のある名前
+#[derive(Debug)]
+struct PendingUse
+{
+    name: String,
+
+    // 使用した時点で開いていたスコープの番号
+    scopes: Vec<usize>,
+}
+
+/// 変数名を (深さ, スロット) に変換する. 変数の隠蔽と宣言前の使用を診断として報告する
+pub struct Resolver<'a>
+{
+    ast: &'a Ast,
+    resolutions: Resolutions,
+    diagnostics: Vec<Diagnostic>,
+
+    // 同じスコープで再宣言されたローカル変数. エラーは意味解析が報告するので診断には含めない
+    redeclarations: Vec<String>,
+
+    // ここまでに宣言されたグローバル変数
+    global_variables: HashSet<String>,
+
+    // 解析中の関数のスコープ. 空の場合はトップレベル
+    scopes: Vec<Scope>,
+    next_scope_id: usize,
+
+    // ローカルにもグローバルにも見つからなかった名前
+    pending_uses: Vec<PendingUse>,
+    function_name: String,
+}
+
+impl<'a> Resolver<'a>
+{
+    pub fn new(ast: &'a Ast) -> Self
+    {
+        Resolver
+        {
+            ast,
+            resolutions: Resolutions::default(),
+            diagnostics: Vec::new(),
+            redeclarations: Vec::new(),
+            global_variables: HashSet::new(),
+            scopes: Vec::new(),
+            next_scope_id: 0,
+            pending_uses: Vec::new(),
+            function_name: String::new(),
+        }
+    }
+
+    /// すべての宣言を解決する
+    pub fn resolve(&mut self) -> &Resolutions
+    {
+        let ast = self.ast;
+        for declaration in ast.declarations().iter()
+        {
+            self.declaration(declaration, None);
+        }
+        &self.resolutions
+    }
+
+    pub fn resolutions(&self) -> &Resolutions
+    {
+        &self.resolutions
+    }
+
+    pub fn diagnostics(&self) -> &Vec<Diagnostic>
+    {
+        &self.diagnostics
+    }
+
+    /// 同じスコープで再宣言されたローカル変数と引数の名前
+    pub fn redeclarations(&self) -> &Vec<String>
+    {
+        &self.redeclarations
+    }
+
+    fn warning(&mut self, message: String)
+    {
+        self.diagnostics.push(Diagnostic::warning(message));
+    }
+
+    fn declaration(&mut self, declaration: &Decl, stmt: Option<StmtId>)
+    {
+        match declaration
+        {
+            Decl::Variable { name, initializer, .. } =>
+                {
+                    // 初期化子は宣言される変数より前に評価する
+                    if let Some(initializer) = initializer
+                    {
+                        self.expression(*initializer);
+                    }
+
+                    if self.scopes.is_empty()
+                    {
+                        self.global_variables.insert(name.clone());
+                    } else {
+                        let resolution = self.declare(name);
+                        if let Some(stmt) = stmt
+                        {
+                            self.resolutions.stmts.insert(stmt, resolution);
+                        }
+                    }
+                }
+            Decl::Function(function) => self.function(function),
+            Decl::Prototype(_) => {}
+        }
+    }
+
+    fn function(&mut self, function: &Function)
+    {
+        self.function_name = function.name.clone();
+
+        // 引数と本体の一番外側の宣言は同じスコープに置く
+        self.begin_scope();
+        for parameter in function.parameters.iter()
+        {
+            self.declare(&parameter.name);
+        }
+        for stmt in function.body.iter()
+        {
+            self.statement(*stmt);
+        }
+        self.end_scope();
+
+        self.pending_uses.clear();
+    }
+
+    fn begin_scope(&mut self)
+    {
+        let id = self.next_scope_id;
+        self.next_scope_id += 1;
+        self.scopes.push(Scope { id, slots: HashMap::new(), slot_count: 0 });
+    }
+
+    fn end_scope(&mut self)
+    {
+        self.scopes.pop();
+    }
+
+    /// 現在のスコープに変数を追加し, 割り当てたスロットを返す
+    fn declare(&mut self, name: &String) -> Resolution
+    {
+        let depth = self.scopes.len() - 1;
+        let scope_id = self.scopes[depth].id;
+
+        // このスコープかその内側で, 宣言より前に同じ名前が使われていないか
+        let function_name = self.function_name.clone();
+        let before = self.pending_uses.len();
+        self.pending_uses.retain(|pending| pending.name != *name || !pending.scopes.contains(&scope_id));
+        if self.pending_uses.len() != before
+        {
+            self.warning(format!("宣言より前に使用されています : {} (関数 {})", name, function_name));
+        }
+
+        if self.scopes[depth].slots.contains_key(name)
+        {
+            self.redeclarations.push(name.clone());
+        }
+
+        let shadowed = self.scopes[..depth].iter().any(|scope| scope.slots.contains_key(name))
+            || self.global_variables.contains(name);
+        if shadowed
+        {
+            self.warning(format!("外側の変数を隠しています : {} (関数 {})", name, function_name));
+        }
+
+        let scope = &mut self.scopes[depth];
+        let slot = scope.slot_count;
+        scope.slot_count += 1;
+        scope.slots.insert(name.clone(), slot);
+        Resolution::Local { depth, slot }
+    }
+
+    /// 内側のスコープから順に名前を探す
+    fn lookup(&mut self, name: &String) -> Resolution
+    {
+        for (depth, scope) in self.scopes.iter().enumerate().rev()
+        {
+            if let Some(slot) = scope.slots.get(name)
+            {
+                return Resolution::Local { depth, slot: *slot };
+            }
+        }
+
+        // 関数の中でグローバル変数にもない名前は, 後で宣言されるかを確認する
+        if !self.scopes.is_empty() && !self.global_variables.contains(name)
+        {
+            let scopes = self.scopes.iter().map(|scope| scope.id).collect();
+            self.pending_uses.push(PendingUse { name: name.clone(), scopes });
+        }
+        Resolution::Global
+    }
+
+    fn statement(&mut self, id: StmtId)
+    {
+        let ast = self.ast;
+        match ast.stmt(id)
+        {
+            Stmt::Declaration(declaration) => self.declaration(declaration, Some(id)),
+            Stmt::Assignment { name, value } =>
+                {
+                    self.expression(*value);
+                    let resolution = self.lookup(name);
+                    self.resolutions.stmts.insert(id, resolution);
+                }
+            Stmt::Expression(expr) => self.expression(*expr),
+            Stmt::Block(stmts) =>
+                {
+                    self.begin_scope();
+                    for stmt in stmts.iter()
+                    {
+                        self.statement(*stmt);
+                    }
+                    self.end_scope();
+                }
+            Stmt::If { condition, then_branch, else_branch } =>
+                {
+                    self.expression(*condition);
+                    self.statement(*then_branch);
+                    if let Some(else_branch) = else_branch
+                    {
+                        self.statement(*else_branch);
+                    }
+                }
+            Stmt::While { condition, body } =>
+                {
+                    self.expression(*condition);
+                    self.statement(*body);
+                }
+            Stmt::Return(value) =>
+                {
+                    if let Some(value) = value
+                    {
+                        self.expression(*value);
+                    }
+                }
+            Stmt::Break | Stmt::Continue => {}
+        }
+    }
+
+    fn expression(&mut self, id: ExprId)
+    {
+        let ast = self.ast;
+        match ast.expr(id)
+        {
+            Expr::Constant(_) => {}
+            Expr::Identifier(name) =>
+                {
+                    let resolution = self.lookup(name);
+                    self.resolutions.exprs.insert(id, resolution);
+                }
+            Expr::Unary { operand, .. } | Expr::Cast { operand, .. } => self.expression(*operand),
+            Expr::Binary { lhs, rhs, .. } =>
+                {
+                    self.expression(*lhs);
+                    self.expression(*rhs);
+                }
+            Expr::Call { arguments, .. } =>
+                {
+                    for argument in arguments.iter()
+                    {
+                        self.expression(*argument);
+                    }
+                }
+            Expr::ArrayAccess { index, .. } =>
+                {
+                    if let Some(index) = index
+                    {
+                        self.expression(*index);
+                    }
+                }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::ast::{Expr, ExprId, NodeId, Stmt, StmtId};
+    use crate::lexical::Lexer;
+    use crate::parser::Parser;
+    use crate::resolver::{Resolution, Resolver};
+
+    fn parse(program: &str) -> Parser
+    {
+        let mut lexer = Lexer::new(program.to_string());
+        lexer.tokenize();
+
+        let mut parser = Parser::new(lexer.tokens().clone());
+        parser.parse();
+        parser
+    }
+
+    #[test]
+    fn test_slots()
+    {
+        let parser = parse("
+int g = 1;
+int f(int a, int b) {
+    int c = a;
+    {
+        int d = b;
+        c = d + g;
+    }
+    int e = c;
+    return e;
+}");
+        let ast = parser.ast();
+        let mut resolver = Resolver::new(ast);
+        let resolutions = resolver.resolve().clone();
+        assert!(resolver.diagnostics().is_empty());
+
+        // 識別子を出現順に並べる
+        let identifiers: Vec<(String, Resolution)> = (0..ast.expr_count())
+            .map(ExprId::new)
+            .filter_map(|id| match ast.expr(id)
+            {
+                Expr::Identifier(name) => Some((name.clone(), resolutions.expr(id))),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(identifiers, vec![
+            ("a".to_string(), Resolution::Local { depth: 0, slot: 0 }),
+            ("b".to_string(), Resolution::Local { depth: 0, slot: 1 }),
+            ("d".to_string(), Resolution::Local { depth: 1, slot: 0 }),
+            ("g".to_string(), Resolution::Global),
+            ("c".to_string(), Resolution::Local { depth: 0, slot: 2 }),
+            ("e".to_string(), Resolution::Local { depth: 0, slot: 3 }),
+        ]);
+
+        // 代入先も同じ場所に解決される
+        let assignment = (0..ast.stmt_count())
+            .map(StmtId::new)
+            .find(|id| matches!(ast.stmt(*id), Stmt::Assignment { .. }))
+            .unwrap();
+        assert_eq!(resolutions.stmt(assignment), Resolution::Local { depth: 0, slot: 2 });
+    }
+
+    #[test]
+    fn test_diagnostics()
+    {
+        let parser = parse("
+int x = 1;
+int f(int n) {
+    int x = n;
+    while (n > 0) {
+        int n = 0;
+        y = 1;
+        int y = 2;
+    }
+    return x;
+}");
+        let mut resolver = Resolver::new(parser.ast());
+        resolver.resolve();
+        let messages: Vec<String> = resolver.diagnostics().iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+        assert_eq!(messages, vec![
+            "warning: 外側の変数を隠しています : x (関数 f)".to_string(),
+            "warning: 外側の変数を隠しています : n (関数 f)".to_string(),
+            "warning: 宣言より前に使用されています : y (関数 f)".to_string(),
+        ]);
+    }
+}
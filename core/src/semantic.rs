@@ -0,0 +1,1022 @@
+use crate::ast::{Ast, Decl, Expr, ExprId, Function, SideTable, Stmt, StmtId};
+use crate::interpreter::{ArithmeticMode, Interpreter, VariableType};
+use crate::lexical::{Operator, UnaryOperator, ValueType};
+use std::collections::{HashMap, HashSet};
+
+/// 診断の重要度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity
+{
+    Error,
+    Warning,
+}
+
+/// 意味解析で見つかった問題
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic
+{
+    severity: Severity,
+    message: String,
+}
+
+impl Diagnostic
+{
+    pub fn error(message: String) -> Self
+    {
+        Diagnostic { severity: Severity::Error, message }
+    }
+
+    pub fn warning(message: String) -> Self
+    {
+        Diagnostic { severity: Severity::Warning, message }
+    }
+
+    pub fn severity(&self) -> Severity
+    {
+        self.severity
+    }
+
+    pub fn message(&self) -> &String
+    {
+        &self.message
+    }
+
+    pub fn is_error(&self) -> bool
+    {
+        self.severity == Severity::Error
+    }
+}
+
+impl std::fmt::Display for Diagnostic
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        match self.severity
+        {
+            Severity::Error => write!(f, "error: {}", self.message),
+            Severity::Warning => write!(f, "warning: {}", self.message),
+        }
+    }
+}
+
+/// 関数のシグネチャ
+#[derive(Debug, Clone)]
+struct Signature
+{
+    return_type: ValueType,
+    parameters: Vec<ValueType>,
+}
+
+impl Signature
+{
+    /// 'int (int, float)' の形式にする
+    fn format(&self) -> String
+    {
+        let parameters: Vec<&str> = self.parameters.iter().map(|parameter| parameter.as_str()).collect();
+        format!("{} ({})", self.return_type.as_str(), parameters.join(", "))
+    }
+}
+
+/// 識別子が指している宣言
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding
+{
+    // グローバル変数
+    Global,
+
+    // ローカル変数. 宣言文の ID を持つ
+    Local(StmtId),
+
+    // 関数の引数. 何番目の引数か
+    Parameter(usize),
+
+    // 関数
+    Function,
+}
+
+/// 構文木を実行前に走査し, 型や識別子の誤りを報告する
+pub struct SemanticAnalyzer<'a>
+{
+    ast: &'a Ast,
+
+    // 登録済みの関数
+    functions: HashMap<String, Signature>,
+
+    // 本体を定義済みの関数
+    defined_functions: HashSet<String>,
+
+    // プロトタイプだけの関数の呼び出しを許すか. 定義を外から与えられるバックエンドで使う
+    external_functions: bool,
+
+    // グローバル変数
+    global_variables: HashMap<String, ValueType>,
+
+    // 初期化子付きで定義済みのグローバル変数
+    defined_globals: HashSet<String>,
+
+    // 関数内のブロックごとのローカル変数
+    local_variables: Vec<HashMap<String, (ValueType, Binding)>>,
+
+    // 解析中の関数名と戻り値の型
+    current_function: Option<(String, ValueType)>,
+
+    // 式ごとの型
+    expr_types: SideTable<ExprId, ValueType>,
+
+    // 識別子と関数呼び出しの名前解決の結果
+    expr_bindings: SideTable<ExprId, Binding>,
+
+    // 代入文の代入先の名前解決の結果
+    assignment_bindings: SideTable<StmtId, Binding>,
+
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> SemanticAnalyzer<'a>
+{
+    pub fn new(ast: &'a Ast) -> Self
+    {
+        SemanticAnalyzer {
+            ast,
+            functions: HashMap::new(),
+            defined_functions: HashSet::new(),
+            external_functions: false,
+            global_variables: HashMap::new(),
+            defined_globals: HashSet::new(),
+            local_variables: Vec::new(),
+            current_function: None,
+            expr_types: SideTable::new(),
+            expr_bindings: SideTable::new(),
+            assignment_bindings: SideTable::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// プロトタイプだけの関数の呼び出しを許すかを設定する. 既定では定義のない関数の呼び出しはエラーになる
+    pub fn set_external_functions(&mut self, external_functions: bool)
+    {
+        self.external_functions = external_functions;
+    }
+
+    /// 解析を実行し, 見つかった診断をすべて返す
+    pub fn analyze(&mut self) -> Vec<Diagnostic>
+    {
+        self.diagnostics.clear();
+        self.defined_functions.clear();
+        self.global_variables.clear();
+        self.defined_globals.clear();
+        self.expr_types.clear();
+        self.expr_bindings.clear();
+        self.assignment_bindings.clear();
+
+        // インタプリタは実行前にすべての関数を登録するので, 定義順に関係なく呼び出せる.
+        // プログラムは 1 ファイルだけなので, プロトタイプだけの関数は外から定義を与える場合にだけ呼び出せる
+        let ast = self.ast;
+        for declaration in ast.declarations()
+        {
+            match declaration
+            {
+                Decl::Function(function) =>
+                    {
+                        self.function_declaration(function);
+                        if !self.defined_functions.insert(function.name.clone())
+                        {
+                            self.error(format!("関数が再定義されています : {}", function.name));
+                        }
+                    }
+                Decl::Prototype(function) =>
+                    {
+                        self.function_declaration(function);
+                    }
+                Decl::Variable { .. } => {}
+            }
+        }
+
+        for declaration in ast.declarations()
+        {
+            match declaration
+            {
+                Decl::Function(function) =>
+                    {
+                        self.function_definition(function);
+                    }
+                Decl::Prototype(_) => {}
+                Decl::Variable { type_specifier, name, initializer } =>
+                    {
+                        self.declaration(type_specifier, name, *initializer, None);
+                    }
+            }
+        }
+
+        self.diagnostics.clone()
+    }
+
+    /// 式の型. 解析済みの式のみ
+    pub fn expr_type(&self, id: ExprId) -> Option<&ValueType>
+    {
+        self.expr_types.get(id)
+    }
+
+    /// 識別子や関数呼び出しが指している宣言
+    pub fn binding(&self, id: ExprId) -> Option<&Binding>
+    {
+        self.expr_bindings.get(id)
+    }
+
+    /// 代入文の代入先が指している宣言
+    pub fn assignment_binding(&self, id: StmtId) -> Option<&Binding>
+    {
+        self.assignment_bindings.get(id)
+    }
+
+    fn error(&mut self, message: String)
+    {
+        let message = self.with_context(message);
+        self.diagnostics.push(Diagnostic::error(message));
+    }
+
+    fn warning(&mut self, message: String)
+    {
+        let message = self.with_context(message);
+        self.diagnostics.push(Diagnostic::warning(message));
+    }
+
+    fn with_context(&self, message: String) -> String
+    {
+        match &self.current_function
+        {
+            Some((name, _)) => format!("{} (関数 {})", message, name),
+            None => message,
+        }
+    }
+
+    /// 関数のシグネチャを登録する. 先に宣言されている場合は戻り値と引数の型が一致するか調べる
+    fn function_declaration(&mut self, function: &Function)
+    {
+        let signature = Signature {
+            return_type: function.return_type.clone(),
+            parameters: function.parameters.iter()
+                .map(|parameter| parameter.type_specifier.clone())
+                .collect(),
+        };
+
+        // 一致しない場合は最初の宣言で呼び出しを調べる
+        match self.functions.get(&function.name)
+        {
+            Some(declared) =>
+                {
+                    if declared.return_type != signature.return_type || declared.parameters != signature.parameters
+                    {
+                        let message = format!("関数の宣言が一致しません : {} ({} と {})",
+                                              function.name,
+                                              declared.format(),
+                                              signature.format());
+                        self.error(message);
+                    }
+                }
+            None =>
+                {
+                    self.functions.insert(function.name.clone(), signature);
+                }
+        }
+    }
+
+    fn function_definition(&mut self, function: &Function)
+    {
+        let return_type = function.return_type.clone();
+        self.current_function = Some((function.name.clone(), return_type.clone()));
+
+        // 引数は関数の一番外側のスコープに入る
+        let mut parameters = HashMap::new();
+        for (index, parameter) in function.parameters.iter().enumerate()
+        {
+            if parameter.type_specifier == ValueType::Void
+            {
+                self.error(format!("引数を void 型にはできません : {}", parameter.name));
+            }
+            if parameter.name.is_empty()
+            {
+                self.error(format!("関数定義の引数には名前が必要です : {} 番目", index + 1));
+            } else if parameters.contains_key(&parameter.name)
+            {
+                self.error(format!("同じスコープで再宣言されています : {}", parameter.name));
+            }
+            parameters.insert(parameter.name.clone(), (parameter.type_specifier.clone(), Binding::Parameter(index)));
+        }
+        self.local_variables.push(parameters);
+
+        for stmt in function.body.iter()
+        {
+            self.statement(*stmt);
+        }
+
+        self.local_variables.pop();
+
+        if return_type != ValueType::Void && !self.always_returns(&function.body)
+        {
+            self.error("非 void 関数が値を返さずに終了する可能性があります".to_string());
+        }
+
+        self.current_function = None;
+    }
+
+    /// 文の並びが必ず return で終わるかどうか
+    fn always_returns(&self, stmts: &[StmtId]) -> bool
+    {
+        stmts.iter().any(|stmt| self.statement_always_returns(*stmt))
+    }
+
+    fn statement_always_returns(&self, stmt: StmtId) -> bool
+    {
+        match self.ast.stmt(stmt)
+        {
+            Stmt::Return(_) => true,
+            Stmt::Block(stmts) => self.always_returns(stmts),
+            Stmt::If { then_branch, else_branch: Some(else_branch), .. } =>
+                self.statement_always_returns(*then_branch) && self.statement_always_returns(*else_branch),
+            // 条件が 0 でない定数で break のないループは, return でしか抜けられない
+            Stmt::While { condition, body } =>
+                self.is_nonzero_constant(*condition) && !self.contains_break(*body),
+            _ => false,
+        }
+    }
+
+    fn is_nonzero_constant(&self, id: ExprId) -> bool
+    {
+        match self.ast.expr(id)
+        {
+            Expr::Constant(constant) => Interpreter::truthy(Interpreter::constant(constant)).unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    /// ループの本体がそのループを抜ける break を含むかどうか. 内側のループの break は数えない
+    fn contains_break(&self, stmt: StmtId) -> bool
+    {
+        match self.ast.stmt(stmt)
+        {
+            Stmt::Break => true,
+            Stmt::Block(stmts) => stmts.iter().any(|stmt| self.contains_break(*stmt)),
+            Stmt::If { then_branch, else_branch, .. } =>
+                self.contains_break(*then_branch) || else_branch.is_some_and(|else_branch| self.contains_break(else_branch)),
+            _ => false,
+        }
+    }
+
+    fn statement(&mut self, id: StmtId)
+    {
+        let ast = self.ast;
+        match ast.stmt(id)
+        {
+            Stmt::Declaration(Decl::Variable { type_specifier, name, initializer }) =>
+                {
+                    self.declaration(type_specifier, name, *initializer, Some(id));
+                }
+            Stmt::Declaration(Decl::Function(_) | Decl::Prototype(_)) => {}
+            Stmt::Assignment { name, value } =>
+                {
+                    self.assignment(id, name, *value);
+                }
+            Stmt::Return(value) =>
+                {
+                    self.return_statement(*value);
+                }
+            Stmt::Block(stmts) =>
+                {
+                    self.local_variables.push(HashMap::new());
+                    for stmt in stmts.iter()
+                    {
+                        self.statement(*stmt);
+                    }
+                    self.local_variables.pop();
+                }
+            Stmt::If { condition, then_branch, else_branch } =>
+                {
+                    self.condition(*condition);
+                    self.statement(*then_branch);
+                    if let Some(else_branch) = else_branch
+                    {
+                        self.statement(*else_branch);
+                    }
+                }
+            Stmt::While { condition, body } =>
+                {
+                    self.condition(*condition);
+                    self.statement(*body);
+                }
+            Stmt::Expression(expr) =>
+                {
+                    // 文としての関数呼び出しは void でもよい
+                    self.expression(*expr);
+                }
+            Stmt::Break | Stmt::Continue => {}
+        }
+    }
+
+    /// 変数宣言を解析する. stmt はローカル変数の場合の宣言文
+    fn declaration(&mut self, value_type: &ValueType, identifier: &str, initializer: Option<ExprId>, stmt: Option<StmtId>)
+    {
+        if *value_type == ValueType::Void
+        {
+            self.error(format!("変数を void 型で宣言することはできません : {}", identifier));
+        }
+
+        // 初期化子は宣言する変数が登録される前に評価する
+        if let Some(initializer) = initializer
+        {
+            let initializer_type = self.value_expression(initializer);
+            self.check_conversion(value_type, &initializer_type, initializer, identifier);
+        }
+
+        // 引数は本体の一番外側のブロックと同じスコープにあるので, そこで同じ名前を宣言することはできない.
+        // 内側のブロックでは外側の変数を隠せる
+        match (self.local_variables.last_mut(), stmt)
+        {
+            (Some(scope), Some(stmt)) =>
+                {
+                    let previous = scope.insert(identifier.to_string(), (value_type.clone(), Binding::Local(stmt)));
+                    if previous.is_some()
+                    {
+                        self.error(format!("同じスコープで再宣言されています : {}", identifier));
+                    }
+                }
+            _ =>
+                {
+                    // C の仮定義と同じく, グローバル変数は同じ型で初期化子が 1 つまでなら何度でも宣言できる
+                    let previous = self.global_variables.insert(identifier.to_string(), value_type.clone());
+                    if previous.is_some_and(|previous| previous != *value_type)
+                    {
+                        self.error(format!("グローバル変数が異なる型で再宣言されています : {}", identifier));
+                    }
+                    if initializer.is_some() && !self.defined_globals.insert(identifier.to_string())
+                    {
+                        self.error(format!("グローバル変数が再定義されています : {}", identifier));
+                    }
+                }
+        }
+    }
+
+    fn assignment(&mut self, id: StmtId, identifier: &str, value: ExprId)
+    {
+        let variable = self.lookup_variable(identifier);
+        match &variable
+        {
+            Some((_, binding)) => self.assignment_bindings.insert(id, *binding),
+            None => self.error(format!("未定義の識別子です : {}", identifier)),
+        }
+
+        let value_type = self.value_expression(value);
+        if let Some((variable_type, _)) = variable
+        {
+            self.check_conversion(&variable_type, &value_type, value, identifier);
+        }
+    }
+
+    fn return_statement(&mut self, value: Option<ExprId>)
+    {
+        let return_type = match &self.current_function
+        {
+            Some((_, return_type)) => return_type.clone(),
+            None => return,
+        };
+
+        if let Some(expression) = value
+        {
+            if return_type == ValueType::Void
+            {
+                self.error("void 関数から値を返しています".to_string());
+                self.expression(expression);
+                return;
+            }
+
+            let value_type = self.value_expression(expression);
+            self.check_conversion(&return_type, &value_type, expression, "戻り値");
+        }
+    }
+
+    /// 代入や初期化, 引数と戻り値の暗黙の変換で値が変わる可能性がある場合に警告する.
+    /// 変換後の型で値が変わらない定数式は警告しない
+    fn check_conversion(&mut self, target: &ValueType, source: &ValueType, value: ExprId, name: &str)
+    {
+        if !Self::is_narrowing(target, source)
+        {
+            return;
+        }
+        if let Some(constant) = self.constant_value(value)
+        {
+            // 負の値は符号なし整数型に変換すると値が変わる
+            let negative = matches!(Interpreter::cast(&ValueType::Double, constant.clone()),
+                                    VariableType::Double(value) if value < 0.0);
+            let round_trip = Interpreter::cast(source, Interpreter::cast(target, constant.clone()));
+            if round_trip == constant && !(negative && target.is_unsigned())
+            {
+                return;
+            }
+        }
+        self.warning(format!("{} から {} への暗黙の縮小変換です : {}", source.as_str(), target.as_str(), name));
+    }
+
+    /// 変換先の型が変換元の型のすべての値を表せないかどうか.
+    /// 浮動小数点数から整数, double から float, 精度の小さい整数型と符号付きから符号なしへの変換が該当する.
+    /// _Bool への変換は真偽値の判定なので含めない
+    fn is_narrowing(target: &ValueType, source: &ValueType) -> bool
+    {
+        if *target == ValueType::Bool || *target == ValueType::Void || *source == ValueType::Void
+        {
+            return false;
+        }
+        match (target.is_floating(), source.is_floating())
+        {
+            (false, true) => true,
+            (true, true) => target.size() < source.size(),
+            (true, false) => false,
+            (false, false) =>
+                (target.is_unsigned() && !source.is_unsigned()) || Self::precision(target) < Self::precision(source),
+        }
+    }
+
+    /// 整数型が値を表すのに使うビット数. 符号付き整数型は符号ビットを含めない
+    fn precision(value_type: &ValueType) -> usize
+    {
+        match value_type
+        {
+            ValueType::Bool => 1,
+            value_type if value_type.is_unsigned() => value_type.size() * 8,
+            value_type => value_type.size() * 8 - 1,
+        }
+    }
+
+    /// 定数と定数の演算だけからなる式の値
+    fn constant_value(&self, id: ExprId) -> Option<VariableType>
+    {
+        match self.ast.expr(id)
+        {
+            Expr::Constant(constant) => Some(Interpreter::constant(constant)),
+            Expr::Unary { op, operand } =>
+                Interpreter::unary_operation(op, self.constant_value(*operand)?, ArithmeticMode::Wrapping, None).ok(),
+            Expr::Binary { op, lhs, rhs } =>
+                {
+                    let (lhs, rhs) = (self.constant_value(*lhs)?, self.constant_value(*rhs)?);
+                    Interpreter::binary_operation(op, lhs, rhs, ArithmeticMode::Wrapping, None).ok()
+                }
+            Expr::Cast { value_type, operand } if *value_type != ValueType::Void =>
+                Some(Interpreter::cast(value_type, self.constant_value(*operand)?)),
+            _ => None,
+        }
+    }
+
+    fn condition(&mut self, condition: ExprId)
+    {
+        self.value_expression(condition);
+    }
+
+    /// 値として使われる式を解析する. void は値として使えない
+    fn value_expression(&mut self, id: ExprId) -> ValueType
+    {
+        let value_type = self.expression(id);
+        if value_type == ValueType::Void
+        {
+            let message = match self.ast.expr(id)
+            {
+                Expr::Call { name, .. } =>
+                    format!("void 関数の結果は値として使用できません : {}", name),
+                _ => "void は値として使用できません".to_string(),
+            };
+            self.error(message);
+        }
+        value_type
+    }
+
+    /// 式の型を推論し, サイドテーブルに記録する
+    fn expression(&mut self, id: ExprId) -> ValueType
+    {
+        let value_type = self.infer_expression(id);
+        self.expr_types.insert(id, value_type.clone());
+        value_type
+    }
+
+    fn infer_expression(&mut self, id: ExprId) -> ValueType
+    {
+        let ast = self.ast;
+        match ast.expr(id)
+        {
+            Expr::Constant(constant) => constant.value_type(),
+            Expr::Identifier(identifier) =>
+                {
+                    match self.lookup_variable(identifier)
+                    {
+                        Some((value_type, binding)) =>
+                            {
+                                self.expr_bindings.insert(id, binding);
+                                value_type
+                            }
+                        None =>
+                            {
+                                self.error(format!("未定義の識別子です : {}", identifier));
+                                ValueType::Int
+                            }
+                    }
+                }
+            Expr::Unary { op, operand } =>
+                {
+                    let operand = self.value_expression(*operand);
+                    match op
+                    {
+                        UnaryOperator::Minus => operand.promoted(),
+                        UnaryOperator::LogicalNot => ValueType::Int,
+                    }
+                }
+            Expr::Binary { op, lhs, rhs } =>
+                {
+                    let lhs = self.value_expression(*lhs);
+                    let rhs = self.value_expression(*rhs);
+                    Self::binary_type(*op, &lhs, &rhs)
+                }
+            // 明示的な変換なので縮小変換でも警告しない
+            Expr::Cast { value_type, operand } =>
+                {
+                    self.value_expression(*operand);
+                    if *value_type == ValueType::Void
+                    {
+                        self.error("void にはキャストできません".to_string());
+                    }
+                    value_type.clone()
+                }
+            Expr::Call { name, arguments } =>
+                {
+                    let mut argument_types = Vec::new();
+                    for argument in arguments
+                    {
+                        argument_types.push(self.value_expression(*argument));
+                    }
+
+                    let signature = match self.functions.get(name)
+                    {
+                        Some(signature) => signature.clone(),
+                        None =>
+                            {
+                                self.error(format!("未定義の関数です : {}", name));
+                                return ValueType::Int;
+                            }
+                    };
+                    self.expr_bindings.insert(id, Binding::Function);
+                    if !self.external_functions && !self.defined_functions.contains(name)
+                    {
+                        self.error(format!("定義のない関数の呼び出しです : {}", name));
+                    }
+
+                    if signature.parameters.len() != argument_types.len()
+                    {
+                        self.error(format!("引数の数が一致しません : {} (期待値 {}, 実際 {})",
+                                           name,
+                                           signature.parameters.len(),
+                                           argument_types.len()));
+                    } else {
+                        let conversions = signature.parameters.iter().zip(argument_types.iter()).zip(arguments.iter());
+                        for ((parameter, argument), value) in conversions
+                        {
+                            self.check_conversion(parameter, argument, *value, name);
+                        }
+                    }
+
+                    signature.return_type
+                }
+            Expr::ArrayAccess { name, index } =>
+                {
+                    // 配列は宣言できないので, 添字の式だけを調べて報告する
+                    if let Some(index) = index
+                    {
+                        self.value_expression(*index);
+                    }
+                    self.error(format!("配列は未対応です : {}", name));
+                    ValueType::Int
+                }
+        }
+    }
+
+    /// 二項演算の結果の型. 算術演算は通常の算術変換による共通の型になり, 比較と論理演算は int になる.
+    /// 浮動小数点数の '%' は剰余を int に切り捨てる
+    pub(crate) fn binary_type(op: Operator, lhs: &ValueType, rhs: &ValueType) -> ValueType
+    {
+        match op
+        {
+            Operator::Plus | Operator::Minus | Operator::Multiply | Operator::Divide =>
+                {
+                    ValueType::common(lhs, rhs)
+                }
+            Operator::Modulo =>
+                {
+                    let common = ValueType::common(lhs, rhs);
+                    if common.is_floating() { ValueType::Int } else { common }
+                }
+            _ => ValueType::Int,
+        }
+    }
+
+    fn lookup_variable(&self, identifier: &str) -> Option<(ValueType, Binding)>
+    {
+        for scope in self.local_variables.iter().rev()
+        {
+            if let Some(variable) = scope.get(identifier)
+            {
+                return Some(variable.clone());
+            }
+        }
+        self.global_variables.get(identifier).map(|value_type| (value_type.clone(), Binding::Global))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::ast::{Decl, Expr, Stmt};
+    use crate::lexical::{Lexer, ValueType};
+    use crate::parser::Parser;
+    use crate::semantic::{Binding, SemanticAnalyzer, Severity};
+
+    fn analyze(program: &str) -> Vec<(Severity, String)>
+    {
+        let mut lexer = Lexer::new(program.to_string());
+        lexer.tokenize();
+
+        let mut parser = Parser::new(lexer.tokens().clone());
+        parser.parse();
+
+        let mut analyzer = SemanticAnalyzer::new(parser.ast());
+        analyzer.analyze()
+            .into_iter()
+            .map(|diagnostic| (diagnostic.severity(), diagnostic.message().clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_program()
+    {
+        let diagnostics = analyze("
+        int x = 10;
+        int fibo(int n) {
+            if (n < 2) {
+                return n;
+            } else {
+                return fibo(n - 1) + fibo(n - 2);
+            }
+        }
+        int main(void) {
+            double y = x * 1.5;
+            x = fibo(x);
+            return x;
+        }
+        ");
+
+        assert_eq!(diagnostics, vec![]);
+    }
+
+    #[test]
+    fn test_infinite_loop_returns()
+    {
+        // 条件が 0 でない定数で break のないループの後には制御が進まない
+        let diagnostics = analyze("
+        int first_above(int n) {
+            while (1) {
+                if (n > 3) {
+                    return n;
+                }
+                n = n + 1;
+            }
+        }
+        int inner_break(int n) {
+            while (1) {
+                while (n > 0) {
+                    break;
+                }
+                return n;
+            }
+        }
+        int breaks(int n) {
+            while (1) {
+                if (n > 3) {
+                    break;
+                }
+                n = n + 1;
+            }
+        }
+        int never(int n) {
+            while (0) {
+                return n;
+            }
+        }
+        ");
+
+        assert_eq!(diagnostics, vec![
+            (Severity::Error, "非 void 関数が値を返さずに終了する可能性があります (関数 breaks)".to_string()),
+            (Severity::Error, "非 void 関数が値を返さずに終了する可能性があります (関数 never)".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_errors()
+    {
+        let diagnostics = analyze("
+        void log(int n) { return n; }
+        int add(int a, int b) { return a + b; }
+        int missing(int n) {
+            if (n > 0) {
+                return 1;
+            }
+        }
+        int main(void) {
+            int a = add(1);
+            int b = log(2);
+            c = a + undefined;
+            (void)a;
+            b = a[b];
+            return a;
+        }
+        ");
+
+        let errors: Vec<&String> = diagnostics.iter()
+            .filter(|(severity, _)| *severity == Severity::Error)
+            .map(|(_, message)| message)
+            .collect();
+
+        assert_eq!(errors, vec![
+            "void 関数から値を返しています (関数 log)",
+            "非 void 関数が値を返さずに終了する可能性があります (関数 missing)",
+            "引数の数が一致しません : add (期待値 2, 実際 1) (関数 main)",
+            "void 関数の結果は値として使用できません : log (関数 main)",
+            "未定義の識別子です : c (関数 main)",
+            "未定義の識別子です : undefined (関数 main)",
+            "void にはキャストできません (関数 main)",
+            "配列は未対応です : a (関数 main)",
+        ]);
+    }
+
+    #[test]
+    fn test_redeclaration()
+    {
+        let diagnostics = analyze("
+        int g;
+        int g = 1;
+        int g;
+        int g = 2;
+        long g;
+        int same_block(void) {
+            int x = 1;
+            int x = 2;
+            return x;
+        }
+        int parameter(int n, int n) {
+            int n = 0;
+            return n;
+        }
+        int shadow(int n) {
+            int g = n;
+            while (n > 0) {
+                int n = 0;
+                int g = n;
+            }
+            {
+                int n = 1;
+            }
+            return g;
+        }
+        ");
+
+        // 内側のブロックでは外側の変数や引数を隠せるが, 同じスコープでは再宣言できない
+        assert_eq!(diagnostics, vec![
+            (Severity::Error, "グローバル変数が再定義されています : g".to_string()),
+            (Severity::Error, "グローバル変数が異なる型で再宣言されています : g".to_string()),
+            (Severity::Error, "同じスコープで再宣言されています : x (関数 same_block)".to_string()),
+            (Severity::Error, "同じスコープで再宣言されています : n (関数 parameter)".to_string()),
+            (Severity::Error, "同じスコープで再宣言されています : n (関数 parameter)".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_prototypes()
+    {
+        let diagnostics = analyze("
+        int twice(int n);
+        float scale(float);
+        int external(int);
+        int main(void) {
+            return twice(2) + external(1);
+        }
+        int twice(int n) { return n * 2; }
+        int scale(float x) { return 1; }
+        void external(int, int);
+        int unnamed(int) { return 0; }
+        int twice(int n) { return n + n; }
+        ");
+
+        // プロトタイプだけの関数は定義を与えられないので呼び出せない
+        assert_eq!(diagnostics, vec![
+            (Severity::Error, "関数の宣言が一致しません : scale (float (float) と int (float))".to_string()),
+            (Severity::Error, "関数の宣言が一致しません : external (int (int) と void (int, int))".to_string()),
+            (Severity::Error, "関数が再定義されています : twice".to_string()),
+            (Severity::Error, "定義のない関数の呼び出しです : external (関数 main)".to_string()),
+            (Severity::Error, "関数定義の引数には名前が必要です : 1 番目 (関数 unnamed)".to_string()),
+        ]);
+
+        // 定義を外から与えるバックエンドではプロトタイプだけの関数も呼び出せる
+        let mut lexer = Lexer::new("int external(int);\nint main(void) { return external(1); }\n".to_string());
+        lexer.tokenize();
+        let mut parser = Parser::new(lexer.tokens().clone());
+        parser.parse();
+        let mut analyzer = SemanticAnalyzer::new(parser.ast());
+        analyzer.set_external_functions(true);
+        assert!(analyzer.analyze().is_empty());
+    }
+
+    #[test]
+    fn test_narrowing_warning()
+    {
+        let diagnostics = analyze("
+        int truncate(float x) {
+            int n = x;
+            return x * 2.0;
+        }
+        char to_char(int n) { return n; }
+        float to_float(double x) { return x; }
+        void take(char c) { }
+        void narrow(long l, int i, unsigned int u) {
+            int from_long = l;
+            short from_int = i;
+            unsigned int from_signed = i;
+            int from_unsigned = u;
+            long widened = i;
+            double exact = 1.5f;
+            _Bool flag = l;
+            char c = 65;
+            unsigned char byte = 255;
+            float half = 0.5;
+            unsigned int negative = -1;
+            char wide = 300;
+            take(1000);
+            take(65);
+        }
+        ");
+
+        // 変換後の型で値が変わらない定数は警告しない
+        assert_eq!(diagnostics, vec![
+            (Severity::Warning, "float から int への暗黙の縮小変換です : n (関数 truncate)".to_string()),
+            (Severity::Warning, "double から int への暗黙の縮小変換です : 戻り値 (関数 truncate)".to_string()),
+            (Severity::Warning, "int から char への暗黙の縮小変換です : 戻り値 (関数 to_char)".to_string()),
+            (Severity::Warning, "double から float への暗黙の縮小変換です : 戻り値 (関数 to_float)".to_string()),
+            (Severity::Warning, "long から int への暗黙の縮小変換です : from_long (関数 narrow)".to_string()),
+            (Severity::Warning, "int から short への暗黙の縮小変換です : from_int (関数 narrow)".to_string()),
+            (Severity::Warning, "int から unsigned int への暗黙の縮小変換です : from_signed (関数 narrow)".to_string()),
+            (Severity::Warning, "unsigned int から int への暗黙の縮小変換です : from_unsigned (関数 narrow)".to_string()),
+            (Severity::Warning, "int から unsigned int への暗黙の縮小変換です : negative (関数 narrow)".to_string()),
+            (Severity::Warning, "int から char への暗黙の縮小変換です : wide (関数 narrow)".to_string()),
+            (Severity::Warning, "int から char への暗黙の縮小変換です : take (関数 narrow)".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_side_tables()
+    {
+        let mut lexer = Lexer::new("
+        int g = 1;
+        double scale(int n) {
+            int m = n + g;
+            m = m * 2;
+            return m * 0.5;
+        }
+        ".to_string());
+        lexer.tokenize();
+
+        let mut parser = Parser::new(lexer.tokens().clone());
+        parser.parse();
+        let ast = parser.ast();
+
+        let mut analyzer = SemanticAnalyzer::new(ast);
+        assert_eq!(analyzer.analyze(), vec![]);
+
+        let Decl::Function(function) = &ast.declarations()[1] else {
+            panic!("関数定義ではありません");
+        };
+        let declaration = function.body[0];
+        let assignment = function.body[1];
+
+        // int m = n + g; の n は引数, g はグローバル変数に解決される
+        let Stmt::Declaration(Decl::Variable { initializer: Some(initializer), .. }) = ast.stmt(declaration) else {
+            panic!("変数宣言ではありません");
+        };
+        let Expr::Binary { lhs, rhs, .. } = ast.expr(*initializer) else {
+            panic!("二項演算ではありません");
+        };
+        assert_eq!(analyzer.binding(*lhs), Some(&Binding::Parameter(0)));
+        assert_eq!(analyzer.binding(*rhs), Some(&Binding::Global));
+        assert_eq!(analyzer.expr_type(*initializer), Some(&ValueType::Int));
+
+        // 代入先の m は宣言文に解決される
+        assert_eq!(analyzer.assignment_binding(assignment), Some(&Binding::Local(declaration)));
+
+        // m * 0.5 は double
+        let Stmt::Return(Some(value)) = ast.stmt(function.body[2]) else {
+            panic!("return 文ではありません");
+        };
+        assert_eq!(analyzer.expr_type(*value), Some(&ValueType::Double));
+    }
+}
@@ -1,11 +1,8 @@
-use std::cell::Ref;
 use petgraph::dot::{Dot, Config};
 use petgraph::graph::{Graph, NodeIndex};
 use std::fs::File;
-use std::io::{self, Write};
-use std::cell::RefCell;
-use std::rc::{Rc, Weak};
-use crate::parser::{Leaf, Node};
+use std::io::Write;
+use crate::ast::{Ast, Decl, Expr, ExprId, Stmt, StmtId};
 
 pub struct TreeViewer {
     graph: Graph<String, String>,
@@ -13,6 +10,12 @@ pub struct TreeViewer {
     node_index: usize,
 }
 
+impl Default for TreeViewer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TreeViewer {
     pub fn new() -> Self {
         TreeViewer {
@@ -22,64 +25,138 @@ impl TreeViewer {
         }
     }
 
-    pub fn make_tree(&mut self, root: &Rc<RefCell<Node>>) {
-        self.add_node(root);
+    pub fn make_tree(&mut self, ast: &Ast, root: &Decl) {
+        self.add_decl(ast, root);
     }
 
-    fn create_graph_node(&mut self, leaf: &Leaf) -> NodeIndex {
-        let node_name = match leaf {
-            Leaf::Node(node) => format!("{}: {:?}", self.node_index, node.borrow().val().unwrap()),
-            Leaf::FunctionCall(func) => format!("{}: Function Call [{:?}]", self.node_index, func.name()),
-            Leaf::FunctionDefinition(func) => format!("{}: Function Definition [{:?}]", self.node_index, func.name()),
-            Leaf::IfStatement(_) => format!("{}: If Statement", self.node_index),
-            Leaf::BlockItem(_) => format!("{}: Block Item", self.node_index),
-            _ => format!("{}: {:?}", self.node_index, leaf),
-        };
+    fn create_graph_node(&mut self, label: String) -> NodeIndex {
+        let node_name = format!("{}: {}", self.node_index, label);
 
         let graph_node = self.graph.add_node(node_name);
         self.node_index_list.push(graph_node);
         self.node_index += 1;
 
-        match leaf {
-            Leaf::FunctionDefinition(func) => self.add_nodes_and_edges(graph_node, func.body()),
-            Leaf::FunctionCall(func) => self.add_nodes_and_edges(graph_node, func.arguments()),
-            Leaf::BlockItem(block) => self.add_nodes_and_edges(graph_node, block),
-            _ => {}
-        }
-
         graph_node
     }
 
-    fn add_nodes_and_edges(&mut self, parent_node: NodeIndex, items: &[Rc<RefCell<Node>>]) {
-        for item in items.iter() {
-            if let Some(node_index) = self.add_node(item) {
-                self.graph.add_edge(parent_node, node_index, String::from(""));
+    fn add_edge(&mut self, parent_node: NodeIndex, child_node: NodeIndex) {
+        self.graph.add_edge(parent_node, child_node, String::from(""));
+    }
+
+    fn add_decl(&mut self, ast: &Ast, decl: &Decl) -> NodeIndex {
+        match decl {
+            Decl::Variable { type_specifier, name, initializer } => {
+                let graph_node = self.create_graph_node(format!("Declaration [{:?} {}]", type_specifier, name));
+                if let Some(initializer) = initializer {
+                    let child = self.add_expr(ast, *initializer);
+                    self.add_edge(graph_node, child);
+                }
+                graph_node
+            }
+            Decl::Prototype(function) => self.create_graph_node(format!("Function Prototype [{:?}]", function.name)),
+            Decl::Function(function) => {
+                let graph_node = self.create_graph_node(format!("Function Definition [{:?}]", function.name));
+                for stmt in function.body.iter() {
+                    let child = self.add_stmt(ast, *stmt);
+                    self.add_edge(graph_node, child);
+                }
+                graph_node
             }
         }
     }
-    fn add_node(&mut self, node: &Rc<RefCell<Node>>) -> Option<NodeIndex> {
-        if let Some(val) = node.borrow().val() {
-            println!("val: {:?}", val);
-            let graph_node = self.create_graph_node(val);
 
-            if let Some(lhs) = node.borrow().lhs() {
-                let lhs_graph_node = self.add_node(lhs);
-                if let Some(lhs_graph_node) = lhs_graph_node {
-                    self.graph.add_edge(graph_node, lhs_graph_node, String::from(""));
+    fn add_stmt(&mut self, ast: &Ast, stmt: StmtId) -> NodeIndex {
+        match ast.stmt(stmt) {
+            Stmt::Declaration(decl) => self.add_decl(ast, decl),
+            Stmt::Assignment { name, value } => {
+                let graph_node = self.create_graph_node(format!("Assignment [{}]", name));
+                let child = self.add_expr(ast, *value);
+                self.add_edge(graph_node, child);
+                graph_node
+            }
+            Stmt::Expression(expr) => self.add_expr(ast, *expr),
+            Stmt::Block(stmts) => {
+                let graph_node = self.create_graph_node(String::from("Block Item"));
+                for stmt in stmts.iter() {
+                    let child = self.add_stmt(ast, *stmt);
+                    self.add_edge(graph_node, child);
                 }
+                graph_node
             }
-
-            if let Some(rhs) = node.borrow().rhs() {
-                let rhs_graph_node = self.add_node(rhs);
-                if let Some(rhs_graph_node) = rhs_graph_node {
-                    self.graph.add_edge(graph_node, rhs_graph_node, String::from(""));
+            Stmt::If { condition, then_branch, else_branch } => {
+                let graph_node = self.create_graph_node(String::from("If Statement"));
+                let child = self.add_expr(ast, *condition);
+                self.add_edge(graph_node, child);
+                let child = self.add_stmt(ast, *then_branch);
+                self.add_edge(graph_node, child);
+                if let Some(else_branch) = else_branch {
+                    let child = self.add_stmt(ast, *else_branch);
+                    self.add_edge(graph_node, child);
                 }
+                graph_node
             }
-
-            return Some(graph_node);
+            Stmt::While { condition, body } => {
+                let graph_node = self.create_graph_node(String::from("While Statement"));
+                let child = self.add_expr(ast, *condition);
+                self.add_edge(graph_node, child);
+                let child = self.add_stmt(ast, *body);
+                self.add_edge(graph_node, child);
+                graph_node
+            }
+            Stmt::Return(value) => {
+                let graph_node = self.create_graph_node(String::from("Return"));
+                if let Some(value) = value {
+                    let child = self.add_expr(ast, *value);
+                    self.add_edge(graph_node, child);
+                }
+                graph_node
+            }
+            Stmt::Break => self.create_graph_node(String::from("Break")),
+            Stmt::Continue => self.create_graph_node(String::from("Continue")),
         }
+    }
 
-        None
+    fn add_expr(&mut self, ast: &Ast, expr: ExprId) -> NodeIndex {
+        match ast.expr(expr) {
+            Expr::Constant(constant) => self.create_graph_node(format!("{:?}", constant)),
+            Expr::Identifier(name) => self.create_graph_node(format!("Identifier [{}]", name)),
+            Expr::Unary { op, operand } => {
+                let graph_node = self.create_graph_node(format!("UnaryExpression [{:?}]", op));
+                let child = self.add_expr(ast, *operand);
+                self.add_edge(graph_node, child);
+                graph_node
+            }
+            Expr::Binary { op, lhs, rhs } => {
+                let graph_node = self.create_graph_node(format!("Operator [{}]", op.as_str()));
+                let child = self.add_expr(ast, *lhs);
+                self.add_edge(graph_node, child);
+                let child = self.add_expr(ast, *rhs);
+                self.add_edge(graph_node, child);
+                graph_node
+            }
+            Expr::Cast { value_type, operand } => {
+                let graph_node = self.create_graph_node(format!("Cast [{}]", value_type.as_str()));
+                let child = self.add_expr(ast, *operand);
+                self.add_edge(graph_node, child);
+                graph_node
+            }
+            Expr::Call { name, arguments } => {
+                let graph_node = self.create_graph_node(format!("Function Call [{:?}]", name));
+                for argument in arguments.iter() {
+                    let child = self.add_expr(ast, *argument);
+                    self.add_edge(graph_node, child);
+                }
+                graph_node
+            }
+            Expr::ArrayAccess { name, index } => {
+                let graph_node = self.create_graph_node(format!("ArrayAccess [{}]", name));
+                if let Some(index) = index {
+                    let child = self.add_expr(ast, *index);
+                    self.add_edge(graph_node, child);
+                }
+                graph_node
+            }
+        }
     }
 
     pub fn output_dot(&self, file_name: &str) {
@@ -89,4 +166,4 @@ impl TreeViewer {
         let mut file = File::create(file_name).unwrap();
         file.write_all(dot_output.as_bytes()).unwrap();
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,937 @@
+use crate::bytecode::{Compiler, FunctionCode, Instruction, Program};
+use crate::interpreter::{parse_program, ArithmeticMode, Interpreter, RuntimeError, Variable, VariableType, DEFAULT_MAX_CALL_DEPTH};
+use crate::lexical::{Operator, ValueType};
+use crate::parser::static_local_name;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// 関数呼び出し 1 回分の実行状態
+struct Frame<'a>
+{
+    function: &'a FunctionCode,
+
+    // 次に実行する命令の位置
+    ip: usize,
+
+    // スタック上のローカル変数の先頭
+    base: usize,
+
+    // 厳密モードで, 宣言されてからまだ代入されていないローカル変数のスロットと名前
+    uninitialized: Vec<(usize, usize)>,
+}
+
+/// バイトコードを実行するスタックマシン
+pub struct Vm
+{
+    program: Rc<Program>,
+
+    // グローバル変数. 定義されるまでは None
+    globals: Vec<Option<VariableType>>,
+
+    // ローカル変数と計算途中の値を積むスタック
+    stack: Vec<VariableType>,
+
+    // グローバル変数の初期化が済んでいるか
+    initialized: bool,
+
+    // 同時に実行できる関数呼び出しの数. 初期化コードは数えない
+    max_call_depth: usize,
+
+    // 代入前のローカル変数の読み出しをエラーにするか
+    strict: bool,
+
+    // 符号付き整数のオーバーフローの扱い
+    arithmetic_mode: ArithmeticMode,
+}
+
+impl Vm
+{
+    pub fn new(program: Program) -> Self
+    {
+        let globals = vec![None; program.globals.len()];
+        Vm {
+            program: Rc::new(program),
+            globals,
+            stack: Vec::new(),
+            initialized: false,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            strict: false,
+            arithmetic_mode: ArithmeticMode::Wrapping,
+        }
+    }
+
+    /// ソースコードをバイトコードにコンパイルし, グローバル変数を初期化した状態で返す
+    pub fn load(program: &str) -> Result<Self, RuntimeError>
+    {
+        let parser = parse_program(program)?;
+        let mut vm = Vm::new(Compiler::new(parser.ast()).compile());
+        vm.initialize()?;
+        Ok(vm)
+    }
+
+    pub fn program(&self) -> &Program
+    {
+        &self.program
+    }
+
+    /// グローバル変数を初期化する. 2回目以降の呼び出しでは何もしない
+    pub fn initialize(&mut self) -> Result<(), RuntimeError>
+    {
+        if self.initialized
+        {
+            return Ok(());
+        }
+
+        let program = Rc::clone(&self.program);
+        self.execute(&program.initializer, &[])?;
+        self.initialized = true;
+        Ok(())
+    }
+
+    /// 任意の関数を引数付きで呼び出す. グローバル変数の状態は呼び出し間で保持される
+    pub fn call(&mut self, name: &str, arguments: &[VariableType]) -> Result<VariableType, RuntimeError>
+    {
+        self.initialize()?;
+
+        let program = Rc::clone(&self.program);
+        let function = match program.function_index(name)
+        {
+            Some(index) => &program.functions[index],
+            None => return Err(RuntimeError::UndefinedFunction(name.to_string())),
+        };
+
+        if arguments.len() != function.parameters.len()
+        {
+            return Err(RuntimeError::ArgumentCountMismatch {
+                name: name.to_string(),
+                expected: function.parameters.len(),
+                found: arguments.len(),
+            });
+        }
+
+        self.execute(function, arguments)
+    }
+
+    /// 同時に実行できる関数呼び出しの数を設定する. 末尾呼び出しは深さに数えない
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize)
+    {
+        self.max_call_depth = max_call_depth;
+    }
+
+    /// 厳密モードを設定する. 有効な場合, 初期化子のないローカル変数を代入前に読むとエラーになる
+    pub fn set_strict(&mut self, strict: bool)
+    {
+        self.strict = strict;
+    }
+
+    /// 符号付き整数のオーバーフローを折り返すか, 実行時エラーにするかを設定する
+    pub fn set_arithmetic_mode(&mut self, arithmetic_mode: ArithmeticMode)
+    {
+        self.arithmetic_mode = arithmetic_mode;
+    }
+
+    /// 定義済みのグローバル変数. static なローカル変数を置き換えたものは含めない
+    pub fn global_variables(&self) -> HashMap<String, Variable>
+    {
+        self.program.globals.iter()
+            .zip(self.globals.iter())
+            .filter(|(global, _)| static_local_name(&global.name).is_none())
+            .filter_map(|(global, value)| {
+                value.as_ref().map(|value| (global.name.clone(), Variable::Value(value.clone())))
+            })
+            .collect()
+    }
+
+    pub fn run(&mut self) -> VariableType
+    {
+        // main 関数を呼び出し実行する
+        match self.call("main", &[])
+        {
+            Ok(val) => val,
+            Err(RuntimeError::UndefinedFunction(_)) => panic!("main 関数が見つかりません"),
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    pub fn show_variables(&self)
+    {
+        for (name, variable) in self.global_variables()
+        {
+            if let Variable::Value(value) = variable
+            {
+                println!("{} = {}", name, value);
+            }
+        }
+    }
+
+    fn execute(&mut self, entry: &FunctionCode, arguments: &[VariableType]) -> Result<VariableType, RuntimeError>
+    {
+        // 前回の呼び出しがエラーで中断した場合に備えてスタックを空にする
+        self.stack.clear();
+        self.stack.extend_from_slice(arguments);
+
+        let program = Rc::clone(&self.program);
+        let mut frames = vec![self.enter(entry)?];
+
+        // 初期化コードのフレームは呼び出しの深さに数えない
+        let is_initializer = std::ptr::eq(entry, &program.initializer);
+        let max_frames = self.max_call_depth + usize::from(is_initializer);
+
+        loop
+        {
+            let frame = frames.last_mut().unwrap();
+            let instruction = &frame.function.code[frame.ip];
+            frame.ip += 1;
+
+            match instruction
+            {
+                Instruction::Constant(index) =>
+                    {
+                        self.stack.push(program.constants[*index].clone());
+                    }
+                Instruction::Pop =>
+                    {
+                        self.stack.pop();
+                    }
+                Instruction::LoadLocal(slot) =>
+                    {
+                        if let Some((_, name)) = frame.uninitialized.iter().find(|(uninitialized, _)| uninitialized == slot)
+                        {
+                            return Err(RuntimeError::UninitializedVariable(program.names[*name].clone()));
+                        }
+                        let value = self.stack[frame.base + slot].clone();
+                        self.stack.push(value);
+                    }
+                Instruction::DefineLocal { slot, value_type, name } =>
+                    {
+                        let value = self.pop();
+                        let value = Self::define(value_type, value, &program.names[*name])?;
+                        self.stack[frame.base + slot] = value;
+                        frame.uninitialized.retain(|(uninitialized, _)| uninitialized != slot);
+                    }
+                Instruction::DeclareLocal { slot, value_type, name } =>
+                    {
+                        let value = Self::define(value_type, VariableType::Int(0), &program.names[*name])?;
+                        self.stack[frame.base + slot] = value;
+                        if self.strict
+                        {
+                            frame.uninitialized.retain(|(uninitialized, _)| uninitialized != slot);
+                            frame.uninitialized.push((*slot, *name));
+                        }
+                    }
+                Instruction::StoreLocal { slot, name } =>
+                    {
+                        let value = self.pop();
+                        let variable = &mut self.stack[frame.base + slot];
+                        *variable = Interpreter::convert(&Interpreter::value_type(variable), value, &program.names[*name])?;
+                        frame.uninitialized.retain(|(uninitialized, _)| uninitialized != slot);
+                    }
+                Instruction::LoadGlobal(slot) =>
+                    {
+                        match &self.globals[*slot]
+                        {
+                            Some(value) => self.stack.push(value.clone()),
+                            None => return Err(RuntimeError::UndefinedVariable(program.globals[*slot].name.clone())),
+                        }
+                    }
+                Instruction::DefineGlobal { slot, value_type } =>
+                    {
+                        let value = self.pop();
+                        let value = Self::define(value_type, value, &program.globals[*slot].name)?;
+                        self.globals[*slot] = Some(value);
+                    }
+                Instruction::StoreGlobal(slot) =>
+                    {
+                        let value = self.pop();
+                        let name = &program.globals[*slot].name;
+                        match &mut self.globals[*slot]
+                        {
+                            Some(variable) =>
+                                {
+                                    *variable = Interpreter::convert(&Interpreter::value_type(variable), value, name)?;
+                                }
+                            None => return Err(RuntimeError::UndefinedVariable(name.clone())),
+                        }
+                    }
+                Instruction::Unary(op) =>
+                    {
+                        let value = self.pop();
+                        let line = Self::line(frame);
+                        self.stack.push(Interpreter::unary_operation(op, value, self.arithmetic_mode, line)?);
+                    }
+                Instruction::Binary(op) =>
+                    {
+                        let rhs = self.pop();
+                        let lhs = self.pop();
+                        let line = Self::line(frame);
+                        self.stack.push(Interpreter::binary_operation(op, lhs, rhs, self.arithmetic_mode, line)?);
+                    }
+                Instruction::Cast(value_type) =>
+                    {
+                        let value = self.pop();
+                        self.stack.push(Interpreter::convert(value_type, value, "キャスト")?);
+                    }
+                Instruction::ShortCircuitAnd(target) =>
+                    {
+                        if Self::is_short_circuit(Operator::LogicalAnd, self.stack.last().unwrap())
+                        {
+                            *self.stack.last_mut().unwrap() = VariableType::Int(0);
+                            frame.ip = *target;
+                        }
+                    }
+                Instruction::ShortCircuitOr(target) =>
+                    {
+                        if Self::is_short_circuit(Operator::LogicalOr, self.stack.last().unwrap())
+                        {
+                            *self.stack.last_mut().unwrap() = VariableType::Int(1);
+                            frame.ip = *target;
+                        }
+                    }
+                Instruction::Jump(target) =>
+                    {
+                        frame.ip = *target;
+                    }
+                Instruction::JumpIfFalse(target) =>
+                    {
+                        let value = self.pop();
+                        if !Interpreter::truthy(value)?
+                        {
+                            frame.ip = *target;
+                        }
+                    }
+                Instruction::Call(index) =>
+                    {
+                        let function = &program.functions[*index];
+                        if frames.len() >= max_frames
+                        {
+                            let mut chain: Vec<String> = frames.iter()
+                                .skip(usize::from(is_initializer))
+                                .map(|frame| frame.function.name.clone())
+                                .collect();
+                            chain.push(function.name.clone());
+                            return Err(RuntimeError::StackOverflow { limit: self.max_call_depth, chain });
+                        }
+
+                        let frame = self.enter(function)?;
+                        frames.push(frame);
+                    }
+                Instruction::TailCall(index) =>
+                    {
+                        // 引数を今のフレームの先頭に移し, 呼び出し元のフレームを呼び出し先で置き換える
+                        let function = &program.functions[*index];
+                        let base = frame.base;
+                        let arguments = self.stack.len() - function.parameters.len();
+                        self.stack.drain(base..arguments);
+
+                        *frames.last_mut().unwrap() = self.enter(function)?;
+                    }
+                Instruction::CheckValue(name) =>
+                    {
+                        // 式の中で void の場合はエラー
+                        if let Some(VariableType::Void) = self.stack.last()
+                        {
+                            return Err(RuntimeError::VoidMisuse(
+                                format!("void 関数の結果は値として使用できません : {}", program.names[*name])));
+                        }
+                    }
+                Instruction::Return | Instruction::ReturnVoid =>
+                    {
+                        let value = match instruction
+                        {
+                            Instruction::Return => self.pop(),
+                            _ => VariableType::Void,
+                        };
+                        let value = Self::return_value(frame.function, value)?;
+
+                        let frame = frames.pop().unwrap();
+                        self.stack.truncate(frame.base);
+                        if frames.is_empty()
+                        {
+                            return Ok(value);
+                        }
+                        self.stack.push(value);
+                    }
+                Instruction::UndefinedVariable(name) =>
+                    {
+                        return Err(RuntimeError::UndefinedVariable(program.names[*name].clone()));
+                    }
+                Instruction::UndefinedFunction(name) =>
+                    {
+                        return Err(RuntimeError::UndefinedFunction(program.names[*name].clone()));
+                    }
+                Instruction::ArgumentCountMismatch { name, expected, found } =>
+                    {
+                        return Err(RuntimeError::ArgumentCountMismatch {
+                            name: program.names[*name].clone(),
+                            expected: *expected,
+                            found: *found,
+                        });
+                    }
+            }
+        }
+    }
+
+    /// スタックに積まれた引数を宣言された型に変換し, 新しいフレームを作る
+    fn enter<'a>(&mut self, function: &'a FunctionCode) -> Result<Frame<'a>, RuntimeError>
+    {
+        let base = self.stack.len() - function.parameters.len();
+        for (offset, parameter) in function.parameters.iter().enumerate()
+        {
+            let argument = std::mem::replace(&mut self.stack[base + offset], VariableType::Void);
+            self.stack[base + offset] = Self::coerce(&parameter.type_specifier, argument, || {
+                format!("{} の引数 {}", function.name, parameter.name)
+            })?;
+        }
+
+        // 引数以外のローカル変数の領域を確保する
+        self.stack.resize(base + function.local_count, VariableType::Void);
+
+        Ok(Frame { function, ip: 0, base, uninitialized: Vec::new() })
+    }
+
+    /// 実行中の命令に対応するソースの行. 不明な場合は None
+    fn line(frame: &Frame) -> Option<usize>
+    {
+        frame.function.lines.get(frame.ip - 1).copied().filter(|line| *line != 0)
+    }
+
+    fn pop(&mut self) -> VariableType
+    {
+        self.stack.pop().expect("スタックが空です")
+    }
+
+    fn define(value_type: &ValueType, value: VariableType, name: &str) -> Result<VariableType, RuntimeError>
+    {
+        if *value_type == ValueType::Void
+        {
+            return Err(RuntimeError::VoidMisuse(format!("変数を void 型で宣言することはできません : {}", name)));
+        }
+        Interpreter::convert(value_type, value, name)
+    }
+
+    /// '&&' と '||' の左辺だけで結果が決まるかどうか
+    fn is_short_circuit(op: Operator, lhs: &VariableType) -> bool
+    {
+        match op
+        {
+            Operator::LogicalAnd => Interpreter::truthy(lhs.clone()) == Ok(false),
+            Operator::LogicalOr => Interpreter::truthy(lhs.clone()) == Ok(true),
+            _ => false,
+        }
+    }
+
+    /// 戻り値を関数の型に変換する
+    fn return_value(function: &FunctionCode, value: VariableType) -> Result<VariableType, RuntimeError>
+    {
+        match (&function.return_type, value)
+        {
+            (ValueType::Void, VariableType::Void) => Ok(VariableType::Void),
+            (ValueType::Void, _) => Err(RuntimeError::VoidMisuse(
+                format!("void 関数から値を返しています : {}", function.name))),
+            (_, VariableType::Void) => Err(RuntimeError::MissingReturnValue(function.name.clone())),
+            (return_type, value) => Self::coerce(return_type, value, || format!("{} の戻り値", function.name)),
+        }
+    }
+
+    /// Interpreter::convert と同じ変換を行う. 型が一致する場合はエラー表示用の名前を作らない
+    fn coerce(value_type: &ValueType, value: VariableType, name: impl FnOnce() -> String)
+        -> Result<VariableType, RuntimeError>
+    {
+        if Interpreter::value_type(&value) == *value_type
+        {
+            return Ok(value);
+        }
+        Interpreter::convert(value_type, value, &name())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::interpreter::VariableType::{Float, Int};
+    use crate::interpreter::{ArithmeticMode, Interpreter, RuntimeError, Variable, VariableType};
+    use crate::vm::Vm;
+    use std::collections::HashMap;
+
+    /// インタプリタのグローバル変数を VM と比べられる形にする
+    fn interpreter_globals(interpreter: &Interpreter) -> HashMap<String, Variable>
+    {
+        interpreter.global_variables().map(|(name, variable)| (name.clone(), variable.clone())).collect()
+    }
+
+    /// VM とインタプリタで同じ呼び出しを行い, 結果とグローバル変数が一致することを確認する
+    fn assert_same(program: &str, calls: &[(&str, Vec<VariableType>)]) -> Vec<Result<VariableType, RuntimeError>>
+    {
+        let mut interpreter = Interpreter::load(program).unwrap();
+        let mut vm = Vm::load(program).unwrap();
+        assert_eq!(vm.global_variables(), interpreter_globals(&interpreter));
+
+        let mut results = Vec::new();
+        for (name, arguments) in calls
+        {
+            let expected = interpreter.call(name, arguments);
+            let result = vm.call(name, arguments);
+            assert_eq!(result, expected, "{} の呼び出し結果が一致しません", name);
+            assert_eq!(vm.global_variables(), interpreter_globals(&interpreter));
+            results.push(result);
+        }
+        results
+    }
+
+    #[test]
+    fn test_static_variable()
+    {
+        let program = "
+        int x = (10 + 20) * 3 - 4 / 2;
+        float y = (x + 1) * 1.5;
+        int z = ((x > 15) && (y < 50.0)) || (x == 26);
+        int fib = 0;
+        int add(int a, int b) { return a + b; }
+        int sub(int a, int b) { return a - b; }
+        int fibo(int n) {
+            if (n == 0) {
+                return 0;
+            } else if (n == 1) {
+                return 1;
+            } else {
+                return fibo(n - 1) + fibo(n - 2);
+            }
+        }
+
+        int main(void) {
+            int a = 10;
+            int b = 20;
+            a = add(a * 2, (b + 10) / 2);
+            int c = sub(a, b);
+            int d = c + x;
+            fib = fibo(10);
+            return d;
+        }
+        ";
+
+        assert_eq!(assert_same(program, &[("main", vec![])]), vec![Ok(Int(103))]);
+
+        let mut vm = Vm::load(program).unwrap();
+        assert_eq!(vm.run(), Int(103));
+        assert_eq!(vm.global_variables().get("fib"), Some(&Variable::Value(Int(55))));
+        assert_eq!(vm.global_variables().get("y"), Some(&Variable::Value(Float(133.5))));
+        assert_eq!(vm.global_variables().get("z"), Some(&Variable::Value(Int(0))));
+    }
+
+    #[test]
+    fn test_static_local()
+    {
+        let program = "
+        static const int step = 2;
+        int counter(void) {
+            static int count = step * 5;
+            count = count + step;
+            return count;
+        }
+        int other(const int n) {
+            static int count;
+            if (n > 0) {
+                static int count = -1;
+                count = count + n;
+                return count;
+            }
+            return count;
+        }
+        int scaled(void) {
+            const int k = 2;
+            static int s = k;
+            s = s * k;
+            return s;
+        }
+        ";
+
+        // static なローカル変数は呼び出しをまたいで値を保ち, 同じ名前でもブロックごとに別の変数になる.
+        // 初期化子にはローカルな const 変数も使える
+        let results = assert_same(program, &[
+            ("counter", vec![]),
+            ("counter", vec![]),
+            ("other", vec![Int(3)]),
+            ("other", vec![Int(4)]),
+            ("other", vec![Int(0)]),
+            ("scaled", vec![]),
+            ("scaled", vec![]),
+        ]);
+        assert_eq!(results, vec![Ok(Int(12)), Ok(Int(14)), Ok(Int(2)), Ok(Int(6)), Ok(Int(0)), Ok(Int(4)), Ok(Int(8))]);
+
+        // グローバル変数に置き換えた static なローカル変数は外からは見えない
+        let vm = Vm::load(program).unwrap();
+        let names: Vec<String> = vm.global_variables().into_keys().collect();
+        assert_eq!(names, vec!["step".to_string()]);
+    }
+
+    #[test]
+    fn test_call_function()
+    {
+        let program = "
+        int count = 0;
+        int fibo(int n) {
+            count = count + 1;
+            if (n == 0) {
+                return 0;
+            } else if (n == 1) {
+                return 1;
+            } else {
+                return fibo(n - 1) + fibo(n - 2);
+            }
+        }
+        float scale(float x, int n) { return x * n; }
+        ";
+
+        assert_same(program, &[
+            ("fibo", vec![Int(10)]),
+            ("scale", vec![Float(1.5), Int(4)]),
+            ("fibo", vec![Int(1)]),
+        ]);
+
+        let mut vm = Vm::load(program).unwrap();
+        assert_eq!(vm.call("fibo", &[Int(10)]), Ok(Int(55)));
+        assert_eq!(vm.global_variables().get("count"), Some(&Variable::Value(Int(177))));
+    }
+
+    #[test]
+    fn test_type_conversion()
+    {
+        let program = "
+        float ratio = 0.0;
+        int half(float x) { return x / 2; }
+        float widen(int n) { return n; }
+        int store(float x) {
+            ratio = x;
+            int n = 0;
+            n = x * 3;
+            return n;
+        }
+        ";
+
+        assert_eq!(assert_same(program, &[
+            ("half", vec![Int(5)]),
+            ("widen", vec![Float(2.7)]),
+            ("store", vec![Int(3)]),
+            ("store", vec![Float(1.5)]),
+        ]), vec![Ok(Int(2)), Ok(Float(2.0)), Ok(Int(9)), Ok(Int(4))]);
+    }
+
+    #[test]
+    fn test_numeric_types()
+    {
+        let program = "
+        unsigned int big = 4000000000u;
+        long wide = 3000000000;
+        unsigned long huge = 18000000000000000000ul;
+        char small = 200;
+        unsigned char byte = 300;
+        short half = 40000;
+        _Bool flag = 5;
+        double ratio = 1.0 / 3;
+        float third = 1.0f / 3;
+        long mix(char c, unsigned int u, long l, float f, double d) {
+            return c + u + l + f + d;
+        }
+        unsigned char low(int n) {
+            return n;
+        }
+        int main(void) {
+            int score = 0;
+            unsigned int u = 1;
+            long l = wide * 3;
+            double d = huge;
+            unsigned long back = d;
+            char c = 127;
+            unsigned short us = 0;
+            c = c + 1;
+            us = us - 1;
+            if (-1 < u) {
+                score = score + 100;
+            }
+            if (u - 2 > u) {
+                score = score + 1;
+            }
+            if (l / 7 == 1285714285 && -wide % 7 == -4) {
+                score = score + 1;
+            }
+            if (huge / 1000000000000000000ul == 18 && back == huge && d > 17000000000000000000.0) {
+                score = score + 1;
+            }
+            if (small == -56 && byte == 44 && half == -25536 && flag == 1) {
+                score = score + 1;
+            }
+            if (c == -128 && us == 65535 && (u - 2) + 0L > 0) {
+                score = score + 1;
+            }
+            if (mix(-3, 10u, 100, 0.5f, 0.25) == 107 && low(513) == 1) {
+                score = score + 1;
+            }
+            if (third + ratio > 0.66 && big / 3 == 1333333333u && big % 7 == 3) {
+                score = score + 1;
+            }
+            return score;
+        }
+        ";
+
+        assert_eq!(assert_same(program, &[("main", vec![])]), vec![Ok(Int(7))]);
+
+        let program = "
+        double ratio = (double)7 / 2;
+        int truncated = (int)3.99;
+        unsigned char low = (unsigned char)300;
+        long size = sizeof(long) + sizeof ratio;
+        int main(void) {
+            int n = -7;
+            unsigned int u = (unsigned int)n;
+            float f = (float)n / 2;
+            _Bool b = (_Bool)0.5;
+            return (int)(u / 2u % 100) + (int)f + b + (char)(n * 40) + sizeof(n + 1L);
+        }
+        ";
+
+        assert_eq!(assert_same(program, &[("main", vec![])]), vec![Ok(Int(26))]);
+    }
+
+    #[test]
+    fn test_void_misuse()
+    {
+        let program = "
+        void give(int n) { return n; }
+        void nothing(int n) { n = 1; }
+        int use_void(int n) {
+            int x = nothing(n);
+            return x;
+        }
+        int no_return(int n) { n = n + 1; }
+        ";
+
+        let results = assert_same(program, &[
+            ("nothing", vec![Int(1)]),
+            ("give", vec![Int(1)]),
+            ("use_void", vec![Int(1)]),
+            ("no_return", vec![Int(1)]),
+        ]);
+        assert_eq!(results[0], Ok(VariableType::Void));
+        assert!(matches!(results[1], Err(RuntimeError::VoidMisuse(_))));
+        assert!(matches!(results[2], Err(RuntimeError::VoidMisuse(_))));
+        assert_eq!(results[3], Err(RuntimeError::MissingReturnValue("no_return".to_string())));
+    }
+
+    #[test]
+    fn test_control_flow()
+    {
+        let program = "
+        int sum_odd(int n) {
+            int i = 0;
+            int sum = 0;
+            while (1) {
+                i = i + 1;
+                if (i > n) {
+                    break;
+                }
+                if (i % 2 == 0) {
+                    continue;
+                }
+                sum = sum + i;
+            }
+            return sum;
+        }
+        int first_multiple(int n, int k) {
+            int i = 1;
+            while (i < 100) {
+                if (i * k >= n) {
+                    return i * k;
+                }
+                i = i + 1;
+            }
+            return 0 - 1;
+        }
+        int shadow(int n) {
+            int x = n;
+            {
+                int x = n * 10;
+                n = x;
+            }
+            return x + n;
+        }
+        ";
+
+        assert_eq!(assert_same(program, &[
+            ("sum_odd", vec![Int(10)]),
+            ("first_multiple", vec![Int(20), Int(7)]),
+            ("shadow", vec![Int(3)]),
+        ]), vec![Ok(Int(25)), Ok(Int(21)), Ok(Int(33))]);
+    }
+
+    #[test]
+    fn test_call_error()
+    {
+        let program = "
+        int add(int a, int b) { return a + b; }
+        int wrong(int a) { return add(a); }
+        int unknown(int a) { return a + missing; }
+        int external(int a);
+        int call_external(int a) { return external(a); }
+        int later(int a);
+        int call_later(int a) { return later(a) + 1; }
+        int later(int a) { return a * 3; }
+        ";
+
+        let results = assert_same(program, &[
+            ("main", vec![]),
+            ("add", vec![Int(1)]),
+            ("wrong", vec![Int(1)]),
+            ("unknown", vec![Int(1)]),
+            ("add", vec![Int(1), Int(2)]),
+            ("call_external", vec![Int(1)]),
+            ("call_later", vec![Int(2)]),
+        ]);
+        assert_eq!(results[0], Err(RuntimeError::UndefinedFunction("main".to_string())));
+        assert_eq!(results[3], Err(RuntimeError::UndefinedVariable("missing".to_string())));
+        assert_eq!(results[4], Ok(Int(3)));
+        assert_eq!(results[5], Err(RuntimeError::UndefinedFunction("external".to_string())));
+        assert_eq!(results[6], Ok(Int(7)));
+    }
+
+    #[test]
+    fn test_call_depth()
+    {
+        let program = "
+        int depth(int n) {
+            if (n == 0) {
+                return 0;
+            }
+            return depth(n - 1) + 1;
+        }
+        float half(float x, int n) {
+            if (n == 0) {
+                return x;
+            }
+            return half(x / 2, n - 1);
+        }
+        float truncated(int n) {
+            return depth(n);
+        }
+        int start = depth(2);
+        ";
+
+        let mut interpreter = Interpreter::load(program).unwrap();
+        let mut vm = Vm::load(program).unwrap();
+        for limit in [3, 1000]
+        {
+            interpreter.set_max_call_depth(limit);
+            vm.set_max_call_depth(limit);
+            for (name, arguments) in [
+                ("depth", vec![Int(2)]),
+                ("depth", vec![Int(3)]),
+                ("half", vec![Float(1.0), Int(3)]),
+                ("half", vec![Int(1), Int(20000)]),
+                ("truncated", vec![Int(2)]),
+                ("truncated", vec![Int(3)]),
+            ]
+            {
+                assert_eq!(vm.call(name, &arguments), interpreter.call(name, &arguments), "{}({:?})", name, arguments);
+            }
+        }
+
+        vm.set_max_call_depth(3);
+        assert_eq!(vm.call("depth", &[Int(3)]), Err(RuntimeError::StackOverflow {
+            limit: 3,
+            chain: vec!["depth".to_string(); 4],
+        }));
+    }
+
+    #[test]
+    fn test_uninitialized()
+    {
+        let program = "
+        int count;
+        float ratio;
+        int increment() {
+            count = count + 1;
+            return count;
+        }
+        float read_later(int n) {
+            float y;
+            if (n > 0) {
+                y = n;
+            }
+            return y;
+        }
+        int reuse_slot() {
+            {
+                int a = 5;
+            }
+            int b;
+            return b;
+        }
+        ";
+
+        let mut interpreter = Interpreter::load(program).unwrap();
+        let mut vm = Vm::load(program).unwrap();
+        assert_eq!(interpreter.global_variable("ratio"), Some(&Variable::Value(Float(0.0))));
+        assert_eq!(vm.global_variables(), interpreter_globals(&interpreter));
+
+        for strict in [false, true]
+        {
+            interpreter.set_strict(strict);
+            vm.set_strict(strict);
+            for (name, arguments) in [
+                ("increment", vec![]),
+                ("read_later", vec![Int(2)]),
+                ("read_later", vec![Int(0)]),
+                ("reuse_slot", vec![]),
+            ]
+            {
+                assert_eq!(vm.call(name, &arguments), interpreter.call(name, &arguments), "{}({:?})", name, arguments);
+            }
+        }
+
+        assert_eq!(vm.call("reuse_slot", &[]), Err(RuntimeError::UninitializedVariable("b".to_string())));
+        vm.set_strict(false);
+        assert_eq!(vm.call("read_later", &[Int(0)]), Ok(Float(0.0)));
+        assert_eq!(vm.call("reuse_slot", &[]), Ok(Int(0)));
+        assert_eq!(vm.global_variables().get("count"), Some(&Variable::Value(Int(2))));
+    }
+
+    #[test]
+    fn test_integer_overflow()
+    {
+        let program = "
+        int add(int a, int b) {
+            return a + b;
+        }
+        int divide(int a, int b) { return a / b; }
+        int modulo(int a, int b) { return a % b; }
+        int negate(int n) { return -n; }
+        long widen(int n) { return n * 2L; }
+        unsigned int wrap(unsigned int n) { return n + 1; }
+        char narrow(char c) { return c + c; }
+        ";
+
+        let mut interpreter = Interpreter::load(program).unwrap();
+        let mut vm = Vm::load(program).unwrap();
+        for mode in [ArithmeticMode::Wrapping, ArithmeticMode::Trapping]
+        {
+            interpreter.set_arithmetic_mode(mode);
+            vm.set_arithmetic_mode(mode);
+            for (name, arguments) in [
+                ("add", vec![Int(i32::MAX), Int(1)]),
+                ("divide", vec![Int(i32::MIN), Int(-1)]),
+                ("divide", vec![Int(1), Int(0)]),
+                ("modulo", vec![Int(i32::MIN), Int(-1)]),
+                ("negate", vec![Int(i32::MIN)]),
+                ("widen", vec![Int(i32::MAX)]),
+                ("narrow", vec![Int(100)]),
+            ]
+            {
+                assert_eq!(vm.call(name, &arguments), interpreter.call(name, &arguments), "{}({:?})", name, arguments);
+            }
+        }
+
+        assert_eq!(vm.call("add", &[Int(i32::MAX), Int(1)]), Err(RuntimeError::IntegerOverflow {
+            operation: "2147483647 + 1".to_string(),
+            line: Some(3),
+        }));
+    }
+}
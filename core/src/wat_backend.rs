@@ -0,0 +1,989 @@
+use crate::ast::{Ast, Decl, Expr, ExprId, Function, NodeId, SideTable, Stmt, StmtId};
+use crate::lexical::{Constant, Operator, UnaryOperator, ValueType};
+use crate::parser::static_local_name;
+use crate::resolver::{Resolution, Resolutions, Resolver};
+use crate::semantic::{Binding, Diagnostic, SemanticAnalyzer};
+use std::collections::HashMap;
+
+/// 構文木から WebAssembly のテキスト形式 (WAT) のモジュールを生成する.
+/// long は i64, float は f32, double は f64, それ以外の整数は i32 に対応させ, 関数とグローバル変数は元の名前でエクスポートする (static なローカル変数は除く).
+/// グローバル変数の初期化子はモジュールの開始関数で宣言順に実行する
+pub struct WatGenerator<'a>
+{
+    ast: &'a Ast,
+    resolutions: Resolutions,
+
+    // 意味解析で求めた式の型と, 代入先の宣言
+    types: SideTable<ExprId, ValueType>,
+    assignment_bindings: SideTable<StmtId, Binding>,
+
+    // グローバル変数の型. 同じ名前で複数回宣言された場合は最後の宣言の型
+    globals: Vec<(String, ValueType)>,
+
+    // 関数名と定義. 関数の再定義は意味解析でエラーになる. 定義のない関数はプロトタイプを持つ
+    functions: HashMap<String, &'a Function>,
+
+    // 生成中の関数
+    function: Option<&'a Function>,
+
+    // 生成中の関数のローカル変数. wasm のローカル変数は関数全体で共有されるので,
+    // (名前, 深さ, スロット, 型) ごとに 1 つ割り当てる
+    locals: Vec<(String, &'static str)>,
+    local_names: HashMap<(String, usize, usize, &'static str), String>,
+
+    // break と continue の飛び先の番号
+    loops: Vec<usize>,
+    label_count: usize,
+
+    // float の剰余の補助関数を使ったか
+    uses_fmod: bool,
+
+    // 符号付き整数の除算と剰余の補助関数を使ったか
+    uses_signed_division: bool,
+}
+
+impl<'a> WatGenerator<'a>
+{
+    pub fn new(ast: &'a Ast) -> Self
+    {
+        WatGenerator {
+            ast,
+            resolutions: Resolutions::default(),
+            types: SideTable::new(),
+            assignment_bindings: SideTable::new(),
+            globals: Vec::new(),
+            functions: HashMap::new(),
+            function: None,
+            locals: Vec::new(),
+            local_names: HashMap::new(),
+            loops: Vec::new(),
+            label_count: 0,
+            uses_fmod: false,
+            uses_signed_division: false,
+        }
+    }
+
+    /// WAT のモジュールを生成する. 意味解析でエラーがある場合は生成しない
+    pub fn generate(mut self) -> Result<String, Vec<Diagnostic>>
+    {
+        let ast = self.ast;
+
+        let mut analyzer = SemanticAnalyzer::new(ast);
+        // 定義のない関数は env から取り込む
+        analyzer.set_external_functions(true);
+        let errors: Vec<Diagnostic> = analyzer.analyze().into_iter()
+            .filter(|diagnostic| diagnostic.is_error())
+            .collect();
+        if !errors.is_empty()
+        {
+            return Err(errors);
+        }
+
+        let mut functions: Vec<&'a Function> = Vec::new();
+        for declaration in ast.declarations()
+        {
+            match declaration
+            {
+                Decl::Function(function) =>
+                    {
+                        functions.retain(|defined| defined.name != function.name);
+                        functions.push(function);
+                        self.functions.insert(function.name.clone(), function);
+                    }
+                Decl::Prototype(function) =>
+                    {
+                        self.functions.entry(function.name.clone()).or_insert(function);
+                    }
+                Decl::Variable { type_specifier, name, .. } =>
+                    {
+                        match self.globals.iter_mut().find(|(global, _)| global == name)
+                        {
+                            Some(global) => global.1 = type_specifier.clone(),
+                            None => self.globals.push((name.clone(), type_specifier.clone())),
+                        }
+                    }
+            }
+        }
+
+        for index in 0..ast.expr_count()
+        {
+            let id = ExprId::new(index);
+            if let Some(value_type) = analyzer.expr_type(id)
+            {
+                self.types.insert(id, value_type.clone());
+            }
+        }
+        for index in 0..ast.stmt_count()
+        {
+            let id = StmtId::new(index);
+            if let Some(binding) = analyzer.assignment_binding(id)
+            {
+                self.assignment_bindings.insert(id, *binding);
+            }
+        }
+        self.resolutions = Resolver::new(ast).resolve().clone();
+
+        let mut output = String::from(";; NagatoInterpreter が生成した WebAssembly モジュール\n(module\n");
+
+        // 定義のない関数は env から取り込む. 取り込みは他の定義より前に置く必要がある
+        let mut imports: Vec<&Function> = self.functions.values()
+            .filter(|function| !functions.iter().any(|defined| defined.name == function.name))
+            .copied()
+            .collect();
+        imports.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+        for function in imports.iter()
+        {
+            let mut signature = String::new();
+            for parameter in function.parameters.iter()
+            {
+                signature.push_str(&format!(" (param {})", wasm_type(&parameter.type_specifier)));
+            }
+            if function.return_type != ValueType::Void
+            {
+                signature.push_str(&format!(" (result {})", wasm_type(&function.return_type)));
+            }
+            output.push_str(&format!("  (import \"env\" \"{}\" (func $f_{}{}))\n", function.name, function.name, signature));
+        }
+
+        // グローバル変数は 0 で宣言しておき, 初期化子は開始関数で代入する.
+        // static なローカル変数を置き換えたグローバル変数は関数の外から見えないのでエクスポートしない
+        for (name, value_type) in self.globals.iter()
+        {
+            if static_local_name(name).is_some()
+            {
+                output.push_str(&format!("  (global $g_{} (mut {}) {})\n", name, wasm_type(value_type), zero(value_type)));
+            } else {
+                output.push_str(&format!("  (global $g_{} (export \"{}\") (mut {}) {})\n",
+                                         name, name, wasm_type(value_type), zero(value_type)));
+            }
+        }
+
+        for function in functions.iter()
+        {
+            output.push('\n');
+            output.push_str(&self.function_definition(function));
+        }
+
+        output.push('\n');
+        output.push_str(&self.initializer());
+
+        if self.uses_fmod
+        {
+            // C の fmod と同じく 0 方向に切り捨てた商で余りを求める. 商が 2^53 を超える場合は誤差が出る
+            output.push_str("
+  (func $nagato_fmod (param $lhs f64) (param $rhs f64) (result f64)
+    (f64.sub (local.get $lhs) (f64.mul (f64.trunc (f64.div (local.get $lhs) (local.get $rhs))) (local.get $rhs)))
+  )
+");
+        }
+        if self.uses_signed_division
+        {
+            // 最小値を -1 で割ると div_s, rem_s がトラップするので, インタプリタの折り返しと同じく
+            // 商は符号反転 (最小値は最小値のまま), 余りは 0 にする
+            for wasm_type in ["i32", "i64"]
+            {
+                output.push_str(&format!("
+  (func $nagato_div_{0} (param $lhs {0}) (param $rhs {0}) (result {0})
+    (if (result {0}) ({0}.eq (local.get $rhs) ({0}.const -1))
+      (then ({0}.sub ({0}.const 0) (local.get $lhs)))
+      (else ({0}.div_s (local.get $lhs) (local.get $rhs))))
+  )
+
+  (func $nagato_rem_{0} (param $lhs {0}) (param $rhs {0}) (result {0})
+    (if (result {0}) ({0}.eq (local.get $rhs) ({0}.const -1))
+      (then ({0}.const 0))
+      (else ({0}.rem_s (local.get $lhs) (local.get $rhs))))
+  )
+", wasm_type));
+            }
+        }
+        output.push_str("\n  (start $nagato_initialize)\n)\n");
+
+        Ok(output)
+    }
+
+    fn function_definition(&mut self, function: &'a Function) -> String
+    {
+        self.function = Some(function);
+        self.locals.clear();
+        self.local_names.clear();
+
+        let mut signature = format!("  (func $f_{} (export \"{}\")", function.name, function.name);
+        for (slot, parameter) in function.parameters.iter().enumerate()
+        {
+            let local = self.local(&parameter.name, 0, slot, &parameter.type_specifier);
+            signature.push_str(&format!(" (param {} {})", local, wasm_type(&parameter.type_specifier)));
+        }
+        if function.return_type != ValueType::Void
+        {
+            signature.push_str(&format!(" (result {})", wasm_type(&function.return_type)));
+        }
+        // 引数はローカル変数として宣言しない
+        self.locals.clear();
+
+        let mut body = String::new();
+        for stmt in function.body.iter()
+        {
+            self.statement(*stmt, 2, &mut body);
+        }
+
+        // 非 void 関数が値を返さずに終わった場合はインタプリタと同じくエラーにする
+        if function.return_type != ValueType::Void
+        {
+            body.push_str("    (unreachable)\n");
+        }
+
+        let mut output = signature;
+        output.push('\n');
+        for (local, local_type) in self.locals.iter()
+        {
+            output.push_str(&format!("    (local {} {})\n", local, local_type));
+        }
+        output.push_str(&body);
+        output.push_str("  )\n");
+
+        self.function = None;
+        output
+    }
+
+    /// グローバル変数の初期化子を宣言順に実行する開始関数
+    fn initializer(&mut self) -> String
+    {
+        let ast = self.ast;
+        let mut output = String::from("  (func $nagato_initialize\n");
+        for declaration in ast.declarations()
+        {
+            if let Decl::Variable { name, initializer: Some(initializer), .. } = declaration
+            {
+                let value_type = self.global_type(name);
+                let value = self.converted(*initializer, &value_type);
+                output.push_str(&format!("    (global.set $g_{} {})\n", name, value));
+            }
+        }
+        output.push_str("  )\n");
+        output
+    }
+
+    fn global_type(&self, name: &str) -> ValueType
+    {
+        self.globals.iter()
+            .find(|(global, _)| global == name)
+            .map(|(_, value_type)| value_type.clone())
+            .unwrap_or(ValueType::Int)
+    }
+
+    /// ローカル変数の名前. 初めて使う場合は宣言に追加する
+    fn local(&mut self, name: &str, depth: usize, slot: usize, value_type: &ValueType) -> String
+    {
+        let local_type = wasm_type(value_type);
+        let key = (name.to_string(), depth, slot, local_type);
+        if let Some(local) = self.local_names.get(&key)
+        {
+            return local.clone();
+        }
+
+        // 兄弟のブロックで同じ名前とスロットに別の型の変数がある場合は型名を付けて区別する
+        let mut local = format!("$l_{}_{}_{}", name, depth, slot);
+        if self.locals.iter().any(|(declared, _)| *declared == local)
+        {
+            local = format!("{}_{}", local, local_type);
+        }
+        self.locals.push((local.clone(), local_type));
+        self.local_names.insert(key, local.clone());
+        local
+    }
+
+    /// 変数を読み出す命令
+    fn get(&mut self, resolution: Resolution, name: &str, value_type: &ValueType) -> String
+    {
+        match resolution
+        {
+            Resolution::Local { depth, slot } => format!("(local.get {})", self.local(name, depth, slot, value_type)),
+            Resolution::Global => format!("(global.get $g_{})", name),
+        }
+    }
+
+    /// 変数に代入する命令
+    fn set(&mut self, resolution: Resolution, name: &str, value_type: &ValueType, value: String) -> String
+    {
+        match resolution
+        {
+            Resolution::Local { depth, slot } =>
+                format!("(local.set {} {})", self.local(name, depth, slot, value_type), value),
+            Resolution::Global => format!("(global.set $g_{} {})", name, value),
+        }
+    }
+
+    fn statement(&mut self, id: StmtId, indent: usize, output: &mut String)
+    {
+        let ast = self.ast;
+        let padding = "  ".repeat(indent);
+        match ast.stmt(id)
+        {
+            Stmt::Declaration(Decl::Variable { type_specifier, name, initializer }) =>
+                {
+                    // 初期化子がない変数は VM と同じく 0 で初期化する. ループ内で再び宣言された場合も 0 に戻す
+                    let value = match initializer
+                    {
+                        Some(initializer) => self.converted(*initializer, type_specifier),
+                        None => zero(type_specifier),
+                    };
+                    let set = self.set(self.resolutions.stmt(id), name, type_specifier, value);
+                    output.push_str(&format!("{}{}\n", padding, set));
+                }
+            Stmt::Declaration(Decl::Function(_) | Decl::Prototype(_)) => {}
+            Stmt::Assignment { name, value } =>
+                {
+                    let value_type = self.assignment_type(id, name);
+                    let value = self.converted(*value, &value_type);
+                    let set = self.set(self.resolutions.stmt(id), name, &value_type, value);
+                    output.push_str(&format!("{}{}\n", padding, set));
+                }
+            Stmt::Expression(expr) =>
+                {
+                    // 値を返す式は結果を捨てる
+                    let text = self.expression(*expr);
+                    if self.expr_type(*expr) == ValueType::Void
+                    {
+                        output.push_str(&format!("{}{}\n", padding, text));
+                    } else {
+                        output.push_str(&format!("{}(drop {})\n", padding, text));
+                    }
+                }
+            Stmt::Block(stmts) =>
+                {
+                    for stmt in stmts.iter()
+                    {
+                        self.statement(*stmt, indent, output);
+                    }
+                }
+            Stmt::If { condition, then_branch, else_branch } =>
+                {
+                    let condition = self.condition(*condition);
+                    output.push_str(&format!("{}(if {}\n{}  (then\n", padding, condition, padding));
+                    self.statement(*then_branch, indent + 2, output);
+                    output.push_str(&format!("{}  )\n", padding));
+                    if let Some(else_branch) = else_branch
+                    {
+                        output.push_str(&format!("{}  (else\n", padding));
+                        self.statement(*else_branch, indent + 2, output);
+                        output.push_str(&format!("{}  )\n", padding));
+                    }
+                    output.push_str(&format!("{})\n", padding));
+                }
+            Stmt::While { condition, body } =>
+                {
+                    self.label_count += 1;
+                    let label = self.label_count;
+                    let condition = self.condition(*condition);
+                    output.push_str(&format!("{}(block $break_{}\n", padding, label));
+                    output.push_str(&format!("{}  (loop $continue_{}\n", padding, label));
+                    output.push_str(&format!("{}    (br_if $break_{} (i32.eqz {}))\n", padding, label, condition));
+                    self.loops.push(label);
+                    self.statement(*body, indent + 2, output);
+                    self.loops.pop();
+                    output.push_str(&format!("{}    (br $continue_{})\n", padding, label));
+                    output.push_str(&format!("{}  )\n{})\n", padding, padding));
+                }
+            Stmt::Return(value) =>
+                {
+                    let return_type = self.function.map(|function| function.return_type.clone()).unwrap_or(ValueType::Void);
+                    match value
+                    {
+                        Some(value) if return_type != ValueType::Void =>
+                            {
+                                let value = self.converted(*value, &return_type);
+                                output.push_str(&format!("{}(return {})\n", padding, value));
+                            }
+                        _ => output.push_str(&format!("{}(return)\n", padding)),
+                    }
+                }
+            Stmt::Break | Stmt::Continue =>
+                {
+                    // ループの外の break と continue はインタプリタでは関数から抜ける
+                    let returns_value = self.function.is_some_and(|function| function.return_type != ValueType::Void);
+                    let text = match (ast.stmt(id), self.loops.last())
+                    {
+                        (Stmt::Break, Some(label)) => format!("(br $break_{})", label),
+                        (Stmt::Continue, Some(label)) => format!("(br $continue_{})", label),
+                        _ if returns_value => "(unreachable)".to_string(),
+                        _ => "(return)".to_string(),
+                    };
+                    output.push_str(&format!("{}{}\n", padding, text));
+                }
+        }
+    }
+
+    /// 代入先の変数の宣言された型
+    fn assignment_type(&self, id: StmtId, name: &str) -> ValueType
+    {
+        match self.assignment_bindings.get(id)
+        {
+            Some(Binding::Local(stmt)) => match self.ast.stmt(*stmt)
+            {
+                Stmt::Declaration(Decl::Variable { type_specifier, .. }) => type_specifier.clone(),
+                _ => ValueType::Int,
+            },
+            Some(Binding::Parameter(index)) => self.function
+                .map(|function| function.parameters[*index].type_specifier.clone())
+                .unwrap_or(ValueType::Int),
+            _ => self.global_type(name),
+        }
+    }
+
+    fn expr_type(&self, id: ExprId) -> ValueType
+    {
+        self.types.get(id).cloned().unwrap_or(ValueType::Int)
+    }
+
+    /// 式を計算し, 指定した型に変換する
+    fn converted(&mut self, id: ExprId, value_type: &ValueType) -> String
+    {
+        let text = self.expression(id);
+        convert(text, &self.expr_type(id), value_type)
+    }
+
+    /// 条件として使う i32 の値. 浮動小数点数は 0 以外 (NaN を含む) を真とする
+    fn condition(&mut self, id: ExprId) -> String
+    {
+        let text = self.expression(id);
+        match wasm_type(&self.expr_type(id))
+        {
+            "i32" => text,
+            wasm_type => format!("({}.ne {} ({}.const 0))", wasm_type, text, wasm_type),
+        }
+    }
+
+    /// 0 か 1 に揃えた真偽値
+    fn boolean(&mut self, id: ExprId) -> String
+    {
+        let text = self.expression(id);
+        let wasm_type = wasm_type(&self.expr_type(id));
+        format!("({}.ne {} ({}.const 0))", wasm_type, text, wasm_type)
+    }
+
+    fn expression(&mut self, id: ExprId) -> String
+    {
+        let ast = self.ast;
+        match ast.expr(id)
+        {
+            Expr::Constant(Constant::Integer(val)) => format!("(i32.const {})", val),
+            Expr::Constant(Constant::UnsignedInt(val)) => format!("(i32.const {})", *val as i32),
+            Expr::Constant(Constant::Long(val)) => format!("(i64.const {})", val),
+            Expr::Constant(Constant::UnsignedLong(val)) => format!("(i64.const {})", *val as i64),
+            // f32 の値は f64 で正確に表せるので, 読み戻すと同じ値になる
+            Expr::Constant(Constant::Float(val)) => format!("(f32.const {})", float_literal(*val as f64)),
+            Expr::Constant(Constant::Double(val)) => format!("(f64.const {})", float_literal(*val)),
+            Expr::Identifier(name) =>
+                {
+                    let value_type = self.expr_type(id);
+                    self.get(self.resolutions.expr(id), name, &value_type)
+                }
+            Expr::Unary { op, operand } =>
+                {
+                    match op
+                    {
+                        // '-' は整数拡張した型で計算する
+                        UnaryOperator::Minus =>
+                            {
+                                let value_type = self.expr_type(*operand).promoted();
+                                let operand = self.converted(*operand, &value_type);
+                                match wasm_type(&value_type)
+                                {
+                                    wasm_type @ ("f32" | "f64") => format!("({}.neg {})", wasm_type, operand),
+                                    wasm_type => format!("({}.sub ({}.const 0) {})", wasm_type, wasm_type, operand),
+                                }
+                            }
+                        UnaryOperator::LogicalNot => format!("(i32.eqz {})", self.boolean(*operand)),
+                    }
+                }
+            Expr::Binary { op, lhs, rhs } => self.binary(*op, *lhs, *rhs),
+            Expr::Call { name, arguments } =>
+                {
+                    let function = self.functions[name];
+                    let arguments: Vec<String> = arguments.iter()
+                        .zip(function.parameters.iter())
+                        .map(|(argument, parameter)| self.converted(*argument, &parameter.type_specifier))
+                        .collect();
+                    if arguments.is_empty()
+                    {
+                        format!("(call $f_{})", name)
+                    } else {
+                        format!("(call $f_{} {})", name, arguments.join(" "))
+                    }
+                }
+            Expr::Cast { value_type, operand } => self.converted(*operand, value_type),
+            Expr::ArrayAccess { .. } =>
+                {
+                    unimplemented!("配列は未実装です");
+                }
+        }
+    }
+
+    fn binary(&mut self, op: Operator, lhs: ExprId, rhs: ExprId) -> String
+    {
+        let common = ValueType::common(&self.expr_type(lhs), &self.expr_type(rhs));
+
+        match op
+        {
+            // '&&' と '||' は左辺で結果が決まる場合は右辺を計算しない
+            Operator::LogicalAnd =>
+                {
+                    let lhs = self.condition(lhs);
+                    let rhs = self.boolean(rhs);
+                    format!("(if (result i32) {} (then {}) (else (i32.const 0)))", lhs, rhs)
+                }
+            Operator::LogicalOr =>
+                {
+                    let lhs = self.condition(lhs);
+                    let rhs = self.boolean(rhs);
+                    format!("(if (result i32) {} (then (i32.const 1)) (else {}))", lhs, rhs)
+                }
+            // 剰余は浮動小数点数が混ざると補助関数で計算する. 結果は int になる
+            Operator::Modulo if common.is_floating() =>
+                {
+                    self.uses_fmod = true;
+                    let lhs = self.converted(lhs, &ValueType::Double);
+                    let rhs = self.converted(rhs, &ValueType::Double);
+                    format!("(i32.trunc_sat_f64_s (call $nagato_fmod {} {}))", lhs, rhs)
+                }
+            // 算術演算と比較は通常の算術変換による共通の型に両辺を変換する
+            _ =>
+                {
+                    if matches!(op, Operator::Divide | Operator::Modulo) && common.is_integer() && !common.is_unsigned()
+                    {
+                        self.uses_signed_division = true;
+                    }
+                    let lhs = self.converted(lhs, &common);
+                    let rhs = self.converted(rhs, &common);
+                    format!("({} {} {})", instruction(op, &common), lhs, rhs)
+                }
+        }
+    }
+}
+
+fn wasm_type(value_type: &ValueType) -> &'static str
+{
+    match value_type
+    {
+        ValueType::Long | ValueType::UnsignedLong => "i64",
+        ValueType::Float => "f32",
+        ValueType::Double => "f64",
+        _ => "i32",
+    }
+}
+
+fn zero(value_type: &ValueType) -> String
+{
+    format!("({}.const 0)", wasm_type(value_type))
+}
+
+/// 値の型を変換する. 浮動小数点数から整数へは Rust の as と同じく範囲外の値を飽和させ, NaN は 0 にする.
+/// i32 で持つ int より小さい型は, 変換後に符号拡張かゼロ拡張で値の範囲に揃える
+fn convert(text: String, from: &ValueType, to: &ValueType) -> String
+{
+    if from == to || *to == ValueType::Void
+    {
+        return text;
+    }
+
+    let (from_wasm, to_wasm) = (wasm_type(from), wasm_type(to));
+    let sign = if from.is_unsigned() { "u" } else { "s" };
+    let text = match (from_wasm, to_wasm)
+    {
+        // _Bool は 0 以外を 1 にする
+        _ if *to == ValueType::Bool => return format!("({}.ne {} ({}.const 0))", from_wasm, text, from_wasm),
+        ("i32", "i32") | ("i64", "i64") => text,
+        ("i32", "i64") => format!("(i64.extend_i32_{} {})", sign, text),
+        ("i64", "i32") => format!("(i32.wrap_i64 {})", text),
+        ("f32", "f64") => format!("(f64.promote_f32 {})", text),
+        ("f64", "f32") => format!("(f32.demote_f64 {})", text),
+        ("i32" | "i64", _) => format!("({}.convert_{}_{} {})", to_wasm, from_wasm, sign, text),
+        (_, _) =>
+            {
+                let sign = if matches!(to, ValueType::UnsignedInt | ValueType::UnsignedLong) { "u" } else { "s" };
+                format!("({}.trunc_sat_{}_{} {})", to_wasm, from_wasm, sign, text)
+            }
+    };
+
+    match to
+    {
+        ValueType::Char => format!("(i32.extend8_s {})", text),
+        ValueType::UnsignedChar => format!("(i32.and {} (i32.const 255))", text),
+        ValueType::Short => format!("(i32.extend16_s {})", text),
+        ValueType::UnsignedShort => format!("(i32.and {} (i32.const 65535))", text),
+        _ => text,
+    }
+}
+
+/// 読み戻したときに同じ値になる浮動小数点数のリテラル
+fn float_literal(val: f64) -> String
+{
+    if val.is_nan()
+    {
+        "nan".to_string()
+    } else if val.is_infinite() {
+        if val > 0.0 { "inf".to_string() } else { "-inf".to_string() }
+    } else {
+        format!("{:?}", val)
+    }
+}
+
+/// 共通の型にそろえた両辺に対する演算の命令. 整数の除算, 剰余と比較は符号の有無で命令を選ぶ
+fn instruction(op: Operator, value_type: &ValueType) -> String
+{
+    let wasm_type = wasm_type(value_type);
+    let floating = value_type.is_floating();
+    let sign = if value_type.is_unsigned() { "_u" } else { "_s" };
+    let name = match op
+    {
+        Operator::Plus => "add",
+        Operator::Minus => "sub",
+        Operator::Multiply => "mul",
+        Operator::Divide if floating => "div",
+        // 符号付きの除算と剰余は最小値 / -1 を扱う補助関数を呼ぶ
+        Operator::Divide if sign == "_s" => return format!("call $nagato_div_{}", wasm_type),
+        Operator::Modulo if sign == "_s" => return format!("call $nagato_rem_{}", wasm_type),
+        Operator::Divide => return format!("{}.div{}", wasm_type, sign),
+        Operator::Modulo => return format!("{}.rem{}", wasm_type, sign),
+        Operator::Equal => "eq",
+        Operator::NotEqual => "ne",
+        Operator::LessThan => "lt",
+        Operator::GreaterThan => "gt",
+        Operator::LessThanOrEqual => "le",
+        Operator::GreaterThanOrEqual => "ge",
+        Operator::LogicalAnd | Operator::LogicalOr => unreachable!("論理演算子は分岐で生成します"),
+    };
+    match op
+    {
+        Operator::LessThan | Operator::GreaterThan | Operator::LessThanOrEqual | Operator::GreaterThanOrEqual
+            if !floating => format!("{}.{}{}", wasm_type, name, sign),
+        _ => format!("{}.{}", wasm_type, name),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::interpreter::{Interpreter, Variable, VariableType};
+    use crate::lexical::{Lexer, ValueType};
+    use crate::parser::Parser;
+    use crate::wat_backend::WatGenerator;
+    use wasmi::{Engine, Linker, Module, Store, Val};
+
+    fn generate(program: &str) -> String
+    {
+        let mut lexer = Lexer::new(program.to_string());
+        lexer.tokenize();
+
+        let mut parser = Parser::new(lexer.tokens().clone());
+        parser.parse();
+        WatGenerator::new(parser.ast()).generate().unwrap()
+    }
+
+    /// 生成した WAT を同梱のパーサでバイナリに変換し, wasm のインタプリタで検証して実行する.
+    /// main の戻り値と, インタプリタで値を持つグローバル変数がすべて一致し, それだけがエクスポートされていることを確認する
+    fn assert_same(program: &str)
+    {
+        let source = generate(program);
+        let binary = wat::parse_str(&source).unwrap_or_else(|error| panic!("{}\n{}", error, source));
+
+        let engine = Engine::default();
+        let module = Module::new(&engine, &binary[..]).unwrap_or_else(|error| panic!("{}\n{}", error, source));
+        let mut store = Store::new(&engine, ());
+        let instance = Linker::<()>::new(&engine)
+            .instantiate(&mut store, &module)
+            .unwrap()
+            .start(&mut store)
+            .unwrap();
+
+        let mut interpreter = Interpreter::load(program).unwrap();
+        let expected = interpreter.call("main", &[]).unwrap();
+        let main = instance.get_func(&store, "main").unwrap();
+        let mut results = vec![Val::I32(0); main.ty(&store).results().len()];
+        main.call(&mut store, &[], &mut results).unwrap();
+        match results.pop()
+        {
+            Some(value) => assert_value("main", &expected, value),
+            None => assert_eq!(expected, VariableType::Void, "{}", source),
+        }
+
+        for (name, variable) in interpreter.global_variables()
+        {
+            let value = instance.get_global(&store, name).unwrap().get(&store);
+            match variable
+            {
+                Variable::Value(expected) => assert_value(name, expected, value),
+                variable => panic!("{} の値が一致しません : {:?} / {:?}", name, variable, value),
+            }
+        }
+
+        // static なローカル変数はエクスポートしない
+        let exported = instance.exports(&mut store).filter(|export| export.clone().into_global().is_some()).count();
+        assert_eq!(exported, interpreter.global_variables().count(), "{}", source);
+    }
+
+    /// wasm の値がインタプリタの値と一致するか. i32 で持つ小さい型は拡張済みの値と比べる
+    fn assert_value(name: &str, expected: &VariableType, value: Val)
+    {
+        match value
+        {
+            Val::I32(value) =>
+                assert_eq!(VariableType::Int(value), Interpreter::cast(&ValueType::Int, expected.clone()), "{}", name),
+            Val::I64(value) =>
+                assert_eq!(VariableType::Long(value), Interpreter::cast(&ValueType::Long, expected.clone()), "{}", name),
+            Val::F32(value) => assert_eq!(VariableType::Float(f32::from(value)), *expected, "{}", name),
+            Val::F64(value) => assert_eq!(VariableType::Double(f64::from(value)), *expected, "{}", name),
+            value => panic!("{} の値が一致しません : {:?} / {:?}", name, expected, value),
+        }
+    }
+
+    #[test]
+    fn test_generate()
+    {
+        let source = generate("
+int limit = 3;
+float scale(float x, int n) {
+    int limit = n;
+    while (limit > 0) {
+        x = x * 2;
+        limit = limit - 1;
+    }
+    return x % 5;
+}");
+        assert_eq!(source, r#";; NagatoInterpreter が生成した WebAssembly モジュール
+(module
+  (global $g_limit (export "limit") (mut i32) (i32.const 0))
+
+  (func $f_scale (export "scale") (param $l_x_0_0 f32) (param $l_n_0_1 i32) (result f32)
+    (local $l_limit_0_2 i32)
+    (local.set $l_limit_0_2 (local.get $l_n_0_1))
+    (block $break_1
+      (loop $continue_1
+        (br_if $break_1 (i32.eqz (i32.gt_s (local.get $l_limit_0_2) (i32.const 0))))
+        (local.set $l_x_0_0 (f32.mul (local.get $l_x_0_0) (f32.convert_i32_s (i32.const 2))))
+        (local.set $l_limit_0_2 (i32.sub (local.get $l_limit_0_2) (i32.const 1)))
+        (br $continue_1)
+      )
+    )
+    (return (f32.convert_i32_s (i32.trunc_sat_f64_s (call $nagato_fmod (f64.promote_f32 (local.get $l_x_0_0)) (f64.convert_i32_s (i32.const 5))))))
+    (unreachable)
+  )
+
+  (func $nagato_initialize
+    (global.set $g_limit (i32.const 3))
+  )
+
+  (func $nagato_fmod (param $lhs f64) (param $rhs f64) (result f64)
+    (f64.sub (local.get $lhs) (f64.mul (f64.trunc (f64.div (local.get $lhs) (local.get $rhs))) (local.get $rhs)))
+  )
+
+  (start $nagato_initialize)
+)
+"#);
+    }
+
+    #[test]
+    fn test_same_result()
+    {
+        assert_same("
+int x = (10 + 20) * 3 - 4 / 2;
+float y = (x + 1) * 1.5;
+int z = ((x > 15) && (y < 50.0)) || (x == 26);
+int fib = 0;
+int fibo(int n) {
+    if (n < 2) {
+        return n;
+    }
+    return fibo(n - 1) + fibo(n - 2);
+}
+int main(void) {
+    fib = fibo(15);
+    return fib + z + -x % 7 + !y;
+}");
+
+        // 既定の折り返しモードと同じく, 符号付き整数の最小値 / -1 や最大値 + 1 でも停止しない
+        assert_same("
+int m = -2147483647 - 1;
+long lm = -9223372036854775807 - 1;
+int divide(int a, int b) { return a / b; }
+long modulo(long a, long b) { return a % b; }
+int main(void) {
+    int big = 2147483647;
+    long lbig = 9223372036854775807;
+    int q = m / -1;
+    int r = m % -1;
+    int over = big + 1;
+    int under = m - 1;
+    int product = big * 2;
+    int negated = -m;
+    long lq = lm / -1;
+    long lover = lbig + 1;
+    return (q == m && divide(m, -1) == m) + (r == 0) * 2 + (over == m) * 4 + (under == big) * 8
+        + (product == -2) * 16 + (negated == m) * 32 + (lq == lm && -lm == lm) * 64
+        + (modulo(lm, -1) == 0 && lover == lm) * 128;
+}");
+
+        assert_same("
+float total = 0;
+int unset;
+int truncate(float value) {
+    return value;
+}
+void accumulate(float value) {
+    total = total + value;
+}
+int main(void) {
+    int i = 0;
+    while (1) {
+        i = i + 1;
+        if (i > 20) {
+            break;
+        }
+        if (i % 2 == 0) {
+            continue;
+        }
+        {
+            int odd = i;
+            accumulate(odd / 3);
+        }
+        {
+            float odd = i * 0.5;
+            accumulate(odd);
+        }
+    }
+    accumulate(7.5 % 2 + 7 % 2.5 + 7.5 % 2.5);
+    int compared = (1 == 1.5) + (2 < 2.5) * 2 + (2.5 > 2) * 4 + (0.0 || 0.5) * 8;
+    return truncate(total) + compared * 1000;
+}");
+
+        assert_same("
+float rate = 0.1;
+float step(float value, int n) {
+    if (n == 0) {
+        return value;
+    }
+    return step(value * (1 + rate), n - 1);
+}
+float main(void) {
+    float x = step(1, 10);
+    {
+        float shadow = x * -2;
+        x = shadow;
+    }
+    return x / 3;
+}");
+
+        assert_same("
+int calls;
+int counter(int n) {
+    static int calls = 10;
+    static long total;
+    calls = calls + 1;
+    total = total + n * calls;
+    return total;
+}
+int main(void) {
+    counter(1);
+    counter(2);
+    calls = counter(3);
+    return calls;
+}");
+    }
+
+    #[test]
+    fn test_numeric_types()
+    {
+        assert_same("
+unsigned int big = 4000000000u;
+long wide = 3000000000;
+unsigned long huge = 18000000000000000000ul;
+char small = 200;
+unsigned char byte = 300;
+short half = 40000;
+_Bool flag = 5;
+double ratio = 1.0 / 3;
+float third = 1.0f / 3;
+long mix(char c, unsigned int u, long l, float f, double d) {
+    return c + u + l + f + d;
+}
+unsigned char low(int n) {
+    return n;
+}
+int main(void) {
+    int score = 0;
+    unsigned int u = 1;
+    long l = wide * 3;
+    double d = huge;
+    unsigned long back = d;
+    char c = 127;
+    unsigned short us = 0;
+    c = c + 1;
+    us = us - 1;
+    if (-1 < u) {
+        score = score + 100;
+    }
+    if (u - 2 > u) {
+        score = score + 1;
+    }
+    if (l / 7 == 1285714285 && -wide % 7 == -4) {
+        score = score + 1;
+    }
+    if (huge / 1000000000000000000ul == 18 && back == huge && d > 17000000000000000000.0) {
+        score = score + 1;
+    }
+    if (small == -56 && byte == 44 && half == -25536 && flag == 1) {
+        score = score + 1;
+    }
+    if (c == -128 && us == 65535 && (u - 2) + 0L > 0) {
+        score = score + 1;
+    }
+    if (mix(-3, 10u, 100, 0.5f, 0.25) == 107 && low(513) == 1) {
+        score = score + 1;
+    }
+    if (third + ratio > 0.66 && big / 3 == 1333333333u && big % 7 == 3) {
+        score = score + 1;
+    }
+    return score;
+}");
+
+        assert_same("
+double ratio = (double)7 / 2;
+int truncated = (int)3.99;
+unsigned char low = (unsigned char)300;
+long size = sizeof(long) + sizeof ratio;
+int main(void) {
+    int n = -7;
+    unsigned int u = (unsigned int)n;
+    float f = (float)n / 2;
+    _Bool b = (_Bool)0.5;
+    return (int)(u / 2u % 100) + (int)f + b + (char)(n * 40) + sizeof(n + 1L);
+}");
+    }
+
+    #[test]
+    fn test_import_prototype()
+    {
+        // 定義のない関数は env から取り込み, 呼び出し側で実装を渡す
+        let source = generate("
+int twice(int n);
+float half(float x);
+int main(void) {
+    return twice(half(6.0));
+}");
+        assert!(source.contains("  (import \"env\" \"half\" (func $f_half (param f32) (result f32)))\n"), "{}", source);
+        assert!(source.contains("  (import \"env\" \"twice\" (func $f_twice (param i32) (result i32)))\n"), "{}", source);
+
+        let binary = wat::parse_str(&source).unwrap();
+        let engine = Engine::default();
+        let module = Module::new(&engine, &binary[..]).unwrap();
+        let mut store = Store::new(&engine, ());
+        let mut linker = Linker::<()>::new(&engine);
+        linker.func_wrap("env", "twice", |n: i32| n * 2).unwrap();
+        linker.func_wrap("env", "half", |x: f32| x / 2.0).unwrap();
+        let instance = linker.instantiate(&mut store, &module).unwrap().start(&mut store).unwrap();
+
+        let main = instance.get_typed_func::<(), i32>(&store, "main").unwrap();
+        assert_eq!(main.call(&mut store, ()).unwrap(), 6);
+    }
+}
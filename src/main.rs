@@ -1,10 +1,373 @@
 use core::tree_viewer::TreeViewer;
 use core::lexical::Lexer;
 use core::parser::Parser;
-use core::interpreter::Interpreter;
+use core::interpreter::{ArithmeticMode, Interpreter, DEFAULT_MAX_CALL_DEPTH};
+use core::semantic::SemanticAnalyzer;
+use core::resolver::Resolver;
+use core::optimizer::Optimizer;
+use core::bytecode::{Compiler, Program};
+use core::bytecode_file;
+use core::vm::Vm;
+use core::c_backend::CGenerator;
+use core::asm_backend::AsmGenerator;
+use core::wat_backend::WatGenerator;
+use core::ir::{IrBuilder, IrProgram};
+use core::lint::Linter;
+use core::ast::Ast;
+
+const USAGE: &str = "使い方:
+    NagatoInterpreter [--backend tree|vm] [-O] [ファイル]   ソースを実行する. バイトコードファイルは VM で実行する
+    NagatoInterpreter compile [-O] [--target bytecode|c|asm|wat] <ソース> [-o <出力>]
+                                                      バイトコードファイル, C ソース, x86-64 アセンブリ,
+                                                      WebAssembly テキストのいずれかを書き出す
+    NagatoInterpreter disasm [-O] <ファイル>                命令の一覧を表示する
+    NagatoInterpreter ir [-O] <ソース> [-o <出力>]         三番地コードを表示し, 制御フローグラフを DOT で書き出す
+    NagatoInterpreter lint <ソース>                       使われていない変数や到達できないコードなどを警告する
+
+    -O               実行前に構文木を最適化する
+    --strict         初期化子のないローカル変数を代入前に読むと実行時エラーにする
+    --overflow wrap|trap
+                     符号付き整数のオーバーフローを折り返すか, 実行時エラーにする (既定値 wrap)
+    --max-depth <N>  関数呼び出しの深さの上限 (既定値 1000, 最大 32768)";
+
+/// 関数呼び出し 1 段あたりに確保するスタックの大きさ. インタプリタは呼び出しごとに Rust のスタックを消費する
+const STACK_PER_CALL: usize = 32 * 1024;
+
+/// 実行用スレッドのスタックの上限
+const MAX_STACK_SIZE: usize = 1024 * 1024 * 1024;
+
+/// 指定できる呼び出しの深さの上限. 実行用スレッドのスタックに収まる深さまでにする
+const MAX_CALL_DEPTH: usize = MAX_STACK_SIZE / STACK_PER_CALL;
+
+/// 実行に使うバックエンド
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Backend {
+    // 構文木を直接評価する
+    Tree,
+
+    // バイトコードにコンパイルしてスタックマシンで実行する
+    Vm,
+}
+
+/// compile の出力形式
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Target {
+    Bytecode,
+
+    // cc でコンパイルできる C ソース
+    C,
+
+    // cc でアセンブルできる x86-64 System V の GNU アセンブリ
+    Asm,
+
+    // WebAssembly のテキスト形式
+    Wat,
+}
+
+/// サブコマンド
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+    Run,
+    Compile,
+    Disasm,
+    Ir,
+    Lint,
+}
+
+/// コマンドライン引数
+#[derive(Debug)]
+struct Options {
+    command: Command,
+    backend: Backend,
+
+    // 入力ファイル. 実行時に指定がない場合は組み込みのサンプルを実行する
+    path: Option<String>,
+
+    // compile の出力先と出力形式
+    output: Option<String>,
+    target: Target,
+
+    // 構文木を最適化するか
+    optimize: bool,
+
+    // 関数呼び出しの深さの上限
+    max_call_depth: usize,
+
+    // 代入前のローカル変数の読み出しをエラーにするか
+    strict: bool,
+
+    // 符号付き整数のオーバーフローの扱い
+    arithmetic_mode: ArithmeticMode,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        command: Command::Run,
+        backend: Backend::Tree,
+        path: None,
+        output: None,
+        target: Target::Bytecode,
+        optimize: false,
+        max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+        strict: false,
+        arithmetic_mode: ArithmeticMode::Wrapping,
+    };
+
+    let mut first = true;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "compile" if first => options.command = Command::Compile,
+            "disasm" if first => options.command = Command::Disasm,
+            "ir" if first => options.command = Command::Ir,
+            "lint" if first => options.command = Command::Lint,
+            "--backend" => {
+                options.backend = match args.next().as_deref() {
+                    Some("tree") => Backend::Tree,
+                    Some("vm") => Backend::Vm,
+                    Some(other) => return Err(format!("不明なバックエンドです : {}", other)),
+                    None => return Err("--backend には tree か vm を指定してください".to_string()),
+                };
+            }
+            "--target" => {
+                options.target = match args.next().as_deref() {
+                    Some("bytecode") => Target::Bytecode,
+                    Some("c") => Target::C,
+                    Some("asm") => Target::Asm,
+                    Some("wat") => Target::Wat,
+                    Some(other) => return Err(format!("不明な出力形式です : {}", other)),
+                    None => return Err("--target には bytecode, c, asm, wat のいずれかを指定してください".to_string()),
+                };
+            }
+            "-o" => {
+                options.output = match args.next() {
+                    Some(output) => Some(output),
+                    None => return Err("-o には出力先を指定してください".to_string()),
+                };
+            }
+            "-O" => options.optimize = true,
+            "--strict" => options.strict = true,
+            "--overflow" => {
+                options.arithmetic_mode = match args.next().as_deref() {
+                    Some("wrap") => ArithmeticMode::Wrapping,
+                    Some("trap") => ArithmeticMode::Trapping,
+                    Some(other) => return Err(format!("不明なオーバーフローの扱いです : {}", other)),
+                    None => return Err("--overflow には wrap か trap を指定してください".to_string()),
+                };
+            }
+            "--max-depth" => {
+                options.max_call_depth = match args.next().map(|depth| depth.parse::<usize>()) {
+                    Some(Ok(depth)) if (1..=MAX_CALL_DEPTH).contains(&depth) => depth,
+                    _ => return Err(format!("--max-depth には 1 以上 {} 以下の整数を指定してください", MAX_CALL_DEPTH)),
+                };
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with('-') => return Err(format!("不明なオプションです : {}", arg)),
+            _ if options.path.is_none() => options.path = Some(arg),
+            _ => return Err(format!("ファイルは 1 つだけ指定できます : {}", arg)),
+        }
+        first = false;
+    }
+
+    if options.command != Command::Run && options.path.is_none() {
+        return Err("ファイルを指定してください".to_string());
+    }
+
+    Ok(options)
+}
 
 fn main() {
-    let program = String::from("
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    // 呼び出しの深さの上限に達するまでスタックが溢れないよう, 十分なスタックを持つスレッドで実行する
+    let stack_size = (options.max_call_depth * STACK_PER_CALL).max(8 * 1024 * 1024);
+    let handle = std::thread::Builder::new()
+        .name("interpreter".to_string())
+        .stack_size(stack_size)
+        .spawn(move || execute(options));
+    match handle.map(|handle| handle.join()) {
+        Ok(Ok(())) => {}
+        Ok(Err(_)) => std::process::exit(101),
+        Err(error) => exit_with_error(&format!("実行用のスレッドを作成できません ({})", error)),
+    }
+}
+
+fn execute(options: Options) {
+
+    let input = match &options.path {
+        Some(path) => match std::fs::read(path) {
+            Ok(input) => input,
+            Err(error) => exit_with_error(&format!("ファイルを読み込めません : {} ({})", path, error)),
+        },
+        None => SAMPLE.as_bytes().to_vec(),
+    };
+
+    // バイトコードファイルは構文解析せずに読み込む
+    if bytecode_file::is_bytecode(&input) {
+        let program = match Program::from_bytes(&input) {
+            Ok(program) => program,
+            Err(error) => exit_with_error(&error.to_string()),
+        };
+
+        match options.command {
+            // バイトコードは VM でのみ実行できる
+            Command::Run => run_bytecode(program, &options),
+            Command::Compile | Command::Ir | Command::Lint => exit_with_error("すでにバイトコードファイルです"),
+            Command::Disasm => print!("{}", program.disassemble(None)),
+        }
+        return;
+    }
+
+    let source = match String::from_utf8(input) {
+        Ok(source) => source,
+        Err(_) => exit_with_error("ソースファイルが UTF-8 ではありません"),
+    };
+
+    match options.command {
+        Command::Run => run(source, &options),
+        Command::Compile => {
+            let (bytes, extension) = match options.target {
+                Target::Bytecode => (compile(&source, options.optimize).to_bytes(), "nbc"),
+                Target::C => (generate_c(&source, options.optimize).into_bytes(), "c"),
+                Target::Asm => (generate_asm(&source, options.optimize).into_bytes(), "s"),
+                Target::Wat => (generate_wat(&source, options.optimize).into_bytes(), "wat"),
+            };
+            let path = options.path.unwrap();
+            let output = options.output.unwrap_or_else(|| output_path(&path, extension));
+            if let Err(error) = std::fs::write(&output, bytes) {
+                exit_with_error(&format!("書き込みに失敗しました : {} ({})", output, error));
+            }
+        }
+        Command::Disasm => print!("{}", compile(&source, options.optimize).disassemble(Some(&source))),
+        Command::Lint => lint(&source),
+        Command::Ir => {
+            let program = build_ir(&source, options.optimize);
+            print!("{}", program);
+            let path = options.path.unwrap();
+            let output = options.output.unwrap_or_else(|| output_path(&path, "dot"));
+            if let Err(error) = std::fs::write(&output, program.to_dot()) {
+                exit_with_error(&format!("書き込みに失敗しました : {} ({})", output, error));
+            }
+        }
+    }
+}
+
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+/// 出力先の指定がない場合は入力ファイルの拡張子を置き換える
+fn output_path(path: &str, extension: &str) -> String {
+    std::path::Path::new(path).with_extension(extension).to_string_lossy().into_owned()
+}
+
+/// ソースを構文解析し, 指定があれば最適化した構文木を返す
+fn parse(source: &str, optimize: bool) -> Ast {
+    let mut lexer = Lexer::new(source.to_string());
+    lexer.tokenize();
+
+    let mut parser = Parser::with_lines(lexer.tokens().clone(), lexer.lines().clone());
+    parser.parse();
+    if optimize {
+        Optimizer::new(parser.ast()).optimize()
+    } else {
+        parser.ast().clone()
+    }
+}
+
+fn compile(source: &str, optimize: bool) -> Program {
+    Compiler::new(&parse(source, optimize)).compile()
+}
+
+fn generate_c(source: &str, optimize: bool) -> String {
+    match CGenerator::new(&parse(source, optimize)).generate() {
+        Ok(source) => source,
+        Err(diagnostics) => {
+            for diagnostic in diagnostics.iter() {
+                eprintln!("{}", diagnostic);
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+fn generate_asm(source: &str, optimize: bool) -> String {
+    match AsmGenerator::new(&parse(source, optimize)).generate() {
+        Ok(source) => source,
+        Err(diagnostics) => {
+            for diagnostic in diagnostics.iter() {
+                eprintln!("{}", diagnostic);
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+fn generate_wat(source: &str, optimize: bool) -> String {
+    match WatGenerator::new(&parse(source, optimize)).generate() {
+        Ok(source) => source,
+        Err(diagnostics) => {
+            for diagnostic in diagnostics.iter() {
+                eprintln!("{}", diagnostic);
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+fn build_ir(source: &str, optimize: bool) -> IrProgram {
+    match IrBuilder::new(&parse(source, optimize)).build() {
+        Ok(program) => program,
+        Err(diagnostics) => {
+            for diagnostic in diagnostics.iter() {
+                eprintln!("{}", diagnostic);
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+/// 名前解決と lint の診断を表示する. エラーがあれば終了コード 1 で終わる
+fn lint(source: &str) {
+    let ast = parse(source, false);
+
+    let mut resolver = Resolver::new(&ast);
+    resolver.resolve();
+    let mut diagnostics = resolver.diagnostics().clone();
+    diagnostics.extend(Linter::new(&ast).lint());
+    for diagnostic in diagnostics.iter() {
+        println!("{}", diagnostic);
+    }
+    if diagnostics.iter().any(|diagnostic| diagnostic.is_error()) {
+        std::process::exit(1);
+    }
+}
+
+fn run_bytecode(program: Program, options: &Options) {
+    let mut vm = Vm::new(program);
+    vm.set_max_call_depth(options.max_call_depth);
+    vm.set_strict(options.strict);
+    vm.set_arithmetic_mode(options.arithmetic_mode);
+    // 時間計測スタート
+    let start = std::time::Instant::now();
+    let val = vm.run();
+    // 時間計測終了
+    let end = std::time::Instant::now();
+    vm.show_variables();
+    println!("----------------------");
+
+    println!("calculation time: {:?}", end.duration_since(start));
+    println!("result: {:?}", val);
+}
+
+const SAMPLE: &str = "
 int main(void) {
     int count = 10;
     int sum = 0;
@@ -14,9 +377,9 @@ int main(void) {
     }
     return sum;
 }
-    ");
-
+    ";
 
+fn run(program: String, options: &Options) {
     let mut lexer = Lexer::new(program);
     lexer.tokenize();
 
@@ -24,8 +387,7 @@ int main(void) {
 
     println!("----------------------");
 
-    let tokens = lexer.tokens().clone();
-    let mut parser = Parser::new(tokens);
+    let mut parser = Parser::with_lines(lexer.tokens().clone(), lexer.lines().clone());
     parser.parse();
 
     println!("----------------------");
@@ -34,22 +396,66 @@ int main(void) {
 
     let mut tree_viewer = TreeViewer::new();
 
-    for (i, root) in parser.roots().iter().enumerate() {
-        tree_viewer.make_tree(root);
+    for root in parser.declarations().iter() {
+        tree_viewer.make_tree(parser.ast(), root);
     }
     tree_viewer.output_dot("trees/output.dot");
 
     println!("----------------------");
-    let mut interpreter = Interpreter::new(parser.roots());
+    // 実行前に意味解析を行い, エラーがあれば実行しない
+    let mut analyzer = SemanticAnalyzer::new(parser.ast());
+    let mut diagnostics = analyzer.analyze();
+
+    // 変数の隠蔽と宣言前の使用は名前解決で報告する
+    let mut resolver = Resolver::new(parser.ast());
+    resolver.resolve();
+    diagnostics.extend(resolver.diagnostics().iter().cloned());
+    for diagnostic in diagnostics.iter() {
+        println!("{}", diagnostic);
+    }
+    if diagnostics.iter().any(|diagnostic| diagnostic.is_error()) {
+        return;
+    }
+
+    // 意味解析が通った構文木だけを最適化する
+    let ast = if options.optimize {
+        let ast = Optimizer::new(parser.ast()).optimize();
+        println!("----------------------");
+        for declaration in ast.declarations().iter() {
+            println!("{}", ast.format_decl(declaration));
+        }
+        ast
+    } else {
+        parser.ast().clone()
+    };
+
+    println!("----------------------");
     // 時間計測スタート
     let start = std::time::Instant::now();
-    let val = interpreter.run();
+    let val = match options.backend {
+        Backend::Tree => {
+            let mut interpreter = Interpreter::new(&ast);
+            interpreter.set_max_call_depth(options.max_call_depth);
+            interpreter.set_strict(options.strict);
+            interpreter.set_arithmetic_mode(options.arithmetic_mode);
+            let val = interpreter.run();
+            interpreter.show_variables();
+            val
+        }
+        Backend::Vm => {
+            let mut vm = Vm::new(Compiler::new(&ast).compile());
+            vm.set_max_call_depth(options.max_call_depth);
+            vm.set_strict(options.strict);
+            vm.set_arithmetic_mode(options.arithmetic_mode);
+            let val = vm.run();
+            vm.show_variables();
+            val
+        }
+    };
     // 時間計測終了
     let end = std::time::Instant::now();
-    interpreter.show_variables();
     println!("----------------------");
 
     println!("calculation time: {:?}", end.duration_since(start));
     println!("result: {:?}", val);
-    let answer = 75025;
 }